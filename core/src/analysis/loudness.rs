@@ -0,0 +1,258 @@
+use crate::audio::convert::convert_sample;
+use anyhow::Context;
+use rawdio::{AudioBuffer, SampleLocation};
+use std::{f64::consts::PI, path::Path};
+
+/// Blocks are 400 ms long and start every 100 ms, as in ITU-R BS.1770.
+const SEGMENTS_PER_BLOCK: usize = 4;
+const SEGMENT_SECONDS: f64 = 0.1;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// Loudness measured as in ITU-R BS.1770-4 and EBU R128.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnessMeasurement {
+    /// Gated integrated loudness, in LUFS.
+    pub integrated: f64,
+    /// The highest peak, including peaks between samples, in dBTP.
+    pub true_peak: f64,
+}
+
+pub fn measure_loudness_from_file(path: &Path) -> anyhow::Result<Option<LoudnessMeasurement>> {
+    let sample_rate = hound::WavReader::open(path)
+        .context("Unable to open file to measure its loudness")?
+        .spec()
+        .sample_rate as usize;
+    let audio = convert_sample(path, sample_rate)?;
+
+    let channels: Vec<Vec<f32>> = (0..audio.channel_count())
+        .map(|channel| {
+            (0..audio.frame_count())
+                .map(|frame| audio.get_sample(SampleLocation::new(channel, frame)))
+                .collect()
+        })
+        .collect();
+
+    Ok(measure_loudness(&channels, sample_rate))
+}
+
+/// Measure the loudness of audio, with every channel weighted equally.
+///
+/// Returns `None` when the audio is shorter than one 400 ms block or quieter
+/// than the absolute gate throughout.
+pub fn measure_loudness(channels: &[Vec<f32>], sample_rate: usize) -> Option<LoudnessMeasurement> {
+    let integrated = integrated_loudness(channels, sample_rate)?;
+
+    let true_peak = channels.iter().map(|channel| true_peak(channel)).fold(0.0, f64::max);
+
+    Some(LoudnessMeasurement {
+        integrated,
+        true_peak: 20.0 * true_peak.max(1e-10).log10(),
+    })
+}
+
+fn integrated_loudness(channels: &[Vec<f32>], sample_rate: usize) -> Option<f64> {
+    let segment_length = (sample_rate as f64 * SEGMENT_SECONDS).round() as usize;
+    if segment_length == 0 {
+        return None;
+    }
+
+    // The summed power of the K-weighted channels in each 100 ms segment
+    let mut segments = vec![];
+    for channel in channels {
+        let mut filters = [Biquad::pre_filter(sample_rate), Biquad::high_pass(sample_rate)];
+        for (index, chunk) in channel.chunks_exact(segment_length).enumerate() {
+            let power: f64 = chunk
+                .iter()
+                .map(|sample| {
+                    let filtered = filters
+                        .iter_mut()
+                        .fold(f64::from(*sample), |value, filter| filter.process(value));
+                    filtered * filtered
+                })
+                .sum();
+
+            if index == segments.len() {
+                segments.push(0.0);
+            }
+            segments[index] += power;
+        }
+    }
+
+    let block_powers: Vec<f64> = segments
+        .windows(SEGMENTS_PER_BLOCK)
+        .map(|block| block.iter().sum::<f64>() / (segment_length * SEGMENTS_PER_BLOCK) as f64)
+        .collect();
+
+    let loudness = |power: f64| -0.691 + 10.0 * power.log10();
+    let gated_loudness = |threshold: f64| {
+        let gated: Vec<f64> = block_powers
+            .iter()
+            .copied()
+            .filter(|power| *power > 0.0 && loudness(*power) > threshold)
+            .collect();
+        if gated.is_empty() {
+            return None;
+        }
+        Some(loudness(gated.iter().sum::<f64>() / gated.len() as f64))
+    };
+
+    let relative_gate = gated_loudness(ABSOLUTE_GATE_LUFS)? + RELATIVE_GATE_LU;
+    gated_loudness(relative_gate.max(ABSOLUTE_GATE_LUFS))
+}
+
+/// The largest absolute value of a channel after oversampling, which catches
+/// peaks that fall between samples.
+fn true_peak(channel: &[f32]) -> f64 {
+    let filter = interpolation_filter();
+    let sample_peak = channel
+        .iter()
+        .fold(0.0, |peak: f64, sample| peak.max(f64::from(sample.abs())));
+
+    (0..channel.len()).fold(sample_peak, |peak, index| {
+        (0..OVERSAMPLING).fold(peak, |peak, phase| {
+            let value: f64 = (0..TAPS_PER_PHASE)
+                .filter(|tap| *tap <= index)
+                .map(|tap| f64::from(channel[index - tap]) * filter[tap * OVERSAMPLING + phase])
+                .sum();
+            peak.max(value.abs())
+        })
+    })
+}
+
+/// A Hann windowed sinc that interpolates between samples at the oversampled rate.
+fn interpolation_filter() -> Vec<f64> {
+    let length = OVERSAMPLING * TAPS_PER_PHASE;
+    let centre = (length - 1) as f64 / 2.0;
+
+    (0..length)
+        .map(|index| {
+            let offset = (index as f64 - centre) / OVERSAMPLING as f64;
+            let sinc = if offset == 0.0 {
+                1.0
+            } else {
+                (PI * offset).sin() / (PI * offset)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * (index as f64 + 0.5) / length as f64).cos();
+            sinc * window
+        })
+        .collect()
+}
+
+/// A second order filter, in direct form I.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    inputs: [f64; 2],
+    outputs: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            inputs: [0.0; 2],
+            outputs: [0.0; 2],
+        }
+    }
+
+    /// The high shelf that models the acoustic effect of the head, at any sample rate.
+    fn pre_filter(sample_rate: usize) -> Self {
+        let frequency = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (PI * frequency / sample_rate as f64).tan();
+        let high_gain = 10.0_f64.powf(gain_db / 20.0);
+        let band_gain = high_gain.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+
+        Self::new(
+            [
+                (high_gain + band_gain * k / q + k * k) / a0,
+                2.0 * (k * k - high_gain) / a0,
+                (high_gain - band_gain * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        )
+    }
+
+    /// The revised low-frequency B-weighting high pass, at any sample rate.
+    fn high_pass(sample_rate: usize) -> Self {
+        let frequency = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (PI * frequency / sample_rate as f64).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        Self::new([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0])
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.inputs[0] + self.b[2] * self.inputs[1]
+            - self.a[0] * self.outputs[0]
+            - self.a[1] * self.outputs[1];
+
+        self.inputs = [input, self.inputs[0]];
+        self.outputs = [output, self.outputs[0]];
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 48_000;
+
+    fn sine(frequency: f64, level_db: f64, phase: f64, seconds: f64) -> Vec<f32> {
+        let amplitude = 10.0_f64.powf(level_db / 20.0);
+        (0..(seconds * SAMPLE_RATE as f64) as usize)
+            .map(|frame| {
+                let time = frame as f64 / SAMPLE_RATE as f64;
+                (amplitude * (2.0 * PI * frequency * time + phase).sin()) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stereo_sine_at_minus_23_dbfs_is_minus_23_lufs() {
+        // The first test signal from EBU Tech 3341
+        let channel = sine(1000.0, -23.0, 0.0, 20.0);
+        let measurement = measure_loudness(&[channel.clone(), channel], SAMPLE_RATE).unwrap();
+        assert!((measurement.integrated - -23.0).abs() < 0.1, "{:?}", measurement);
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let mut channel = vec![0.0; SAMPLE_RATE * 10];
+        channel.extend(sine(1000.0, -23.0, 0.0, 10.0));
+        let measurement = measure_loudness(&[channel.clone(), channel], SAMPLE_RATE).unwrap();
+        assert!((measurement.integrated - -23.0).abs() < 0.1, "{:?}", measurement);
+    }
+
+    #[test]
+    fn silent_audio_has_no_loudness() {
+        assert_eq!(measure_loudness(&[vec![0.0; SAMPLE_RATE]], SAMPLE_RATE), None);
+    }
+
+    #[test]
+    fn short_audio_has_no_loudness() {
+        assert_eq!(measure_loudness(&[sine(1000.0, -6.0, 0.0, 0.3)], SAMPLE_RATE), None);
+    }
+
+    #[test]
+    fn true_peak_finds_peaks_between_samples() {
+        // A quarter of the sample rate, a eighth of a cycle out, never samples its peaks
+        let channel = sine(SAMPLE_RATE as f64 / 4.0, -6.0, PI / 4.0, 1.0);
+        let sample_peak = channel.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        assert!((20.0 * f64::from(sample_peak).log10() - -9.0).abs() < 0.1);
+
+        let measurement = measure_loudness(&[channel], SAMPLE_RATE).unwrap();
+        assert!((measurement.true_peak - -6.0).abs() < 0.5, "{:?}", measurement);
+    }
+}
@@ -0,0 +1,12 @@
+mod loudness;
+mod onsets;
+mod silence;
+mod structure;
+mod tempo;
+#[cfg(test)]
+mod test_signals;
+
+pub use loudness::{measure_loudness_from_file, LoudnessMeasurement};
+pub use silence::find_first_sound;
+pub use structure::{analyse_structure_from_file, Structure};
+pub use tempo::{estimate_tempo_from_file, TempoEstimate};
@@ -0,0 +1,53 @@
+use crate::audio::convert::convert_sample;
+use rawdio::{AudioBuffer, SampleLocation};
+use std::path::Path;
+
+/// Audio is resampled to this rate before analysis, which is plenty for finding onsets.
+const ANALYSIS_SAMPLE_RATE: usize = 22_050;
+const FRAME_SIZE: usize = 1024;
+pub const HOP_SIZE: usize = 256;
+/// Quieter frames than this (about -60 dB) are treated as silence.
+const ENERGY_FLOOR: f64 = 1e-6;
+
+/// Read a sample as mono audio at the analysis sample rate.
+pub fn read_mono(path: &Path) -> anyhow::Result<(Vec<f32>, usize)> {
+    let audio = convert_sample(path, ANALYSIS_SAMPLE_RATE)?;
+    Ok((mix_to_mono(&audio), audio.sample_rate()))
+}
+
+fn mix_to_mono(audio: &dyn AudioBuffer) -> Vec<f32> {
+    let channel_count = audio.channel_count();
+
+    (0..audio.frame_count())
+        .map(|frame| {
+            let sum: f32 = (0..channel_count)
+                .map(|channel| audio.get_sample(SampleLocation::new(channel, frame)))
+                .sum();
+            sum / channel_count.max(1) as f32
+        })
+        .collect()
+}
+
+/// How much a frame's energy rose on the previous one, on a log scale, with one
+/// value per hop.
+pub fn onset_envelope(audio: &[f32]) -> Vec<f64> {
+    let log_energies: Vec<f64> = audio
+        .windows(FRAME_SIZE)
+        .step_by(HOP_SIZE)
+        .map(|frame| {
+            let energy = frame.iter().map(|sample| f64::from(*sample).powi(2)).sum::<f64>() / FRAME_SIZE as f64;
+            energy.max(ENERGY_FLOOR).ln()
+        })
+        .collect();
+
+    std::iter::once(0.0)
+        .chain(log_energies.windows(2).map(|pair| (pair[1] - pair[0]).max(0.0)))
+        .take(log_energies.len())
+        .collect()
+}
+
+/// Roughly where in the audio the onset at `index` in the envelope happened, in
+/// sample frames. Onsets show up as new audio enters the end of a frame.
+pub fn onset_position(index: f64) -> f64 {
+    index * HOP_SIZE as f64 + (FRAME_SIZE - HOP_SIZE / 2) as f64
+}
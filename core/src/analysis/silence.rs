@@ -0,0 +1,70 @@
+use anyhow::Context;
+use hound::SampleFormat;
+use std::path::Path;
+
+/// Samples quieter than this (about -48 dBFS) count as silence.
+const SILENCE_THRESHOLD: f32 = 0.004;
+
+/// The first frame of a WAV file that isn't silent, or zero if the whole file is.
+pub fn find_first_sound(path: &Path) -> anyhow::Result<i64> {
+    let mut reader = hound::WavReader::open(path).context("Unable to open file to find its start")?;
+    let spec = reader.spec();
+    let channel_count = i64::from(spec.channels.max(1));
+
+    let first_loud_sample = match spec.sample_format {
+        SampleFormat::Float => reader
+            .samples::<f32>()
+            .position(|sample| sample.map_or(false, |sample| sample.abs() >= SILENCE_THRESHOLD)),
+        SampleFormat::Int => {
+            let threshold = (SILENCE_THRESHOLD * 2.0_f32.powi(i32::from(spec.bits_per_sample) - 1)) as i32;
+            reader
+                .samples::<i32>()
+                .position(|sample| sample.map_or(false, |sample| sample.abs() >= threshold))
+        }
+    };
+
+    Ok(first_loud_sample.map_or(0, |index| index as i64 / channel_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_wav(path: &Path, frames: &[[i16; 2]]) {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for frame in frames {
+            writer.write_sample(frame[0]).unwrap();
+            writer.write_sample(frame[1]).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn finds_the_end_of_leading_silence() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("pre-roll.wav");
+
+        let mut frames = vec![[0, 0]; 300];
+        frames.extend(vec![[10, -10]; 100]);
+        frames.push([0, 8000]);
+        frames.extend(vec![[4000, 4000]; 100]);
+        write_wav(&path, &frames);
+
+        assert_eq!(find_first_sound(&path).unwrap(), 400);
+    }
+
+    #[test]
+    fn silent_files_start_at_zero() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("silence.wav");
+        write_wav(&path, &[[0, 0]; 100]);
+
+        assert_eq!(find_first_sound(&path).unwrap(), 0);
+    }
+}
@@ -0,0 +1,292 @@
+use super::onsets::{onset_envelope, onset_position, read_mono, HOP_SIZE};
+use anyhow::{anyhow, Context};
+use std::{f64::consts::PI, path::Path};
+
+const BEATS_PER_BAR: usize = 4;
+/// How many bars either side of a boundary are compared, which is also the
+/// shortest section that will be suggested.
+const KERNEL_BARS: usize = 4;
+/// Boundaries less novel than this aren't suggested. A 6 dB change in one band
+/// scores about 0.6.
+const MIN_NOVELTY: f64 = 0.3;
+/// Level differences are measured in units of this many dB.
+const LEVEL_SCALE_DB: f64 = 6.0;
+const LOW_BAND_HZ: f64 = 150.0;
+const HIGH_BAND_HZ: f64 = 3000.0;
+/// An intro shorter than this, in beats, is left in the first section.
+const MIN_INTRO_BEATS: f64 = 1.0;
+
+/// The bar grid of a sample and where its character changes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Structure {
+    /// Where the first bar starts, in beats from the start of the audio.
+    pub first_downbeat: f64,
+    /// Where the audio changes, in beats from the start of the audio. These are
+    /// always on a bar line.
+    pub boundaries: Vec<f64>,
+}
+
+impl Structure {
+    /// Where suggested sections start, in beats. The first starts at zero, and an
+    /// intro before the first downbeat gets a section of its own.
+    pub fn section_starts(&self) -> Vec<f64> {
+        let mut starts = vec![0.0];
+        if self.first_downbeat >= MIN_INTRO_BEATS {
+            starts.push(self.first_downbeat);
+        }
+        starts.extend(self.boundaries.iter().filter(|boundary| **boundary > 0.0));
+        starts
+    }
+}
+
+/// Analyse a sample from its start offset, in frames of the file, so beats are
+/// counted from there.
+pub fn analyse_structure_from_file(path: &Path, bpm: f64, start_offset: i64) -> anyhow::Result<Structure> {
+    let file_sample_rate = hound::WavReader::open(path)
+        .context("Unable to open file to analyse its structure")?
+        .spec()
+        .sample_rate;
+    let (audio, sample_rate) = read_mono(path)?;
+
+    let start = (start_offset.max(0) as f64 * sample_rate as f64 / f64::from(file_sample_rate.max(1))).round() as usize;
+    analyse_structure(audio.get(start..).unwrap_or_default(), sample_rate, bpm)
+}
+
+/// Find the first downbeat of audio at a known tempo, assuming 4/4, and the bar
+/// lines where its level or spectrum changes.
+pub fn analyse_structure(audio: &[f32], sample_rate: usize, bpm: f64) -> anyhow::Result<Structure> {
+    if bpm <= 0.0 || sample_rate == 0 {
+        return Err(anyhow!("Can't analyse structure at {bpm} BPM"));
+    }
+
+    let envelope = onset_envelope(audio);
+    let beat_length = 60.0 * sample_rate as f64 / bpm;
+    let beat_count = (audio.len() as f64 / beat_length).ceil() as usize;
+
+    // The strength of onsets on beats from a given position
+    let beat_strengths = |first_beat: f64| -> Vec<Option<f64>> {
+        (0..beat_count)
+            .map(|beat| envelope_at(&envelope, first_beat + beat as f64 * beat_length))
+            .collect()
+    };
+
+    // Positions are searched from a little before the start, as onsets are only
+    // located to within a hop
+    let first_beat = (0..)
+        .map(|step| step as f64 * HOP_SIZE as f64 / 2.0 - HOP_SIZE as f64)
+        .take_while(|position| *position < beat_length - HOP_SIZE as f64)
+        .max_by(|a, b| {
+            let score = |position: f64| beat_strengths(position).iter().flatten().sum::<f64>();
+            score(*a).total_cmp(&score(*b))
+        })
+        .unwrap_or_default()
+        .max(0.0);
+
+    // Downbeats are usually the strongest beats in the bar. Ties go to the earliest.
+    let strengths = beat_strengths(first_beat);
+    let downbeat_index = (0..BEATS_PER_BAR)
+        .rev()
+        .max_by(|a, b| {
+            let mean_strength = |offset: usize| {
+                let values: Vec<f64> = strengths
+                    .iter()
+                    .skip(offset)
+                    .step_by(BEATS_PER_BAR)
+                    .flatten()
+                    .copied()
+                    .collect();
+                values.iter().sum::<f64>() / values.len().max(1) as f64
+            };
+            mean_strength(*a).total_cmp(&mean_strength(*b))
+        })
+        .unwrap_or_default();
+
+    let first_downbeat = first_beat + downbeat_index as f64 * beat_length;
+    let bar_length = beat_length * BEATS_PER_BAR as f64;
+    let bars = bar_features(audio, sample_rate, first_downbeat, bar_length);
+    let first_downbeat_beats = round_beats(first_downbeat / beat_length);
+
+    let novelty: Vec<f64> = (0..bars.len()).map(|bar| novelty(&bars, bar)).collect();
+    let boundaries = (1..bars.len())
+        .filter(|bar| {
+            let before = bar.saturating_sub(KERNEL_BARS - 1)..*bar;
+            let after = bar + 1..(bar + KERNEL_BARS).min(bars.len());
+            novelty[*bar] >= MIN_NOVELTY
+                && novelty[before].iter().all(|value| *value < novelty[*bar])
+                && novelty[after].iter().all(|value| *value <= novelty[*bar])
+        })
+        .map(|bar| round_beats(first_downbeat_beats + (bar * BEATS_PER_BAR) as f64))
+        .collect();
+
+    Ok(Structure {
+        first_downbeat: first_downbeat_beats,
+        boundaries,
+    })
+}
+
+fn round_beats(beats: f64) -> f64 {
+    (beats * 100.0).round() / 100.0
+}
+
+/// The onset strength for an onset at a position in the audio, if the envelope covers it.
+fn envelope_at(envelope: &[f64], position: f64) -> Option<f64> {
+    let index = (position - onset_position(0.0)) / HOP_SIZE as f64;
+    if index < 0.0 {
+        return None;
+    }
+    envelope.get(index.round() as usize).copied()
+}
+
+/// The overall, low and high band levels of each complete bar, scaled so that a
+/// difference of one is clearly audible.
+fn bar_features(audio: &[f32], sample_rate: usize, first_downbeat: f64, bar_length: f64) -> Vec<[f64; 3]> {
+    let low = low_pass(audio, sample_rate, LOW_BAND_HZ);
+    let high: Vec<f32> = audio
+        .iter()
+        .zip(low_pass(audio, sample_rate, HIGH_BAND_HZ))
+        .map(|(sample, low)| sample - low)
+        .collect();
+
+    let level = |signal: &[f32]| {
+        let energy = signal.iter().map(|sample| f64::from(*sample).powi(2)).sum::<f64>() / signal.len().max(1) as f64;
+        10.0 * energy.max(1e-6).log10() / LEVEL_SCALE_DB
+    };
+
+    (0..)
+        .map(|bar| first_downbeat + bar as f64 * bar_length)
+        .map(|start| (start.round() as usize, (start + bar_length).round() as usize))
+        .take_while(|(_, end)| *end <= audio.len())
+        .map(|(start, end)| {
+            [
+                level(&audio[start..end]),
+                level(&low[start..end]),
+                level(&high[start..end]),
+            ]
+        })
+        .collect()
+}
+
+fn low_pass(audio: &[f32], sample_rate: usize, cutoff: f64) -> Vec<f32> {
+    let coefficient = (1.0 - (-2.0 * PI * cutoff / sample_rate as f64).exp()) as f32;
+    let mut state = 0.0;
+    audio
+        .iter()
+        .map(|sample| {
+            state += coefficient * (sample - state);
+            state
+        })
+        .collect()
+}
+
+/// How different the bars before `boundary` are from the bars after it, compared
+/// with how alike each side is within itself.
+fn novelty(bars: &[[f64; 3]], boundary: usize) -> f64 {
+    let before = &bars[boundary.saturating_sub(KERNEL_BARS)..boundary];
+    let after = &bars[boundary..(boundary + KERNEL_BARS).min(bars.len())];
+    if before.len() < 2 || after.len() < 2 {
+        return 0.0;
+    }
+
+    let similarity = |a: &[f64; 3], b: &[f64; 3]| {
+        let distance: f64 = a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum();
+        (-distance).exp()
+    };
+    let mean_similarity = |a: &[[f64; 3]], b: &[[f64; 3]]| {
+        let total: f64 = a.iter().flat_map(|x| b.iter().map(move |y| similarity(x, y))).sum();
+        total / (a.len() * b.len()) as f64
+    };
+
+    let within = (mean_similarity(before, before) + mean_similarity(after, after)) / 2.0;
+    within - mean_similarity(before, after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_signals::{add_noise, clicks, SAMPLE_RATE};
+
+    /// Clicks on every beat, with the beat at `first_accent` and every fourth one after louder.
+    fn accented_clicks(bpm: f64, beats: usize, first_accent: usize) -> Vec<f32> {
+        let length = (beats as f64 * 60.0 * SAMPLE_RATE as f64 / bpm) as usize;
+        clicks(bpm, length, |beat| {
+            if beat % BEATS_PER_BAR == first_accent {
+                0.8
+            } else {
+                0.2
+            }
+        })
+    }
+
+    #[test]
+    fn finds_the_first_downbeat() {
+        // Two beats of silence before the clicks start
+        let mut audio = vec![0.0; SAMPLE_RATE];
+        audio.extend(accented_clicks(120.0, 32, 0));
+
+        let structure = analyse_structure(&audio, SAMPLE_RATE, 120.0).unwrap();
+        assert!((structure.first_downbeat - 2.0).abs() < 0.1, "{:?}", structure);
+    }
+
+    #[test]
+    fn finds_the_first_downbeat_after_a_pickup() {
+        let audio = accented_clicks(120.0, 33, 1);
+
+        let structure = analyse_structure(&audio, SAMPLE_RATE, 120.0).unwrap();
+        assert!((structure.first_downbeat - 1.0).abs() < 0.1, "{:?}", structure);
+        assert_eq!(structure.section_starts()[..2], [0.0, structure.first_downbeat]);
+    }
+
+    #[test]
+    fn finds_where_the_level_changes() {
+        let mut audio = accented_clicks(120.0, 64, 0);
+        let half = audio.len() / 2;
+        audio.truncate(half);
+        let mut louder = accented_clicks(120.0, 64, 0);
+        add_noise(&mut louder, 0.4);
+        audio.extend(&louder[half..]);
+
+        let structure = analyse_structure(&audio, SAMPLE_RATE, 120.0).unwrap();
+        assert_eq!(structure.boundaries.len(), 1, "{:?}", structure);
+        assert!((structure.boundaries[0] - 32.0).abs() < 0.25, "{:?}", structure);
+    }
+
+    #[test]
+    fn steady_audio_has_one_section() {
+        let structure = analyse_structure(&accented_clicks(120.0, 64, 0), SAMPLE_RATE, 120.0).unwrap();
+        assert!(structure.first_downbeat < 0.1, "{:?}", structure);
+        assert_eq!(structure.section_starts(), vec![0.0]);
+    }
+
+    #[test]
+    fn beats_are_counted_from_the_start_offset() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("pre-roll.wav");
+
+        // Two beats of silence before the clicks, written at twice the analysis rate
+        let mut audio = vec![0.0; SAMPLE_RATE];
+        audio.extend(accented_clicks(120.0, 32, 0));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 2 * SAMPLE_RATE as u32,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for sample in audio {
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let structure = analyse_structure_from_file(&path, 120.0, 0).unwrap();
+        assert!((structure.first_downbeat - 2.0).abs() < 0.1, "{:?}", structure);
+
+        let structure = analyse_structure_from_file(&path, 120.0, 2 * SAMPLE_RATE as i64).unwrap();
+        assert!(structure.first_downbeat < 0.1, "{:?}", structure);
+    }
+
+    #[test]
+    fn tempo_must_be_positive() {
+        assert!(analyse_structure(&[0.0; 100], SAMPLE_RATE, 0.0).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use super::onsets::{onset_envelope, read_mono, HOP_SIZE};
+use std::path::Path;
+
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+/// Tempos near this are preferred, so tracks aren't reported at half or double speed.
+const PREFERRED_BPM: f64 = 120.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoEstimate {
+    pub bpm: f64,
+    /// How strongly onsets repeat at this tempo, from 0 to 1.
+    pub confidence: f64,
+}
+
+pub fn estimate_tempo_from_file(path: &Path) -> anyhow::Result<Option<TempoEstimate>> {
+    let (audio, sample_rate) = read_mono(path)?;
+    Ok(estimate_tempo(&audio, sample_rate))
+}
+
+/// Estimate the tempo of mono audio from the autocorrelation of its onsets.
+///
+/// Returns `None` when the audio is silent, too short to hold a few beats, or
+/// doesn't repeat at any tempo between 60 and 200 BPM.
+pub fn estimate_tempo(audio: &[f32], sample_rate: usize) -> Option<TempoEstimate> {
+    let envelope = onset_envelope(audio);
+    let envelope_rate = sample_rate as f64 / HOP_SIZE as f64;
+
+    let min_lag = (60.0 * envelope_rate / MAX_BPM).floor() as usize;
+    let max_lag = (60.0 * envelope_rate / MIN_BPM).ceil() as usize;
+    if min_lag < 2 || envelope.len() < 4 * max_lag {
+        return None;
+    }
+
+    let mean = envelope.iter().sum::<f64>() / envelope.len() as f64;
+    let envelope: Vec<f64> = envelope.iter().map(|value| value - mean).collect();
+
+    let energy = autocorrelation(&envelope, 0);
+    if energy <= f64::EPSILON {
+        return None;
+    }
+
+    // One lag either side of the range, for interpolating the peak
+    let first_lag = min_lag - 1;
+    let correlations: Vec<f64> = (first_lag..=max_lag + 1)
+        .map(|lag| autocorrelation(&envelope, lag) / energy)
+        .collect();
+
+    let best = (1..correlations.len() - 1).max_by(|a, b| {
+        let score =
+            |index: usize| correlations[index] * tempo_weight(60.0 * envelope_rate / (first_lag + index) as f64);
+        score(*a).total_cmp(&score(*b))
+    })?;
+
+    let correlation = correlations[best];
+    if correlation <= 0.0 {
+        return None;
+    }
+
+    let offset = parabolic_peak_offset(correlations[best - 1], correlation, correlations[best + 1]);
+    let bpm = 60.0 * envelope_rate / ((first_lag + best) as f64 + offset);
+
+    Some(TempoEstimate {
+        bpm: (bpm * 10.0).round() / 10.0,
+        confidence: correlation.clamp(0.0, 1.0),
+    })
+}
+
+fn autocorrelation(values: &[f64], lag: usize) -> f64 {
+    let count = values.len() - lag;
+    let sum: f64 = values.iter().zip(&values[lag..]).map(|(a, b)| a * b).sum();
+    sum / count as f64
+}
+
+/// A log-normal weighting, one octave wide, centred on the preferred tempo.
+fn tempo_weight(bpm: f64) -> f64 {
+    (-0.5 * (bpm / PREFERRED_BPM).log2().powi(2)).exp()
+}
+
+/// Where the peak of a parabola through three evenly spaced points lies, relative
+/// to the middle one.
+fn parabolic_peak_offset(before: f64, peak: f64, after: f64) -> f64 {
+    let curvature = before - 2.0 * peak + after;
+    if curvature.abs() <= f64::EPSILON {
+        return 0.0;
+    }
+
+    (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_signals::{add_noise, clicks, SAMPLE_RATE};
+
+    fn click_track(bpm: f64, seconds: f64) -> Vec<f32> {
+        clicks(bpm, (seconds * SAMPLE_RATE as f64) as usize, |_| 0.8)
+    }
+
+    fn noise(seconds: f64) -> Vec<f32> {
+        let mut audio = vec![0.0; (seconds * SAMPLE_RATE as f64) as usize];
+        add_noise(&mut audio, 1.0);
+        audio
+    }
+
+    #[test]
+    fn finds_the_tempo_of_a_click_track() {
+        let estimate = estimate_tempo(&click_track(120.0, 16.0), SAMPLE_RATE).unwrap();
+        assert!((estimate.bpm - 120.0).abs() < 1.0, "{:?}", estimate);
+        assert!(estimate.confidence > 0.5, "{:?}", estimate);
+    }
+
+    #[test]
+    fn finds_tempos_away_from_the_preferred_tempo() {
+        let estimate = estimate_tempo(&click_track(97.0, 16.0), SAMPLE_RATE).unwrap();
+        assert!((estimate.bpm - 97.0).abs() < 1.0, "{:?}", estimate);
+    }
+
+    #[test]
+    fn noise_has_low_confidence() {
+        if let Some(estimate) = estimate_tempo(&noise(16.0), SAMPLE_RATE) {
+            assert!(estimate.confidence < 0.3, "{:?}", estimate);
+        }
+    }
+
+    #[test]
+    fn silence_has_no_tempo() {
+        assert_eq!(estimate_tempo(&[0.0; SAMPLE_RATE * 16], SAMPLE_RATE), None);
+    }
+
+    #[test]
+    fn short_audio_has_no_tempo() {
+        assert_eq!(estimate_tempo(&click_track(120.0, 1.0), SAMPLE_RATE), None);
+    }
+}
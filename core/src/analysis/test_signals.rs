@@ -0,0 +1,30 @@
+//! Synthetic audio for testing the analysers.
+
+pub const SAMPLE_RATE: usize = 22_050;
+
+/// A short decaying click on every beat, with each beat's level from `gain`.
+pub fn clicks(bpm: f64, length: usize, gain: impl Fn(usize) -> f32) -> Vec<f32> {
+    let beat_length = 60.0 * SAMPLE_RATE as f64 / bpm;
+    let click_length = SAMPLE_RATE / 100;
+    let mut audio = vec![0.0; length];
+
+    for beat in (0..).take_while(|beat| ((*beat as f64 * beat_length) as usize) < length) {
+        let position = (beat as f64 * beat_length) as usize;
+        let gain = gain(beat);
+        for (offset, sample) in audio[position..].iter_mut().take(click_length).enumerate() {
+            let decay = 1.0 - offset as f32 / click_length as f32;
+            *sample = gain * decay * (offset as f32 * 0.3).sin();
+        }
+    }
+
+    audio
+}
+
+/// Add white noise from a fixed seed, so tests always hear the same noise.
+pub fn add_noise(audio: &mut [f32], amplitude: f32) {
+    let mut state: u32 = 12345;
+    for sample in audio.iter_mut() {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        *sample += amplitude * ((state >> 8) as f32 / (1 << 24) as f32 - 0.5);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::bloop::ErrorCode;
+use std::fmt;
+
+/// An error carrying a code that's reported back to the client that made the
+/// request.
+#[derive(Debug)]
+pub struct ApiError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+pub fn api_error(code: ErrorCode, message: impl Into<String>) -> anyhow::Error {
+    ApiError {
+        code,
+        message: message.into(),
+    }
+    .into()
+}
+
+/// The code for an HTTP status from the cloud backend.
+pub fn error_code_for_status(status: reqwest::StatusCode) -> ErrorCode {
+    match status {
+        reqwest::StatusCode::BAD_REQUEST => ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+        reqwest::StatusCode::UNAUTHORIZED => ErrorCode::ERROR_CODE_NOT_LOGGED_IN,
+        reqwest::StatusCode::FORBIDDEN => ErrorCode::ERROR_CODE_FORBIDDEN,
+        reqwest::StatusCode::NOT_FOUND => ErrorCode::ERROR_CODE_NOT_FOUND,
+        _ => ErrorCode::ERROR_CODE_BACKEND_UNAVAILABLE,
+    }
+}
+
+/// The code for an error, from the first `ApiError` in its chain. Errors from the
+/// cloud backend and missing files are recognised without being tagged.
+pub fn error_code(error: &anyhow::Error) -> ErrorCode {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(api_error) = cause.downcast_ref::<ApiError>() {
+                return Some(api_error.code);
+            }
+
+            if let Some(reqwest_error) = cause.downcast_ref::<reqwest::Error>() {
+                return Some(match reqwest_error.status() {
+                    Some(status) => error_code_for_status(status),
+                    None => ErrorCode::ERROR_CODE_BACKEND_UNAVAILABLE,
+                });
+            }
+
+            match cause.downcast_ref::<std::io::Error>() {
+                Some(io_error) if io_error.kind() == std::io::ErrorKind::NotFound => {
+                    Some(ErrorCode::ERROR_CODE_NOT_FOUND)
+                }
+                _ => None,
+            }
+        })
+        .unwrap_or(ErrorCode::ERROR_CODE_UNKNOWN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn code_is_found_through_context() {
+        let error = Err::<(), _>(api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song not found"))
+            .context("Selecting song")
+            .unwrap_err();
+
+        assert_eq!(error_code(&error), ErrorCode::ERROR_CODE_NOT_FOUND);
+    }
+
+    #[test]
+    fn message_is_preserved() {
+        let error = api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Invalid project");
+        assert_eq!(error.to_string(), "Invalid project");
+    }
+
+    #[test]
+    fn untagged_errors_are_unknown() {
+        assert_eq!(error_code(&anyhow!("Oops")), ErrorCode::ERROR_CODE_UNKNOWN);
+    }
+
+    #[test]
+    fn backend_statuses_have_codes() {
+        assert_eq!(
+            error_code_for_status(reqwest::StatusCode::UNAUTHORIZED),
+            ErrorCode::ERROR_CODE_NOT_LOGGED_IN
+        );
+        assert_eq!(
+            error_code_for_status(reqwest::StatusCode::NOT_FOUND),
+            ErrorCode::ERROR_CODE_NOT_FOUND
+        );
+        assert_eq!(
+            error_code_for_status(reqwest::StatusCode::BAD_GATEWAY),
+            ErrorCode::ERROR_CODE_BACKEND_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn missing_files_are_not_found() {
+        let error = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(error_code(&error), ErrorCode::ERROR_CODE_NOT_FOUND);
+    }
+}
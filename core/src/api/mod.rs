@@ -1,2 +1,5 @@
+mod error;
 mod request;
 mod response;
+
+pub use error::{api_error, error_code, error_code_for_status};
@@ -20,6 +20,113 @@ impl Request {
         }
     }
 
+    pub fn export_project_request(project_id: &str, path: &str) -> Self {
+        Self {
+            export_project: Some(ExportProjectRequest {
+                project_id: project_id.to_string(),
+                path: path.to_string(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn import_project_request(path: &str) -> Self {
+        Self {
+            import_project: Some(ImportProjectRequest {
+                path: path.to_string(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn undo_request() -> Self {
+        Self {
+            undo: Some(UndoRequest::default()).into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn redo_request() -> Self {
+        Self {
+            redo: Some(RedoRequest::default()).into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn analyse_tempo_request(song_id: ID) -> Self {
+        Self {
+            analyse_tempo: Some(AnalyseTempoRequest {
+                song_id,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn suggest_sections_request(song_id: ID) -> Self {
+        Self {
+            suggest_sections: Some(SuggestSectionsRequest {
+                song_id,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn replace_sections_request(song_id: ID, sections: &[Section]) -> Self {
+        Self {
+            replace_sections: Some(ReplaceSectionsRequest {
+                song_id,
+                sections: sections.to_vec(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn detect_sample_start_request(song_id: ID) -> Self {
+        Self {
+            detect_sample_start: Some(DetectSampleStartRequest {
+                song_id,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_target_loudness_request(target_loudness: Option<f64>) -> Self {
+        Self {
+            set_target_loudness: Some(SetTargetLoudnessRequest {
+                target_loudness,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn waveform_range_request(sample_id: ID, length: i32, start: i64, end: i64) -> Self {
+        Self {
+            waveform_range: Some(WaveformRangeRequest {
+                sample_id,
+                length,
+                start,
+                end,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
     pub fn select_request(entity: Entity, id: ID) -> Self {
         Self {
             select: Some(SelectRequest {
@@ -32,6 +139,18 @@ impl Request {
         }
     }
 
+    pub fn remove_request(entity: Entity, id: ID) -> Self {
+        Self {
+            remove: Some(RemoveRequest {
+                entity: entity.into(),
+                id,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
     pub fn transport_request(method: TransportMethod) -> Request {
         Self {
             transport: Some(TransportRequest {
@@ -87,4 +206,114 @@ impl Request {
             ..Default::default()
         }
     }
+
+    pub fn midi_mapping_request(method: MidiMappingMethod, mapping: MidiMapping) -> Self {
+        Self {
+            midi_mapping: Some(MidiMappingRequest {
+                method: method.into(),
+                mapping: Some(mapping).into(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn run_macro_request(macro_name: &str) -> Self {
+        Self {
+            action: Some(ActionRequest::from_action(Action::ACTION_RUN_MACRO, 0).with_macro_name(macro_name)).into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn action_request(action: Action, value: u32) -> Self {
+        Self {
+            action: Some(ActionRequest::from_action(action, value)).into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn pair_request(pin: &str, device_name: &str) -> Self {
+        Self {
+            pair: Some(PairRequest {
+                pin: pin.to_string(),
+                device_name: device_name.to_string(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn authenticate_request(token: &str) -> Self {
+        Self {
+            authenticate: Some(AuthenticateRequest {
+                token: token.to_string(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn revoke_device_request(device_id: &str) -> Self {
+        Self {
+            revoke_device: Some(RevokeDeviceRequest {
+                device_id: device_id.to_string(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_device_role_request(device_id: &str, role: DeviceRole) -> Self {
+        Self {
+            set_device_role: Some(SetDeviceRoleRequest {
+                device_id: device_id.to_string(),
+                role: role.into(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn subscribe_request(subscriptions: &[(Topic, f64)]) -> Self {
+        Self {
+            subscribe: Some(SubscribeRequest {
+                subscriptions: subscriptions
+                    .iter()
+                    .map(|(topic, max_rate)| TopicSubscription {
+                        topic: (*topic).into(),
+                        max_rate: *max_rate,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = request_id.to_string();
+        self
+    }
+}
+
+impl ActionRequest {
+    pub fn from_action(action: Action, value: u32) -> Self {
+        Self {
+            action: action.into(),
+            value,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_macro_name(mut self, macro_name: &str) -> Self {
+        self.macro_name = macro_name.to_string();
+        self
+    }
 }
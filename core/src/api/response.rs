@@ -1,8 +1,10 @@
 #![allow(dead_code)]
 
+use super::error_code;
 use crate::bloop::{
-    AudioDevices, AudioStatus, MidiDevices, PlaybackState, Progress, Project, ProjectInfo, ProjectSyncResponse,
-    UploadAck, User, UserStatusResponse, WaveformResponse,
+    Acknowledgement, AudioDevices, AudioStatus, DeviceAuthentication, ErrorCode, MidiDevices, MidiMappings,
+    NetworkStatus, PairingStatus, PlaybackState, Progress, Project, ProjectInfo, ProjectPatch, ProjectSyncResponse,
+    SectionSuggestions, TempoAnalysis, UndoStatus, UploadAck, User, UserStatusResponse, WaveformResponse,
 };
 
 impl crate::bloop::Response {
@@ -16,6 +18,36 @@ impl crate::bloop::Response {
         self
     }
 
+    pub fn with_project_version(mut self, version: u64) -> Self {
+        self.project_version = version;
+        self
+    }
+
+    pub fn with_project_patch(mut self, patch: &ProjectPatch) -> Self {
+        self.project_patch = Some(patch.clone()).into();
+        self
+    }
+
+    pub fn with_undo_status(mut self, undo_status: &UndoStatus) -> Self {
+        self.undo_status = Some(undo_status.clone()).into();
+        self
+    }
+
+    pub fn with_exported_bundle(mut self, path: &str) -> Self {
+        self.exported_bundle = path.to_string();
+        self
+    }
+
+    pub fn with_tempo_analysis(mut self, tempo_analysis: &TempoAnalysis) -> Self {
+        self.tempo_analysis = Some(tempo_analysis.clone()).into();
+        self
+    }
+
+    pub fn with_section_suggestions(mut self, suggestions: &SectionSuggestions) -> Self {
+        self.section_suggestions = Some(suggestions.clone()).into();
+        self
+    }
+
     pub fn with_projects(mut self, projects: &[ProjectInfo]) -> Self {
         self.projects = projects.to_vec();
         self
@@ -89,8 +121,49 @@ impl crate::bloop::Response {
         self
     }
 
+    pub fn with_network_status(mut self, network_status: &NetworkStatus) -> Self {
+        self.network_status = Some(network_status.clone()).into();
+        self
+    }
+
     pub fn with_midi_devices(mut self, midi_devices: &MidiDevices) -> Self {
         self.midi_devices = Some(midi_devices.clone()).into();
         self
     }
+
+    pub fn with_midi_mappings(mut self, midi_mappings: &MidiMappings) -> Self {
+        self.midi_mappings = Some(midi_mappings.clone()).into();
+        self
+    }
+
+    pub fn with_pairing_status(mut self, pairing_status: &PairingStatus) -> Self {
+        self.pairing_status = Some(pairing_status.clone()).into();
+        self
+    }
+
+    pub fn with_device_authentication(mut self, device_authentication: &DeviceAuthentication) -> Self {
+        self.device_authentication = Some(device_authentication.clone()).into();
+        self
+    }
+
+    /// Acknowledge the request with the given ID, with the error code when it failed.
+    pub fn with_acknowledgement(mut self, request_id: &str, result: &anyhow::Result<()>) -> Self {
+        self.acknowledgement = Some(match result {
+            Ok(()) => Acknowledgement {
+                request_id: request_id.to_string(),
+                success: true,
+                error_code: ErrorCode::ERROR_CODE_NONE.into(),
+                ..Default::default()
+            },
+            Err(error) => Acknowledgement {
+                request_id: request_id.to_string(),
+                success: false,
+                error_code: error_code(error).into(),
+                error: error.to_string(),
+                ..Default::default()
+            },
+        })
+        .into();
+        self
+    }
 }
@@ -3,6 +3,7 @@ use super::{
     process::{query_native_channel_count, query_native_sample_rate, AudioProcessRunner, NoopProcess},
     sampler_converter::{SampleConversionResult, SampleConverter},
     sequencer::Sequencer,
+    transport::{ExternalTransportEvent, TransportPosition},
 };
 use crate::bloop::AudioEngineStatus;
 use crate::bloop::AudioPreferences;
@@ -11,7 +12,7 @@ use crate::{
     bloop::{AudioStatus, Response},
 };
 use crate::{
-    model::{PlaybackState, PlayingState, Progress, Project, ID},
+    model::{PlaybackState, PlayingState, Progress, Project, Song, ID},
     samples::SamplesCache,
 };
 use futures::StreamExt;
@@ -22,12 +23,13 @@ use rawdio::{
 };
 use std::{
     collections::{HashMap, HashSet},
-    time::Duration,
+    time::{Duration, Instant},
 };
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, watch};
 
 const SCHEDULER_TICK_RATE_HZ: f64 = 60.0;
 const PLAYBACK_START_LOOKAHEAD_SECONDS: f64 = 0.05;
+const EXTERNAL_START_MINIMUM_DELAY_SECONDS: f64 = 0.001;
 
 /// Tracks whether the audio backend is healthy, stopped, or failed to initialise.
 #[derive(Debug, Clone, PartialEq)]
@@ -191,7 +193,7 @@ fn remove_samples_from_engine(engine: &mut AudioEngine, project: &Project) {
     }
 }
 
-fn update_song_gains(engine: &mut AudioEngine, project: &Project) {
+fn update_song_gains(engine: &mut AudioEngine, project: &Project, master_volume_db: f64) {
     let current_time = engine.context.current_time();
 
     for song in &project.songs {
@@ -202,11 +204,26 @@ fn update_song_gains(engine: &mut AudioEngine, project: &Project) {
             continue;
         };
 
-        gain.gain()
-            .set_value_at_time(Level::from_db(song.volume_db()).as_linear(), current_time);
+        gain.gain().set_value_at_time(
+            Level::from_db(song_gain_db(song, project, master_volume_db)).as_linear(),
+            current_time,
+        );
     }
 }
 
+/// The gain of a song's sampler: its volume and loudness correction, then the master volume.
+fn song_gain_db(song: &Song, project: &Project, master_volume_db: f64) -> f64 {
+    song.volume_db() + song.loudness_correction_db(project.target_loudness) + master_volume_db
+}
+
+fn sample_gain_db(project: &Project, sample_id: ID, master_volume_db: f64) -> f64 {
+    project
+        .songs
+        .iter()
+        .find(|song| song.sample.as_ref().is_some_and(|sample| sample.id == sample_id))
+        .map_or(master_volume_db, |song| song_gain_db(song, project, master_volume_db))
+}
+
 /// Long-lived controller. The rawdio context and cpal stream live inside the
 /// `Option<AudioEngine>` so they can be dropped and rebuilt without affecting
 /// project state, samples cache, or the response channel.
@@ -226,6 +243,10 @@ pub struct AudioController {
     project: Project,
     preferences: AudioPreferences,
     current_sample_rate: u32,
+    transport_tx: watch::Sender<TransportPosition>,
+    external_sync: bool,
+    external_tempo: Option<f64>,
+    master_volume_db: f64,
 }
 
 impl AudioController {
@@ -249,9 +270,58 @@ impl AudioController {
             project: Project::empty(),
             preferences,
             current_sample_rate,
+            transport_tx: watch::Sender::new(TransportPosition::stopped()),
+            external_sync: false,
+            external_tempo: None,
+            master_volume_db: 0.0,
         }
     }
 
+    /// Applied on top of each song's own volume.
+    pub fn set_master_volume_db(&mut self, master_volume_db: f64) {
+        self.master_volume_db = master_volume_db;
+        if let Some(engine) = self.engine.as_mut() {
+            update_song_gains(engine, &self.project, master_volume_db);
+        }
+    }
+
+    /// Follow an external clock master. When disabled, the song's own tempo is used again.
+    pub fn set_external_sync(&mut self, enabled: bool) {
+        self.external_sync = enabled;
+
+        if !enabled {
+            self.external_tempo = None;
+            if let Some(engine) = self.engine.as_mut() {
+                engine.sequencer.set_tempo_override(None);
+            }
+        }
+    }
+
+    pub fn handle_external_transport(&mut self, event: ExternalTransportEvent) {
+        if !self.external_sync {
+            return;
+        }
+
+        match event {
+            ExternalTransportEvent::Start { next_pulse } => {
+                let delay = next_pulse.saturating_duration_since(Instant::now()).as_secs_f64();
+                self.play_after(delay.max(EXTERNAL_START_MINIMUM_DELAY_SECONDS));
+            }
+            ExternalTransportEvent::Stop => self.stop(),
+            ExternalTransportEvent::Tempo(bpm) => {
+                self.external_tempo = Some(bpm);
+                if let Some(engine) = self.engine.as_mut() {
+                    engine.sequencer.set_tempo_override(Some(bpm));
+                }
+            }
+        }
+    }
+
+    /// Subscribe to the transport position, which is updated on every scheduler tick.
+    pub fn subscribe_transport(&self) -> watch::Receiver<TransportPosition> {
+        self.transport_tx.subscribe()
+    }
+
     /// Returns the current state of the audio engine.
     #[allow(dead_code)]
     pub fn engine_state(&self) -> &AudioEngineState {
@@ -291,9 +361,10 @@ impl AudioController {
             return;
         }
 
-        let (engine, state) = build_audio_engine(&self.preferences, self.use_dummy_audio);
+        let (mut engine, state) = build_audio_engine(&self.preferences, self.use_dummy_audio);
         self.output_channel_count = engine.output_channel_count;
         self.set_current_sample_rate(engine.output_sample_rate);
+        engine.sequencer.set_tempo_override(self.external_tempo);
         self.engine = Some(engine);
         self.engine_state = state;
 
@@ -370,8 +441,14 @@ impl AudioController {
 
     /// Reset playback/progress state to stopped and broadcast both to clients.
     fn broadcast_stopped_playback(&mut self) {
-        self.playback_state = PlaybackState::default();
+        self.playback_state = PlaybackState {
+            external_sync: self.external_sync,
+            external_bpm: self.external_tempo.unwrap_or_default(),
+            master_volume_db: self.master_volume_db,
+            ..PlaybackState::default()
+        };
         self.progress = Progress::default();
+        self.transport_tx.send_replace(TransportPosition::stopped());
         let _ = self
             .response_tx
             .send(Response::default().with_playback_state(&self.playback_state));
@@ -403,6 +480,10 @@ impl AudioController {
     }
 
     pub fn play(&mut self) {
+        self.play_after(PLAYBACK_START_LOOKAHEAD_SECONDS);
+    }
+
+    fn play_after(&mut self, delay_seconds: f64) {
         let Some(engine) = self.engine.as_mut() else {
             return;
         };
@@ -426,10 +507,7 @@ impl AudioController {
             );
         }
 
-        let lookahead = engine
-            .context
-            .current_time()
-            .incremented_by_seconds(PLAYBACK_START_LOOKAHEAD_SECONDS);
+        let lookahead = engine.context.current_time().incremented_by_seconds(delay_seconds);
         engine
             .sequencer
             .play(lookahead, self.project.clone(), &mut engine.samplers);
@@ -497,7 +575,7 @@ impl AudioController {
                 &mut self.samples_being_converted,
             );
             remove_samples_from_engine(engine, project);
-            update_song_gains(engine, project);
+            update_song_gains(engine, project, self.master_volume_db);
         }
         self.project = project.clone();
     }
@@ -512,10 +590,24 @@ impl AudioController {
         engine.sequencer.set_current_time(current_time);
         engine.metronome.schedule(&current_time, &engine.sequencer);
 
-        let playback_state = engine.sequencer.get_playback_state();
+        let mut playback_state = engine.sequencer.get_playback_state();
         let progress = engine.sequencer.get_progress();
+        let transport = match engine.sequencer.beat_position() {
+            Some((beat, bpm)) => TransportPosition {
+                playing: true,
+                beat,
+                bpm,
+                captured_at: Instant::now(),
+            },
+            None => TransportPosition::stopped(),
+        };
         // NLL ends the engine borrow here; safe to access other self fields below.
 
+        self.transport_tx.send_replace(transport);
+        playback_state.external_sync = self.external_sync;
+        playback_state.external_bpm = self.external_tempo.unwrap_or_default();
+        playback_state.master_volume_db = self.master_volume_db;
+
         if self.playback_state != playback_state {
             self.playback_state = playback_state;
             let _ = self
@@ -564,12 +656,7 @@ impl AudioController {
         };
 
         let mut sampler = Sampler::new_with_event_capacity(engine.context.as_ref(), audio_data, 1024);
-        let song_volume = self
-            .project
-            .songs
-            .iter()
-            .find(|song| song.sample.as_ref().is_some_and(|sample| sample.id == sample_id))
-            .map_or(0.0, |song| song.volume_db());
+        let song_gain = sample_gain_db(&self.project, sample_id, self.master_volume_db);
         let gain_channel_count = if audio_channel_count == 1 && available_output_channels >= 2 {
             2
         } else {
@@ -577,7 +664,7 @@ impl AudioController {
         };
         let mut gain = Gain::new(engine.context.as_ref(), gain_channel_count);
         gain.gain()
-            .set_value_at_time(Level::from_db(song_volume).as_linear(), engine.context.current_time());
+            .set_value_at_time(Level::from_db(song_gain).as_linear(), engine.context.current_time());
 
         if audio_channel_count == 1 && available_output_channels >= 2 {
             let splitter = Mixer::mono_to_stereo_splitter(engine.context.as_ref());
@@ -603,6 +690,7 @@ mod tests {
     use super::*;
     use crate::preferences::default_audio_preferences;
     use crate::samples::SamplesCache;
+    use approx::assert_relative_eq;
     use tempfile::tempdir;
     use tokio::sync::broadcast;
 
@@ -618,6 +706,53 @@ mod tests {
         assert!(PLAYBACK_START_LOOKAHEAD_SECONDS > scheduler_interval);
     }
 
+    #[test]
+    fn converted_samples_start_with_their_loudness_correction() {
+        let mut project = Project::empty()
+            .with_songs(1, 1)
+            .with_target_loudness(Some(-14.0))
+            .unwrap();
+        let song = &mut project.songs[0];
+        song.volume = Some(-3.0);
+        song.sample = Some(crate::bloop::Sample {
+            id: 42,
+            loudness: Some(crate::bloop::Loudness {
+                integrated: -8.0,
+                true_peak: -0.5,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        })
+        .into();
+
+        assert_relative_eq!(sample_gain_db(&project, 42, -2.0), -3.0 - 6.0 - 2.0);
+        assert_relative_eq!(sample_gain_db(&project, 43, -2.0), -2.0);
+    }
+
+    #[tokio::test]
+    async fn external_tempo_is_reported_in_playback_state() {
+        let mut controller = test_controller();
+        controller.set_external_sync(true);
+        controller.handle_external_transport(ExternalTransportEvent::Tempo(100.0));
+
+        tokio::time::timeout(std::time::Duration::from_millis(200), controller.run())
+            .await
+            .expect("run() timed out while engine was running");
+
+        let playback_state = controller.get_playback_state();
+        assert!(playback_state.external_sync);
+        assert_eq!(playback_state.external_bpm, 100.0);
+    }
+
+    #[tokio::test]
+    async fn external_transport_is_ignored_without_external_sync() {
+        let mut controller = test_controller();
+        controller.handle_external_transport(ExternalTransportEvent::Tempo(100.0));
+
+        assert!(controller.external_tempo.is_none());
+    }
+
     #[tokio::test]
     async fn stop_audio_cleanly_drops_engine() {
         let mut controller = test_controller();
@@ -2,9 +2,7 @@ use std::time::Duration;
 
 use rawdio::{connect_nodes, Adsr, Context, GraphNode, Level, Mixer, Oscillator, Timestamp};
 
-use crate::model::Tempo;
-
-use super::sequencer::Sequencer;
+use super::{sequence::SequencePoint, sequence_generator::SequenceData, sequencer::Sequencer};
 
 pub struct Metronome {
     oscillator: Oscillator,
@@ -17,6 +15,7 @@ const BAR_FREQUENCY: f64 = 2_000.0;
 const BEAT_FREQUENCY: f64 = 1_000.0;
 const OUTPUT_COUNT: usize = 1;
 const LOOKAHEAD: f64 = 0.2;
+const BEATS_PER_BAR: i64 = 4;
 
 impl Metronome {
     pub fn new(context: &dyn Context) -> Self {
@@ -73,33 +72,94 @@ impl Metronome {
             return;
         }
 
-        let mut beat_index = last_beat_before(&start, &sequence_point.start_time, &sequence_point.data.tempo);
-        let mut beat_position = sequence_point
+        for (beat_position, is_bar) in beats_between(start, lookahead_time, &sequence_point) {
+            let frequency = if is_bar { BAR_FREQUENCY } else { BEAT_FREQUENCY };
+
+            self.oscillator.frequency().set_value_at_time(frequency, beat_position);
+            self.adsr.note_on_at_time(beat_position);
+        }
+    }
+}
+
+/// The song's beats from `start` up to `end`, and whether each one starts a bar.
+///
+/// Beats are counted from beat 0 of the song, at the sample's start offset, so a
+/// section that starts between beats still clicks on the song's grid.
+fn beats_between(
+    start: Timestamp,
+    end: Timestamp,
+    sequence_point: &SequencePoint<SequenceData>,
+) -> Vec<(Timestamp, bool)> {
+    let bpm = sequence_point.data.tempo.get_bpm();
+    let section_start_beat = sequence_point.data.start_beat;
+    let beats_into_section = (start - sequence_point.start_time).as_beats(bpm);
+
+    let mut beats = vec![];
+    let mut beat = (section_start_beat + beats_into_section).ceil();
+
+    loop {
+        let position = sequence_point
             .start_time
-            .incremented_by_beats(beat_index as f64, sequence_point.data.tempo.get_bpm());
-
-        while beat_position < lookahead_time {
-            if start <= beat_position && beat_position < lookahead_time {
-                let frequency = if beat_index % 4 == 0 {
-                    BAR_FREQUENCY
-                } else {
-                    BEAT_FREQUENCY
-                };
-
-                self.oscillator.frequency().set_value_at_time(frequency, beat_position);
-                self.adsr.note_on_at_time(beat_position);
-            }
-
-            beat_position = beat_position.incremented_by_beats(1.0, sequence_point.data.tempo.get_bpm());
-            beat_index += 1;
+            .incremented_by_beats(beat - section_start_beat, bpm);
+
+        if position >= end {
+            break;
+        }
+
+        if position >= start {
+            beats.push((position, (beat as i64).rem_euclid(BEATS_PER_BAR) == 0));
         }
+
+        beat += 1.0;
     }
+
+    beats
 }
 
-fn last_beat_before(time: &Timestamp, sequence_start: &Timestamp, tempo: &Tempo) -> i32 {
-    let beat_interval = 1.0 / tempo.beat_frequency();
-    let elapsed = *time - *sequence_start;
-    let elapsed = elapsed.as_seconds();
-    let beats_elapsed = elapsed / beat_interval;
-    beats_elapsed.floor() as i32
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Tempo;
+
+    fn sequence_point(start_time: f64, start_beat: f64) -> SequencePoint<SequenceData> {
+        SequencePoint {
+            start_time: Timestamp::from_seconds(start_time),
+            duration: Timestamp::from_seconds(60.0),
+            loop_enabled: false,
+            data: SequenceData {
+                start_beat,
+                metronome: true,
+                tempo: Tempo::new_with_bpm(120.0),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn seconds(beats: &[(Timestamp, bool)]) -> Vec<(f64, bool)> {
+        beats
+            .iter()
+            .map(|(position, is_bar)| (position.as_seconds(), *is_bar))
+            .collect()
+    }
+
+    #[test]
+    fn beats_follow_the_section() {
+        let point = sequence_point(1.0, 4.0);
+        let beats = beats_between(Timestamp::from_seconds(1.0), Timestamp::from_seconds(2.5), &point);
+        assert_eq!(seconds(&beats), vec![(1.0, true), (1.5, false), (2.0, false)]);
+    }
+
+    #[test]
+    fn sections_between_beats_click_on_the_song_grid() {
+        let point = sequence_point(1.0, 3.5);
+        let beats = beats_between(Timestamp::from_seconds(1.0), Timestamp::from_seconds(2.0), &point);
+        assert_eq!(seconds(&beats), vec![(1.25, true), (1.75, false)]);
+    }
+
+    #[test]
+    fn beats_before_the_start_are_skipped() {
+        let point = sequence_point(0.0, 0.0);
+        let beats = beats_between(Timestamp::from_seconds(0.6), Timestamp::from_seconds(1.6), &point);
+        assert_eq!(seconds(&beats), vec![(1.0, false), (1.5, false)]);
+    }
 }
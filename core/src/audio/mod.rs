@@ -7,5 +7,7 @@ mod sampler_converter;
 mod sequence;
 mod sequence_generator;
 mod sequencer;
+mod transport;
 
 pub use controller::AudioController;
+pub use transport::{ExternalTransportEvent, TransportPosition};
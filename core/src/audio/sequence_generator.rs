@@ -10,25 +10,40 @@ pub struct SequenceData {
     pub section_id: Option<ID>,
     pub sample_id: Option<ID>,
     pub position_in_sample: Timestamp,
+    /// The beat within the song at which this point starts.
+    pub start_beat: f64,
     pub metronome: bool,
     pub tempo: Tempo,
 }
 
+/// Generate the sequence for a song, starting at `from_section`.
+///
+/// When `tempo_override` is set (e.g. when following an external clock), section
+/// timing and the metronome use that tempo instead of the song's. Positions within
+/// the sample are still derived from the song's tempo.
 pub fn generate_sequence_for_song(
     start_time: Timestamp,
     project: &Project,
     song_id: ID,
     from_section: ID,
+    tempo_override: Option<f64>,
 ) -> Sequence<SequenceData> {
     let song = match project.song_with_id(song_id) {
         Some(song) => song,
         None => return Sequence::default(),
     };
 
+    let timeline_tempo = match tempo_override {
+        Some(bpm) => Tempo::new_with_bpm(bpm),
+        None => song.tempo.clone().unwrap_or(Tempo::new_with_bpm(120.0)),
+    };
+
     let points = song
         .sections
         .iter()
-        .filter_map(|section| sequence_point_for_section_from_reference(section, from_section, start_time, song))
+        .filter_map(|section| {
+            sequence_point_for_section_from_reference(section, from_section, start_time, song, &timeline_tempo)
+        })
         .collect();
 
     Sequence { points }
@@ -39,9 +54,10 @@ fn sequence_point_for_section_from_reference(
     reference_section_id: ID,
     reference_time: Timestamp,
     song: &Song,
+    timeline_tempo: &Tempo,
 ) -> Option<SequencePoint<SequenceData>> {
-    start_time_of_section(song, section.id, reference_section_id, reference_time)
-        .map(|start_time| sequence_point_for_section(section, song, start_time))
+    start_time_of_section(song, section.id, reference_section_id, reference_time, timeline_tempo)
+        .map(|start_time| sequence_point_for_section(section, song, start_time, timeline_tempo))
 }
 
 fn start_time_of_section(
@@ -49,9 +65,10 @@ fn start_time_of_section(
     section_id: ID,
     reference_section_id: ID,
     reference_time: Timestamp,
+    timeline_tempo: &Tempo,
 ) -> Option<Timestamp> {
     if let Some(beat_position) = beat_position_of_section(song, section_id, reference_section_id) {
-        return Some(reference_time.incremented_by_beats(beat_position, song.tempo.get_bpm()));
+        return Some(reference_time.incremented_by_beats(beat_position, timeline_tempo.get_bpm()));
     }
 
     None
@@ -70,10 +87,17 @@ fn beat_position_of_section(song: &Song, section_id: ID, reference_section_id: I
     None
 }
 
-fn sequence_point_for_section(section: &Section, song: &Song, start_time: Timestamp) -> SequencePoint<SequenceData> {
+fn sequence_point_for_section(
+    section: &Section,
+    song: &Song,
+    start_time: Timestamp,
+    timeline_tempo: &Tempo,
+) -> SequencePoint<SequenceData> {
     let section_length = song.section_length(section.id);
-    let section_duration = Timestamp::from_beats(section_length, song.tempo.get_bpm());
-    let start_position_in_sample = Timestamp::from_beats(section.start, song.tempo.get_bpm());
+    let section_duration = Timestamp::from_beats(section_length, timeline_tempo.get_bpm());
+    let start_offset = song.sample.as_ref().map_or(0.0, |sample| sample.start_offset_seconds());
+    let start_position_in_sample =
+        Timestamp::from_beats(section.start, song.tempo.get_bpm()).incremented_by_seconds(start_offset);
 
     SequencePoint {
         start_time,
@@ -84,8 +108,9 @@ fn sequence_point_for_section(section: &Section, song: &Song, start_time: Timest
             section_id: Some(section.id),
             sample_id: song.sample.as_ref().map(|sample| sample.id),
             position_in_sample: start_position_in_sample,
+            start_beat: section.start,
             metronome: section.metronome,
-            tempo: song.tempo.clone().unwrap_or(Tempo::new_with_bpm(120.0)),
+            tempo: timeline_tempo.clone(),
         },
     }
 }
@@ -94,6 +119,7 @@ fn sequence_point_for_section(section: &Section, song: &Song, start_time: Timest
 mod test {
 
     use crate::model::{Sample, Tempo};
+    use approx::assert_relative_eq;
 
     use super::*;
 
@@ -133,7 +159,7 @@ mod test {
         let start_time = Timestamp::from_seconds(8.0);
 
         let song_id = song.id;
-        let sequence = generate_sequence_for_song(start_time, &project, song_id, song.sections[0].id);
+        let sequence = generate_sequence_for_song(start_time, &project, song_id, song.sections[0].id, None);
 
         assert_eq!(sequence.points.len(), 3);
 
@@ -147,6 +173,7 @@ mod test {
                     section_id: Some(song.sections[0].id),
                     sample_id: Some(sample.id),
                     position_in_sample: Timestamp::from_beats(1.0, tempo),
+                    start_beat: 1.0,
                     metronome: false,
                     tempo: Tempo::new_with_bpm(tempo),
                 },
@@ -160,6 +187,7 @@ mod test {
                     section_id: Some(song.sections[1].id),
                     sample_id: Some(sample.id),
                     position_in_sample: Timestamp::from_beats(5.0, tempo),
+                    start_beat: 5.0,
                     metronome: false,
                     tempo: Tempo::new_with_bpm(tempo),
                 },
@@ -173,6 +201,7 @@ mod test {
                     section_id: Some(song.sections[2].id),
                     sample_id: Some(sample.id),
                     position_in_sample: Timestamp::from_beats(10.0, tempo),
+                    start_beat: 10.0,
                     metronome: false,
                     tempo: Tempo::new_with_bpm(tempo),
                 },
@@ -218,7 +247,7 @@ mod test {
         let start_time = Timestamp::from_seconds(4.0);
 
         let song = &project.songs[0];
-        let sequence = generate_sequence_for_song(start_time, &project, song.id, song.sections[0].id);
+        let sequence = generate_sequence_for_song(start_time, &project, song.id, song.sections[0].id, None);
 
         assert_eq!(sequence.points.len(), 3);
 
@@ -232,6 +261,7 @@ mod test {
                     section_id: Some(song.sections[0].id),
                     sample_id: Some(sample.id),
                     position_in_sample: Timestamp::from_beats(7.0, tempo),
+                    start_beat: 7.0,
                     metronome: false,
                     tempo: Tempo::new_with_bpm(tempo),
                 },
@@ -245,6 +275,7 @@ mod test {
                     section_id: Some(song.sections[1].id),
                     sample_id: Some(sample.id),
                     position_in_sample: Timestamp::from_beats(9.0, tempo),
+                    start_beat: 9.0,
                     metronome: false,
                     tempo: Tempo::new_with_bpm(tempo),
                 },
@@ -258,6 +289,7 @@ mod test {
                     section_id: Some(song.sections[2].id),
                     sample_id: Some(sample.id),
                     position_in_sample: Timestamp::from_beats(15.0, tempo),
+                    start_beat: 15.0,
                     metronome: false,
                     tempo: Tempo::new_with_bpm(tempo),
                 },
@@ -269,4 +301,58 @@ mod test {
 
         assert_eq!(sequence.points, expected_values);
     }
+
+    #[test]
+    fn tempo_override_sets_timeline_but_not_sample_position() {
+        let mut project = Project::empty().with_songs(1, 2);
+
+        let tempo = 120.0;
+        let sample = Sample::empty().with_beat_length(Tempo::new_with_bpm(tempo), 8.0, 48_000);
+
+        {
+            let song = &mut project.songs[0];
+            song.tempo = Some(Tempo::new_with_bpm(tempo)).into();
+            song.sample = Some(sample).into();
+            song.sections[0].start = 0.0;
+            song.sections[1].start = 4.0;
+        }
+
+        let song = &project.songs[0];
+        let start_time = Timestamp::from_seconds(1.0);
+        let sequence = generate_sequence_for_song(start_time, &project, song.id, song.sections[0].id, Some(60.0));
+
+        let second = &sequence.points[1];
+        assert_eq!(second.start_time, Timestamp::from_seconds(5.0));
+        assert_eq!(second.duration, Timestamp::from_seconds(4.0));
+        assert_eq!(second.data.position_in_sample, Timestamp::from_seconds(2.0));
+        assert_eq!(second.data.start_beat, 4.0);
+        assert_eq!(second.data.tempo, Tempo::new_with_bpm(60.0));
+    }
+
+    #[test]
+    fn positions_in_sample_start_from_the_offset() {
+        let mut project = Project::empty().with_songs(1, 2);
+
+        let tempo = 120.0;
+        let mut sample = Sample::empty().with_beat_length(Tempo::new_with_bpm(tempo), 8.0, 48_000);
+        sample.start_offset = 14_400;
+
+        {
+            let song = &mut project.songs[0];
+            song.tempo = Some(Tempo::new_with_bpm(tempo)).into();
+            song.sample = Some(sample).into();
+            song.sections[0].start = 0.0;
+            song.sections[1].start = 4.0;
+        }
+
+        let song = &project.songs[0];
+        let sequence = generate_sequence_for_song(Timestamp::zero(), &project, song.id, song.sections[0].id, None);
+
+        let second = &sequence.points[1];
+        assert_relative_eq!(sequence.points[0].data.position_in_sample.as_seconds(), 0.3);
+        assert_relative_eq!(second.data.position_in_sample.as_seconds(), 2.3);
+        assert_eq!(second.start_time, Timestamp::from_seconds(2.0));
+        // The sample has 7.4 beats after the offset
+        assert_relative_eq!(second.duration.as_beats(tempo), 3.4);
+    }
 }
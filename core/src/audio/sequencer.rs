@@ -15,6 +15,7 @@ pub struct Sequencer {
     queued_song: Option<ID>,
     queued_section: Option<ID>,
     current_time: Timestamp,
+    tempo_override: Option<f64>,
 }
 
 impl Sequencer {
@@ -32,6 +33,11 @@ impl Sequencer {
         }
     }
 
+    /// Use `bpm` for the timeline of subsequently generated sequences, instead of the song's tempo.
+    pub fn set_tempo_override(&mut self, bpm: Option<f64>) {
+        self.tempo_override = bpm;
+    }
+
     pub fn sequence_point_at_time(&self, time: Timestamp) -> Option<SequencePoint<SequenceData>> {
         self.sequence.point_at_time(time)
     }
@@ -63,6 +69,21 @@ impl Sequencer {
         }
     }
 
+    /// Returns the beat position within the playing song's sample, and its tempo.
+    pub fn beat_position(&self) -> Option<(f64, f64)> {
+        let point = self.sequence.point_at_time(self.current_time)?;
+        let bpm = point.data.tempo.get_bpm();
+        let duration = point.duration.as_seconds();
+        if duration <= 0.0 {
+            return None;
+        }
+
+        let seconds_into_section = (self.current_time.as_seconds() - point.start_time.as_seconds()) % duration;
+        let beat = point.data.start_beat + Timestamp::from_seconds(seconds_into_section).as_beats(bpm);
+
+        Some((beat, bpm))
+    }
+
     pub fn get_progress(&self) -> Progress {
         let current_point = self.sequence.point_at_time(self.current_time);
 
@@ -91,7 +112,13 @@ impl Sequencer {
             return;
         }
 
-        let sequence = generate_sequence_for_song(start_time, &self.project, selected_song_id, selected_section_id);
+        let sequence = generate_sequence_for_song(
+            start_time,
+            &self.project,
+            selected_song_id,
+            selected_section_id,
+            self.tempo_override,
+        );
 
         self.set_sequence(sequence, samplers);
     }
@@ -109,7 +136,8 @@ impl Sequencer {
     pub fn queue(&mut self, after_time: Timestamp, song_id: ID, section_id: ID, samplers: &mut HashMap<ID, Sampler>) {
         let transition_time = self.sequence.next_transition(after_time);
         let existing_sequence = self.sequence.truncate_to_time(transition_time);
-        let new_sequence = generate_sequence_for_song(transition_time, &self.project, song_id, section_id);
+        let new_sequence =
+            generate_sequence_for_song(transition_time, &self.project, song_id, section_id, self.tempo_override);
         let sequence = existing_sequence.append(new_sequence);
 
         self.set_sequence(sequence, samplers);
@@ -138,7 +166,8 @@ impl Sequencer {
             (Some(section), Some(song), Some(sample)) => {
                 let seconds_into_section =
                     (time.as_seconds() - point.start_time.as_seconds()) % point.duration.as_seconds();
-                let beats_into_section = Timestamp::from_seconds(seconds_into_section).as_beats(song.tempo.get_bpm());
+                let beats_into_section =
+                    Timestamp::from_seconds(seconds_into_section).as_beats(point.data.tempo.get_bpm());
 
                 let position_in_sample = seconds_into_section + point.data.position_in_sample.as_seconds();
                 let sample_duration =
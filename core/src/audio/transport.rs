@@ -0,0 +1,73 @@
+use std::time::Instant;
+
+/// A snapshot of the sequencer's musical position, published on every scheduler
+/// tick so that other components (e.g. MIDI clock output) can follow the timeline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransportPosition {
+    pub playing: bool,
+    /// Position in beats from the start of the playing song's sample.
+    pub beat: f64,
+    pub bpm: f64,
+    /// The wall-clock time at which `beat` was sampled.
+    pub captured_at: Instant,
+}
+
+impl TransportPosition {
+    pub fn stopped() -> Self {
+        Self {
+            playing: false,
+            beat: 0.0,
+            bpm: 120.0,
+            captured_at: Instant::now(),
+        }
+    }
+
+    /// Extrapolate the beat position to `time`, assuming the tempo is constant.
+    pub fn beat_at(&self, time: Instant) -> f64 {
+        let elapsed = if time >= self.captured_at {
+            time.duration_since(self.captured_at).as_secs_f64()
+        } else {
+            -self.captured_at.duration_since(time).as_secs_f64()
+        };
+
+        self.beat + elapsed * self.bpm / 60.0
+    }
+}
+
+/// Transport changes received from an external clock master.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExternalTransportEvent {
+    /// Playback should start in time with the next clock pulse, expected at `next_pulse`.
+    Start {
+        next_pulse: Instant,
+    },
+    Stop,
+    /// The smoothed tempo of the external clock has changed.
+    Tempo(f64),
+}
+
+impl Default for TransportPosition {
+    fn default() -> Self {
+        Self::stopped()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn beat_at_extrapolates_forwards_and_backwards() {
+        let now = Instant::now();
+        let position = TransportPosition {
+            playing: true,
+            beat: 4.0,
+            bpm: 120.0,
+            captured_at: now,
+        };
+
+        assert!((position.beat_at(now + Duration::from_millis(500)) - 5.0).abs() < 1e-9);
+        assert!((position.beat_at(now - Duration::from_millis(500)) - 3.0).abs() < 1e-9);
+    }
+}
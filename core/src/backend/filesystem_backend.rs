@@ -1,7 +1,9 @@
 use std::path::{Path, PathBuf};
 
 use crate::{
+    api::api_error,
     backend::{Backend, DbProject},
+    bloop::ErrorCode,
     model::random_project_id,
 };
 use anyhow::{Context, Result};
@@ -92,7 +94,10 @@ impl Backend for FilesystemBackend {
 
         // Check if the project directory exists
         if !project_dir.exists() {
-            return Err(anyhow::anyhow!("Project {project_id} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project {project_id} does not exist"),
+            ));
         }
 
         // Read the project metadata file
@@ -129,7 +134,10 @@ impl Backend for FilesystemBackend {
 
         // Check if the project directory exists
         if !project_dir.exists() {
-            return Err(anyhow::anyhow!("Project {project_id} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project {project_id} does not exist"),
+            ));
         }
 
         // Read the current project metadata
@@ -150,7 +158,10 @@ impl Backend for FilesystemBackend {
 
         // Check if the project directory exists
         if !project_dir.exists() {
-            return Err(anyhow::anyhow!("Project {project_id} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project {project_id} does not exist"),
+            ));
         }
 
         // Read the current project metadata
@@ -175,7 +186,10 @@ impl Backend for FilesystemBackend {
         // Get the project directory and check if it exists
         let project_dir = self.directory_for_project(project_id);
         if !project_dir.exists() {
-            return Err(anyhow::anyhow!("Project {project_id} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project {project_id} does not exist"),
+            ));
         }
 
         // Read the current project metadata
@@ -208,7 +222,10 @@ impl Backend for FilesystemBackend {
         // Get the project directory and check if it exists
         let project_dir = self.directory_for_project(project_id);
         if !project_dir.exists() {
-            return Err(anyhow::anyhow!("Project {project_id} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project {project_id} does not exist"),
+            ));
         }
 
         // Read the current project metadata
@@ -217,7 +234,10 @@ impl Backend for FilesystemBackend {
         // Remove the sample file from the filesystem
         let sample_file_path = project_dir.join("samples").join(format!("{sample_name}.wav"));
         if !sample_file_path.exists() {
-            return Err(anyhow::anyhow!("Sample {sample_name} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Sample {sample_name} does not exist"),
+            ));
         }
 
         tokio::fs::remove_file(&sample_file_path)
@@ -252,7 +272,10 @@ impl Backend for FilesystemBackend {
 
         // Check if project directory exists
         if !project_dir.exists() {
-            return Err(anyhow::anyhow!("Project {project_id} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project {project_id} does not exist"),
+            ));
         }
 
         let samples_dir = project_dir.join("samples");
@@ -290,7 +313,10 @@ impl Backend for FilesystemBackend {
 
         // Check if project directory exists
         if !project_dir.exists() {
-            return Err(anyhow::anyhow!("Project {project_id} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project {project_id} does not exist"),
+            ));
         }
 
         // Validate sample_name to prevent path traversal
@@ -306,7 +332,10 @@ impl Backend for FilesystemBackend {
 
         // Check if sample file exists
         if !sample_file_path.exists() {
-            return Err(anyhow::anyhow!("Sample {sample_name} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Sample {sample_name} does not exist"),
+            ));
         }
 
         // Read and return sample contents
@@ -320,14 +349,20 @@ impl Backend for FilesystemBackend {
 
         // Check if project directory exists
         if !project_dir.exists() {
-            return Err(anyhow::anyhow!("Project {project_id} does not exist"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project {project_id} does not exist"),
+            ));
         }
 
         let project_file_path = project_dir.join("project.bin");
 
         // Check if project file exists
         if !project_file_path.exists() {
-            return Err(anyhow::anyhow!("Project file does not exist for project {project_id}"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Project file does not exist for project {project_id}"),
+            ));
         }
 
         // Read and return project file contents
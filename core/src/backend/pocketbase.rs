@@ -1,7 +1,10 @@
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::backend::Auth;
+use crate::{
+    api::{api_error, error_code_for_status},
+    backend::Auth,
+};
 
 use super::{Backend, DbProject};
 use anyhow::{Context, Result};
@@ -324,6 +327,7 @@ impl Default for ErrorResponse {
 pub(crate) async fn handle_error_response(response: Response, request_name: &str) -> anyhow::Error {
     assert!(!response.status().is_success());
 
+    let code = error_code_for_status(response.status());
     let response = response.json::<ErrorResponse>().await.unwrap_or_default();
 
     let error_message = format!(
@@ -332,5 +336,5 @@ pub(crate) async fn handle_error_response(response: Response, request_name: &str
     );
 
     warn!("{}", error_message);
-    anyhow::anyhow!("{}", error_message)
+    api_error(code, error_message)
 }
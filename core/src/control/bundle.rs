@@ -0,0 +1,179 @@
+use super::migrations::{read_project, write_project, PROJECT_SCHEMA_VERSION};
+use crate::model::{Project, ID};
+use anyhow::{anyhow, Context};
+use async_zip::{
+    tokio::{read::fs::ZipFileReader, write::ZipFileWriter},
+    Compression, ZipEntryBuilder,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, str::FromStr};
+use tokio::io::AsyncWriteExt;
+
+const PROJECT_ENTRY: &str = "project.bin";
+const METADATA_ENTRY: &str = "metadata.json";
+const SAMPLES_DIRECTORY: &str = "samples/";
+
+/// A project with everything needed to open it on another machine.
+///
+/// Bundles are zip files holding the project file, its metadata and the samples
+/// the project refers to.
+pub struct Bundle {
+    pub name: String,
+    pub project: Project,
+    /// Sample files, by sample ID.
+    pub samples: Vec<(ID, Vec<u8>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    name: String,
+    schema_version: u32,
+}
+
+pub async fn write_bundle(bundle: &Bundle, path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .context(format!("Creating directory: {}", parent.display()))?;
+    }
+
+    let file = tokio::fs::File::create(path)
+        .await
+        .context(format!("Creating bundle: {}", path.display()))?;
+    let mut writer = ZipFileWriter::with_tokio(file);
+
+    let metadata = Metadata {
+        name: bundle.name.clone(),
+        schema_version: PROJECT_SCHEMA_VERSION,
+    };
+    let entries = [
+        (PROJECT_ENTRY.to_string(), write_project(&bundle.project)?),
+        (METADATA_ENTRY.to_string(), serde_json::to_vec_pretty(&metadata)?),
+    ];
+
+    for (name, data) in entries.iter() {
+        let entry = ZipEntryBuilder::new(name.clone().into(), Compression::Deflate);
+        writer.write_entry_whole(entry, data).await?;
+    }
+
+    for (sample_id, data) in bundle.samples.iter() {
+        let entry = ZipEntryBuilder::new(
+            format!("{SAMPLES_DIRECTORY}{sample_id}.wav").into(),
+            Compression::Deflate,
+        );
+        writer.write_entry_whole(entry, data).await?;
+    }
+
+    // Closing the writer doesn't flush the file
+    writer.close().await?.into_inner().shutdown().await?;
+    Ok(())
+}
+
+pub async fn read_bundle(path: &Path) -> anyhow::Result<Bundle> {
+    let reader = ZipFileReader::new(path)
+        .await
+        .context(format!("Opening bundle: {}", path.display()))?;
+
+    let mut project = None;
+    let mut metadata = None;
+    let mut samples = vec![];
+
+    for (index, entry) in reader.file().entries().iter().enumerate() {
+        let name = entry.filename().as_str()?.to_string();
+
+        let mut data = vec![];
+        reader
+            .reader_with_entry(index)
+            .await?
+            .read_to_end_checked(&mut data)
+            .await
+            .context(format!("Reading {name} from bundle"))?;
+
+        if name == PROJECT_ENTRY {
+            project = Some(read_project(&data)?);
+        } else if name == METADATA_ENTRY {
+            metadata = Some(serde_json::from_slice::<Metadata>(&data).context("Parsing bundle metadata")?);
+        } else if let Some(sample_name) = name.strip_prefix(SAMPLES_DIRECTORY) {
+            let sample_id = sample_name
+                .strip_suffix(".wav")
+                .and_then(|id| ID::from_str(id).ok())
+                .ok_or_else(|| anyhow!("Unexpected sample in bundle: {name}"))?;
+            samples.push((sample_id, data));
+        }
+    }
+
+    let project = project.ok_or_else(|| anyhow!("Bundle has no project file"))?;
+    let metadata = metadata.ok_or_else(|| anyhow!("Bundle has no metadata"))?;
+
+    let missing_sample = project
+        .songs
+        .iter()
+        .filter_map(|song| song.sample.as_ref())
+        .find(|sample| !samples.iter().any(|(id, _)| *id == sample.id));
+    if let Some(sample) = missing_sample {
+        anyhow::bail!("Bundle is missing sample: {}", sample.id);
+    }
+
+    Ok(Bundle {
+        name: metadata.name,
+        project,
+        samples,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloop::Sample;
+
+    fn bundle() -> Bundle {
+        let mut project = Project::empty().with_songs(2, 2);
+        project.songs[1].sample = Some(Sample {
+            id: 42,
+            ..Default::default()
+        })
+        .into();
+
+        Bundle {
+            name: "Gig".to_string(),
+            project,
+            samples: vec![(42, vec![1, 2, 3, 4])],
+        }
+    }
+
+    #[tokio::test]
+    async fn bundles_round_trip() {
+        let directory = tempfile::TempDir::new().unwrap();
+        let path = directory.path().join("exports").join("gig.bloop");
+        let original = bundle();
+
+        write_bundle(&original, &path).await.unwrap();
+        let bundle = read_bundle(&path).await.unwrap();
+
+        assert_eq!(bundle.name, original.name);
+        assert_eq!(bundle.project, original.project);
+        assert_eq!(bundle.samples, original.samples);
+    }
+
+    #[tokio::test]
+    async fn bundles_must_contain_every_sample() {
+        let directory = tempfile::TempDir::new().unwrap();
+        let path = directory.path().join("gig.bloop");
+        let mut original = bundle();
+        original.samples.clear();
+
+        write_bundle(&original, &path).await.unwrap();
+        let error = read_bundle(&path).await.err().unwrap();
+        assert!(error.to_string().contains("missing sample"));
+    }
+
+    #[tokio::test]
+    async fn other_files_are_not_bundles() {
+        let directory = tempfile::TempDir::new().unwrap();
+        let path = directory.path().join("gig.bloop");
+        tokio::fs::write(&path, b"not a zip").await.unwrap();
+
+        assert!(read_bundle(&path).await.is_err());
+    }
+}
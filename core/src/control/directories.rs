@@ -1,5 +1,6 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
+use crate::{api::api_error, bloop::ErrorCode};
 use log::info;
 
 pub struct Directories {
@@ -7,6 +8,7 @@ pub struct Directories {
     pub samples: PathBuf,
     pub root: PathBuf,
     pub backend: PathBuf,
+    pub exports: PathBuf,
 }
 
 impl Directories {
@@ -22,11 +24,54 @@ impl Directories {
         let mut backend = root.clone();
         backend.push("backend");
 
+        let mut exports = root.clone();
+        exports.push("exports");
+
         Self {
             projects,
             samples,
             root,
             backend,
+            exports,
+        }
+    }
+
+    /// A path for a bundle inside the exports directory. Clients choose only the
+    /// name, so absolute paths and `..` are rejected rather than letting them read
+    /// or overwrite other files.
+    pub fn export_path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let relative = Path::new(name);
+        let is_inside = relative.components().next().is_some()
+            && relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+
+        if !is_inside {
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                format!("Bundle paths must be inside the exports directory: {name}"),
+            ));
         }
+
+        Ok(self.exports.join(relative))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_paths_stay_inside_the_exports_directory() {
+        let directories = Directories::new(PathBuf::from("/home/bloop"));
+
+        assert_eq!(
+            directories.export_path("gigs/friday.bloop").unwrap(),
+            PathBuf::from("/home/bloop/exports/gigs/friday.bloop")
+        );
+        assert!(directories.export_path("/etc/passwd").is_err());
+        assert!(directories.export_path("../projects/project.bloop").is_err());
+        assert!(directories.export_path("gigs/../../samples").is_err());
+        assert!(directories.export_path("").is_err());
     }
 }
@@ -0,0 +1,109 @@
+use crate::{
+    analysis::{measure_loudness_from_file, LoudnessMeasurement},
+    model::{Loudness, Project, ID},
+    samples::SamplesCache,
+};
+use log::{info, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    thread::spawn,
+};
+use tokio::sync::mpsc;
+
+pub struct LoudnessAnalysisResult {
+    pub sample_id: ID,
+    pub measurement: anyhow::Result<Option<LoudnessMeasurement>>,
+}
+
+/// Measures the loudness of samples on their own threads, and remembers what it
+/// measured so samples brought back by undo, or silent samples, aren't measured again.
+pub struct LoudnessAnalyser {
+    result_tx: mpsc::Sender<LoudnessAnalysisResult>,
+    samples_being_analysed: HashSet<ID>,
+    measured: HashMap<ID, Option<Loudness>>,
+}
+
+impl LoudnessAnalyser {
+    pub fn new(result_tx: mpsc::Sender<LoudnessAnalysisResult>) -> Self {
+        Self {
+            result_tx,
+            samples_being_analysed: HashSet::new(),
+            measured: HashMap::new(),
+        }
+    }
+
+    /// Fill in the loudness of samples that have already been measured, and start
+    /// measuring the cached samples that haven't.
+    pub fn with_loudness(&mut self, mut project: Project, samples_cache: &SamplesCache) -> Project {
+        for song in project.songs.iter_mut() {
+            let Some(sample) = song.sample.as_mut() else {
+                continue;
+            };
+
+            if sample.loudness.is_some() {
+                continue;
+            }
+
+            match self.measured.get(&sample.id) {
+                Some(loudness) => sample.loudness = loudness.clone().into(),
+                None => self.analyse(sample.id, samples_cache),
+            }
+        }
+
+        project
+    }
+
+    fn analyse(&mut self, sample_id: ID, samples_cache: &SamplesCache) {
+        if self.samples_being_analysed.contains(&sample_id) {
+            return;
+        }
+
+        let Some(sample) = samples_cache.get_sample(sample_id).filter(|sample| sample.is_cached()) else {
+            return;
+        };
+
+        self.samples_being_analysed.insert(sample_id);
+
+        let result_tx = self.result_tx.clone();
+        let sample_path = sample.get_path().to_path_buf();
+
+        info!("Measuring loudness of sample: {sample_id}");
+
+        spawn(move || {
+            let measurement = measure_loudness_from_file(&sample_path);
+            let _ = result_tx.blocking_send(LoudnessAnalysisResult { sample_id, measurement });
+        });
+    }
+
+    /// Remember the result of a measurement, returning whether there's a loudness to apply.
+    pub fn complete(&mut self, result: LoudnessAnalysisResult) -> bool {
+        let sample_id = result.sample_id;
+        self.samples_being_analysed.remove(&sample_id);
+
+        let loudness = match result.measurement {
+            Ok(Some(measurement)) => {
+                info!(
+                    "Measured loudness of sample {sample_id}: {:.1} LUFS, {:.1} dBTP",
+                    measurement.integrated, measurement.true_peak
+                );
+                Some(Loudness {
+                    integrated: measurement.integrated,
+                    true_peak: measurement.true_peak,
+                    ..Default::default()
+                })
+            }
+            Ok(None) => {
+                info!("Sample is too short or quiet to measure its loudness: {sample_id}");
+                None
+            }
+            Err(error) => {
+                warn!("Error measuring loudness of sample {sample_id}: {error}");
+                None
+            }
+        };
+
+        let measured = loudness.is_some();
+        self.measured.insert(sample_id, loudness);
+        measured
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    audio::TransportPosition,
+    bloop::{ActionRequest, Macro, MacroStep, MacroWait},
+    model::Action,
+};
+use anyhow::anyhow;
+use log::info;
+use std::time::{Duration, Instant};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time,
+};
+
+/// Bars are counted in four beats, matching the metronome.
+const BEATS_PER_BAR: f64 = 4.0;
+
+/// How close to a boundary counts as being on it, so that consecutive musical waits
+/// don't complete together.
+const BOUNDARY_TOLERANCE: f64 = 0.01;
+
+/// Runs macros one at a time, sending each step's action back to the main controller
+/// once its wait has completed.
+pub struct MacroRunner {
+    action_tx: mpsc::Sender<ActionRequest>,
+    transport_rx: watch::Receiver<TransportPosition>,
+    running: Option<JoinHandle<()>>,
+}
+
+impl MacroRunner {
+    pub fn new(action_tx: mpsc::Sender<ActionRequest>, transport_rx: watch::Receiver<TransportPosition>) -> Self {
+        Self {
+            action_tx,
+            transport_rx,
+            running: None,
+        }
+    }
+
+    /// Start running `macro_`, cancelling any macro that is already running.
+    pub fn run(&mut self, macro_: &Macro) -> anyhow::Result<()> {
+        if let Some(step) = macro_.steps.iter().find(|step| {
+            matches!(
+                step.action.enum_value_or_default(),
+                Action::ACTION_RUN_MACRO | Action::ACTION_CANCEL_MACRO
+            )
+        }) {
+            return Err(anyhow!(
+                "Macro '{}' can't contain {:?}",
+                macro_.name,
+                step.action.enum_value_or_default()
+            ));
+        }
+
+        self.cancel();
+
+        info!("Running macro '{}'", macro_.name);
+        self.running = Some(tokio::spawn(run_steps(
+            macro_.steps.clone(),
+            self.action_tx.clone(),
+            self.transport_rx.clone(),
+        )));
+        Ok(())
+    }
+
+    /// Stop the running macro before any of its remaining steps are performed.
+    pub fn cancel(&mut self) {
+        if let Some(running) = self.running.take() {
+            if !running.is_finished() {
+                info!("Cancelling macro");
+            }
+
+            running.abort();
+        }
+    }
+}
+
+impl Drop for MacroRunner {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+async fn run_steps(
+    steps: Vec<MacroStep>,
+    action_tx: mpsc::Sender<ActionRequest>,
+    mut transport_rx: watch::Receiver<TransportPosition>,
+) {
+    for step in steps {
+        match step.wait.enum_value_or_default() {
+            MacroWait::MACRO_WAIT_NONE => (),
+            MacroWait::MACRO_WAIT_DELAY => time::sleep(Duration::from_millis(step.delay_ms as u64)).await,
+            MacroWait::MACRO_WAIT_NEXT_BEAT => wait_for_next_boundary(&mut transport_rx, 1.0).await,
+            MacroWait::MACRO_WAIT_NEXT_BAR => wait_for_next_boundary(&mut transport_rx, BEATS_PER_BAR).await,
+        }
+
+        let request = ActionRequest::from_action(step.action.enum_value_or_default(), step.value);
+        if action_tx.send(request).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// The first multiple of `beats` after `beat`.
+fn next_boundary(beat: f64, beats: f64) -> f64 {
+    (((beat + BOUNDARY_TOLERANCE) / beats).floor() + 1.0) * beats
+}
+
+/// Wait until the transport reaches the next multiple of `beats`. Returns straight away
+/// if playback is stopped, or as soon as it stops.
+async fn wait_for_next_boundary(transport_rx: &mut watch::Receiver<TransportPosition>, beats: f64) {
+    let mut target = None;
+
+    loop {
+        let position = *transport_rx.borrow_and_update();
+        if !position.playing || position.bpm <= 0.0 {
+            return;
+        }
+
+        let beat = position.beat_at(Instant::now());
+
+        // Count from the new position if playback has jumped backwards, e.g. into a new song
+        let boundary = match target {
+            Some(boundary) if beat >= boundary - beats => boundary,
+            _ => *target.insert(next_boundary(beat, beats)),
+        };
+
+        let remaining_beats = boundary - beat;
+        if remaining_beats <= 0.0 {
+            return;
+        }
+
+        let delay = Duration::from_secs_f64(remaining_beats * 60.0 / position.bpm);
+        tokio::select! {
+            _ = time::sleep(delay) => return,
+            changed = transport_rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(wait: MacroWait, delay_ms: u32, action: Action) -> MacroStep {
+        MacroStep {
+            wait: wait.into(),
+            delay_ms,
+            action: action.into(),
+            ..Default::default()
+        }
+    }
+
+    fn macro_with_steps(steps: Vec<MacroStep>) -> Macro {
+        Macro {
+            name: "Test".to_string(),
+            steps,
+            ..Default::default()
+        }
+    }
+
+    fn runner() -> (
+        MacroRunner,
+        mpsc::Receiver<ActionRequest>,
+        watch::Sender<TransportPosition>,
+    ) {
+        let (action_tx, action_rx) = mpsc::channel(16);
+        let (transport_tx, transport_rx) = watch::channel(TransportPosition::stopped());
+        (MacroRunner::new(action_tx, transport_rx), action_rx, transport_tx)
+    }
+
+    async fn next_action(action_rx: &mut mpsc::Receiver<ActionRequest>) -> Action {
+        time::timeout(Duration::from_secs(1), action_rx.recv())
+            .await
+            .expect("Timed out waiting for an action")
+            .unwrap()
+            .action
+            .enum_value_or_default()
+    }
+
+    #[test]
+    fn next_boundary_rounds_up_to_the_next_bar() {
+        assert_eq!(next_boundary(0.0, 4.0), 4.0);
+        assert_eq!(next_boundary(5.5, 4.0), 8.0);
+        assert_eq!(next_boundary(7.995, 4.0), 12.0);
+        assert_eq!(next_boundary(2.5, 1.0), 3.0);
+    }
+
+    #[tokio::test]
+    async fn steps_are_sent_in_order() {
+        let (mut runner, mut action_rx, _transport_tx) = runner();
+
+        runner
+            .run(&macro_with_steps(vec![
+                step(MacroWait::MACRO_WAIT_NONE, 0, Action::ACTION_NEXT_SONG),
+                step(MacroWait::MACRO_WAIT_DELAY, 10, Action::ACTION_QUEUE_SELECTED),
+                step(MacroWait::MACRO_WAIT_NEXT_BAR, 0, Action::ACTION_TOGGLE_PLAY),
+            ]))
+            .unwrap();
+
+        assert_eq!(next_action(&mut action_rx).await, Action::ACTION_NEXT_SONG);
+        assert_eq!(next_action(&mut action_rx).await, Action::ACTION_QUEUE_SELECTED);
+        assert_eq!(next_action(&mut action_rx).await, Action::ACTION_TOGGLE_PLAY);
+    }
+
+    #[tokio::test]
+    async fn musical_wait_completes_on_the_beat() {
+        let (mut runner, mut action_rx, transport_tx) = runner();
+
+        let started = Instant::now();
+        transport_tx.send_replace(TransportPosition {
+            playing: true,
+            beat: 3.9,
+            bpm: 120.0,
+            captured_at: started,
+        });
+
+        runner
+            .run(&macro_with_steps(vec![step(
+                MacroWait::MACRO_WAIT_NEXT_BAR,
+                0,
+                Action::ACTION_NEXT_SECTION,
+            )]))
+            .unwrap();
+
+        assert_eq!(next_action(&mut action_rx).await, Action::ACTION_NEXT_SECTION);
+        assert!(started.elapsed() >= Duration::from_millis(45));
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_remaining_steps() {
+        let (mut runner, mut action_rx, _transport_tx) = runner();
+
+        runner
+            .run(&macro_with_steps(vec![
+                step(MacroWait::MACRO_WAIT_NONE, 0, Action::ACTION_NEXT_SONG),
+                step(MacroWait::MACRO_WAIT_DELAY, 50, Action::ACTION_TOGGLE_PLAY),
+            ]))
+            .unwrap();
+
+        assert_eq!(next_action(&mut action_rx).await, Action::ACTION_NEXT_SONG);
+        runner.cancel();
+
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(action_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn macros_cannot_run_other_macros() {
+        let (mut runner, _action_rx, _transport_tx) = runner();
+
+        assert!(runner
+            .run(&macro_with_steps(vec![step(
+                MacroWait::MACRO_WAIT_NONE,
+                0,
+                Action::ACTION_RUN_MACRO
+            )]))
+            .is_err());
+    }
+}
@@ -1,32 +1,95 @@
-use super::{directories::Directories, project_store::ProjectStore, waveform_store::WaveformStore};
+use super::{
+    directories::Directories,
+    loudness_analyser::{LoudnessAnalyser, LoudnessAnalysisResult},
+    macros::MacroRunner,
+    project_store::ProjectStore,
+    sample_start_detector::{SampleStartDetector, SampleStartResult},
+    section_analyser::{SectionAnalyser, SectionAnalysisResult},
+    tempo_analyser::{ApplyTempo, TempoAnalyser, TempoAnalysisResult},
+    undo::UndoHistory,
+    waveform_store::WaveformStore,
+};
 
 use crate::{
-    audio::{devices::enumerate_output_devices, AudioController},
+    api::{api_error, error_code},
+    audio::{devices::enumerate_output_devices, AudioController, ExternalTransportEvent},
     backend::{create_filesystem_backend, create_pocketbase_auth, create_pocketbase_backend, sync_project, Backend},
     bloop::*,
     config::AppConfig,
     control::user_store::UserStore,
     midi::MidiController,
-    model::{Action, Project, Sample, Section, Tempo, INVALID_ID},
+    model::{Action, Project, Sample, Section, Song, Tempo, ID, INVALID_ID},
+    network::{lag_recoveries, PairingStore},
     preferences::{self, default_audio_preferences, default_midi_preferences, default_preferences, read_preferences},
-    samples::SamplesCache,
+    samples::{SampleMetadata, SamplesCache},
     switch,
 };
 
-use anyhow::anyhow;
 use log::{error, info, warn};
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::{
     sync::{broadcast, mpsc},
     time,
 };
 
+/// The largest value of an action, matching the range of a MIDI data byte.
+const MAX_ACTION_VALUE: u32 = 127;
+const MIN_MASTER_VOLUME_DB: f64 = -60.0;
+
+/// The tempo songs without one play at.
+const DEFAULT_BPM: f64 = 120.0;
+
+/// Map an action value onto a volume between `min_db` and `max_db`.
+fn volume_db_from_action_value(value: u32, min_db: f64, max_db: f64) -> f64 {
+    let proportion = value.min(MAX_ACTION_VALUE) as f64 / MAX_ACTION_VALUE as f64;
+    min_db + proportion * (max_db - min_db)
+}
+
+/// Requests that change songs or sections can be undone. Selections, transport and
+/// loading projects can't.
+fn is_edit(request: &Request) -> bool {
+    request.add.is_some()
+        || request.remove.is_some()
+        || request.update.is_some()
+        || request.remove_sample.is_some()
+        || request.add_sample.is_some()
+        || request.add_section.is_some()
+        || request.replace_sections.is_some()
+        || request.set_target_loudness.is_some()
+}
+
+/// Replace a song's sections with those marked in its sample file, if there are any.
+fn with_marker_sections(project: Project, song_id: ID, sections: Vec<Section>) -> anyhow::Result<Project> {
+    if sections.is_empty() {
+        return Ok(project);
+    }
+
+    let mut song = project
+        .song_with_id(song_id)
+        .ok_or_else(|| {
+            api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Couldn't find song with ID: {song_id}"),
+            )
+        })?
+        .clone();
+    song.sections = sections;
+    let project = project.replace_song(&song)?;
+
+    if project.selection_is_valid() {
+        Ok(project)
+    } else {
+        Ok(project.select_song_with_id(song_id))
+    }
+}
+
 pub async fn run_main_controller(
     request_rx: mpsc::Receiver<Request>,
     response_tx: broadcast::Sender<Response>,
     app_config: AppConfig,
+    pairing: Arc<PairingStore>,
 ) {
-    let mut main_controller = MainController::new(request_rx, response_tx, app_config);
+    let mut main_controller = MainController::new(request_rx, response_tx, app_config, pairing);
     main_controller.run().await;
 }
 
@@ -37,11 +100,26 @@ struct MainController {
     request_rx: mpsc::Receiver<Request>,
     response_tx: broadcast::Sender<Response>,
     project: Project,
+    /// Counts changes to the project, so clients can tell when they've missed a patch.
+    project_version: u64,
+    undo_history: UndoHistory,
     audio_controller: AudioController,
     waveform_store: WaveformStore,
+    tempo_analyser: TempoAnalyser,
+    /// Samples whose markers become sections once their tempo has been analysed.
+    markers_awaiting_tempo: HashMap<ID, SampleMetadata>,
+    section_analyser: SectionAnalyser,
+    section_analysis_rx: mpsc::Receiver<SectionAnalysisResult>,
+    tempo_analysis_rx: mpsc::Receiver<TempoAnalysisResult>,
+    loudness_analyser: LoudnessAnalyser,
+    loudness_analysis_rx: mpsc::Receiver<LoudnessAnalysisResult>,
+    sample_start_detector: SampleStartDetector,
+    sample_start_rx: mpsc::Receiver<SampleStartResult>,
     midi_controller: Option<MidiController>,
-    action_rx: mpsc::Receiver<Action>,
-    action_tx: mpsc::Sender<Action>,
+    action_rx: mpsc::Receiver<ActionRequest>,
+    action_tx: mpsc::Sender<ActionRequest>,
+    macro_runner: MacroRunner,
+    external_transport_rx: mpsc::Receiver<ExternalTransportEvent>,
     should_save: bool,
     preferences: Preferences,
     project_info: ProjectInfo,
@@ -49,6 +127,7 @@ struct MainController {
     local_backend: Arc<dyn Backend>,
     remote_backend: Arc<dyn Backend>,
     directories: Directories,
+    pairing: Arc<PairingStore>,
 }
 
 impl MainController {
@@ -56,10 +135,16 @@ impl MainController {
         request_rx: mpsc::Receiver<Request>,
         response_tx: broadcast::Sender<Response>,
         app_config: AppConfig,
+        pairing: Arc<PairingStore>,
     ) -> Self {
         let directories = Directories::new(app_config.root_directory);
 
         let (action_tx, action_rx) = mpsc::channel(128);
+        let (external_transport_tx, external_transport_rx) = mpsc::channel(128);
+        let (tempo_analysis_tx, tempo_analysis_rx) = mpsc::channel(16);
+        let (loudness_analysis_tx, loudness_analysis_rx) = mpsc::channel(16);
+        let (section_analysis_tx, section_analysis_rx) = mpsc::channel(16);
+        let (sample_start_tx, sample_start_rx) = mpsc::channel(16);
 
         let preferences = match read_preferences(&directories.root) {
             Ok(preferences) => {
@@ -80,17 +165,25 @@ impl MainController {
 
         let local_backend = create_filesystem_backend(&directories.projects);
 
+        let mut audio_controller =
+            AudioController::new(response_tx.clone(), audio_preferences, app_config.use_dummy_audio);
+        audio_controller.set_external_sync(app_config.use_midi && midi_preferences.sync_to_external_clock);
+
         let midi_controller = if app_config.use_midi {
             Some(MidiController::new(
                 action_tx.clone(),
+                external_transport_tx,
                 midi_preferences,
                 &directories.root.join("midi_mappings"),
                 response_tx.clone(),
+                audio_controller.subscribe_transport(),
             ))
         } else {
             None
         };
 
+        let macro_runner = MacroRunner::new(action_tx.clone(), audio_controller.subscribe_transport());
+
         Self {
             samples_cache: SamplesCache::new(&directories.samples),
             project_store: ProjectStore::new(&directories.projects, local_backend.clone(), remote_backend.clone()),
@@ -98,11 +191,24 @@ impl MainController {
             request_rx,
             response_tx: response_tx.clone(),
             project: Project::empty().with_songs(1, 1),
-            audio_controller: AudioController::new(response_tx.clone(), audio_preferences, app_config.use_dummy_audio),
+            project_version: 0,
+            undo_history: UndoHistory::default(),
+            audio_controller,
             waveform_store: WaveformStore::new(response_tx),
+            section_analyser: SectionAnalyser::new(section_analysis_tx),
+            section_analysis_rx,
+            tempo_analyser: TempoAnalyser::new(tempo_analysis_tx),
+            markers_awaiting_tempo: HashMap::new(),
+            tempo_analysis_rx,
+            loudness_analyser: LoudnessAnalyser::new(loudness_analysis_tx),
+            loudness_analysis_rx,
+            sample_start_detector: SampleStartDetector::new(sample_start_tx),
+            sample_start_rx,
             midi_controller,
             action_rx,
             action_tx,
+            macro_runner,
+            external_transport_rx,
             should_save: false,
             preferences,
             project_info: ProjectInfo::empty(),
@@ -110,6 +216,7 @@ impl MainController {
             local_backend,
             remote_backend,
             directories,
+            pairing,
         }
     }
 
@@ -125,6 +232,7 @@ impl MainController {
 
     async fn handle_request(&mut self, request: Request) -> anyhow::Result<()> {
         let mut project = self.project.clone();
+        let undo_status = self.undo_history.status();
 
         if let Some(add_request) = request.add.as_ref() {
             project = self.handle_add(project, add_request)?;
@@ -136,6 +244,7 @@ impl MainController {
 
         if let Some(load_request) = request.load.as_ref() {
             project = self.handle_load(load_request).await?;
+            self.undo_history.clear();
         }
 
         if let Some(remove_request) = request.remove.as_ref() {
@@ -182,6 +291,45 @@ impl MainController {
             project = self.handle_add_section_with_params(add_section_request, project)?;
         }
 
+        if let Some(analyse_tempo_request) = request.analyse_tempo.as_ref() {
+            let sample = project
+                .song_with_id(analyse_tempo_request.song_id)
+                .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song not found"))?
+                .sample
+                .as_ref()
+                .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song has no sample"))?;
+            self.tempo_analyser.analyse(
+                sample.id,
+                sample.tempo.clone().into_option(),
+                &self.samples_cache,
+                ApplyTempo::Always,
+            )?;
+        }
+
+        if let Some(suggest_sections_request) = request.suggest_sections.as_ref() {
+            let song = project
+                .song_with_id(suggest_sections_request.song_id)
+                .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song not found"))?;
+            self.section_analyser.suggest_sections(song, &self.samples_cache)?;
+        }
+
+        if let Some(detect_sample_start_request) = request.detect_sample_start.as_ref() {
+            self.handle_detect_sample_start(detect_sample_start_request, &project)?;
+        }
+
+        if let Some(waveform_range_request) = request.waveform_range.as_ref() {
+            self.waveform_store
+                .get_waveform_range(waveform_range_request, &self.samples_cache)?;
+        }
+
+        if let Some(set_target_loudness_request) = request.set_target_loudness.as_ref() {
+            project = project.with_target_loudness(set_target_loudness_request.target_loudness)?;
+        }
+
+        if let Some(replace_sections_request) = request.replace_sections.as_ref() {
+            project = project.replace_sections(replace_sections_request.song_id, &replace_sections_request.sections)?;
+        }
+
         if let Some(remove_project_request) = request.remove_project.as_ref() {
             let removal_targets = remove_project_request
                 .targets
@@ -212,6 +360,32 @@ impl MainController {
             self.set_project_info(project_info);
         }
 
+        if let Some(export_project_request) = request.export_project.as_ref() {
+            let project_id = &export_project_request.project_id;
+            let projects = self.project_store.projects().await?;
+            if !projects.iter().any(|project| project.id == *project_id) {
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_NOT_FOUND,
+                    format!("Project not found: {project_id}"),
+                ));
+            }
+
+            let path = match export_project_request.path.as_str() {
+                "" => self.directories.export_path(&format!("{project_id}.bloop"))?,
+                path => self.directories.export_path(path)?,
+            };
+
+            self.project_store.export_bundle(project_id, &path).await?;
+            self.send_response(Response::default().with_exported_bundle(&path.to_string_lossy()));
+        }
+
+        if let Some(import_project_request) = request.import_project.as_ref() {
+            let path = self.directories.export_path(&import_project_request.path)?;
+            self.project_store.import_bundle(&path, &self.get_user_id()).await?;
+            let projects = self.project_store.projects().await?;
+            self.send_response(Response::default().with_projects(&projects));
+        }
+
         if let Some(rename_project_request) = request.rename_project.as_ref() {
             self.project_store
                 .rename_project(&rename_project_request.project_id, &rename_project_request.new_name)
@@ -229,8 +403,11 @@ impl MainController {
                 }
                 Err(error) => {
                     error!("Unable to log in: {error}");
-                    self.send_error_response(&format!("Login failed: {error}"));
                     self.set_user(None);
+                    return Err(api_error(
+                        ErrorCode::ERROR_CODE_NOT_LOGGED_IN,
+                        format!("Login failed: {error}"),
+                    ));
                 }
             }
         }
@@ -243,17 +420,90 @@ impl MainController {
         }
 
         if let Some(project_sync) = request.project_sync.as_ref() {
-            self.handle_project_sync(project_sync).await?;
+            self.handle_project_sync(project_sync, !request.request_id.is_empty())
+                .await?;
         }
 
         if let Some(audio_control) = request.audio_control.as_ref() {
             self.handle_audio_control(audio_control)?;
         }
 
+        if let Some(midi_mapping) = request.midi_mapping.as_ref() {
+            self.handle_midi_mapping(midi_mapping)?;
+        }
+
+        if let Some(revoke_device) = request.revoke_device.as_ref() {
+            self.pairing
+                .revoke(&revoke_device.device_id)
+                .map_err(|error| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, error.to_string()))?;
+            self.send_response(Response::default().with_pairing_status(&self.pairing.status()));
+        }
+
+        if let Some(set_device_role) = request.set_device_role.as_ref() {
+            self.pairing
+                .set_role(&set_device_role.device_id, set_device_role.role.enum_value_or_default())
+                .map_err(|error| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, error.to_string()))?;
+            self.send_response(Response::default().with_pairing_status(&self.pairing.status()));
+        }
+
+        if request.undo.is_some() {
+            let previous = self
+                .undo_history
+                .undo(project.clone())
+                .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Nothing to undo"))?;
+            project = project.with_edits_from(previous);
+        }
+
+        if request.redo.is_some() {
+            let next = self
+                .undo_history
+                .redo(project.clone())
+                .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Nothing to redo"))?;
+            project = project.with_edits_from(next);
+        }
+
+        if is_edit(&request) && project.has_edits_from(&self.project) {
+            self.undo_history.record(self.project.clone());
+        }
+
         self.set_project(project);
+
+        if self.undo_history.status() != undo_status {
+            self.send_response(Response::default().with_undo_status(&self.undo_history.status()));
+        }
+
+        if let Some(action) = request.action.as_ref() {
+            self.handle_action(action)?;
+        }
+
         Ok(())
     }
 
+    /// Requests with an ID are acknowledged, so only the connection that sent them
+    /// sees the outcome. Errors from other requests are broadcast.
+    async fn handle_request_and_acknowledge(&mut self, request: Request) {
+        let request_id = request.request_id.clone();
+
+        // A snapshot goes out with its acknowledgement, so only the connection
+        // catching up receives it
+        if !request_id.is_empty() && request.get.entity.enum_value_or_default() == Entity::SNAPSHOT {
+            self.send_response(self.snapshot().with_acknowledgement(&request_id, &Ok(())));
+            return;
+        }
+
+        let result = self.handle_request(request).await;
+
+        if let Err(error) = result.as_ref() {
+            warn!("Error handling request: {error}");
+        }
+
+        if !request_id.is_empty() {
+            self.send_response(Response::default().with_acknowledgement(&request_id, &result));
+        } else if let Err(error) = result {
+            self.send_error_response(&error.to_string());
+        }
+    }
+
     fn get_user_id(&self) -> String {
         match &self.user {
             Some(user) => user.id.clone(),
@@ -281,15 +531,25 @@ impl MainController {
     }
 
     fn set_project(&mut self, project: Project) {
-        if self.project.songs != project.songs {
+        let project = self.loudness_analyser.with_loudness(project, &self.samples_cache);
+
+        if project.has_edits_from(&self.project) {
             self.should_save = true;
         }
 
         if self.project != project {
+            self.project_version += 1;
+            let patch = ProjectPatch::between(&self.project, &project, self.project_version);
             self.project = project;
-            self.send_project_response(&self.project);
+            self.send_response(
+                Response::default()
+                    .with_project(&self.project)
+                    .with_project_version(self.project_version)
+                    .with_project_patch(&patch),
+            );
             self.audio_controller
                 .on_project_updated(&self.project, &self.samples_cache);
+            self.waveform_store.retain_samples(&self.project);
         }
     }
 
@@ -311,8 +571,10 @@ impl MainController {
         let entity = match get_request.entity.enum_value() {
             Ok(entity) => entity,
             Err(error) => {
-                self.send_error_response(&format!("Invalid entity type: {error}"));
-                return Ok(());
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_INVALID_ENTITY,
+                    format!("Invalid entity type: {error}"),
+                ));
             }
         };
 
@@ -330,15 +592,16 @@ impl MainController {
                     }
                 }
 
+                self.send_response(self.snapshot())
+            }
+            Entity::SNAPSHOT => self.send_response(self.snapshot()),
+            Entity::PROJECT => {
                 self.send_response(
                     Response::default()
                         .with_project(&self.project)
-                        .with_playback_state(self.audio_controller.get_playback_state())
-                        .with_project_info(&self.project_info)
-                        .with_user(self.user.clone())
-                        .with_preferences(&self.preferences)
-                        .with_audio_status(&self.audio_controller.get_audio_status()),
-                )
+                        .with_project_version(self.project_version)
+                        .with_playback_state(self.audio_controller.get_playback_state()),
+                );
             }
             Entity::PROJECTS => {
                 let projects = self.project_store.projects().await?;
@@ -377,14 +640,40 @@ impl MainController {
                 let midi_devices = crate::midi::get_midi_devices();
                 self.send_response(Response::default().with_midi_devices(&midi_devices));
             }
+            Entity::MIDI_MAPPINGS => {
+                let midi_mappings = self
+                    .midi_controller
+                    .as_ref()
+                    .map(|midi_controller| midi_controller.mappings())
+                    .unwrap_or_default();
+                self.send_response(Response::default().with_midi_mappings(&midi_mappings));
+            }
+            Entity::PAIRED_DEVICES => {
+                self.send_response(Response::default().with_pairing_status(&self.pairing.status()));
+            }
+            Entity::NETWORK_STATUS => {
+                let network_status = NetworkStatus {
+                    lag_recoveries: lag_recoveries(),
+                    ..Default::default()
+                };
+                self.send_response(Response::default().with_network_status(&network_status));
+            }
             _ => (),
         };
 
         Ok(())
     }
 
-    fn send_project_response(&self, project: &Project) {
-        self.send_response(Response::default().with_project(project));
+    fn snapshot(&self) -> Response {
+        Response::default()
+            .with_project(&self.project)
+            .with_project_version(self.project_version)
+            .with_playback_state(self.audio_controller.get_playback_state())
+            .with_project_info(&self.project_info)
+            .with_user(self.user.clone())
+            .with_preferences(&self.preferences)
+            .with_audio_status(&self.audio_controller.get_audio_status())
+            .with_undo_status(&self.undo_history.status())
     }
 
     fn send_error_response(&self, message: &str) {
@@ -416,14 +705,18 @@ impl MainController {
 
         loop {
             tokio::select! {
-                Some(request) = self.request_rx.recv() => {
-                    if let Err(error) = self.handle_request(request).await {
-                        warn!("Error handling request: {error}");
-                        self.send_error_response(&error.to_string());
+                Some(request) = self.request_rx.recv() => self.handle_request_and_acknowledge(request).await,
+                _ = self.audio_controller.run() => (),
+                Some(action) = self.action_rx.recv() => {
+                    if let Err(error) = self.handle_action(&action) {
+                        warn!("Error handling action: {error}");
                     }
                 }
-                _ = self.audio_controller.run() => (),
-                Some(action) = self.action_rx.recv() => self.handle_action(action),
+                Some(event) = self.external_transport_rx.recv() => self.audio_controller.handle_external_transport(event),
+                Some(result) = self.tempo_analysis_rx.recv() => self.handle_tempo_analysis(result),
+                Some(result) = self.loudness_analysis_rx.recv() => self.handle_loudness_analysis(result),
+                Some(result) = self.sample_start_rx.recv() => self.handle_sample_start(result),
+                Some(result) = self.section_analysis_rx.recv() => self.handle_section_analysis(result),
                 _ = save_interval.tick() => self.auto_save_project().await,
                 else => break,
             }
@@ -432,7 +725,14 @@ impl MainController {
         drop(switch_task);
     }
 
-    fn handle_action(&mut self, action: Action) {
+    fn handle_action(&mut self, request: &ActionRequest) -> anyhow::Result<()> {
+        let action = request.action.enum_value().map_err(|value| {
+            api_error(
+                ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                format!("Unknown action {value}"),
+            )
+        })?;
+
         match action {
             Action::ACTION_UNKNOWN => (),
             Action::ACTION_PREVIOUS_SONG => self.previous_song(),
@@ -442,7 +742,88 @@ impl MainController {
             Action::ACTION_QUEUE_SELECTED => self.queue_selected(),
             Action::ACTION_TOGGLE_LOOP => self.audio_controller.toggle_loop(),
             Action::ACTION_TOGGLE_PLAY => self.audio_controller.toggle_play(),
+            Action::ACTION_SELECT_SONG => self.select_song_at_index(request.value as usize)?,
+            Action::ACTION_SELECT_SECTION => self.select_section_at_index(request.value as usize)?,
+            Action::ACTION_SET_SONG_VOLUME => self.set_song_volume(request.value)?,
+            Action::ACTION_SET_MASTER_VOLUME => self
+                .audio_controller
+                .set_master_volume_db(volume_db_from_action_value(request.value, MIN_MASTER_VOLUME_DB, 0.0)),
+            Action::ACTION_RUN_MACRO => self.run_macro(&request.macro_name)?,
+            Action::ACTION_CANCEL_MACRO => self.macro_runner.cancel(),
+        }
+
+        Ok(())
+    }
+
+    fn run_macro(&mut self, name: &str) -> anyhow::Result<()> {
+        let macro_ = self
+            .preferences
+            .macros
+            .iter()
+            .find(|macro_| macro_.name == name)
+            .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, format!("No macro named '{name}'")))?;
+
+        self.macro_runner.run(macro_)
+    }
+
+    /// Select a song or section directly. While playing, the new selection is queued
+    /// so that playback jumps to it.
+    fn select_song_at_index(&mut self, index: usize) -> anyhow::Result<()> {
+        if index >= self.project.songs.len() {
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("No song at index {index}"),
+            ));
         }
+
+        let project = self.project.clone().select_song_index(index);
+        self.set_project(project);
+        self.queue_selected_if_playing();
+        Ok(())
+    }
+
+    fn select_section_at_index(&mut self, index: usize) -> anyhow::Result<()> {
+        let section_count = self.project.selected_song().map_or(0, |song| song.sections.len());
+        if index >= section_count {
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("No section at index {index}"),
+            ));
+        }
+
+        let project = self.project.clone().select_section_at_index(index)?;
+        self.set_project(project);
+        self.queue_selected_if_playing();
+        Ok(())
+    }
+
+    fn queue_selected_if_playing(&mut self) {
+        if self
+            .audio_controller
+            .get_playback_state()
+            .playing
+            .enum_value_or_default()
+            == PlayingState::PLAYING
+        {
+            self.queue_selected();
+        }
+    }
+
+    fn set_song_volume(&mut self, value: u32) -> anyhow::Result<()> {
+        let mut song = self
+            .project
+            .selected_song()
+            .cloned()
+            .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "No song selected"))?;
+        song.volume = Some(volume_db_from_action_value(
+            value,
+            Song::MIN_VOLUME_DB,
+            Song::MAX_VOLUME_DB,
+        ));
+
+        let project = self.project.clone().replace_song(&song)?;
+        self.set_project(project);
+        Ok(())
     }
 
     fn previous_song(&mut self) {
@@ -495,7 +876,7 @@ impl MainController {
 
     fn handle_add_section(&self, project: Project, request: &AddRequest) -> anyhow::Result<Project> {
         if request.id == INVALID_ID {
-            return Err(anyhow!("Missing parent ID"));
+            return Err(api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Missing parent ID"));
         }
 
         project.add_section_to_song(request.id)
@@ -535,7 +916,7 @@ impl MainController {
 
         if let Some(new_project) = update_request.project.as_ref() {
             if !new_project.is_valid() {
-                return Err(anyhow!("Invalid project"));
+                return Err(api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Invalid project"));
             }
 
             project = new_project.clone();
@@ -549,6 +930,8 @@ impl MainController {
             if let Some(new_midi_prefs) = preferences.midi.as_ref() {
                 if let Some(midi_controller) = &self.midi_controller {
                     midi_controller.update_preferences(new_midi_prefs.clone());
+                    self.audio_controller
+                        .set_external_sync(new_midi_prefs.sync_to_external_clock);
                 }
             }
             self.preferences = preferences.clone();
@@ -562,10 +945,27 @@ impl MainController {
     }
 
     async fn handle_load(&mut self, request: &LoadProjectRequest) -> anyhow::Result<Project> {
-        let (project, project_info) = self
+        let (project, project_info) = match self
             .project_store
             .load(&request.project_id, &mut self.samples_cache)
-            .await?;
+            .await
+        {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                let projects = self.project_store.projects().await.unwrap_or_default();
+                if projects
+                    .iter()
+                    .any(|project_info| project_info.id == request.project_id)
+                {
+                    return Err(error);
+                }
+
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_NOT_FOUND,
+                    format!("Project not found: {}", request.project_id),
+                ));
+            }
+        };
 
         self.set_project_info(project_info);
 
@@ -609,7 +1009,7 @@ impl MainController {
         let sample_metadata = self.samples_cache.get_sample_metadata(request.upload_id)?;
 
         let mut sample = Sample::new_with_id(&request.upload_id);
-        sample.name = sample_metadata.name;
+        sample.name = sample_metadata.name.clone();
         sample.sample_rate = sample_metadata.sample_rate as i32;
         sample.channel_count = sample_metadata.num_channels as i32;
         sample.sample_count = sample_metadata.sample_count as i64;
@@ -618,10 +1018,201 @@ impl MainController {
             sample.tempo = Some(Tempo::new_with_bpm(tempo)).into();
         }
 
+        let tempo = sample.tempo.clone().into_option();
         project = project.add_sample_to_song(sample, request.song_id)?;
+
+        // The audio is analysed either way, but the result only replaces a tempo
+        // that couldn't be found from the name or metadata
+        let apply = match sample_metadata.detected_tempo {
+            Some(_) => ApplyTempo::Never,
+            None => ApplyTempo::IfConfident,
+        };
+        let analysing = match self
+            .tempo_analyser
+            .analyse(request.upload_id, tempo, &self.samples_cache, apply)
+        {
+            Ok(()) => true,
+            Err(error) => {
+                warn!("Unable to analyse tempo of sample {}: {error}", request.upload_id);
+                false
+            }
+        };
+
+        // Sections marked in the file replace the song's sections. Without a tempo from
+        // the name or metadata, they wait for the analysed one so they land on the beats.
+        match sample_metadata.detected_tempo {
+            Some(bpm) => project = with_marker_sections(project, request.song_id, sample_metadata.sections(bpm))?,
+            None if analysing => {
+                self.markers_awaiting_tempo.insert(request.upload_id, sample_metadata);
+            }
+            None => project = with_marker_sections(project, request.song_id, sample_metadata.sections(DEFAULT_BPM))?,
+        }
+
         Ok(project)
     }
 
+    fn handle_detect_sample_start(
+        &mut self,
+        request: &DetectSampleStartRequest,
+        project: &Project,
+    ) -> anyhow::Result<()> {
+        let sample_id = project
+            .song_with_id(request.song_id)
+            .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song not found"))?
+            .sample
+            .as_ref()
+            .map(|sample| sample.id)
+            .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song has no sample"))?;
+
+        self.sample_start_detector.detect(sample_id, &self.samples_cache)
+    }
+
+    fn handle_section_analysis(&mut self, result: SectionAnalysisResult) {
+        let song_id = result.song_id;
+        self.section_analyser.complete(song_id);
+
+        let structure = match result.structure {
+            Ok(structure) => structure,
+            Err(error) => {
+                self.send_error_response(&format!("Error suggesting sections for song {song_id}: {error}"));
+                return;
+            }
+        };
+
+        self.send_response(
+            Response::default().with_section_suggestions(&SectionSuggestions {
+                song_id,
+                first_downbeat: structure.first_downbeat,
+                sections: structure
+                    .section_starts()
+                    .into_iter()
+                    .map(|start| Section::empty().with_start(start))
+                    .collect(),
+                ..Default::default()
+            }),
+        );
+    }
+
+    fn handle_sample_start(&mut self, result: SampleStartResult) {
+        let sample_id = result.sample_id;
+        self.sample_start_detector.complete(sample_id);
+
+        let start_offset = match result.start_offset {
+            Ok(start_offset) => start_offset,
+            Err(error) => {
+                warn!("Error finding the start of sample {sample_id}: {error}");
+                return;
+            }
+        };
+
+        info!("Sample {sample_id} starts at frame {start_offset}");
+
+        // The sample may have been removed while it was being analysed
+        let Some(sample) = self.project.find_sample(sample_id) else {
+            return;
+        };
+
+        let mut sample = sample.clone();
+        sample.start_offset = start_offset;
+        self.apply_analysed_sample(&sample);
+    }
+
+    /// Measured loudness isn't an edit, so it's applied without an undo step.
+    fn handle_loudness_analysis(&mut self, result: LoudnessAnalysisResult) {
+        if self.loudness_analyser.complete(result) {
+            self.set_project(self.project.clone());
+        }
+    }
+
+    fn handle_tempo_analysis(&mut self, result: TempoAnalysisResult) {
+        let sample_id = result.sample_id;
+        // The tempo may have been set by hand while the sample was being analysed
+        let tempo = self
+            .project
+            .find_sample(sample_id)
+            .and_then(|sample| sample.tempo.as_ref());
+        let apply = self.tempo_analyser.complete(sample_id, tempo);
+        let marker_metadata = self.markers_awaiting_tempo.remove(&sample_id);
+
+        let estimate = match result.estimate {
+            Ok(Some(estimate)) => Some(estimate),
+            Ok(None) => {
+                info!("No tempo found in sample: {sample_id}");
+                None
+            }
+            Err(error) => {
+                warn!("Error analysing tempo of sample {sample_id}: {error}");
+                None
+            }
+        };
+
+        if let Some(estimate) = estimate.as_ref() {
+            info!(
+                "Estimated tempo of sample {sample_id}: {} BPM (confidence {:.2})",
+                estimate.bpm, estimate.confidence
+            );
+            self.send_response(Response::default().with_tempo_analysis(&TempoAnalysis {
+                sample_id,
+                bpm: estimate.bpm,
+                confidence: estimate.confidence,
+                ..Default::default()
+            }));
+        }
+
+        // The sample may have been removed while it was being analysed
+        let Some(sample) = self.project.find_sample(sample_id) else {
+            return;
+        };
+
+        let mut sample = sample.clone();
+        if let Some(estimate) = estimate.filter(|estimate| apply.accepts(estimate)) {
+            sample.tempo = Some(Tempo::new_with_bpm(estimate.bpm)).into();
+        }
+
+        let project = self.project.clone().replace_sample(&sample).and_then(|project| {
+            // Markers are placed at whichever tempo the sample ended up with
+            let Some(marker_metadata) = marker_metadata else {
+                return Ok(project);
+            };
+            let Some(song_id) = project
+                .songs
+                .iter()
+                .find(|song| song.sample.as_ref().is_some_and(|sample| sample.id == sample_id))
+                .map(|song| song.id)
+            else {
+                return Ok(project);
+            };
+            let bpm = sample.tempo.as_ref().map_or(DEFAULT_BPM, Tempo::get_bpm);
+            with_marker_sections(project, song_id, marker_metadata.sections(bpm))
+        });
+
+        match project {
+            Ok(project) => self.apply_analysed_edit(project),
+            Err(error) => warn!("Unable to update sample {sample_id}: {error}"),
+        }
+    }
+
+    /// Apply what was found by analysing a sample as an edit that can be undone.
+    fn apply_analysed_sample(&mut self, sample: &Sample) {
+        match self.project.clone().replace_sample(sample) {
+            Ok(project) => self.apply_analysed_edit(project),
+            Err(error) => warn!("Unable to update sample {}: {error}", sample.id),
+        }
+    }
+
+    fn apply_analysed_edit(&mut self, project: Project) {
+        if !project.has_edits_from(&self.project) {
+            return;
+        }
+
+        let undo_status = self.undo_history.status();
+        self.undo_history.record(self.project.clone());
+        self.set_project(project);
+        if self.undo_history.status() != undo_status {
+            self.send_response(Response::default().with_undo_status(&self.undo_history.status()));
+        }
+    }
+
     fn handle_add_section_with_params(
         &mut self,
         request: &AddSectionRequest,
@@ -635,7 +1226,7 @@ impl MainController {
 
         let song = project
             .song_with_id_mut(request.song_id)
-            .ok_or_else(|| anyhow!("Song not found"))?;
+            .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song not found"))?;
 
         song.sections.push(section);
 
@@ -654,7 +1245,10 @@ impl MainController {
                 .audio_controller
                 .queue(transport_request.queue.song_id, transport_request.queue.section_id),
             Err(error) => {
-                return Err(anyhow!("Invalid transport method: {error}"));
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                    format!("Invalid transport method: {error}"),
+                ));
             }
         }
 
@@ -677,18 +1271,51 @@ impl MainController {
                 self.audio_controller.start_audio(&self.samples_cache);
             }
             Err(error) => {
-                return Err(anyhow!("Invalid audio control method: {error}"));
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                    format!("Invalid audio control method: {error}"),
+                ));
             }
         }
 
         Ok(())
     }
 
-    async fn handle_project_sync(&mut self, project_sync: &ProjectSyncRequest) -> anyhow::Result<()> {
+    fn handle_midi_mapping(&self, request: &MidiMappingRequest) -> anyhow::Result<()> {
+        let midi_controller = self
+            .midi_controller
+            .as_ref()
+            .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_INVALID_STATE, "MIDI is disabled"))?;
+        let mapping = request.mapping.get_or_default();
+
+        match request.method.enum_value() {
+            Ok(MidiMappingMethod::MIDI_MAPPING_METHOD_LEARN) => {
+                midi_controller.learn(mapping.action.enum_value_or_default())
+            }
+            Ok(MidiMappingMethod::MIDI_MAPPING_METHOD_CANCEL_LEARN) => midi_controller.cancel_learn(),
+            Ok(MidiMappingMethod::MIDI_MAPPING_METHOD_UPDATE) => midi_controller.update_mapping(mapping),
+            Ok(MidiMappingMethod::MIDI_MAPPING_METHOD_REMOVE) => midi_controller.remove_mapping(mapping),
+            Err(error) => Err(api_error(
+                ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                format!("Invalid MIDI mapping method: {error}"),
+            )),
+        }
+    }
+
+    /// Sync a project with the cloud. The final sync status reports a failure, so
+    /// it's only returned as an error when it's acknowledged as well.
+    async fn handle_project_sync(
+        &mut self,
+        project_sync: &ProjectSyncRequest,
+        acknowledged: bool,
+    ) -> anyhow::Result<()> {
         let user_id = match &self.user {
             Some(user) => user.id.clone(),
             None => {
-                return Err(anyhow!("User not logged in, cannot sync project"));
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_NOT_LOGGED_IN,
+                    "User not logged in, cannot sync project",
+                ));
             }
         };
 
@@ -700,7 +1327,10 @@ impl MainController {
 
         let result = match project_sync.method.enum_value_or_default() {
             SyncMethod::SYNC_METHOD_UNDEFINED => {
-                return Err(anyhow!("Undefined sync method"));
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                    "Undefined sync method",
+                ));
             }
             SyncMethod::SYNC_METHOD_PUSH => {
                 sync_project(
@@ -725,7 +1355,9 @@ impl MainController {
         let projects = self.project_store.projects().await?;
         let cloud_projects = self.project_store.cloud_projects().await.unwrap_or_default();
 
-        let response = Response::default()
+        let result = result.map_err(|error| api_error(error_code(&error), format!("Sync failed: {error}")));
+
+        let mut response = Response::default()
             .with_project_sync(&ProjectSyncResponse {
                 project_id: project_sync.project_id.clone(),
                 status: if result.is_err() {
@@ -739,8 +1371,17 @@ impl MainController {
             .with_projects(&projects)
             .with_cloud_projects(&cloud_projects);
 
-        self.send_response(response);
-
-        Ok(())
+        match result {
+            Err(error) if !acknowledged => {
+                warn!("{error}");
+                response = response.with_error(&error.to_string());
+                self.send_response(response);
+                Ok(())
+            }
+            result => {
+                self.send_response(response);
+                result
+            }
+        }
     }
 }
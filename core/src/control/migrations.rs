@@ -0,0 +1,116 @@
+use crate::{
+    api::api_error,
+    bloop::ErrorCode,
+    model::{Project, Tempo},
+};
+use anyhow::Context;
+use protobuf::Message;
+
+/// The version of the project file format written by this build.
+///
+/// - 0: Files saved before the version was stamped. Older clients didn't always
+///   keep songs' tempos, the order of sections or the selection valid.
+/// - 1: The first stamped version.
+pub const PROJECT_SCHEMA_VERSION: u32 = 1;
+
+/// Each migration upgrades a project from the version at its index to the next.
+const MIGRATIONS: [fn(Project) -> Project; PROJECT_SCHEMA_VERSION as usize] = [migrate_from_unversioned];
+
+/// Parse a project file, upgrading it from older versions of the format.
+pub fn read_project(data: &[u8]) -> anyhow::Result<Project> {
+    let mut project = Project::parse_from_bytes(data).context("Parse project data")?;
+
+    let version = project.schema_version;
+    if version > PROJECT_SCHEMA_VERSION {
+        return Err(api_error(
+            ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+            format!(
+                "Project was saved by a newer version of bloop (schema version {version}, \
+                 this version supports up to {PROJECT_SCHEMA_VERSION})"
+            ),
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        project = migration(project);
+    }
+
+    // The version only matters in the file
+    project.schema_version = 0;
+    Ok(project)
+}
+
+pub fn write_project(project: &Project) -> anyhow::Result<Vec<u8>> {
+    let mut project = project.clone();
+    project.schema_version = PROJECT_SCHEMA_VERSION;
+    Ok(project.write_to_bytes()?)
+}
+
+fn migrate_from_unversioned(mut project: Project) -> Project {
+    for song in project.songs.iter_mut() {
+        if song.tempo.is_none() {
+            song.tempo = Some(Tempo::new_with_bpm(120.0)).into();
+        }
+
+        song.sections.sort_by(|a, b| a.start.total_cmp(&b.start));
+    }
+
+    if !project.songs.is_empty() && !project.selection_is_valid() {
+        project = project.select_song_index(0);
+    }
+
+    project
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNVERSIONED_PROJECT: &[u8] = include_bytes!("../../tests/fixtures/projects/v0.bin");
+    const VERSION_1_PROJECT: &[u8] = include_bytes!("../../tests/fixtures/projects/v1.bin");
+
+    #[test]
+    fn unversioned_projects_are_repaired() {
+        let project = read_project(UNVERSIONED_PROJECT).unwrap();
+
+        assert_eq!(project.songs.len(), 2);
+        assert_eq!(project.songs[0].name, "Intro");
+        assert!(project.songs.iter().all(|song| song.tempo.is_some()));
+        assert!(project.is_valid());
+        assert!(project.selection_is_valid());
+        assert_eq!(project.schema_version, 0);
+    }
+
+    #[test]
+    fn version_1_projects_are_read() {
+        let project = read_project(VERSION_1_PROJECT).unwrap();
+
+        assert_eq!(project.songs.len(), 2);
+        assert_eq!(project.songs[1].name, "Outro");
+        assert_eq!(project.songs[1].tempo.bpm, 90.0);
+        assert!(project.is_valid());
+        assert!(project.selection_is_valid());
+    }
+
+    #[test]
+    fn written_projects_are_stamped() {
+        let project = Project::empty().with_songs(2, 3);
+        let data = write_project(&project).unwrap();
+
+        assert_eq!(
+            Project::parse_from_bytes(&data).unwrap().schema_version,
+            PROJECT_SCHEMA_VERSION
+        );
+        assert_eq!(read_project(&data).unwrap(), project);
+    }
+
+    #[test]
+    fn newer_projects_are_refused() {
+        let mut project = Project::empty().with_songs(1, 1);
+        project.schema_version = PROJECT_SCHEMA_VERSION + 1;
+
+        let error = read_project(&project.write_to_bytes().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("newer version"));
+        assert_eq!(crate::api::error_code(&error), ErrorCode::ERROR_CODE_VALIDATION_FAILED);
+    }
+}
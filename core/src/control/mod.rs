@@ -1,6 +1,14 @@
+mod bundle;
 mod directories;
+mod loudness_analyser;
+mod macros;
 mod main;
+mod migrations;
 mod project_store;
+mod sample_start_detector;
+mod section_analyser;
+mod tempo_analyser;
+mod undo;
 mod user_store;
 mod waveform_store;
 
@@ -1,3 +1,7 @@
+use super::{
+    bundle::{read_bundle, write_bundle, Bundle},
+    migrations::{read_project, write_project},
+};
 use crate::{
     backend::Backend,
     bloop::{AudioFileFormat, ProjectRemovalTarget},
@@ -6,7 +10,6 @@ use crate::{
 };
 use anyhow::{anyhow, Context};
 use log::{debug, error, info, warn};
-use protobuf::Message;
 use std::{
     fs,
     path::{Path, PathBuf},
@@ -146,8 +149,69 @@ impl ProjectStore {
         Ok(())
     }
 
+    /// Write a saved project and its samples to a bundle at `path`.
+    pub async fn export_bundle(&self, project_id: &str, path: &Path) -> anyhow::Result<()> {
+        let (project, project_info) = self.read_project_file(project_id).await?;
+
+        let mut samples = vec![];
+        for sample in project.songs.iter().filter_map(|song| song.sample.as_ref()) {
+            let data = self
+                .backend
+                .read_sample(project_id, &sample.id.to_string())
+                .await
+                .context(format!("Reading sample for bundle: {}", sample.id))?;
+            samples.push((sample.id, data));
+        }
+
+        let bundle = Bundle {
+            name: project_info.name,
+            project,
+            samples,
+        };
+        write_bundle(&bundle, path).await?;
+
+        info!("Project exported: id = {project_id}, path = {}", path.display());
+        Ok(())
+    }
+
+    /// Add the project in a bundle as a new project. The songs and sections get new
+    /// IDs, so they can't clash with projects already on this machine.
+    pub async fn import_bundle(&self, path: &Path, user_id: &str) -> anyhow::Result<String> {
+        let mut bundle = read_bundle(path).await?;
+        bundle.project = bundle.project.replace_ids();
+
+        let project_id = self.backend.create_project(user_id, None).await?.id;
+        if let Err(error) = self.write_imported_project(&project_id, &bundle).await {
+            // Don't leave a half-imported project behind
+            if let Err(remove_error) = self.backend.remove_project(&project_id).await {
+                warn!("Failed to remove partially imported project: {remove_error}");
+            }
+            return Err(error);
+        }
+
+        info!("Project imported: id = {project_id}, path = {}", path.display());
+        Ok(project_id)
+    }
+
+    async fn write_imported_project(&self, project_id: &str, bundle: &Bundle) -> anyhow::Result<()> {
+        self.backend.update_project_name(project_id, &bundle.name).await?;
+
+        for (sample_id, data) in bundle.samples.iter() {
+            self.backend
+                .add_project_sample(project_id, data, &sample_id.to_string())
+                .await?;
+        }
+
+        self.backend
+            .update_project_file(project_id, &write_project(&bundle.project)?)
+            .await
+            .context("Updating project file")?;
+
+        Ok(())
+    }
+
     async fn write_project_file(&mut self, project_id: &str, project: Project) -> anyhow::Result<()> {
-        let data = project.write_to_bytes()?;
+        let data = write_project(&project)?;
         self.backend
             .update_project_file(project_id, &data)
             .await
@@ -171,7 +235,7 @@ impl ProjectStore {
             .read_project_file(project_id)
             .await
             .context("Get project file")?;
-        let project = Project::parse_from_bytes(&project_data).context("Parse project data")?;
+        let project = read_project(&project_data)?;
 
         Ok((project, project_info))
     }
@@ -316,8 +380,14 @@ mod tests {
             anyhow::bail!("Unexpected read_project call")
         }
 
-        async fn create_project(&self, _user_id: &str, _project_id: Option<String>) -> anyhow::Result<DbProject> {
-            anyhow::bail!("Unexpected create_project call")
+        async fn create_project(&self, user_id: &str, _project_id: Option<String>) -> anyhow::Result<DbProject> {
+            Ok(DbProject {
+                id: "created-project-id".to_string(),
+                name: String::new(),
+                user_id: user_id.to_string(),
+                created: chrono::Utc::now(),
+                updated: chrono::Utc::now(),
+            })
         }
 
         async fn update_project_name(&self, _project_id: &str, _name: &str) -> anyhow::Result<DbProject> {
@@ -443,4 +513,70 @@ mod tests {
             ["project-id"]
         );
     }
+
+    #[tokio::test]
+    async fn imported_bundles_get_new_ids_and_keep_their_samples() {
+        let root_directory = tempfile::tempdir().unwrap();
+        let backend = crate::backend::create_filesystem_backend(&root_directory.path().join("projects"));
+        let store = ProjectStore::new(
+            root_directory.path(),
+            backend.clone(),
+            Arc::new(RecordingBackend::default()),
+        );
+
+        let mut project = Project::empty().with_songs(2, 2);
+        project.songs[0].sample = Some(crate::bloop::Sample {
+            id: 42,
+            ..Default::default()
+        })
+        .into();
+
+        let project_id = backend.create_project("", None).await.unwrap().id;
+        backend.update_project_name(&project_id, "Gig").await.unwrap();
+        backend.add_project_sample(&project_id, &[1, 2, 3], "42").await.unwrap();
+        backend
+            .update_project_file(&project_id, &write_project(&project).unwrap())
+            .await
+            .unwrap();
+
+        let bundle_path = root_directory.path().join("gig.bloop");
+        store.export_bundle(&project_id, &bundle_path).await.unwrap();
+        let imported_id = store.import_bundle(&bundle_path, "").await.unwrap();
+        assert_ne!(imported_id, project_id);
+
+        let (imported, imported_info) = store.read_project_file(&imported_id).await.unwrap();
+        assert_eq!(imported_info.name, "Gig");
+        assert_eq!(imported.songs.len(), 2);
+        assert!(imported
+            .songs
+            .iter()
+            .all(|song| project.song_with_id(song.id).is_none()));
+        assert_eq!(imported.songs[0].sample.id, 42);
+        assert_eq!(backend.read_sample(&imported_id, "42").await.unwrap(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn failed_imports_remove_the_created_project() {
+        let root_directory = tempfile::tempdir().unwrap();
+        let bundle_path = root_directory.path().join("gig.bloop");
+        let bundle = Bundle {
+            name: "Gig".to_string(),
+            project: Project::empty().with_songs(1, 1),
+            samples: vec![],
+        };
+        write_bundle(&bundle, &bundle_path).await.unwrap();
+
+        let local_backend = Arc::new(RecordingBackend::default());
+        let store = ProjectStore::new(
+            root_directory.path(),
+            local_backend.clone(),
+            Arc::new(RecordingBackend::default()),
+        );
+
+        assert!(store.import_bundle(&bundle_path, "").await.is_err());
+        assert_eq!(
+            local_backend.removed_projects.lock().unwrap().as_slice(),
+            ["created-project-id"]
+        );
+    }
 }
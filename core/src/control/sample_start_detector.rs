@@ -0,0 +1,60 @@
+use crate::{analysis::find_first_sound, model::ID, samples::SamplesCache};
+use anyhow::anyhow;
+use log::info;
+use std::{collections::HashSet, thread::spawn};
+use tokio::sync::mpsc;
+
+pub struct SampleStartResult {
+    pub sample_id: ID,
+    pub start_offset: anyhow::Result<i64>,
+}
+
+/// Finds where samples start on their own threads, sending results back to the main controller.
+pub struct SampleStartDetector {
+    result_tx: mpsc::Sender<SampleStartResult>,
+    samples_being_analysed: HashSet<ID>,
+}
+
+impl SampleStartDetector {
+    pub fn new(result_tx: mpsc::Sender<SampleStartResult>) -> Self {
+        Self {
+            result_tx,
+            samples_being_analysed: HashSet::new(),
+        }
+    }
+
+    pub fn detect(&mut self, sample_id: ID, samples_cache: &SamplesCache) -> anyhow::Result<()> {
+        if self.samples_being_analysed.contains(&sample_id) {
+            return Ok(());
+        }
+
+        let sample = samples_cache
+            .get_sample(sample_id)
+            .ok_or_else(|| anyhow!("Couldn't find sample with ID: {sample_id}"))?;
+
+        if !sample.is_cached() {
+            return Err(anyhow!("Sample is not cached: {sample_id}"));
+        }
+
+        self.samples_being_analysed.insert(sample_id);
+
+        let result_tx = self.result_tx.clone();
+        let sample_path = sample.get_path().to_path_buf();
+
+        info!("Finding the start of sample: {sample_id}");
+
+        spawn(move || {
+            let start_offset = find_first_sound(&sample_path);
+            let _ = result_tx.blocking_send(SampleStartResult {
+                sample_id,
+                start_offset,
+            });
+        });
+
+        Ok(())
+    }
+
+    pub fn complete(&mut self, sample_id: ID) {
+        self.samples_being_analysed.remove(&sample_id);
+    }
+}
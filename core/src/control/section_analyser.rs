@@ -0,0 +1,72 @@
+use crate::{
+    analysis::{analyse_structure_from_file, Structure},
+    bloop::Song,
+    model::ID,
+    samples::SamplesCache,
+};
+use anyhow::anyhow;
+use log::info;
+use std::{collections::HashSet, thread::spawn};
+use tokio::sync::mpsc;
+
+pub struct SectionAnalysisResult {
+    pub song_id: ID,
+    pub structure: anyhow::Result<Structure>,
+}
+
+/// Suggests sections for songs from the structure of their samples, analysing them
+/// on their own threads and sending results back to the main controller.
+pub struct SectionAnalyser {
+    result_tx: mpsc::Sender<SectionAnalysisResult>,
+    songs_being_analysed: HashSet<ID>,
+}
+
+impl SectionAnalyser {
+    pub fn new(result_tx: mpsc::Sender<SectionAnalysisResult>) -> Self {
+        Self {
+            result_tx,
+            songs_being_analysed: HashSet::new(),
+        }
+    }
+
+    pub fn suggest_sections(&mut self, song: &Song, samples_cache: &SamplesCache) -> anyhow::Result<()> {
+        let song_id = song.id;
+        if self.songs_being_analysed.contains(&song_id) {
+            return Ok(());
+        }
+
+        let sample_id = song
+            .sample
+            .as_ref()
+            .map(|sample| sample.id)
+            .ok_or_else(|| anyhow!("Song has no sample: {song_id}"))?;
+
+        let sample = samples_cache
+            .get_sample(sample_id)
+            .ok_or_else(|| anyhow!("Couldn't find sample with ID: {sample_id}"))?;
+
+        if !sample.is_cached() {
+            return Err(anyhow!("Sample is not cached: {sample_id}"));
+        }
+
+        self.songs_being_analysed.insert(song_id);
+
+        let result_tx = self.result_tx.clone();
+        let sample_path = sample.get_path().to_path_buf();
+        let bpm = song.tempo.bpm;
+        let start_offset = song.sample.start_offset;
+
+        info!("Suggesting sections for song: {song_id}");
+
+        spawn(move || {
+            let structure = analyse_structure_from_file(&sample_path, bpm, start_offset);
+            let _ = result_tx.blocking_send(SectionAnalysisResult { song_id, structure });
+        });
+
+        Ok(())
+    }
+
+    pub fn complete(&mut self, song_id: ID) {
+        self.songs_being_analysed.remove(&song_id);
+    }
+}
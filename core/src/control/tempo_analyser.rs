@@ -0,0 +1,132 @@
+use crate::{
+    analysis::{estimate_tempo_from_file, TempoEstimate},
+    model::{Tempo, ID},
+    samples::SamplesCache,
+};
+use anyhow::anyhow;
+use log::info;
+use std::{collections::HashMap, thread::spawn};
+use tokio::sync::mpsc;
+
+/// Estimates below this confidence aren't applied unless the user asked for them.
+const MIN_CONFIDENCE_TO_APPLY: f64 = 0.25;
+
+/// Whether an estimate should replace the tempo of the sample it came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApplyTempo {
+    Never,
+    IfConfident,
+    Always,
+}
+
+impl ApplyTempo {
+    pub fn accepts(&self, estimate: &TempoEstimate) -> bool {
+        match self {
+            ApplyTempo::Never => false,
+            ApplyTempo::IfConfident => estimate.confidence >= MIN_CONFIDENCE_TO_APPLY,
+            ApplyTempo::Always => true,
+        }
+    }
+}
+
+pub struct TempoAnalysisResult {
+    pub sample_id: ID,
+    pub estimate: anyhow::Result<Option<TempoEstimate>>,
+}
+
+/// An analysis that's running, and the tempo of the sample when it was asked for.
+struct PendingAnalysis {
+    apply: ApplyTempo,
+    tempo: Option<Tempo>,
+}
+
+/// Analyses samples on their own threads, sending results back to the main controller.
+pub struct TempoAnalyser {
+    result_tx: mpsc::Sender<TempoAnalysisResult>,
+    samples_being_analysed: HashMap<ID, PendingAnalysis>,
+}
+
+impl TempoAnalyser {
+    pub fn new(result_tx: mpsc::Sender<TempoAnalysisResult>) -> Self {
+        Self {
+            result_tx,
+            samples_being_analysed: HashMap::new(),
+        }
+    }
+
+    /// Analyse a sample whose tempo is currently `tempo`. The result is only applied
+    /// if the tempo is still the same when the analysis finishes.
+    pub fn analyse(
+        &mut self,
+        sample_id: ID,
+        tempo: Option<Tempo>,
+        samples_cache: &SamplesCache,
+        apply: ApplyTempo,
+    ) -> anyhow::Result<()> {
+        // Asking again while the analysis runs can only make the result more likely to be used
+        if let Some(pending) = self.samples_being_analysed.get_mut(&sample_id) {
+            if apply > pending.apply {
+                *pending = PendingAnalysis { apply, tempo };
+            }
+            return Ok(());
+        }
+
+        let sample = samples_cache
+            .get_sample(sample_id)
+            .ok_or_else(|| anyhow!("Couldn't find sample with ID: {sample_id}"))?;
+
+        if !sample.is_cached() {
+            return Err(anyhow!("Sample is not cached: {sample_id}"));
+        }
+
+        self.samples_being_analysed
+            .insert(sample_id, PendingAnalysis { apply, tempo });
+
+        let result_tx = self.result_tx.clone();
+        let sample_path = sample.get_path().to_path_buf();
+
+        info!("Analysing tempo of sample: {sample_id}");
+
+        spawn(move || {
+            let estimate = estimate_tempo_from_file(&sample_path);
+            let _ = result_tx.blocking_send(TempoAnalysisResult { sample_id, estimate });
+        });
+
+        Ok(())
+    }
+
+    /// Mark the analysis of a sample as finished, returning whether its result should
+    /// be used. A tempo set by hand while the analysis ran is never replaced.
+    pub fn complete(&mut self, sample_id: ID, tempo: Option<&Tempo>) -> ApplyTempo {
+        match self.samples_being_analysed.remove(&sample_id) {
+            Some(pending) if pending.tempo.as_ref() == tempo => pending.apply,
+            _ => ApplyTempo::Never,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tempos_changed_during_analysis_are_kept() {
+        let (result_tx, _result_rx) = mpsc::channel(1);
+        let mut analyser = TempoAnalyser::new(result_tx);
+        let pending = || PendingAnalysis {
+            apply: ApplyTempo::IfConfident,
+            tempo: None,
+        };
+
+        analyser.samples_being_analysed.insert(1, pending());
+        assert_eq!(analyser.complete(1, None), ApplyTempo::IfConfident);
+
+        analyser.samples_being_analysed.insert(1, pending());
+        assert_eq!(
+            analyser.complete(1, Some(&Tempo::new_with_bpm(100.0))),
+            ApplyTempo::Never
+        );
+
+        assert_eq!(analyser.complete(2, None), ApplyTempo::Never);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::bloop::{Project, UndoStatus};
+use std::collections::VecDeque;
+
+const MAX_UNDO_STEPS: usize = 50;
+
+/// Earlier and later states of the project, for undoing and redoing edits.
+pub struct UndoHistory {
+    undo: VecDeque<Project>,
+    redo: Vec<Project>,
+    max_steps: usize,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            max_steps: MAX_UNDO_STEPS,
+        }
+    }
+}
+
+impl UndoHistory {
+    #[cfg(test)]
+    fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Remember the project from before an edit. The oldest state is forgotten once
+    /// the history is full, and anything that was undone can no longer be redone.
+    pub fn record(&mut self, previous: Project) {
+        if self.undo.len() == self.max_steps {
+            self.undo.pop_front();
+        }
+
+        self.undo.push_back(previous);
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, current: Project) -> Option<Project> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    pub fn redo(&mut self, current: Project) -> Option<Project> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        Some(next)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    pub fn status(&self) -> UndoStatus {
+        UndoStatus {
+            can_undo: !self.undo.is_empty(),
+            can_redo: !self.redo.is_empty(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(num_songs: usize) -> Project {
+        Project::empty().with_songs(num_songs, 1)
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = UndoHistory::default();
+        assert_eq!(history.status(), UndoStatus::default());
+
+        history.record(project(1));
+        let current = project(2);
+
+        let undone = history.undo(current.clone()).unwrap();
+        assert_eq!(undone.songs.len(), 1);
+        assert!(!history.status().can_undo);
+        assert!(history.status().can_redo);
+
+        let redone = history.redo(undone).unwrap();
+        assert_eq!(redone, current);
+        assert!(history.status().can_undo);
+        assert!(!history.status().can_redo);
+    }
+
+    #[test]
+    fn nothing_to_undo_or_redo() {
+        let mut history = UndoHistory::default();
+        assert!(history.undo(project(1)).is_none());
+        assert!(history.redo(project(1)).is_none());
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let mut history = UndoHistory::default();
+        history.record(project(1));
+        history.undo(project(2)).unwrap();
+
+        history.record(project(1));
+        assert!(!history.status().can_redo);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = UndoHistory::default().with_max_steps(2);
+        for num_songs in 1..=3 {
+            history.record(project(num_songs));
+        }
+
+        let mut current = project(4);
+        let mut undone = vec![];
+        while let Some(previous) = history.undo(current) {
+            undone.push(previous.songs.len());
+            current = previous;
+        }
+
+        assert_eq!(undone, vec![3, 2]);
+    }
+
+    #[test]
+    fn clear_forgets_everything() {
+        let mut history = UndoHistory::default();
+        history.record(project(1));
+        history.undo(project(2)).unwrap();
+        history.record(project(1));
+
+        history.clear();
+        assert_eq!(history.status(), UndoStatus::default());
+    }
+}
@@ -1,20 +1,50 @@
 use crate::{
-    bloop::{Response, WaveformAlgorithm, WaveformResponse},
-    model::ID,
+    bloop::{Response, WaveformAlgorithm, WaveformCacheFile, WaveformData, WaveformRangeRequest, WaveformResponse},
+    model::{Project, ID},
     samples::SamplesCache,
-    waveform::{generate_waveform_from_file, Options},
+    waveform::{content_hash, generate_waveform_from_file, read_cache_file, write_cache_file, Options},
 };
 use anyhow::anyhow;
-use log::info;
-use std::{collections::HashSet, sync::mpsc, thread::spawn};
+use log::{info, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+    thread::spawn,
+    time::SystemTime,
+};
 use tokio::sync::broadcast;
 
+/// Frames per peak at each level of detail, each a quarter of the one after.
+const LEVELS: [i32; 4] = [128, 512, 2048, 8192];
+/// Whole waveforms only include levels at least this coarse. Finer levels are
+/// requested a range at a time. Clients draw the overview from the 512 level.
+const OVERVIEW_MIN_LENGTH: i32 = 512;
+/// Bump when `LEVELS` or how peaks are made changes, so older caches are regenerated.
+const CACHE_VERSION: u32 = 2;
+
+/// What's being fetched: a whole waveform, or part of one.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct WaveformKey {
+    sample_id: ID,
+    range: Option<(i32, i64, i64)>,
+}
+
+/// A waveform that's been loaded, along with the size and modification time of
+/// the audio it was made from.
+struct LoadedWaveform {
+    file_stamp: (u64, Option<SystemTime>),
+    waveform_data: Arc<WaveformData>,
+}
+
+type LoadedWaveforms = Arc<Mutex<HashMap<ID, LoadedWaveform>>>;
+
 pub struct WaveformStore {
     response_tx: broadcast::Sender<Response>,
-    samples_being_generated: HashSet<ID>,
-    sample_rate: usize,
-    complete_channel_rx: mpsc::Receiver<ID>,
-    complete_channel_tx: mpsc::Sender<ID>,
+    loaded_waveforms: LoadedWaveforms,
+    waveforms_being_fetched: HashSet<WaveformKey>,
+    complete_channel_rx: mpsc::Receiver<WaveformKey>,
+    complete_channel_tx: mpsc::Sender<WaveformKey>,
 }
 
 impl WaveformStore {
@@ -23,22 +53,59 @@ impl WaveformStore {
 
         Self {
             response_tx,
-            samples_being_generated: HashSet::new(),
-            sample_rate: 44_100,
+            loaded_waveforms: Arc::new(Mutex::new(HashMap::new())),
+            waveforms_being_fetched: HashSet::new(),
             complete_channel_rx,
             complete_channel_tx,
         }
     }
 
+    /// Forget the waveforms of samples that aren't in the project, so removing or
+    /// replacing samples and loading other projects doesn't keep them in memory.
+    /// They're read from the cache file again if they're needed.
+    pub fn retain_samples(&mut self, project: &Project) {
+        let sample_ids: HashSet<ID> = project
+            .songs
+            .iter()
+            .filter_map(|song| song.sample.as_ref().map(|sample| sample.id))
+            .collect();
+
+        self.loaded_waveforms
+            .lock()
+            .unwrap()
+            .retain(|sample_id, _| sample_ids.contains(sample_id));
+    }
+
     pub fn get_waveform(&mut self, sample_id: ID, samples_cache: &SamplesCache) -> anyhow::Result<()> {
-        while let Ok(completed_id) = self.complete_channel_rx.try_recv() {
-            self.samples_being_generated.remove(&completed_id);
+        self.fetch(WaveformKey { sample_id, range: None }, samples_cache)
+    }
+
+    pub fn get_waveform_range(
+        &mut self,
+        request: &WaveformRangeRequest,
+        samples_cache: &SamplesCache,
+    ) -> anyhow::Result<()> {
+        if request.start < 0 || request.end <= request.start {
+            return Err(anyhow!("Invalid waveform range: {}..{}", request.start, request.end));
         }
 
-        if self.samples_being_generated.contains(&sample_id) {
+        let key = WaveformKey {
+            sample_id: request.sample_id,
+            range: Some((request.length, request.start, request.end)),
+        };
+        self.fetch(key, samples_cache)
+    }
+
+    fn fetch(&mut self, key: WaveformKey, samples_cache: &SamplesCache) -> anyhow::Result<()> {
+        while let Ok(completed_key) = self.complete_channel_rx.try_recv() {
+            self.waveforms_being_fetched.remove(&completed_key);
+        }
+
+        if self.waveforms_being_fetched.contains(&key) {
             return Ok(());
         }
 
+        let sample_id = key.sample_id;
         let sample = match samples_cache.get_sample(sample_id) {
             Some(sample) => sample,
             None => return Err(anyhow!("Couldn't find sample with ID: {sample_id}")),
@@ -48,47 +115,201 @@ impl WaveformStore {
             return Err(anyhow!("Sample is not cached: {sample_id}"));
         }
 
-        self.samples_being_generated.insert(sample_id);
+        self.waveforms_being_fetched.insert(key);
 
         let tx = self.response_tx.clone();
         let sample_path = sample.get_path().to_path_buf();
-
-        info!("Generating waveform for sample: {sample_id}");
-
-        let sample_rate = self.sample_rate;
-
+        let waveform_path = sample.get_waveform_path();
         let complete_tx = self.complete_channel_tx.clone();
+        let loaded_waveforms = self.loaded_waveforms.clone();
 
         spawn(move || {
-            let mut lengths = HashSet::new();
-            lengths.insert(512);
-            lengths.insert(8192);
-
-            let mut algorithms = HashSet::new();
-            algorithms.insert(WaveformAlgorithm::MIN);
-            algorithms.insert(WaveformAlgorithm::MAX);
-
-            let options = Options {
-                lengths,
-                algorithms,
-                num_channels: 0,
-                sample_rate,
-            };
+            let response = match load(sample_id, &sample_path, &waveform_path, &loaded_waveforms) {
+                Ok(waveform_data) => {
+                    let (waveform_data, start) = match key.range {
+                        Some((length, start, end)) => waveform_data.range(length, start, end),
+                        None => (
+                            waveform_data.as_ref().clone().without_levels_below(OVERVIEW_MIN_LENGTH),
+                            0,
+                        ),
+                    };
 
-            let response = match generate_waveform_from_file(&sample_path, options) {
-                Ok(waveform_data) => Response::default().with_waveform(&WaveformResponse {
-                    sample_id,
-                    waveform_data: Some(waveform_data).into(),
-                    ..Default::default()
-                }),
+                    Response::default().with_waveform(&WaveformResponse {
+                        sample_id,
+                        waveform_data: Some(waveform_data).into(),
+                        start,
+                        ..Default::default()
+                    })
+                }
                 Err(error) => Response::default().with_error(&error.to_string()),
             };
 
-            tx.send(response).unwrap();
+            let _ = tx.send(response);
 
-            let _ = complete_tx.send(sample_id);
+            let _ = complete_tx.send(key);
         });
 
         Ok(())
     }
 }
+
+/// A sample's waveform from memory, as long as the audio hasn't changed since it
+/// was loaded, or otherwise from the cache file.
+fn load(
+    sample_id: ID,
+    sample_path: &Path,
+    waveform_path: &Path,
+    loaded_waveforms: &Mutex<HashMap<ID, LoadedWaveform>>,
+) -> anyhow::Result<Arc<WaveformData>> {
+    let metadata = std::fs::metadata(sample_path)?;
+    let file_stamp = (metadata.len(), metadata.modified().ok());
+
+    if let Some(loaded) = loaded_waveforms.lock().unwrap().get(&sample_id) {
+        if loaded.file_stamp == file_stamp {
+            return Ok(loaded.waveform_data.clone());
+        }
+    }
+
+    let waveform_data = Arc::new(load_or_generate(sample_id, sample_path, waveform_path)?);
+    loaded_waveforms.lock().unwrap().insert(
+        sample_id,
+        LoadedWaveform {
+            file_stamp,
+            waveform_data: waveform_data.clone(),
+        },
+    );
+
+    Ok(waveform_data)
+}
+
+/// Read a sample's waveform from the cache next to it, or generate and cache it
+/// if it's missing or was made from different audio.
+fn load_or_generate(sample_id: ID, sample_path: &Path, waveform_path: &Path) -> anyhow::Result<WaveformData> {
+    let content_hash = content_hash(sample_path)?;
+
+    if let Ok(cache) = read_cache_file(waveform_path) {
+        if cache.sample_id == sample_id && cache.content_hash == content_hash && cache.version == CACHE_VERSION {
+            return Ok(cache.waveform_data.unwrap_or_default());
+        }
+    }
+
+    info!("Generating waveform for sample: {sample_id}");
+
+    let options = Options {
+        lengths: LEVELS.iter().copied().collect(),
+        algorithms: [WaveformAlgorithm::MIN, WaveformAlgorithm::MAX]
+            .iter()
+            .copied()
+            .collect(),
+        num_channels: 0,
+    };
+
+    let waveform_data = generate_waveform_from_file(sample_path, options)?;
+
+    let cache = WaveformCacheFile {
+        sample_id,
+        content_hash,
+        version: CACHE_VERSION,
+        waveform_data: Some(waveform_data.clone()).into(),
+        ..Default::default()
+    };
+
+    if let Err(error) = write_cache_file(waveform_path, &cache) {
+        warn!("Unable to cache waveform for sample {sample_id}: {error}");
+    }
+
+    Ok(waveform_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_wav(path: &Path, value: i16, frame_count: usize) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for _ in 0..frame_count {
+            writer.write_sample(value).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn waveforms_are_cached_until_the_audio_changes() {
+        let directory = tempfile::tempdir().unwrap();
+        let sample_path = directory.path().join("1.wav");
+        let waveform_path = directory.path().join("1.waveform");
+
+        write_wav(&sample_path, 1000, 48_000);
+        let waveform = load_or_generate(1, &sample_path, &waveform_path).unwrap();
+        assert_eq!(waveform.sample_rate, 48_000);
+        assert_eq!(
+            read_cache_file(&waveform_path).unwrap().waveform_data.unwrap(),
+            waveform
+        );
+        assert_eq!(load_or_generate(1, &sample_path, &waveform_path).unwrap(), waveform);
+
+        write_wav(&sample_path, 2000, 48_000);
+        assert_ne!(load_or_generate(1, &sample_path, &waveform_path).unwrap(), waveform);
+    }
+
+    #[test]
+    fn loaded_waveforms_are_kept_until_the_audio_changes() {
+        let directory = tempfile::tempdir().unwrap();
+        let sample_path = directory.path().join("1.wav");
+        let waveform_path = directory.path().join("1.waveform");
+        let loaded_waveforms = Mutex::new(HashMap::new());
+
+        write_wav(&sample_path, 1000, 48_000);
+        let waveform = load(1, &sample_path, &waveform_path, &loaded_waveforms).unwrap();
+
+        // Without the cache file, only the copy in memory can be used
+        std::fs::remove_file(&waveform_path).unwrap();
+        let loaded = load(1, &sample_path, &waveform_path, &loaded_waveforms).unwrap();
+        assert!(Arc::ptr_eq(&loaded, &waveform));
+        assert!(!waveform_path.exists());
+
+        write_wav(&sample_path, 2000, 24_000);
+        let loaded = load(1, &sample_path, &waveform_path, &loaded_waveforms).unwrap();
+        assert_ne!(loaded, waveform);
+        assert!(waveform_path.exists());
+    }
+
+    #[test]
+    fn waveforms_of_removed_samples_are_reloaded_from_the_cache_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let sample_path = directory.path().join("1.wav");
+        let waveform_path = directory.path().join("1.waveform");
+        let (response_tx, _) = broadcast::channel(1);
+        let mut store = WaveformStore::new(response_tx);
+
+        write_wav(&sample_path, 1000, 48_000);
+        let waveform = load(1, &sample_path, &waveform_path, &store.loaded_waveforms).unwrap();
+        let cache_modified = std::fs::metadata(&waveform_path).unwrap().modified().unwrap();
+
+        let mut project = Project::empty().with_songs(1, 1);
+        project.songs[0].sample = Some(crate::bloop::Sample {
+            id: 1,
+            ..Default::default()
+        })
+        .into();
+        store.retain_samples(&project);
+        assert!(store.loaded_waveforms.lock().unwrap().contains_key(&1));
+
+        store.retain_samples(&Project::empty());
+        assert!(store.loaded_waveforms.lock().unwrap().is_empty());
+
+        let loaded = load(1, &sample_path, &waveform_path, &store.loaded_waveforms).unwrap();
+        assert!(!Arc::ptr_eq(&loaded, &waveform));
+        assert_eq!(loaded, waveform);
+        assert_eq!(
+            std::fs::metadata(&waveform_path).unwrap().modified().unwrap(),
+            cache_modified
+        );
+    }
+}
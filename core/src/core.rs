@@ -1,4 +1,4 @@
-use std::thread;
+use std::{sync::Arc, thread};
 
 use tokio::{
     join,
@@ -9,7 +9,8 @@ use crate::{
     bloop::{Request, Response},
     config::AppConfig,
     control::run_main_controller,
-    network::run_server,
+    network::{run_server, PairingStore},
+    preferences::{default_osc_preferences, read_preferences},
 };
 
 pub fn run_core(
@@ -19,10 +20,17 @@ pub fn run_core(
     app_config: AppConfig,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
+        let osc_preferences = read_preferences(&app_config.root_directory)
+            .ok()
+            .and_then(|preferences| preferences.osc.into_option())
+            .unwrap_or_else(default_osc_preferences);
+
+        let pairing = Arc::new(PairingStore::new(&app_config.root_directory));
+
         let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
         runtime.block_on(async {
-            let control = run_main_controller(request_rx, response_tx.clone(), app_config);
-            let network = run_server(request_tx, response_tx.clone());
+            let control = run_main_controller(request_rx, response_tx.clone(), app_config, pairing.clone());
+            let network = run_server(request_tx, response_tx.clone(), osc_preferences, pairing);
             join!(control, network);
         });
     })
@@ -2,6 +2,7 @@ include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
 
 use std::fs;
 
+mod analysis;
 mod api;
 mod audio;
 pub mod backend;
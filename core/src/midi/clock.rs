@@ -0,0 +1,287 @@
+use crate::audio::TransportPosition;
+use std::time::{Duration, Instant};
+
+pub const TIMING_CLOCK: u8 = 0xF8;
+pub const START: u8 = 0xFA;
+pub const CONTINUE: u8 = 0xFB;
+pub const STOP: u8 = 0xFC;
+pub const SONG_POSITION_POINTER: u8 = 0xF2;
+
+pub const PULSES_PER_QUARTER_NOTE: f64 = 24.0;
+const PULSES_PER_SIXTEENTH: f64 = 6.0;
+
+/// Drift from the sequencer, in pulses, beyond which the position is treated as a jump.
+const JUMP_THRESHOLD_PULSES: f64 = 3.0;
+
+/// Proportion of the measured drift that is corrected on each update.
+const DRIFT_CORRECTION: f64 = 0.05;
+
+/// The longest the clock thread should wait before checking the transport again.
+pub const MAX_WAIT: Duration = Duration::from_millis(5);
+
+pub fn song_position_pointer(sixteenths: u16) -> [u8; 3] {
+    let sixteenths = sixteenths.min(0x3FFF);
+    [
+        SONG_POSITION_POINTER,
+        (sixteenths & 0x7F) as u8,
+        (sixteenths >> 7) as u8,
+    ]
+}
+
+fn offset_instant(time: Instant, seconds: f64) -> Instant {
+    if seconds >= 0.0 {
+        time + Duration::from_secs_f64(seconds)
+    } else {
+        time.checked_sub(Duration::from_secs_f64(-seconds)).unwrap_or(time)
+    }
+}
+
+fn seconds_between(from: Instant, to: Instant) -> f64 {
+    if to >= from {
+        to.duration_since(from).as_secs_f64()
+    } else {
+        -from.duration_since(to).as_secs_f64()
+    }
+}
+
+/// The sixteenth note to resume from when the clock (re)starts at `pulse`. Positions
+/// just past a sixteenth round down so that playback resumes without a gap.
+fn resume_sixteenth(pulse: f64) -> u16 {
+    (pulse / PULSES_PER_SIXTEENTH - 0.25).ceil().max(0.0) as u16
+}
+
+struct RunningClock {
+    anchor_time: Instant,
+    anchor_pulse: f64,
+    bpm: f64,
+    next_pulse: i64,
+}
+
+impl RunningClock {
+    fn pulses_per_second(&self) -> f64 {
+        self.bpm * PULSES_PER_QUARTER_NOTE / 60.0
+    }
+
+    fn pulse_at(&self, time: Instant) -> f64 {
+        self.anchor_pulse + seconds_between(self.anchor_time, time) * self.pulses_per_second()
+    }
+
+    fn time_of_pulse(&self, pulse: i64) -> Instant {
+        offset_instant(
+            self.anchor_time,
+            (pulse as f64 - self.anchor_pulse) / self.pulses_per_second(),
+        )
+    }
+
+    fn restart_from(&mut self, time: Instant, pulse: f64, sixteenth: u16) {
+        self.anchor_time = time;
+        self.anchor_pulse = pulse;
+        self.next_pulse = sixteenth as i64 * PULSES_PER_SIXTEENTH as i64;
+    }
+}
+
+/// Generates MIDI beat clock messages from the sequencer's transport position.
+///
+/// The clock runs from its own timeline so that pulses are evenly spaced, and is
+/// gently pulled towards the transport position on each update. Large
+/// discontinuities (seeking, section jumps, loops) are announced with a Song
+/// Position Pointer.
+#[derive(Default)]
+pub struct ClockScheduler {
+    running: Option<RunningClock>,
+    latency_seconds: f64,
+}
+
+impl ClockScheduler {
+    /// Positive values delay the clock relative to the audio, negative values send it earlier.
+    pub fn set_latency_ms(&mut self, latency_ms: f64) {
+        self.latency_seconds = latency_ms / 1000.0;
+    }
+
+    /// Returns the messages that are due at `now`.
+    pub fn update(&mut self, position: &TransportPosition, now: Instant) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+
+        if !position.playing || position.bpm <= 0.0 {
+            if self.running.take().is_some() {
+                messages.push(vec![STOP]);
+            }
+            return messages;
+        }
+
+        let target_pulse = position.beat_at(offset_instant(now, -self.latency_seconds)) * PULSES_PER_QUARTER_NOTE;
+
+        let clock = match self.running.as_mut() {
+            Some(clock) => {
+                if clock.bpm != position.bpm {
+                    clock.anchor_pulse = clock.pulse_at(now);
+                    clock.anchor_time = now;
+                    clock.bpm = position.bpm;
+                }
+
+                let drift = target_pulse - clock.pulse_at(now);
+                if drift.abs() > JUMP_THRESHOLD_PULSES {
+                    let sixteenth = resume_sixteenth(target_pulse);
+                    messages.push(song_position_pointer(sixteenth).to_vec());
+                    clock.restart_from(now, target_pulse, sixteenth);
+                } else {
+                    clock.anchor_pulse += drift * DRIFT_CORRECTION;
+                }
+
+                clock
+            }
+            None => {
+                let sixteenth = resume_sixteenth(target_pulse);
+                if sixteenth == 0 {
+                    messages.push(vec![START]);
+                } else {
+                    messages.push(song_position_pointer(sixteenth).to_vec());
+                    messages.push(vec![CONTINUE]);
+                }
+
+                let mut clock = RunningClock {
+                    anchor_time: now,
+                    anchor_pulse: target_pulse,
+                    bpm: position.bpm,
+                    next_pulse: 0,
+                };
+                clock.restart_from(now, target_pulse, sixteenth);
+                self.running.insert(clock)
+            }
+        };
+
+        while clock.time_of_pulse(clock.next_pulse) <= now {
+            messages.push(vec![TIMING_CLOCK]);
+            clock.next_pulse += 1;
+        }
+
+        messages
+    }
+
+    /// The time at which the next clock pulse is due, if the clock is running.
+    pub fn next_pulse_time(&self) -> Option<Instant> {
+        self.running.as_ref().map(|clock| clock.time_of_pulse(clock.next_pulse))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing_at(beat: f64, bpm: f64, captured_at: Instant) -> TransportPosition {
+        TransportPosition {
+            playing: true,
+            beat,
+            bpm,
+            captured_at,
+        }
+    }
+
+    fn count_pulses(messages: &[Vec<u8>]) -> usize {
+        messages.iter().filter(|m| m.as_slice() == [TIMING_CLOCK]).count()
+    }
+
+    #[test]
+    fn song_position_pointer_encodes_14_bit_value() {
+        assert_eq!(song_position_pointer(0), [0xF2, 0, 0]);
+        assert_eq!(song_position_pointer(200), [0xF2, 200 & 0x7F, 1]);
+        assert_eq!(song_position_pointer(u16::MAX), [0xF2, 0x7F, 0x7F]);
+    }
+
+    #[test]
+    fn starting_from_the_top_sends_start() {
+        let now = Instant::now();
+        let mut scheduler = ClockScheduler::default();
+
+        let messages = scheduler.update(&playing_at(0.0, 120.0, now), now);
+
+        assert_eq!(messages, vec![vec![START], vec![TIMING_CLOCK]]);
+    }
+
+    #[test]
+    fn starting_mid_song_sends_song_position_and_continue() {
+        let now = Instant::now();
+        let mut scheduler = ClockScheduler::default();
+
+        let messages = scheduler.update(&playing_at(8.0, 120.0, now), now);
+
+        assert_eq!(messages[0], song_position_pointer(32).to_vec());
+        assert_eq!(messages[1], vec![CONTINUE]);
+    }
+
+    #[test]
+    fn sends_24_pulses_per_beat() {
+        let start = Instant::now();
+        let position = playing_at(0.0, 120.0, start);
+        let mut scheduler = ClockScheduler::default();
+
+        let mut pulses = 0;
+        let mut now = start;
+        while now < start + Duration::from_millis(1000) {
+            pulses += count_pulses(&scheduler.update(&position, now));
+            now += Duration::from_millis(1);
+        }
+
+        // Two beats at 120 bpm
+        assert_eq!(pulses, 48);
+    }
+
+    #[test]
+    fn next_pulse_time_follows_tempo() {
+        let now = Instant::now();
+        let mut scheduler = ClockScheduler::default();
+        scheduler.update(&playing_at(0.0, 60.0, now), now);
+
+        let next = scheduler.next_pulse_time().unwrap();
+        let expected = 1.0 / 24.0;
+        assert!((seconds_between(now, next) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn jump_sends_song_position_pointer() {
+        let start = Instant::now();
+        let mut scheduler = ClockScheduler::default();
+        scheduler.update(&playing_at(0.0, 120.0, start), start);
+
+        let later = start + Duration::from_millis(100);
+        let messages = scheduler.update(&playing_at(16.0, 120.0, later), later);
+
+        assert_eq!(messages[0], song_position_pointer(64).to_vec());
+    }
+
+    #[test]
+    fn small_drift_does_not_send_song_position_pointer() {
+        let start = Instant::now();
+        let mut scheduler = ClockScheduler::default();
+        scheduler.update(&playing_at(0.0, 120.0, start), start);
+
+        let later = start + Duration::from_millis(100);
+        let messages = scheduler.update(&playing_at(0.22, 120.0, later), later);
+
+        assert!(messages.iter().all(|m| m[0] != SONG_POSITION_POINTER));
+    }
+
+    #[test]
+    fn stopping_sends_stop_once() {
+        let now = Instant::now();
+        let mut scheduler = ClockScheduler::default();
+        scheduler.update(&playing_at(0.0, 120.0, now), now);
+
+        let stopped = TransportPosition::stopped();
+        assert_eq!(scheduler.update(&stopped, now), vec![vec![STOP]]);
+        assert!(scheduler.update(&stopped, now).is_empty());
+        assert!(scheduler.next_pulse_time().is_none());
+    }
+
+    #[test]
+    fn positive_latency_delays_the_clock() {
+        let now = Instant::now();
+        let mut scheduler = ClockScheduler::default();
+        scheduler.set_latency_ms(250.0);
+
+        // At 120 bpm, 250 ms is half a beat: the clock should still be before the song position
+        let messages = scheduler.update(&playing_at(4.0, 120.0, now), now);
+
+        assert_eq!(messages[0], song_position_pointer(14).to_vec());
+    }
+}
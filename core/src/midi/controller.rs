@@ -1,50 +1,132 @@
-use super::mappings::{load_mappings, Mapping};
-use crate::bloop::{Action, MidiDevices, MidiPreferences, Response};
-use log::{error, info};
-use midir::{MidiInput, MidiInputConnection};
+use super::clock::{ClockScheduler, MAX_WAIT};
+use super::feedback::{Feedback, FeedbackValues};
+use super::mappings::{learned_mapping, load_mappings, FileMapping, Mapping, UserMappings};
+use super::message::MessageParser;
+use super::sync::ExternalClock;
+use crate::audio::{ExternalTransportEvent, TransportPosition};
+use crate::bloop::{
+    Action, ActionRequest, MidiDevices, MidiMapping, MidiMappings, MidiPreferences, PlaybackState, Project, Response,
+};
+use anyhow::anyhow;
+use log::{error, info, warn};
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use regex::Regex;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio::sync::{broadcast, mpsc};
+use std::thread;
+use std::time::Instant;
+use tokio::sync::{broadcast, broadcast::error::RecvError, mpsc, watch};
 use tokio::time::{self, Duration};
 
 const DEFAULT_ENABLED_DEVICE: &str = "iCON G_Boar";
 const POLL_INTERVAL_SECS: u64 = 2;
 
+/// A message captured while learning, and the port it arrived on.
+struct LearnedMessage {
+    port_name: String,
+    mapping: FileMapping,
+}
+
 struct Context {
-    action_tx: mpsc::Sender<Action>,
+    port_name: String,
+    action_tx: mpsc::Sender<ActionRequest>,
+    sync_tx: mpsc::Sender<ExternalTransportEvent>,
+    external_sync: Arc<AtomicBool>,
+    external_clock: ExternalClock,
+    parser: MessageParser,
+    learning: Arc<Mutex<Option<Action>>>,
+    learn_tx: mpsc::Sender<LearnedMessage>,
 }
 
 struct SharedState {
     enabled_patterns: Vec<String>,
     midi_mappings_dir: PathBuf,
-    action_tx: mpsc::Sender<Action>,
+    action_tx: mpsc::Sender<ActionRequest>,
+    sync_tx: mpsc::Sender<ExternalTransportEvent>,
+    /// Whether incoming clock and transport messages drive playback.
+    external_sync: Arc<AtomicBool>,
+    /// The action waiting to be mapped to the next message from any input.
+    learning: Arc<Mutex<Option<Action>>>,
+    learn_tx: mpsc::Sender<LearnedMessage>,
     /// Active connections, keyed by port name.
     input_connections: Vec<(String, MidiInputConnection<Context>)>,
-    /// All port names visible at the last poll.
-    known_port_names: Vec<String>,
+    /// All input and output port names visible at the last poll.
+    known_devices: MidiDevices,
+    clock_output: Arc<Mutex<ClockOutput>>,
+    feedback_outputs: Vec<FeedbackOutput>,
+    /// The values last sent to the feedback outputs.
+    feedback_values: Option<FeedbackValues>,
+}
+
+/// An output port that receives feedback for a connected input.
+struct FeedbackOutput {
+    input_port: String,
+    feedback: Vec<Feedback>,
+    connection: MidiOutputConnection,
+}
+
+impl FeedbackOutput {
+    fn send(&mut self, previous: Option<&FeedbackValues>, current: &FeedbackValues) {
+        for message in self
+            .feedback
+            .iter()
+            .filter_map(|feedback| feedback.message(previous, current))
+        {
+            if let Err(error) = self.connection.send(&message) {
+                error!("Unable to send MIDI feedback for {}: {error}", self.input_port);
+            }
+        }
+    }
+}
+
+/// Output ports that receive MIDI clock, shared with the clock thread.
+struct ClockOutput {
+    enabled_patterns: Vec<String>,
+    latency_ms: f64,
+    /// Active connections, keyed by port name.
+    connections: Vec<(String, MidiOutputConnection)>,
+}
+
+impl ClockOutput {
+    fn send(&mut self, message: &[u8]) {
+        for (port_name, connection) in self.connections.iter_mut() {
+            if let Err(error) = connection.send(message) {
+                error!("Unable to send MIDI clock to {port_name}: {error}");
+            }
+        }
+    }
 }
 
 #[allow(dead_code)]
 pub struct MidiController {
     shared: Arc<Mutex<SharedState>>,
     poller: tokio::task::JoinHandle<()>,
+    learner: tokio::task::JoinHandle<()>,
+    feedback: tokio::task::JoinHandle<()>,
+    response_tx: broadcast::Sender<Response>,
+    clock_running: Arc<AtomicBool>,
+    clock_thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Drop for MidiController {
     fn drop(&mut self) {
         self.poller.abort();
+        self.learner.abort();
+        self.feedback.abort();
+        self.clock_running.store(false, Ordering::Relaxed);
+        if let Some(clock_thread) = self.clock_thread.take() {
+            let _ = clock_thread.join();
+        }
     }
 }
 
-fn enumerate_port_names() -> Vec<String> {
-    super::devices::get_midi_devices().port_names
+fn enumerate_devices() -> MidiDevices {
+    super::devices::get_midi_devices()
 }
 
-fn try_connect(
-    port_name: &str,
-    midi_mappings_dir: &Path,
-    action_tx: &mpsc::Sender<Action>,
-) -> Option<MidiInputConnection<Context>> {
+fn try_connect(port_name: &str, state: &SharedState) -> Option<MidiInputConnection<Context>> {
+    let midi_mappings_dir = &state.midi_mappings_dir;
     let port_mappings: Vec<Mapping> = load_mappings(midi_mappings_dir)
         .into_iter()
         .filter(|dm| dm.device_regex.is_match(port_name))
@@ -67,13 +149,40 @@ fn try_connect(
     match midi_input.connect(
         port,
         "Bloop Input",
-        move |_timestamp, message, context| {
-            port_mappings.iter().filter(|m| m.matches(message)).for_each(|m| {
-                let _ = context.action_tx.try_send(m.action);
-            });
+        move |_timestamp, data, context| {
+            for message in context.parser.parse(data) {
+                {
+                    let mut learning = context.learning.lock().unwrap();
+                    if let Some(mapping) = learning.and_then(|action| learned_mapping(&message, action)) {
+                        *learning = None;
+                        let _ = context.learn_tx.try_send(LearnedMessage {
+                            port_name: context.port_name.clone(),
+                            mapping,
+                        });
+                        continue;
+                    }
+                }
+
+                if context.external_sync.load(Ordering::Relaxed) {
+                    if let Some(event) = context.external_clock.handle_message(&message, Instant::now()) {
+                        let _ = context.sync_tx.try_send(event);
+                    }
+                }
+
+                port_mappings.iter().filter(|m| m.matches(&message)).for_each(|m| {
+                    let _ = context.action_tx.try_send(m.action_request(&message));
+                });
+            }
         },
         Context {
-            action_tx: action_tx.clone(),
+            port_name: port_name.to_string(),
+            action_tx: state.action_tx.clone(),
+            sync_tx: state.sync_tx.clone(),
+            external_sync: state.external_sync.clone(),
+            external_clock: ExternalClock::default(),
+            parser: MessageParser::default(),
+            learning: state.learning.clone(),
+            learn_tx: state.learn_tx.clone(),
         },
     ) {
         Ok(connection) => {
@@ -87,6 +196,32 @@ fn try_connect(
     }
 }
 
+fn try_connect_output(port_name: &str) -> Option<MidiOutputConnection> {
+    let midi_output = match MidiOutput::new("Bloop") {
+        Ok(output) => output,
+        Err(error) => {
+            error!("Unable to create MIDI output for {port_name}: {error}");
+            return None;
+        }
+    };
+
+    let ports = midi_output.ports();
+    let port = ports
+        .iter()
+        .find(|p| midi_output.port_name(p).ok().as_deref() == Some(port_name))?;
+
+    match midi_output.connect(port, "Bloop Output") {
+        Ok(connection) => {
+            info!("Connected to MIDI output port: {port_name}");
+            Some(connection)
+        }
+        Err(error) => {
+            error!("Unable to connect to MIDI output port {port_name}: {error}");
+            None
+        }
+    }
+}
+
 /// Returns port names from `current_ports` that should have a new connection opened:
 /// they are not already connected and match at least one enabled pattern.
 pub(crate) fn ports_to_connect<'a>(
@@ -124,12 +259,206 @@ fn sync_connections(state: &mut SharedState, current_ports: &[String]) {
     }
     let to_connect = ports_to_connect(current_ports, &connected, &state.enabled_patterns);
     for port_name in to_connect {
-        if let Some(conn) = try_connect(port_name, &state.midi_mappings_dir.clone(), &state.action_tx.clone()) {
+        if let Some(conn) = try_connect(port_name, state) {
             state.input_connections.push((port_name.to_string(), conn));
         }
     }
 
-    state.known_port_names = current_ports.to_vec();
+    state.known_devices.port_names = current_ports.to_vec();
+}
+
+/// Reconnect all inputs so that changes to the mapping files take effect.
+fn reload_mappings(state: &mut SharedState) {
+    state.input_connections.clear();
+    state.feedback_outputs.clear();
+    let current_ports = state.known_devices.port_names.clone();
+    sync_connections(state, &current_ports);
+    sync_feedback_outputs(state);
+}
+
+/// The output port that feedback for `input_port` is sent to: the output with the
+/// same name if there is one, otherwise the first output matching `device_regex`.
+pub(crate) fn feedback_output_port<'a>(
+    input_port: &str,
+    device_regex: &Regex,
+    output_ports: &'a [String],
+) -> Option<&'a str> {
+    output_ports
+        .iter()
+        .find(|name| name.as_str() == input_port)
+        .or_else(|| output_ports.iter().find(|name| device_regex.is_match(name)))
+        .map(|name| name.as_str())
+}
+
+/// Open feedback outputs for connected inputs whose device mappings have feedback,
+/// and close those whose input has gone. New outputs are sent the current state.
+fn sync_feedback_outputs(state: &mut SharedState) {
+    let connected_inputs: Vec<String> = state.input_connections.iter().map(|(n, _)| n.clone()).collect();
+    state
+        .feedback_outputs
+        .retain(|output| connected_inputs.contains(&output.input_port));
+
+    let new_inputs: Vec<&String> = connected_inputs
+        .iter()
+        .filter(|name| !state.feedback_outputs.iter().any(|output| &output.input_port == *name))
+        .collect();
+    if new_inputs.is_empty() {
+        return;
+    }
+
+    let device_mappings = load_mappings(&state.midi_mappings_dir);
+
+    for input_port in new_inputs {
+        let matching: Vec<_> = device_mappings
+            .iter()
+            .filter(|dm| dm.device_regex.is_match(input_port) && !dm.feedback.is_empty())
+            .collect();
+        let Some(first) = matching.first() else {
+            continue;
+        };
+
+        let Some(output_port) =
+            feedback_output_port(input_port, &first.device_regex, &state.known_devices.output_port_names)
+        else {
+            info!("No MIDI output port found for feedback to {input_port}");
+            continue;
+        };
+
+        let Some(connection) = try_connect_output(output_port) else {
+            continue;
+        };
+
+        let mut output = FeedbackOutput {
+            input_port: input_port.clone(),
+            feedback: matching.iter().flat_map(|dm| dm.feedback.clone()).collect(),
+            connection,
+        };
+
+        if let Some(values) = state.feedback_values.as_ref() {
+            output.send(None, values);
+        }
+
+        state.feedback_outputs.push(output);
+    }
+}
+
+/// Send feedback for the latest project and playback state to the connected devices.
+async fn run_feedback(shared: Arc<Mutex<SharedState>>, mut response_rx: broadcast::Receiver<Response>) {
+    let mut project = Project::default();
+    let mut playback_state = PlaybackState::default();
+
+    loop {
+        let response = match response_rx.recv().await {
+            Ok(response) => response,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+
+        if response.project.is_none() && response.playback_state.is_none() {
+            continue;
+        }
+
+        if let Some(new_project) = response.project.as_ref() {
+            project = new_project.clone();
+        }
+
+        if let Some(new_playback_state) = response.playback_state.as_ref() {
+            playback_state = new_playback_state.clone();
+        }
+
+        let values = FeedbackValues::new(&playback_state, &project);
+
+        let mut state = shared.lock().unwrap();
+        let previous = state.feedback_values.replace(values);
+        if previous == Some(values) {
+            continue;
+        }
+
+        for output in state.feedback_outputs.iter_mut() {
+            output.send(previous.as_ref(), &values);
+        }
+    }
+}
+
+fn mappings_response(state: &SharedState) -> Response {
+    let mappings = MidiMappings {
+        mappings: UserMappings::new(&state.midi_mappings_dir).list(),
+        learning: state.learning.lock().unwrap().unwrap_or_default().into(),
+        ..Default::default()
+    };
+    Response::default().with_midi_mappings(&mappings)
+}
+
+/// Save messages captured while learning and apply the new mappings.
+async fn run_learner(
+    shared: Arc<Mutex<SharedState>>,
+    mut learn_rx: mpsc::Receiver<LearnedMessage>,
+    response_tx: broadcast::Sender<Response>,
+) {
+    while let Some(learned) = learn_rx.recv().await {
+        let mut state = shared.lock().unwrap();
+        let store = UserMappings::new(&state.midi_mappings_dir);
+
+        match store.add_learned(&learned.port_name, learned.mapping) {
+            Ok(()) => {
+                info!("Learned MIDI mapping from {}", learned.port_name);
+                reload_mappings(&mut state);
+            }
+            Err(error) => warn!("Unable to save learned MIDI mapping: {error}"),
+        }
+
+        let _ = response_tx.send(mappings_response(&state));
+    }
+}
+
+/// Synchronise the clock output connections with `current_ports`, in the same way as
+/// [`sync_connections`] does for inputs.
+fn sync_clock_outputs(state: &mut SharedState, current_ports: &[String]) {
+    let mut clock_output = state.clock_output.lock().unwrap();
+
+    let enabled_patterns = clock_output.enabled_patterns.clone();
+    clock_output
+        .connections
+        .retain(|(name, _)| current_ports.contains(name) && enabled_patterns.iter().any(|p| name.contains(p.as_str())));
+
+    let connected: Vec<String> = clock_output.connections.iter().map(|(n, _)| n.clone()).collect();
+    for port_name in ports_to_connect(current_ports, &connected, &enabled_patterns) {
+        if let Some(connection) = try_connect_output(port_name) {
+            clock_output.connections.push((port_name.to_string(), connection));
+        }
+    }
+
+    drop(clock_output);
+    state.known_devices.output_port_names = current_ports.to_vec();
+}
+
+/// Send MIDI clock to the clock outputs until `running` is cleared, following the
+/// sequencer's transport position.
+fn run_clock(
+    clock_output: Arc<Mutex<ClockOutput>>,
+    transport_rx: watch::Receiver<TransportPosition>,
+    running: Arc<AtomicBool>,
+) {
+    let mut scheduler = ClockScheduler::default();
+
+    while running.load(Ordering::Relaxed) {
+        let position = *transport_rx.borrow();
+        let now = Instant::now();
+
+        {
+            let mut clock_output = clock_output.lock().unwrap();
+            scheduler.set_latency_ms(clock_output.latency_ms);
+            for message in scheduler.update(&position, now) {
+                clock_output.send(&message);
+            }
+        }
+
+        let wait = scheduler
+            .next_pulse_time()
+            .map_or(MAX_WAIT, |time| time.saturating_duration_since(Instant::now()))
+            .min(MAX_WAIT);
+        thread::sleep(wait);
+    }
 }
 
 fn log_midi_ports(current_ports: &[String]) {
@@ -146,31 +475,32 @@ async fn run_poller(shared: Arc<Mutex<SharedState>>, response_tx: broadcast::Sen
     loop {
         interval.tick().await;
 
-        let current_ports = enumerate_port_names();
+        let current_devices = enumerate_devices();
 
         let changed = {
             let state = shared.lock().unwrap();
-            state.known_port_names != current_ports
+            state.known_devices != current_devices
         };
 
         if changed {
-            log_midi_ports(&current_ports);
+            log_midi_ports(&current_devices.port_names);
             let mut state = shared.lock().unwrap();
-            sync_connections(&mut state, &current_ports);
-            let _ = response_tx.send(Response::default().with_midi_devices(&MidiDevices {
-                port_names: current_ports,
-                ..Default::default()
-            }));
+            sync_connections(&mut state, &current_devices.port_names);
+            sync_clock_outputs(&mut state, &current_devices.output_port_names);
+            sync_feedback_outputs(&mut state);
+            let _ = response_tx.send(Response::default().with_midi_devices(&current_devices));
         }
     }
 }
 
 impl MidiController {
     pub fn new(
-        action_tx: mpsc::Sender<Action>,
+        action_tx: mpsc::Sender<ActionRequest>,
+        sync_tx: mpsc::Sender<ExternalTransportEvent>,
         preferences: MidiPreferences,
         midi_mappings_dir: &Path,
         response_tx: broadcast::Sender<Response>,
+        transport_rx: watch::Receiver<TransportPosition>,
     ) -> Self {
         let enabled_patterns = if preferences.enabled_devices.is_empty() {
             vec![DEFAULT_ENABLED_DEVICE.to_string()]
@@ -178,21 +508,54 @@ impl MidiController {
             preferences.enabled_devices.clone()
         };
 
-        let current_ports = enumerate_port_names();
+        let current_devices = enumerate_devices();
+
+        let clock_output = Arc::new(Mutex::new(ClockOutput {
+            enabled_patterns: preferences.clock_output_devices.clone(),
+            latency_ms: preferences.clock_latency_ms,
+            connections: Vec::new(),
+        }));
+
+        let (learn_tx, learn_rx) = mpsc::channel(8);
 
         let mut state = SharedState {
             enabled_patterns,
             midi_mappings_dir: midi_mappings_dir.to_path_buf(),
             action_tx,
+            sync_tx,
+            external_sync: Arc::new(AtomicBool::new(preferences.sync_to_external_clock)),
+            learning: Arc::new(Mutex::new(None)),
+            learn_tx,
             input_connections: Vec::new(),
-            known_port_names: Vec::new(),
+            known_devices: MidiDevices::default(),
+            clock_output: clock_output.clone(),
+            feedback_outputs: Vec::new(),
+            feedback_values: None,
         };
-        sync_connections(&mut state, &current_ports);
+        sync_connections(&mut state, &current_devices.port_names);
+        sync_clock_outputs(&mut state, &current_devices.output_port_names);
+        sync_feedback_outputs(&mut state);
 
         let shared = Arc::new(Mutex::new(state));
-        let poller = tokio::spawn(run_poller(shared.clone(), response_tx));
+        let poller = tokio::spawn(run_poller(shared.clone(), response_tx.clone()));
+        let learner = tokio::spawn(run_learner(shared.clone(), learn_rx, response_tx.clone()));
+        let feedback = tokio::spawn(run_feedback(shared.clone(), response_tx.subscribe()));
+
+        let clock_running = Arc::new(AtomicBool::new(true));
+        let clock_thread = {
+            let clock_running = clock_running.clone();
+            thread::spawn(move || run_clock(clock_output, transport_rx, clock_running))
+        };
 
-        Self { shared, poller }
+        Self {
+            shared,
+            poller,
+            learner,
+            feedback,
+            response_tx,
+            clock_running,
+            clock_thread: Some(clock_thread),
+        }
     }
 
     /// Update the enabled device patterns and immediately re-evaluate connections
@@ -206,8 +569,60 @@ impl MidiController {
 
         let mut state = self.shared.lock().unwrap();
         state.enabled_patterns = enabled_patterns;
-        let current_ports = state.known_port_names.clone();
-        sync_connections(&mut state, &current_ports);
+        state
+            .external_sync
+            .store(preferences.sync_to_external_clock, Ordering::Relaxed);
+        {
+            let mut clock_output = state.clock_output.lock().unwrap();
+            clock_output.enabled_patterns = preferences.clock_output_devices.clone();
+            clock_output.latency_ms = preferences.clock_latency_ms;
+        }
+        let current_devices = state.known_devices.clone();
+        sync_connections(&mut state, &current_devices.port_names);
+        sync_clock_outputs(&mut state, &current_devices.output_port_names);
+        sync_feedback_outputs(&mut state);
+    }
+
+    /// The user mappings and the action currently being learned, if any.
+    pub fn mappings(&self) -> MidiMappings {
+        let state = self.shared.lock().unwrap();
+        mappings_response(&state).midi_mappings.unwrap_or_default()
+    }
+
+    /// Map `action` to the next message received from any enabled input.
+    pub fn learn(&self, action: Action) -> anyhow::Result<()> {
+        if action == Action::ACTION_UNKNOWN {
+            return Err(anyhow!("No action to learn"));
+        }
+
+        let state = self.shared.lock().unwrap();
+        *state.learning.lock().unwrap() = Some(action);
+        info!("Learning MIDI mapping for {action:?}");
+        let _ = self.response_tx.send(mappings_response(&state));
+        Ok(())
+    }
+
+    pub fn cancel_learn(&self) -> anyhow::Result<()> {
+        let state = self.shared.lock().unwrap();
+        *state.learning.lock().unwrap() = None;
+        let _ = self.response_tx.send(mappings_response(&state));
+        Ok(())
+    }
+
+    pub fn update_mapping(&self, mapping: &MidiMapping) -> anyhow::Result<()> {
+        self.edit_mappings(|store| store.update(mapping))
+    }
+
+    pub fn remove_mapping(&self, mapping: &MidiMapping) -> anyhow::Result<()> {
+        self.edit_mappings(|store| store.remove(mapping))
+    }
+
+    fn edit_mappings(&self, edit: impl FnOnce(&UserMappings) -> anyhow::Result<()>) -> anyhow::Result<()> {
+        let mut state = self.shared.lock().unwrap();
+        edit(&UserMappings::new(&state.midi_mappings_dir))?;
+        reload_mappings(&mut state);
+        let _ = self.response_tx.send(mappings_response(&state));
+        Ok(())
     }
 }
 
@@ -315,4 +730,27 @@ mod tests {
 
         assert!(retained);
     }
+
+    #[test]
+    fn feedback_output_prefers_the_port_with_the_same_name() {
+        let outputs = vec!["iCON G_Boar Out".to_string(), "iCON G_Boar V1.03".to_string()];
+        let regex = Regex::new("iCON G_Boar").unwrap();
+
+        assert_eq!(
+            feedback_output_port("iCON G_Boar V1.03", &regex, &outputs),
+            Some("iCON G_Boar V1.03")
+        );
+    }
+
+    #[test]
+    fn feedback_output_falls_back_to_the_device_regex() {
+        let outputs = vec!["Other".to_string(), "iCON G_Boar Out".to_string()];
+        let regex = Regex::new("iCON G_Boar").unwrap();
+
+        assert_eq!(
+            feedback_output_port("iCON G_Boar In", &regex, &outputs),
+            Some("iCON G_Boar Out")
+        );
+        assert_eq!(feedback_output_port("iCON G_Boar In", &regex, &outputs[..1]), None);
+    }
 }
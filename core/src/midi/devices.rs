@@ -1,13 +1,21 @@
 use crate::bloop::MidiDevices;
 use log::warn;
-use midir::MidiInput;
+use midir::{MidiInput, MidiOutput};
 
 pub fn get_midi_devices() -> MidiDevices {
+    MidiDevices {
+        port_names: get_input_port_names(),
+        output_port_names: get_output_port_names(),
+        ..Default::default()
+    }
+}
+
+fn get_input_port_names() -> Vec<String> {
     let midi_input = match MidiInput::new("Bloop") {
         Ok(input) => input,
         Err(error) => {
             warn!("Unable to enumerate MIDI ports: {error}");
-            return MidiDevices::default();
+            return Vec::new();
         }
     };
 
@@ -21,10 +29,29 @@ pub fn get_midi_devices() -> MidiDevices {
         }
     }
 
-    MidiDevices {
-        port_names,
-        ..Default::default()
+    port_names
+}
+
+fn get_output_port_names() -> Vec<String> {
+    let midi_output = match MidiOutput::new("Bloop") {
+        Ok(output) => output,
+        Err(error) => {
+            warn!("Unable to enumerate MIDI output ports: {error}");
+            return Vec::new();
+        }
+    };
+
+    let ports = midi_output.ports();
+    let mut port_names = Vec::new();
+
+    for port in &ports {
+        match midi_output.port_name(port) {
+            Ok(name) => port_names.push(name),
+            Err(error) => warn!("Unable to get MIDI output port name: {error}"),
+        }
     }
+
+    port_names
 }
 
 #[cfg(test)]
@@ -36,5 +63,6 @@ mod tests {
         let devices = get_midi_devices();
         // On CI there may be no MIDI ports; we just verify the struct is well-formed.
         assert!(devices.port_names.iter().all(|n| !n.is_empty()));
+        assert!(devices.output_port_names.iter().all(|n| !n.is_empty()));
     }
 }
@@ -1,9 +1,11 @@
+use anyhow::anyhow;
 use log::info;
 use std::path::Path;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, watch};
 
 use crate::{
-    bloop::{MidiPreferences, Response},
+    audio::{ExternalTransportEvent, TransportPosition},
+    bloop::{ActionRequest, MidiMapping, MidiMappings, MidiPreferences, Response},
     model::Action,
 };
 
@@ -11,16 +13,38 @@ pub struct MidiController {}
 
 impl MidiController {
     pub fn new(
-        _action_tx: mpsc::Sender<Action>,
+        _action_tx: mpsc::Sender<ActionRequest>,
+        _sync_tx: mpsc::Sender<ExternalTransportEvent>,
         _preferences: MidiPreferences,
         _midi_mappings_dir: &Path,
         _response_tx: broadcast::Sender<Response>,
+        _transport_rx: watch::Receiver<TransportPosition>,
     ) -> Self {
         info!("MIDI feature not enabled");
         Self {}
     }
 
     pub fn update_preferences(&self, _preferences: MidiPreferences) {}
+
+    pub fn mappings(&self) -> MidiMappings {
+        MidiMappings::default()
+    }
+
+    pub fn learn(&self, _action: Action) -> anyhow::Result<()> {
+        Err(anyhow!("MIDI feature not enabled"))
+    }
+
+    pub fn cancel_learn(&self) -> anyhow::Result<()> {
+        Err(anyhow!("MIDI feature not enabled"))
+    }
+
+    pub fn update_mapping(&self, _mapping: &MidiMapping) -> anyhow::Result<()> {
+        Err(anyhow!("MIDI feature not enabled"))
+    }
+
+    pub fn remove_mapping(&self, _mapping: &MidiMapping) -> anyhow::Result<()> {
+        Err(anyhow!("MIDI feature not enabled"))
+    }
 }
 
 pub fn get_midi_devices() -> crate::bloop::MidiDevices {
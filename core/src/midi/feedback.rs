@@ -0,0 +1,241 @@
+use crate::bloop::{PlaybackState, PlayingState, Project};
+use crate::model::INVALID_ID;
+
+/// Playback flags that can be shown on a controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlagState {
+    Playing,
+    Looping,
+    /// A song or section is queued to play next.
+    Queued,
+}
+
+/// Positions that can be shown on a controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexState {
+    Song,
+    Section,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemplateByte {
+    Byte(u8),
+    /// Replaced with the zero-based index, limited to 127.
+    Index,
+}
+
+/// A message sent to a controller when part of the playback state changes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Feedback {
+    Flag {
+        state: FlagState,
+        on: Option<Vec<u8>>,
+        off: Option<Vec<u8>>,
+    },
+    Index {
+        state: IndexState,
+        message: Vec<TemplateByte>,
+    },
+}
+
+/// The parts of the playback state that controllers can display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeedbackValues {
+    playing: bool,
+    looping: bool,
+    queued: bool,
+    song_index: Option<usize>,
+    section_index: Option<usize>,
+}
+
+impl FeedbackValues {
+    /// While playing, the indices follow the playing song and section; otherwise they
+    /// follow the selection.
+    pub fn new(playback_state: &PlaybackState, project: &Project) -> Self {
+        let playing = playback_state.playing.enum_value_or_default() == PlayingState::PLAYING;
+
+        let (song_id, section_id) = if playing && playback_state.song_id != INVALID_ID {
+            (playback_state.song_id, playback_state.section_id)
+        } else {
+            (project.selections.song, project.selections.section)
+        };
+
+        let song_index = project.songs.iter().position(|song| song.id == song_id);
+        let section_index = song_index.and_then(|index| {
+            project.songs[index]
+                .sections
+                .iter()
+                .position(|section| section.id == section_id)
+        });
+
+        Self {
+            playing,
+            looping: playback_state.looping,
+            queued: playback_state.queued_song_id != INVALID_ID || playback_state.queued_section_id != INVALID_ID,
+            song_index,
+            section_index,
+        }
+    }
+
+    fn flag(&self, state: FlagState) -> bool {
+        match state {
+            FlagState::Playing => self.playing,
+            FlagState::Looping => self.looping,
+            FlagState::Queued => self.queued,
+        }
+    }
+
+    fn index(&self, state: IndexState) -> Option<usize> {
+        match state {
+            IndexState::Song => self.song_index,
+            IndexState::Section => self.section_index,
+        }
+    }
+}
+
+impl Feedback {
+    /// The message to send for `current`, or `None` if nothing needs sending because
+    /// the value hasn't changed since `previous`.
+    pub fn message(&self, previous: Option<&FeedbackValues>, current: &FeedbackValues) -> Option<Vec<u8>> {
+        match self {
+            Feedback::Flag { state, on, off } => {
+                let value = current.flag(*state);
+                if previous.is_some_and(|previous| previous.flag(*state) == value) {
+                    return None;
+                }
+
+                if value {
+                    on.clone()
+                } else {
+                    off.clone()
+                }
+            }
+            Feedback::Index { state, message } => {
+                let index = current.index(*state)?;
+                if previous.is_some_and(|previous| previous.index(*state) == Some(index)) {
+                    return None;
+                }
+
+                let index = index.min(127) as u8;
+                Some(
+                    message
+                        .iter()
+                        .map(|byte| match byte {
+                            TemplateByte::Byte(byte) => *byte,
+                            TemplateByte::Index => index,
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloop::{Section, Selections, Song};
+
+    fn project() -> Project {
+        let song = |id, section_ids: &[u64]| Song {
+            id,
+            sections: section_ids
+                .iter()
+                .map(|&id| Section {
+                    id,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        Project {
+            songs: vec![song(1, &[10, 11]), song(2, &[20, 21, 22])],
+            selections: Some(Selections {
+                song: 1,
+                section: 11,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    fn playing(song_id: u64, section_id: u64) -> PlaybackState {
+        PlaybackState {
+            playing: PlayingState::PLAYING.into(),
+            song_id,
+            section_id,
+            ..Default::default()
+        }
+    }
+
+    fn playing_led() -> Feedback {
+        Feedback::Flag {
+            state: FlagState::Playing,
+            on: Some(vec![0xB0, 1, 127]),
+            off: Some(vec![0xB0, 1, 0]),
+        }
+    }
+
+    #[test]
+    fn indices_follow_the_selection_when_stopped() {
+        let values = FeedbackValues::new(&PlaybackState::default(), &project());
+        assert_eq!(values.song_index, Some(0));
+        assert_eq!(values.section_index, Some(1));
+    }
+
+    #[test]
+    fn indices_follow_playback_when_playing() {
+        let values = FeedbackValues::new(&playing(2, 22), &project());
+        assert_eq!(values.song_index, Some(1));
+        assert_eq!(values.section_index, Some(2));
+    }
+
+    #[test]
+    fn queued_when_a_song_or_section_is_queued() {
+        let playback_state = PlaybackState {
+            queued_section_id: 21,
+            ..playing(2, 20)
+        };
+        assert!(FeedbackValues::new(&playback_state, &project()).queued);
+        assert!(!FeedbackValues::new(&playing(2, 20), &project()).queued);
+    }
+
+    #[test]
+    fn flag_sends_on_and_off() {
+        let stopped = FeedbackValues::new(&PlaybackState::default(), &project());
+        let playing = FeedbackValues::new(&playing(1, 10), &project());
+
+        assert_eq!(
+            playing_led().message(Some(&stopped), &playing),
+            Some(vec![0xB0, 1, 127])
+        );
+        assert_eq!(playing_led().message(Some(&playing), &stopped), Some(vec![0xB0, 1, 0]));
+    }
+
+    #[test]
+    fn unchanged_values_send_nothing() {
+        let values = FeedbackValues::new(&playing(1, 10), &project());
+        assert_eq!(playing_led().message(Some(&values), &values), None);
+    }
+
+    #[test]
+    fn first_update_sends_every_value() {
+        let values = FeedbackValues::new(&PlaybackState::default(), &project());
+        assert_eq!(playing_led().message(None, &values), Some(vec![0xB0, 1, 0]));
+    }
+
+    #[test]
+    fn index_is_substituted_into_the_template() {
+        let feedback = Feedback::Index {
+            state: IndexState::Section,
+            message: vec![TemplateByte::Byte(0xB0), TemplateByte::Byte(20), TemplateByte::Index],
+        };
+
+        let previous = FeedbackValues::new(&playing(2, 20), &project());
+        let current = FeedbackValues::new(&playing(2, 21), &project());
+
+        assert_eq!(feedback.message(Some(&previous), &current), Some(vec![0xB0, 20, 1]));
+    }
+}
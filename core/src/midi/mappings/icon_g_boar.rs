@@ -1,4 +1,4 @@
-use super::{Mapping, MidiDeviceMapping};
+use super::{Mapping, MidiDeviceMapping, Parameter};
 use crate::bloop::Action;
 use crate::midi::matcher::ExactMatcher;
 use regex::Regex;
@@ -12,32 +12,47 @@ pub fn device_mapping() -> MidiDeviceMapping {
             Mapping {
                 matcher: Box::new(ExactMatcher::new(&[0xB0, 0x28, 0x7F])),
                 action: Action::ACTION_PREVIOUS_SONG,
+                parameter: Parameter::None,
+                macro_name: None,
             },
             Mapping {
                 matcher: Box::new(ExactMatcher::new(&[0xB0, 0x29, 0x7F])),
                 action: Action::ACTION_NEXT_SONG,
+                parameter: Parameter::None,
+                macro_name: None,
             },
             Mapping {
                 matcher: Box::new(ExactMatcher::new(&[0xB0, 0x2A, 0x7F])),
                 action: Action::ACTION_QUEUE_SELECTED,
+                parameter: Parameter::None,
+                macro_name: None,
             },
             Mapping {
                 matcher: Box::new(ExactMatcher::new(&[0xB0, 0x2C, 0x7F])),
                 action: Action::ACTION_PREVIOUS_SECTION,
+                parameter: Parameter::None,
+                macro_name: None,
             },
             Mapping {
                 matcher: Box::new(ExactMatcher::new(&[0xB0, 0x2D, 0x7F])),
                 action: Action::ACTION_NEXT_SECTION,
+                parameter: Parameter::None,
+                macro_name: None,
             },
             Mapping {
                 matcher: Box::new(ExactMatcher::new(&[0xB0, 0x2E, 0x7F])),
                 action: Action::ACTION_TOGGLE_LOOP,
+                parameter: Parameter::None,
+                macro_name: None,
             },
             Mapping {
                 matcher: Box::new(ExactMatcher::new(&[0xB0, 0x2F, 0x7F])),
                 action: Action::ACTION_TOGGLE_PLAY,
+                parameter: Parameter::None,
+                macro_name: None,
             },
         ],
+        feedback: vec![],
     }
 }
 
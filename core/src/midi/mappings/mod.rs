@@ -1,30 +1,68 @@
-use crate::bloop::Action;
-use crate::midi::matcher::{ExactMatcher, Matcher};
+use crate::bloop::{Action, ActionRequest};
+use crate::midi::feedback::{Feedback, FlagState, IndexState, TemplateByte};
+use crate::midi::matcher::{ExactMatcher, Matcher, PatternMatcher};
+use crate::midi::message::ChannelMessageKind;
 use anyhow::{anyhow, Result};
 use log::warn;
 use regex::Regex;
+use std::ops::RangeInclusive;
 use std::path::Path;
 
 mod footctrl_bluetooth;
 mod icon_g_boar;
 mod sinco;
+mod store;
+
+pub use store::{learned_mapping, UserMappings};
+
+/// Where a mapping takes the value for its action from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parameter {
+    None,
+    Fixed(u32),
+    /// The first data byte: the note, controller or program number.
+    Number,
+    /// The second data byte: the velocity or controller value.
+    Value,
+}
 
 /// A single MIDI message-to-action binding.
 pub struct Mapping {
     matcher: Box<dyn Matcher + Send>,
     pub action: Action,
+    pub parameter: Parameter,
+    /// The macro to run for `ACTION_RUN_MACRO`.
+    pub macro_name: Option<String>,
 }
 
 impl Mapping {
     pub fn matches(&self, message: &[u8]) -> bool {
         self.matcher.matches(message)
     }
+
+    /// The action to perform for a matching `message`.
+    pub fn action_request(&self, message: &[u8]) -> ActionRequest {
+        let value = match self.parameter {
+            Parameter::None => 0,
+            Parameter::Fixed(value) => value,
+            Parameter::Number => message.get(1).copied().unwrap_or_default() as u32,
+            Parameter::Value => message.get(2).copied().unwrap_or_default() as u32,
+        };
+
+        let request = ActionRequest::from_action(self.action, value);
+        match self.macro_name.as_ref() {
+            Some(macro_name) => request.with_macro_name(macro_name),
+            None => request,
+        }
+    }
 }
 
 /// A set of [`Mapping`]s scoped to MIDI ports whose name matches `device_regex`.
 pub struct MidiDeviceMapping {
     pub device_regex: Regex,
     pub mappings: Vec<Mapping>,
+    /// Messages sent back to the device when the playback state changes.
+    pub feedback: Vec<Feedback>,
 }
 
 fn default_mappings() -> Vec<MidiDeviceMapping> {
@@ -60,6 +98,71 @@ fn default_mappings() -> Vec<MidiDeviceMapping> {
 /// | `message`      | array of u8     | Raw MIDI bytes to match exactly (e.g. `[176, 40, 127]`). |
 /// | `action`       | string          | One of the action names listed below.                    |
 ///
+/// ## Pattern entries
+///
+/// Instead of `message`, an entry can describe the messages it matches:
+///
+/// ```json
+/// { "type": "note_on", "channel": 10, "number": { "min": 36, "max": 51 }, "action": "ACTION_TOGGLE_PLAY" }
+/// { "type": "control_change", "channel": "any", "number": 64, "value": { "min": 64 }, "action": "ACTION_NEXT_SONG" }
+/// ```
+///
+/// | Field     | Type                  | Description                                                          |
+/// |-----------|-----------------------|----------------------------------------------------------------------|
+/// | `type`    | string                | `note_on`, `note_off`, `control_change` or `program_change`.         |
+/// | `channel` | 1–16 or `"any"`       | Optional; defaults to any channel.                                   |
+/// | `number`  | u8 or `{ min, max }`  | Note, controller or program number. Optional; defaults to any.       |
+/// | `value`   | u8 or `{ min, max }`  | Velocity or controller value. Either bound may be omitted.           |
+///
+/// A note on with zero velocity is treated as a note off. Messages sent using
+/// running status are matched as if they included their status byte.
+///
+/// ## Feedback
+///
+/// A file can also list messages to send back to the device, for example to light
+/// LEDs or update a display. They're sent to the output port with the same name as
+/// the connected input or, failing that, the first output port matching
+/// `device_regex`:
+///
+/// ```json
+/// "feedback": [
+///   { "state": "playing", "on": [176, 40, 127], "off": [176, 40, 0] },
+///   { "state": "song", "message": [192, "index"] }
+/// ]
+/// ```
+///
+/// | Field     | Type                      | Description                                                   |
+/// |-----------|---------------------------|---------------------------------------------------------------|
+/// | `state`   | string                    | `playing`, `looping`, `queued`, `song` or `section`.          |
+/// | `on`      | array of u8               | Sent when a `playing`, `looping` or `queued` state turns on.  |
+/// | `off`     | array of u8               | Sent when it turns off.                                       |
+/// | `message` | array of u8 and `"index"` | Sent when the `song` or `section` index changes; `"index"` is |
+/// |           |                           | replaced with the zero-based index.                           |
+///
+/// While stopped, the song and section indices follow the selection.
+///
+/// ## Parameters
+///
+/// Some actions take a value: `ACTION_SELECT_SONG` and `ACTION_SELECT_SECTION` take a
+/// zero-based index, and the volume actions take a level between 0 and 127. An
+/// entry's `parameter` is either a fixed number or where to read the value from the
+/// matched message: `"number"` (note, controller or program number) or `"value"`
+/// (velocity or controller value):
+///
+/// ```json
+/// { "type": "program_change", "action": "ACTION_SELECT_SONG", "parameter": "number" }
+/// { "type": "control_change", "number": 7, "action": "ACTION_SET_MASTER_VOLUME", "parameter": "value" }
+/// ```
+///
+/// ## Macros
+///
+/// `ACTION_RUN_MACRO` runs one of the macros defined in the preferences, named by
+/// the entry's `macro`:
+///
+/// ```json
+/// { "message": [176, 42, 127], "action": "ACTION_RUN_MACRO", "macro": "Next song" }
+/// ```
+///
 /// ## Valid action names
 ///
 /// - `ACTION_PREVIOUS_SONG`
@@ -69,9 +172,19 @@ fn default_mappings() -> Vec<MidiDeviceMapping> {
 /// - `ACTION_QUEUE_SELECTED`
 /// - `ACTION_TOGGLE_LOOP`
 /// - `ACTION_TOGGLE_PLAY`
+/// - `ACTION_SELECT_SONG`
+/// - `ACTION_SELECT_SECTION`
+/// - `ACTION_SET_SONG_VOLUME`
+/// - `ACTION_SET_MASTER_VOLUME`
+/// - `ACTION_RUN_MACRO`
+/// - `ACTION_CANCEL_MACRO`
 ///
 /// Files that are not valid JSON, contain an invalid regex, or reference an
 /// unknown action are skipped with a warning; all other files still load.
+///
+/// Clients can also record mappings with MIDI learn and list, edit or remove them
+/// through a `MidiMappingRequest`; these changes are written back to the files in
+/// this directory.
 pub fn load_mappings(midi_mappings_dir: &Path) -> Vec<MidiDeviceMapping> {
     let mut result = default_mappings();
 
@@ -94,16 +207,222 @@ pub fn load_mappings(midi_mappings_dir: &Path) -> Vec<MidiDeviceMapping> {
     result
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize)]
 struct FileMidiMapping {
     device_regex: String,
     mappings: Vec<FileMapping>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    feedback: Vec<FileFeedback>,
 }
 
-#[derive(serde::Deserialize)]
-struct FileMapping {
-    message: Vec<u8>,
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum FileFeedbackState {
+    Playing,
+    Looping,
+    Queued,
+    Song,
+    Section,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+enum FileTemplateByte {
+    Byte(u8),
+    Placeholder(String),
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+struct FileFeedback {
+    state: FileFeedbackState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    on: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    off: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<Vec<FileTemplateByte>>,
+}
+
+fn build_feedback(feedback: &FileFeedback) -> Result<Feedback> {
+    let flag_state = match feedback.state {
+        FileFeedbackState::Playing => Some(FlagState::Playing),
+        FileFeedbackState::Looping => Some(FlagState::Looping),
+        FileFeedbackState::Queued => Some(FlagState::Queued),
+        FileFeedbackState::Song | FileFeedbackState::Section => None,
+    };
+
+    if let Some(state) = flag_state {
+        if feedback.message.is_some() {
+            return Err(anyhow!("Feedback for {state:?} uses 'on' and 'off', not 'message'"));
+        }
+        if feedback.on.is_none() && feedback.off.is_none() {
+            return Err(anyhow!("Feedback for {state:?} needs 'on' or 'off'"));
+        }
+
+        return Ok(Feedback::Flag {
+            state,
+            on: feedback.on.clone(),
+            off: feedback.off.clone(),
+        });
+    }
+
+    let state = match feedback.state {
+        FileFeedbackState::Song => IndexState::Song,
+        _ => IndexState::Section,
+    };
+
+    if feedback.on.is_some() || feedback.off.is_some() {
+        return Err(anyhow!("Feedback for {state:?} uses 'message', not 'on' and 'off'"));
+    }
+
+    let message = feedback
+        .message
+        .as_ref()
+        .ok_or_else(|| anyhow!("Feedback for {state:?} needs a 'message'"))?
+        .iter()
+        .map(|byte| match byte {
+            FileTemplateByte::Byte(byte) => Ok(TemplateByte::Byte(*byte)),
+            FileTemplateByte::Placeholder(name) if name == "index" => Ok(TemplateByte::Index),
+            FileTemplateByte::Placeholder(name) => Err(anyhow!("Unknown placeholder '{name}'")),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Feedback::Index { state, message })
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct FileMapping {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<Vec<u8>>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    message_type: Option<FileMessageType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channel: Option<FileChannel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number: Option<FileRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<FileRange>,
     action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parameter: Option<FileParameter>,
+    #[serde(rename = "macro", default, skip_serializing_if = "Option::is_none")]
+    macro_name: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+enum FileParameter {
+    Fixed(u32),
+    Named(String),
+}
+
+impl FileParameter {
+    fn parse(&self) -> Result<Parameter> {
+        match self {
+            FileParameter::Fixed(value) => Ok(Parameter::Fixed(*value)),
+            FileParameter::Named(name) if name == "number" => Ok(Parameter::Number),
+            FileParameter::Named(name) if name == "value" => Ok(Parameter::Value),
+            FileParameter::Named(name) => Err(anyhow!("Invalid parameter '{name}'")),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum FileMessageType {
+    NoteOn,
+    NoteOff,
+    ControlChange,
+    ProgramChange,
+}
+
+impl From<FileMessageType> for ChannelMessageKind {
+    fn from(message_type: FileMessageType) -> Self {
+        match message_type {
+            FileMessageType::NoteOn => ChannelMessageKind::NoteOn,
+            FileMessageType::NoteOff => ChannelMessageKind::NoteOff,
+            FileMessageType::ControlChange => ChannelMessageKind::ControlChange,
+            FileMessageType::ProgramChange => ChannelMessageKind::ProgramChange,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+enum FileChannel {
+    Number(u8),
+    Named(String),
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+enum FileRange {
+    Exact(u8),
+    Range {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<u8>,
+    },
+}
+
+impl FileChannel {
+    /// Returns the zero-based channel, or `None` for any channel.
+    fn parse(&self) -> Result<Option<u8>> {
+        match self {
+            FileChannel::Number(channel @ 1..=16) => Ok(Some(channel - 1)),
+            FileChannel::Number(channel) => Err(anyhow!("Invalid channel {channel}, expected 1-16")),
+            FileChannel::Named(name) if name == "any" => Ok(None),
+            FileChannel::Named(name) => Err(anyhow!("Invalid channel '{name}'")),
+        }
+    }
+}
+
+impl FileRange {
+    fn parse(&self) -> Result<RangeInclusive<u8>> {
+        let (min, max) = match self {
+            FileRange::Exact(value) => (*value, *value),
+            FileRange::Range { min, max } => (min.unwrap_or(0), max.unwrap_or(127)),
+        };
+
+        if max > 127 || min > max {
+            return Err(anyhow!("Invalid range {min}-{max}"));
+        }
+
+        Ok(min..=max)
+    }
+}
+
+fn build_matcher(mapping: &FileMapping) -> Result<Box<dyn Matcher + Send>> {
+    match (&mapping.message, mapping.message_type) {
+        (Some(message), None) => {
+            if mapping.channel.is_some() || mapping.number.is_some() || mapping.value.is_some() {
+                return Err(anyhow!(
+                    "'message' cannot be combined with 'channel', 'number' or 'value'"
+                ));
+            }
+            Ok(Box::new(ExactMatcher::new(message)))
+        }
+        (None, Some(message_type)) => {
+            let mut matcher = PatternMatcher::new(message_type.into());
+
+            if let Some(channel) = mapping.channel.as_ref().map(FileChannel::parse).transpose()?.flatten() {
+                matcher = matcher.with_channel(channel);
+            }
+
+            if let Some(number) = mapping.number.as_ref() {
+                matcher = matcher.with_number(number.parse()?);
+            }
+
+            if let Some(value) = mapping.value.as_ref() {
+                matcher = matcher.with_value(value.parse()?);
+            }
+
+            Ok(Box::new(matcher))
+        }
+        (Some(_), Some(_)) => Err(anyhow!("A mapping cannot have both 'message' and 'type'")),
+        (None, None) => Err(anyhow!("A mapping needs either 'message' or 'type'")),
+    }
 }
 
 fn parse_action(s: &str) -> Option<Action> {
@@ -116,6 +435,12 @@ fn parse_action(s: &str) -> Option<Action> {
         "ACTION_QUEUE_SELECTED" => Some(Action::ACTION_QUEUE_SELECTED),
         "ACTION_TOGGLE_LOOP" => Some(Action::ACTION_TOGGLE_LOOP),
         "ACTION_TOGGLE_PLAY" => Some(Action::ACTION_TOGGLE_PLAY),
+        "ACTION_SELECT_SONG" => Some(Action::ACTION_SELECT_SONG),
+        "ACTION_SELECT_SECTION" => Some(Action::ACTION_SELECT_SECTION),
+        "ACTION_SET_SONG_VOLUME" => Some(Action::ACTION_SET_SONG_VOLUME),
+        "ACTION_SET_MASTER_VOLUME" => Some(Action::ACTION_SET_MASTER_VOLUME),
+        "ACTION_RUN_MACRO" => Some(Action::ACTION_RUN_MACRO),
+        "ACTION_CANCEL_MACRO" => Some(Action::ACTION_CANCEL_MACRO),
         _ => None,
     }
 }
@@ -132,14 +457,33 @@ fn load_mapping_file(path: &Path) -> Result<MidiDeviceMapping> {
         .into_iter()
         .map(|fm| {
             let action = parse_action(&fm.action).ok_or_else(|| anyhow!("Unknown action '{}'", fm.action))?;
+            if action == Action::ACTION_RUN_MACRO && fm.macro_name.is_none() {
+                return Err(anyhow!("ACTION_RUN_MACRO needs a macro"));
+            }
+
             Ok(Mapping {
-                matcher: Box::new(ExactMatcher::new(&fm.message)),
+                matcher: build_matcher(&fm)?,
                 action,
+                parameter: fm
+                    .parameter
+                    .as_ref()
+                    .map_or(Ok(Parameter::None), FileParameter::parse)?,
+                macro_name: fm.macro_name,
             })
         })
         .collect::<Result<Vec<_>>>()?;
 
-    Ok(MidiDeviceMapping { device_regex, mappings })
+    let feedback = file_mapping
+        .feedback
+        .iter()
+        .map(build_feedback)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(MidiDeviceMapping {
+        device_regex,
+        mappings,
+        feedback,
+    })
 }
 
 #[cfg(test)]
@@ -204,4 +548,145 @@ mod tests {
         let mappings = load_mappings(dir.path());
         assert_eq!(mappings.len(), default_mappings().len());
     }
+
+    fn load_single_mapping(mapping_json: &str) -> Result<MidiDeviceMapping> {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("mapping.json");
+        std::fs::write(
+            &path,
+            format!(r#"{{"device_regex":"Test Device","mappings":[{mapping_json}]}}"#),
+        )
+        .unwrap();
+        load_mapping_file(&path)
+    }
+
+    #[test]
+    fn exact_message_entries_still_load() {
+        let mapping = load_single_mapping(r#"{"message":[176,10,127],"action":"ACTION_TOGGLE_PLAY"}"#).unwrap();
+        assert!(mapping.mappings[0].matches(&[176, 10, 127]));
+        assert!(!mapping.mappings[0].matches(&[176, 10, 0]));
+    }
+
+    #[test]
+    fn pattern_entry_with_channel_wildcard_and_threshold() {
+        let mapping = load_single_mapping(
+            r#"{"type":"control_change","channel":"any","number":64,"value":{"min":64},"action":"ACTION_NEXT_SONG"}"#,
+        )
+        .unwrap();
+
+        let mapping = &mapping.mappings[0];
+        assert_eq!(mapping.action, Action::ACTION_NEXT_SONG);
+        assert!(mapping.matches(&[0xB0, 64, 127]));
+        assert!(mapping.matches(&[0xB5, 64, 64]));
+        assert!(!mapping.matches(&[0xB0, 64, 63]));
+        assert!(!mapping.matches(&[0xB0, 65, 127]));
+    }
+
+    #[test]
+    fn pattern_entry_with_channel_and_note_range() {
+        let mapping = load_single_mapping(
+            r#"{"type":"note_on","channel":10,"number":{"min":36,"max":51},"action":"ACTION_TOGGLE_PLAY"}"#,
+        )
+        .unwrap();
+
+        let mapping = &mapping.mappings[0];
+        assert!(mapping.matches(&[0x99, 40, 100]));
+        assert!(!mapping.matches(&[0x99, 40, 0]));
+        assert!(!mapping.matches(&[0x90, 40, 100]));
+        assert!(!mapping.matches(&[0x99, 60, 100]));
+    }
+
+    #[test]
+    fn pattern_entry_rejects_invalid_values() {
+        assert!(load_single_mapping(r#"{"type":"note_on","channel":17,"action":"ACTION_TOGGLE_PLAY"}"#).is_err());
+        assert!(load_single_mapping(r#"{"type":"note_on","channel":"all","action":"ACTION_TOGGLE_PLAY"}"#).is_err());
+        assert!(load_single_mapping(
+            r#"{"type":"note_on","number":{"min":60,"max":50},"action":"ACTION_TOGGLE_PLAY"}"#
+        )
+        .is_err());
+        assert!(load_single_mapping(r#"{"type":"pitch_bend","action":"ACTION_TOGGLE_PLAY"}"#).is_err());
+    }
+
+    #[test]
+    fn entry_needs_exactly_one_of_message_or_type() {
+        assert!(load_single_mapping(r#"{"action":"ACTION_TOGGLE_PLAY"}"#).is_err());
+        assert!(load_single_mapping(
+            r#"{"message":[176,10,127],"type":"control_change","action":"ACTION_TOGGLE_PLAY"}"#
+        )
+        .is_err());
+        assert!(load_single_mapping(r#"{"message":[176,10,127],"channel":1,"action":"ACTION_TOGGLE_PLAY"}"#).is_err());
+    }
+
+    fn load_feedback(feedback_json: &str) -> Result<MidiDeviceMapping> {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("mapping.json");
+        std::fs::write(
+            &path,
+            format!(r#"{{"device_regex":"Test Device","mappings":[],"feedback":[{feedback_json}]}}"#),
+        )
+        .unwrap();
+        load_mapping_file(&path)
+    }
+
+    #[test]
+    fn feedback_entries_load() {
+        let mapping =
+            load_feedback(r#"{"state":"looping","on":[144,1,127]},{"state":"section","message":[176,20,"index"]}"#)
+                .unwrap();
+
+        assert_eq!(
+            mapping.feedback,
+            vec![
+                Feedback::Flag {
+                    state: FlagState::Looping,
+                    on: Some(vec![144, 1, 127]),
+                    off: None,
+                },
+                Feedback::Index {
+                    state: IndexState::Section,
+                    message: vec![TemplateByte::Byte(176), TemplateByte::Byte(20), TemplateByte::Index],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_feedback_entries_are_rejected() {
+        assert!(load_feedback(r#"{"state":"playing"}"#).is_err());
+        assert!(load_feedback(r#"{"state":"playing","message":[144,1,127]}"#).is_err());
+        assert!(load_feedback(r#"{"state":"song","on":[192,1]}"#).is_err());
+        assert!(load_feedback(r#"{"state":"song","message":[192,"value"]}"#).is_err());
+        assert!(load_feedback(r#"{"state":"tempo","on":[192,1]}"#).is_err());
+    }
+
+    #[test]
+    fn parameter_is_read_from_the_message() {
+        let mapping = load_single_mapping(
+            r#"{"type":"control_change","number":7,"action":"ACTION_SET_SONG_VOLUME","parameter":"value"}"#,
+        )
+        .unwrap();
+        let request = mapping.mappings[0].action_request(&[0xB0, 7, 90]);
+        assert_eq!(request.action.enum_value(), Ok(Action::ACTION_SET_SONG_VOLUME));
+        assert_eq!(request.value, 90);
+
+        let mapping =
+            load_single_mapping(r#"{"message":[176,20,127],"action":"ACTION_SELECT_SECTION","parameter":2}"#).unwrap();
+        assert_eq!(mapping.mappings[0].action_request(&[176, 20, 127]).value, 2);
+
+        assert!(load_single_mapping(
+            r#"{"message":[176,20,127],"action":"ACTION_SELECT_SECTION","parameter":"velocity"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn run_macro_mapping_needs_a_macro() {
+        let mapping =
+            load_single_mapping(r#"{"message":[176,42,127],"action":"ACTION_RUN_MACRO","macro":"Outro"}"#).unwrap();
+        let request = mapping.mappings[0].action_request(&[176, 42, 127]);
+        assert_eq!(request.action.enum_value(), Ok(Action::ACTION_RUN_MACRO));
+        assert_eq!(request.macro_name, "Outro");
+
+        assert!(load_single_mapping(r#"{"message":[176,42,127],"action":"ACTION_RUN_MACRO"}"#).is_err());
+    }
 }
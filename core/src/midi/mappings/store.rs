@@ -0,0 +1,585 @@
+use super::{
+    build_matcher, default_mappings, parse_action, FileChannel, FileMapping, FileMessageType, FileMidiMapping,
+    FileParameter, FileRange, Parameter,
+};
+use crate::bloop::{Action, MidiMapping, MidiMessageType, MidiParameter, MidiRange};
+use crate::midi::message::{ChannelMessage, ChannelMessageKind};
+use anyhow::{anyhow, Result};
+use protobuf::EnumFull;
+use regex::Regex;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+/// Returns the mapping to record when `message` is captured while learning `action`,
+/// or `None` if the message can't be learned (real-time messages and note offs).
+///
+/// Notes and program changes match on their channel and number only; other
+/// messages match their exact bytes. For actions that take a value, program changes
+/// match any program and pass on its number, controllers pass on their value, and
+/// notes pass on their number for selections or their velocity for volumes.
+pub fn learned_mapping(message: &[u8], action: Action) -> Option<FileMapping> {
+    let status = *message.first()?;
+    if !(0x80..0xF8).contains(&status) {
+        return None;
+    }
+
+    let mut mapping = FileMapping {
+        message: None,
+        message_type: None,
+        channel: None,
+        number: None,
+        value: None,
+        action: action_name(action),
+        parameter: None,
+        macro_name: None,
+    };
+
+    let parsed = ChannelMessage::parse(message);
+    if let Some(parsed) = parsed {
+        mapping.message_type = Some(parsed.kind.into());
+        mapping.channel = Some(FileChannel::Number(parsed.channel + 1));
+        mapping.number = Some(FileRange::Exact(parsed.number));
+    }
+
+    let is_selection = matches!(action, Action::ACTION_SELECT_SONG | Action::ACTION_SELECT_SECTION);
+    let is_level = matches!(
+        action,
+        Action::ACTION_SET_SONG_VOLUME | Action::ACTION_SET_MASTER_VOLUME
+    );
+
+    match parsed.map(|parsed| parsed.kind) {
+        Some(ChannelMessageKind::NoteOff) => return None,
+        Some(ChannelMessageKind::ProgramChange) if is_selection || is_level => {
+            mapping.number = None;
+            mapping.parameter = Some(FileParameter::Named("number".to_string()));
+        }
+        Some(ChannelMessageKind::NoteOn) if is_selection => {
+            mapping.number = None;
+            mapping.parameter = Some(FileParameter::Named("number".to_string()));
+        }
+        Some(ChannelMessageKind::NoteOn) if is_level => {
+            mapping.parameter = Some(FileParameter::Named("value".to_string()));
+        }
+        Some(ChannelMessageKind::ControlChange) if is_selection || is_level => {
+            mapping.parameter = Some(FileParameter::Named("value".to_string()));
+        }
+        Some(ChannelMessageKind::NoteOn | ChannelMessageKind::ProgramChange) => (),
+        Some(ChannelMessageKind::ControlChange) | None => {
+            mapping.message_type = None;
+            mapping.channel = None;
+            mapping.number = None;
+            mapping.message = Some(message.to_vec());
+        }
+    }
+
+    Some(mapping)
+}
+
+fn action_name(action: Action) -> String {
+    action.descriptor().name().to_string()
+}
+
+impl From<ChannelMessageKind> for FileMessageType {
+    fn from(kind: ChannelMessageKind) -> Self {
+        match kind {
+            ChannelMessageKind::NoteOn => FileMessageType::NoteOn,
+            ChannelMessageKind::NoteOff => FileMessageType::NoteOff,
+            ChannelMessageKind::ControlChange => FileMessageType::ControlChange,
+            ChannelMessageKind::ProgramChange => FileMessageType::ProgramChange,
+        }
+    }
+}
+
+fn range_to_proto(range: &FileRange) -> MidiRange {
+    let (min, max) = match range {
+        FileRange::Exact(value) => (*value, *value),
+        FileRange::Range { min, max } => (min.unwrap_or(0), max.unwrap_or(127)),
+    };
+
+    MidiRange {
+        min: min as u32,
+        max: max as u32,
+        ..Default::default()
+    }
+}
+
+fn range_from_proto(range: &MidiRange) -> Result<FileRange> {
+    let min = u8::try_from(range.min).map_err(|_| anyhow!("Invalid range minimum {}", range.min))?;
+    let max = u8::try_from(range.max).map_err(|_| anyhow!("Invalid range maximum {}", range.max))?;
+
+    Ok(if min == max {
+        FileRange::Exact(min)
+    } else {
+        FileRange::Range {
+            min: Some(min),
+            max: Some(max),
+        }
+    })
+}
+
+fn parameter_to_proto(parameter: Option<&FileParameter>) -> (MidiParameter, u32) {
+    match parameter.map(FileParameter::parse) {
+        Some(Ok(Parameter::Fixed(value))) => (MidiParameter::MIDI_PARAMETER_FIXED, value),
+        Some(Ok(Parameter::Number)) => (MidiParameter::MIDI_PARAMETER_NUMBER, 0),
+        Some(Ok(Parameter::Value)) => (MidiParameter::MIDI_PARAMETER_VALUE, 0),
+        _ => (MidiParameter::MIDI_PARAMETER_NONE, 0),
+    }
+}
+
+fn parameter_from_proto(mapping: &MidiMapping) -> Result<Option<FileParameter>> {
+    match mapping.parameter.enum_value() {
+        Ok(MidiParameter::MIDI_PARAMETER_NONE) => Ok(None),
+        Ok(MidiParameter::MIDI_PARAMETER_FIXED) => Ok(Some(FileParameter::Fixed(mapping.parameter_value))),
+        Ok(MidiParameter::MIDI_PARAMETER_NUMBER) => Ok(Some(FileParameter::Named("number".to_string()))),
+        Ok(MidiParameter::MIDI_PARAMETER_VALUE) => Ok(Some(FileParameter::Named("value".to_string()))),
+        Err(value) => Err(anyhow!("Unknown MIDI parameter {value}")),
+    }
+}
+
+fn mapping_to_proto(file: &str, index: usize, device_regex: &str, mapping: &FileMapping) -> MidiMapping {
+    let (parameter, parameter_value) = parameter_to_proto(mapping.parameter.as_ref());
+
+    let mut result = MidiMapping {
+        file: file.to_string(),
+        index: index as u32,
+        device_regex: device_regex.to_string(),
+        action: parse_action(&mapping.action).unwrap_or_default().into(),
+        parameter: parameter.into(),
+        parameter_value,
+        macro_name: mapping.macro_name.clone().unwrap_or_default(),
+        ..Default::default()
+    };
+
+    if let Some(message) = mapping.message.as_ref() {
+        result.type_ = MidiMessageType::MIDI_MESSAGE_TYPE_EXACT.into();
+        result.message = message.clone();
+        return result;
+    }
+
+    result.type_ = match mapping.message_type {
+        Some(FileMessageType::NoteOn) => MidiMessageType::MIDI_MESSAGE_TYPE_NOTE_ON,
+        Some(FileMessageType::NoteOff) => MidiMessageType::MIDI_MESSAGE_TYPE_NOTE_OFF,
+        Some(FileMessageType::ControlChange) => MidiMessageType::MIDI_MESSAGE_TYPE_CONTROL_CHANGE,
+        Some(FileMessageType::ProgramChange) => MidiMessageType::MIDI_MESSAGE_TYPE_PROGRAM_CHANGE,
+        None => MidiMessageType::MIDI_MESSAGE_TYPE_EXACT,
+    }
+    .into();
+
+    result.channel = match mapping.channel {
+        Some(FileChannel::Number(channel)) => Some(channel as u32),
+        _ => None,
+    };
+    result.number = mapping.number.as_ref().map(range_to_proto).into();
+    result.value = mapping.value.as_ref().map(range_to_proto).into();
+    result
+}
+
+fn mapping_from_proto(mapping: &MidiMapping) -> Result<FileMapping> {
+    let action = mapping
+        .action
+        .enum_value()
+        .map_err(|value| anyhow!("Unknown action {value}"))?;
+    if action == Action::ACTION_UNKNOWN {
+        return Err(anyhow!("A MIDI mapping needs an action"));
+    }
+
+    let macro_name = Some(mapping.macro_name.clone()).filter(|name| !name.is_empty());
+    if action == Action::ACTION_RUN_MACRO && macro_name.is_none() {
+        return Err(anyhow!("ACTION_RUN_MACRO needs a macro"));
+    }
+
+    let message_type = match mapping.type_.enum_value() {
+        Ok(MidiMessageType::MIDI_MESSAGE_TYPE_EXACT) => None,
+        Ok(MidiMessageType::MIDI_MESSAGE_TYPE_NOTE_ON) => Some(FileMessageType::NoteOn),
+        Ok(MidiMessageType::MIDI_MESSAGE_TYPE_NOTE_OFF) => Some(FileMessageType::NoteOff),
+        Ok(MidiMessageType::MIDI_MESSAGE_TYPE_CONTROL_CHANGE) => Some(FileMessageType::ControlChange),
+        Ok(MidiMessageType::MIDI_MESSAGE_TYPE_PROGRAM_CHANGE) => Some(FileMessageType::ProgramChange),
+        Err(value) => return Err(anyhow!("Unknown MIDI message type {value}")),
+    };
+
+    let file_mapping = match message_type {
+        None => {
+            if mapping.message.is_empty() {
+                return Err(anyhow!("A MIDI mapping needs a message to match"));
+            }
+
+            FileMapping {
+                message: Some(mapping.message.clone()),
+                message_type: None,
+                channel: None,
+                number: None,
+                value: None,
+                action: action_name(action),
+                parameter: parameter_from_proto(mapping)?,
+                macro_name,
+            }
+        }
+        Some(message_type) => FileMapping {
+            message: None,
+            message_type: Some(message_type),
+            channel: mapping
+                .channel
+                .map(|channel| u8::try_from(channel).map(FileChannel::Number))
+                .transpose()
+                .map_err(|_| anyhow!("Invalid channel {}", mapping.channel.unwrap_or_default()))?,
+            number: mapping.number.as_ref().map(range_from_proto).transpose()?,
+            value: mapping.value.as_ref().map(range_from_proto).transpose()?,
+            action: action_name(action),
+            parameter: parameter_from_proto(mapping)?,
+            macro_name,
+        },
+    };
+
+    build_matcher(&file_mapping)?;
+    Ok(file_mapping)
+}
+
+/// Returns a file name for `port_name` that isn't already in use in `directory`.
+fn new_file_path(directory: &Path, port_name: &str) -> PathBuf {
+    let stem: String = port_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+
+    let mut path = directory.join(format!("{stem}.json"));
+    let mut suffix = 1;
+    while path.exists() {
+        suffix += 1;
+        path = directory.join(format!("{stem}_{suffix}.json"));
+    }
+    path
+}
+
+/// The user mapping files in `$BLOOP_HOME/midi_mappings`, which can be listed and
+/// edited through the API. The compiled-in defaults aren't included.
+pub struct UserMappings {
+    directory: PathBuf,
+}
+
+impl UserMappings {
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+        }
+    }
+
+    /// All mapping files that load successfully, sorted by file name.
+    fn read_files(&self) -> Vec<(String, FileMidiMapping)> {
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+
+        let mut files: Vec<(String, FileMidiMapping)> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("json"))
+            .filter_map(|path| {
+                let file_name = path.file_name()?.to_str()?.to_string();
+                let content = std::fs::read_to_string(&path).ok()?;
+                let file_mapping: FileMidiMapping = serde_json::from_str(&content).ok()?;
+                let is_valid = file_mapping
+                    .mappings
+                    .iter()
+                    .all(|mapping| build_matcher(mapping).is_ok());
+                is_valid.then_some((file_name, file_mapping))
+            })
+            .collect();
+
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        files
+    }
+
+    fn read_file(&self, file_name: &str) -> Result<FileMidiMapping> {
+        if file_name.is_empty() || file_name.contains(['/', '\\']) || file_name.starts_with('.') {
+            return Err(anyhow!("Invalid MIDI mapping file name '{file_name}'"));
+        }
+
+        let content = std::fs::read_to_string(self.directory.join(file_name))
+            .map_err(|error| anyhow!("Unable to read MIDI mapping file '{file_name}': {error}"))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    fn write_file(&self, path: &Path, file_mapping: &FileMidiMapping) -> Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+        std::fs::write(path, serde_json::to_string_pretty(file_mapping)?)?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<MidiMapping> {
+        self.read_files()
+            .iter()
+            .flat_map(|(file_name, file_mapping)| {
+                file_mapping.mappings.iter().enumerate().map(move |(index, mapping)| {
+                    mapping_to_proto(file_name, index, &file_mapping.device_regex, mapping)
+                })
+            })
+            .collect()
+    }
+
+    /// Record a learned mapping for `port_name`. It's added to the first file whose
+    /// device regex matches the port, replacing the action of an identical entry if
+    /// there is one. Otherwise a new file is created, using the regex of a matching
+    /// built-in device or else the port name itself.
+    pub fn add_learned(&self, port_name: &str, mapping: FileMapping) -> Result<()> {
+        let existing = self.read_files().into_iter().find(|(_, file_mapping)| {
+            Regex::new(&file_mapping.device_regex).is_ok_and(|regex| regex.is_match(port_name))
+        });
+
+        let (path, mut file_mapping) = match existing {
+            Some((file_name, file_mapping)) => (self.directory.join(file_name), file_mapping),
+            None => {
+                let device_regex = default_mappings()
+                    .into_iter()
+                    .find(|device_mapping| device_mapping.device_regex.is_match(port_name))
+                    .map_or_else(
+                        || regex::escape(port_name),
+                        |device_mapping| device_mapping.device_regex.as_str().to_string(),
+                    );
+
+                let file_mapping = FileMidiMapping {
+                    device_regex,
+                    mappings: vec![],
+                    feedback: vec![],
+                };
+                (new_file_path(&self.directory, port_name), file_mapping)
+            }
+        };
+
+        let same_message = |other: &FileMapping| {
+            FileMapping {
+                action: mapping.action.clone(),
+                parameter: mapping.parameter.clone(),
+                macro_name: mapping.macro_name.clone(),
+                ..other.clone()
+            } == mapping
+        };
+
+        match file_mapping.mappings.iter_mut().find(|other| same_message(other)) {
+            Some(other) => *other = mapping,
+            None => file_mapping.mappings.push(mapping),
+        }
+
+        self.write_file(&path, &file_mapping)
+    }
+
+    /// Replace the mapping at `mapping.file` and `mapping.index`.
+    pub fn update(&self, mapping: &MidiMapping) -> Result<()> {
+        let new_mapping = mapping_from_proto(mapping)?;
+        let mut file_mapping = self.read_file(&mapping.file)?;
+
+        let existing = file_mapping
+            .mappings
+            .get_mut(mapping.index as usize)
+            .ok_or_else(|| anyhow!("No MIDI mapping at index {} in '{}'", mapping.index, mapping.file))?;
+        *existing = new_mapping;
+
+        self.write_file(&self.directory.join(&mapping.file), &file_mapping)
+    }
+
+    /// Remove the mapping at `mapping.file` and `mapping.index`. The file is deleted
+    /// once it has no mappings left.
+    pub fn remove(&self, mapping: &MidiMapping) -> Result<()> {
+        let mut file_mapping = self.read_file(&mapping.file)?;
+
+        if mapping.index as usize >= file_mapping.mappings.len() {
+            return Err(anyhow!(
+                "No MIDI mapping at index {} in '{}'",
+                mapping.index,
+                mapping.file
+            ));
+        }
+        file_mapping.mappings.remove(mapping.index as usize);
+
+        let path = self.directory.join(&mapping.file);
+        if file_mapping.mappings.is_empty() {
+            std::fs::remove_file(path)?;
+            return Ok(());
+        }
+
+        self.write_file(&path, &file_mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::mappings::load_mappings;
+    use tempfile::TempDir;
+
+    fn learn(store: &UserMappings, port_name: &str, message: &[u8], action: Action) {
+        let mapping = learned_mapping(message, action).unwrap();
+        store.add_learned(port_name, mapping).unwrap();
+    }
+
+    #[test]
+    fn learned_note_matches_any_velocity_on_its_channel() {
+        let mapping = learned_mapping(&[0x92, 60, 100], Action::ACTION_NEXT_SONG).unwrap();
+        let matcher = build_matcher(&mapping).unwrap();
+
+        assert!(matcher.matches(&[0x92, 60, 1]));
+        assert!(!matcher.matches(&[0x92, 60, 0]));
+        assert!(!matcher.matches(&[0x93, 60, 100]));
+    }
+
+    #[test]
+    fn learned_control_change_matches_exactly() {
+        let mapping = learned_mapping(&[0xB0, 40, 127], Action::ACTION_NEXT_SONG).unwrap();
+        assert_eq!(mapping.message, Some(vec![0xB0, 40, 127]));
+    }
+
+    #[test]
+    fn real_time_messages_and_note_offs_are_not_learned() {
+        assert!(learned_mapping(&[0xF8], Action::ACTION_NEXT_SONG).is_none());
+        assert!(learned_mapping(&[0x80, 60, 0], Action::ACTION_NEXT_SONG).is_none());
+        assert!(learned_mapping(&[0x90, 60, 0], Action::ACTION_NEXT_SONG).is_none());
+    }
+
+    #[test]
+    fn learning_creates_a_file_that_loads() {
+        let dir = TempDir::new().unwrap();
+        let store = UserMappings::new(dir.path());
+
+        learn(&store, "My Controller", &[0xC0, 3], Action::ACTION_TOGGLE_PLAY);
+
+        let device_mapping = load_mappings(dir.path())
+            .into_iter()
+            .find(|device_mapping| device_mapping.device_regex.is_match("My Controller"))
+            .unwrap();
+        assert_eq!(device_mapping.mappings.len(), 1);
+        assert!(device_mapping.mappings[0].matches(&[0xC0, 3]));
+        assert_eq!(device_mapping.mappings[0].action, Action::ACTION_TOGGLE_PLAY);
+    }
+
+    #[test]
+    fn learning_uses_the_built_in_device_regex() {
+        let dir = TempDir::new().unwrap();
+        let store = UserMappings::new(dir.path());
+
+        learn(
+            &store,
+            "iCON G_Boar V1.03",
+            &[0xB0, 50, 127],
+            Action::ACTION_TOGGLE_LOOP,
+        );
+
+        let mappings = store.list();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].device_regex, "iCON G_Boar");
+    }
+
+    #[test]
+    fn learning_the_same_message_replaces_its_action() {
+        let dir = TempDir::new().unwrap();
+        let store = UserMappings::new(dir.path());
+
+        learn(&store, "My Controller", &[0xB0, 40, 127], Action::ACTION_NEXT_SONG);
+        learn(&store, "My Controller", &[0xB0, 41, 127], Action::ACTION_PREVIOUS_SONG);
+        learn(&store, "My Controller", &[0xB0, 40, 127], Action::ACTION_TOGGLE_PLAY);
+
+        let mappings = store.list();
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[0].action.enum_value(), Ok(Action::ACTION_TOGGLE_PLAY));
+        assert_eq!(mappings[1].action.enum_value(), Ok(Action::ACTION_PREVIOUS_SONG));
+    }
+
+    #[test]
+    fn update_replaces_the_mapping() {
+        let dir = TempDir::new().unwrap();
+        let store = UserMappings::new(dir.path());
+        learn(&store, "My Controller", &[0x90, 60, 100], Action::ACTION_NEXT_SONG);
+
+        let mut mapping = store.list()[0].clone();
+        mapping.channel = None;
+        mapping.number = Some(MidiRange {
+            min: 60,
+            max: 72,
+            ..Default::default()
+        })
+        .into();
+        mapping.action = Action::ACTION_NEXT_SECTION.into();
+        store.update(&mapping).unwrap();
+
+        let mappings = store.list();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0], mapping);
+    }
+
+    #[test]
+    fn update_rejects_invalid_mappings() {
+        let dir = TempDir::new().unwrap();
+        let store = UserMappings::new(dir.path());
+        learn(&store, "My Controller", &[0x90, 60, 100], Action::ACTION_NEXT_SONG);
+
+        let mut mapping = store.list()[0].clone();
+        mapping.channel = Some(17);
+        assert!(store.update(&mapping).is_err());
+
+        let mut mapping = store.list()[0].clone();
+        mapping.index = 5;
+        assert!(store.update(&mapping).is_err());
+
+        let mut mapping = store.list()[0].clone();
+        mapping.file = "../preferences.json".to_string();
+        assert!(store.update(&mapping).is_err());
+    }
+
+    #[test]
+    fn removing_the_last_mapping_deletes_the_file() {
+        let dir = TempDir::new().unwrap();
+        let store = UserMappings::new(dir.path());
+        learn(&store, "My Controller", &[0xB0, 40, 127], Action::ACTION_NEXT_SONG);
+        learn(&store, "My Controller", &[0xB0, 41, 127], Action::ACTION_PREVIOUS_SONG);
+
+        store.remove(&store.list()[0]).unwrap();
+        let mappings = store.list();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].message, vec![0xB0, 41, 127]);
+
+        store.remove(&mappings[0]).unwrap();
+        assert!(store.list().is_empty());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn learned_program_change_passes_its_number_to_song_selection() {
+        let mapping = learned_mapping(&[0xC0, 3], Action::ACTION_SELECT_SONG).unwrap();
+        let dir = TempDir::new().unwrap();
+        let store = UserMappings::new(dir.path());
+        store.add_learned("My Controller", mapping).unwrap();
+
+        let device_mapping = load_mappings(dir.path())
+            .into_iter()
+            .find(|device_mapping| device_mapping.device_regex.is_match("My Controller"))
+            .unwrap();
+        let mapping = &device_mapping.mappings[0];
+
+        assert!(mapping.matches(&[0xC0, 9]));
+        assert_eq!(mapping.action_request(&[0xC0, 9]).value, 9);
+    }
+
+    #[test]
+    fn learned_control_change_passes_its_value_to_volume() {
+        let mapping = learned_mapping(&[0xB2, 7, 100], Action::ACTION_SET_MASTER_VOLUME).unwrap();
+        let matcher = build_matcher(&mapping).unwrap();
+
+        assert!(matcher.matches(&[0xB2, 7, 0]));
+        assert!(!matcher.matches(&[0xB2, 8, 0]));
+        assert_eq!(mapping.parameter, Some(FileParameter::Named("value".to_string())));
+    }
+
+    #[test]
+    fn parameter_round_trips_through_update() {
+        let dir = TempDir::new().unwrap();
+        let store = UserMappings::new(dir.path());
+        learn(&store, "My Controller", &[0x90, 60, 100], Action::ACTION_NEXT_SONG);
+
+        let mut mapping = store.list()[0].clone();
+        mapping.action = Action::ACTION_SELECT_SECTION.into();
+        mapping.parameter = MidiParameter::MIDI_PARAMETER_FIXED.into();
+        mapping.parameter_value = 2;
+        store.update(&mapping).unwrap();
+
+        assert_eq!(store.list()[0], mapping);
+    }
+}
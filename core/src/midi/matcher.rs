@@ -1,3 +1,6 @@
+use super::message::{ChannelMessage, ChannelMessageKind};
+use std::ops::RangeInclusive;
+
 pub trait Matcher {
     fn matches(&self, message: &[u8]) -> bool;
 }
@@ -19,3 +22,114 @@ impl ExactMatcher {
         }
     }
 }
+
+/// Matches channel messages by kind, with an optional channel and ranges for the
+/// note/controller/program number and the velocity/value.
+pub struct PatternMatcher {
+    kind: ChannelMessageKind,
+    /// Zero-based channel, or `None` to match any channel
+    channel: Option<u8>,
+    number: RangeInclusive<u8>,
+    value: RangeInclusive<u8>,
+}
+
+impl PatternMatcher {
+    pub fn new(kind: ChannelMessageKind) -> Self {
+        Self {
+            kind,
+            channel: None,
+            number: 0..=127,
+            value: 0..=127,
+        }
+    }
+
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    pub fn with_number(mut self, number: RangeInclusive<u8>) -> Self {
+        self.number = number;
+        self
+    }
+
+    pub fn with_value(mut self, value: RangeInclusive<u8>) -> Self {
+        self.value = value;
+        self
+    }
+}
+
+impl Matcher for PatternMatcher {
+    fn matches(&self, message: &[u8]) -> bool {
+        let Some(message) = ChannelMessage::parse(message) else {
+            return false;
+        };
+
+        message.kind == self.kind
+            && self.channel.is_none_or(|channel| channel == message.channel)
+            && self.number.contains(&message.number)
+            && (self.kind == ChannelMessageKind::ProgramChange || self.value.contains(&message.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_matcher_matches_identical_bytes_only() {
+        let matcher = ExactMatcher::new(&[176, 40, 127]);
+        assert!(matcher.matches(&[176, 40, 127]));
+        assert!(!matcher.matches(&[176, 40, 0]));
+        assert!(!matcher.matches(&[176, 40]));
+    }
+
+    #[test]
+    fn pattern_matcher_matches_any_channel_by_default() {
+        let matcher = PatternMatcher::new(ChannelMessageKind::ControlChange).with_number(40..=40);
+        assert!(matcher.matches(&[0xB0, 40, 127]));
+        assert!(matcher.matches(&[0xBF, 40, 0]));
+        assert!(!matcher.matches(&[0xB0, 41, 127]));
+    }
+
+    #[test]
+    fn pattern_matcher_filters_channel() {
+        let matcher = PatternMatcher::new(ChannelMessageKind::NoteOn).with_channel(9);
+        assert!(matcher.matches(&[0x99, 36, 100]));
+        assert!(!matcher.matches(&[0x90, 36, 100]));
+    }
+
+    #[test]
+    fn pattern_matcher_matches_number_range() {
+        let matcher = PatternMatcher::new(ChannelMessageKind::NoteOn).with_number(36..=51);
+        assert!(matcher.matches(&[0x90, 36, 100]));
+        assert!(matcher.matches(&[0x90, 51, 100]));
+        assert!(!matcher.matches(&[0x90, 52, 100]));
+    }
+
+    #[test]
+    fn pattern_matcher_applies_value_threshold() {
+        let matcher = PatternMatcher::new(ChannelMessageKind::ControlChange).with_value(64..=127);
+        assert!(matcher.matches(&[0xB0, 64, 127]));
+        assert!(!matcher.matches(&[0xB0, 64, 63]));
+    }
+
+    #[test]
+    fn note_on_does_not_match_zero_velocity() {
+        let note_on = PatternMatcher::new(ChannelMessageKind::NoteOn);
+        let note_off = PatternMatcher::new(ChannelMessageKind::NoteOff);
+
+        assert!(!note_on.matches(&[0x90, 60, 0]));
+        assert!(note_off.matches(&[0x90, 60, 0]));
+        assert!(note_off.matches(&[0x80, 60, 64]));
+    }
+
+    #[test]
+    fn program_change_ignores_value_range() {
+        let matcher = PatternMatcher::new(ChannelMessageKind::ProgramChange)
+            .with_number(0..=3)
+            .with_value(64..=127);
+        assert!(matcher.matches(&[0xC0, 2]));
+        assert!(!matcher.matches(&[0xC0, 4]));
+    }
+}
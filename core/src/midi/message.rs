@@ -0,0 +1,214 @@
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PROGRAM_CHANGE: u8 = 0xC0;
+const SYSTEM_EXCLUSIVE: u8 = 0xF0;
+const END_OF_EXCLUSIVE: u8 = 0xF7;
+
+/// The kinds of channel message that mappings can match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelMessageKind {
+    NoteOn,
+    NoteOff,
+    ControlChange,
+    ProgramChange,
+}
+
+/// A decoded channel voice message. `number` is the note, controller or program
+/// number, `value` the velocity or controller value (zero for program changes).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelMessage {
+    pub kind: ChannelMessageKind,
+    /// Zero-based MIDI channel
+    pub channel: u8,
+    pub number: u8,
+    pub value: u8,
+}
+
+impl ChannelMessage {
+    /// Decode a complete message. A note on with zero velocity is treated as a note off.
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        let status = *message.first()?;
+        let channel = status & 0x0F;
+
+        let (kind, number, value) = match (status & 0xF0, message.len()) {
+            (NOTE_ON, 3) if message[2] > 0 => (ChannelMessageKind::NoteOn, message[1], message[2]),
+            (NOTE_ON, 3) | (NOTE_OFF, 3) => (ChannelMessageKind::NoteOff, message[1], message[2]),
+            (CONTROL_CHANGE, 3) => (ChannelMessageKind::ControlChange, message[1], message[2]),
+            (PROGRAM_CHANGE, 2) => (ChannelMessageKind::ProgramChange, message[1], 0),
+            _ => return None,
+        };
+
+        Some(Self {
+            kind,
+            channel,
+            number,
+            value,
+        })
+    }
+}
+
+/// The number of data bytes following `status`, or `None` for system exclusive.
+fn data_length(status: u8) -> Option<usize> {
+    match status {
+        0x80..=0xBF | 0xE0..=0xEF => Some(2),
+        0xC0..=0xDF => Some(1),
+        0xF1 | 0xF3 => Some(1),
+        0xF2 => Some(2),
+        SYSTEM_EXCLUSIVE => None,
+        _ => Some(0),
+    }
+}
+
+/// Splits incoming MIDI data into complete messages, restoring the status byte of
+/// messages sent using running status.
+#[derive(Default)]
+pub struct MessageParser {
+    running_status: Option<u8>,
+}
+
+impl MessageParser {
+    pub fn parse(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        let mut index = 0;
+
+        while index < data.len() {
+            let byte = data[index];
+
+            if byte >= 0xF8 {
+                // Real-time messages may appear anywhere and don't affect running status
+                messages.push(vec![byte]);
+                index += 1;
+                continue;
+            }
+
+            let status = if byte & 0x80 != 0 {
+                index += 1;
+                if byte < SYSTEM_EXCLUSIVE {
+                    self.running_status = Some(byte);
+                } else {
+                    self.running_status = None;
+                }
+                byte
+            } else if let Some(status) = self.running_status {
+                status
+            } else {
+                // Data without a status byte; skip it
+                index += 1;
+                continue;
+            };
+
+            match data_length(status) {
+                Some(length) => {
+                    let end = index + length;
+                    if end > data.len() {
+                        break;
+                    }
+
+                    let mut message = Vec::with_capacity(length + 1);
+                    message.push(status);
+                    message.extend_from_slice(&data[index..end]);
+                    messages.push(message);
+                    index = end;
+                }
+                None => {
+                    let end = data[index..]
+                        .iter()
+                        .position(|&b| b == END_OF_EXCLUSIVE)
+                        .map_or(data.len(), |position| index + position + 1);
+
+                    let mut message = vec![status];
+                    message.extend_from_slice(&data[index..end]);
+                    messages.push(message);
+                    index = end;
+                }
+            }
+        }
+
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_note_on() {
+        let message = ChannelMessage::parse(&[0x93, 60, 100]).unwrap();
+        assert_eq!(message.kind, ChannelMessageKind::NoteOn);
+        assert_eq!(message.channel, 3);
+        assert_eq!(message.number, 60);
+        assert_eq!(message.value, 100);
+    }
+
+    #[test]
+    fn note_on_with_zero_velocity_is_note_off() {
+        let message = ChannelMessage::parse(&[0x90, 60, 0]).unwrap();
+        assert_eq!(message.kind, ChannelMessageKind::NoteOff);
+    }
+
+    #[test]
+    fn parse_program_change() {
+        let message = ChannelMessage::parse(&[0xC1, 5]).unwrap();
+        assert_eq!(message.kind, ChannelMessageKind::ProgramChange);
+        assert_eq!(message.channel, 1);
+        assert_eq!(message.number, 5);
+    }
+
+    #[test]
+    fn parse_rejects_truncated_and_unsupported_messages() {
+        assert!(ChannelMessage::parse(&[0x90, 60]).is_none());
+        assert!(ChannelMessage::parse(&[0xE0, 0, 64]).is_none());
+        assert!(ChannelMessage::parse(&[]).is_none());
+    }
+
+    #[test]
+    fn parser_passes_complete_messages_through() {
+        let mut parser = MessageParser::default();
+        assert_eq!(parser.parse(&[0xB0, 40, 127]), vec![vec![0xB0, 40, 127]]);
+    }
+
+    #[test]
+    fn parser_restores_running_status() {
+        let mut parser = MessageParser::default();
+
+        parser.parse(&[0x90, 60, 100]);
+        assert_eq!(parser.parse(&[62, 100]), vec![vec![0x90, 62, 100]]);
+    }
+
+    #[test]
+    fn parser_splits_running_status_within_one_packet() {
+        let mut parser = MessageParser::default();
+        assert_eq!(
+            parser.parse(&[0xC0, 1, 2, 3]),
+            vec![vec![0xC0, 1], vec![0xC0, 2], vec![0xC0, 3]]
+        );
+    }
+
+    #[test]
+    fn real_time_messages_do_not_cancel_running_status() {
+        let mut parser = MessageParser::default();
+        assert_eq!(
+            parser.parse(&[0xB0, 1, 2, 0xF8, 3, 4]),
+            vec![vec![0xB0, 1, 2], vec![0xF8], vec![0xB0, 3, 4]]
+        );
+    }
+
+    #[test]
+    fn system_messages_cancel_running_status() {
+        let mut parser = MessageParser::default();
+        parser.parse(&[0x90, 60, 100]);
+        parser.parse(&[0xF2, 0, 0]);
+        assert!(parser.parse(&[62, 100]).is_empty());
+    }
+
+    #[test]
+    fn parser_keeps_system_exclusive_together() {
+        let mut parser = MessageParser::default();
+        assert_eq!(
+            parser.parse(&[0xF0, 0x7E, 0x01, 0xF7, 0xFA]),
+            vec![vec![0xF0, 0x7E, 0x01, 0xF7], vec![0xFA]]
+        );
+    }
+}
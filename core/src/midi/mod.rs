@@ -1,11 +1,19 @@
 #[cfg(feature = "midi")]
+mod clock;
+#[cfg(feature = "midi")]
 mod controller;
 #[cfg(feature = "midi")]
 mod devices;
 #[cfg(feature = "midi")]
+mod feedback;
+#[cfg(feature = "midi")]
 mod mappings;
 #[cfg(feature = "midi")]
 mod matcher;
+#[cfg(feature = "midi")]
+mod message;
+#[cfg(feature = "midi")]
+mod sync;
 
 #[cfg(not(feature = "midi"))]
 mod fallback;
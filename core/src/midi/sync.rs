@@ -0,0 +1,177 @@
+use super::clock::{CONTINUE, PULSES_PER_QUARTER_NOTE, START, STOP, TIMING_CLOCK};
+use crate::audio::ExternalTransportEvent;
+use std::time::{Duration, Instant};
+
+/// Weight given to each new pulse interval when smoothing the tempo.
+const SMOOTHING: f64 = 0.05;
+
+/// A gap between pulses longer than this means the clock has stopped.
+const MAX_PULSE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Tempo changes smaller than this are not reported.
+const TEMPO_CHANGE_THRESHOLD_BPM: f64 = 0.05;
+
+/// Pulse interval assumed before the tempo has been measured (120 bpm).
+const DEFAULT_PULSE_INTERVAL: f64 = 60.0 / (120.0 * PULSES_PER_QUARTER_NOTE);
+
+/// Follows MIDI clock, Start, Continue and Stop messages from an external clock master.
+#[derive(Default)]
+pub struct ExternalClock {
+    last_pulse: Option<Instant>,
+    pulse_interval: Option<f64>,
+    pulses_since_report: u32,
+    reported_bpm: Option<f64>,
+}
+
+impl ExternalClock {
+    /// The smoothed tempo of the incoming clock, once at least two pulses have been received.
+    pub fn bpm(&self) -> Option<f64> {
+        self.pulse_interval
+            .map(|interval| 60.0 / (interval * PULSES_PER_QUARTER_NOTE))
+    }
+
+    pub fn handle_message(&mut self, message: &[u8], time: Instant) -> Option<ExternalTransportEvent> {
+        match message.first() {
+            Some(&TIMING_CLOCK) => self.handle_pulse(time),
+            Some(&START) | Some(&CONTINUE) => {
+                let interval = self.pulse_interval.unwrap_or(DEFAULT_PULSE_INTERVAL);
+                Some(ExternalTransportEvent::Start {
+                    next_pulse: time + Duration::from_secs_f64(interval),
+                })
+            }
+            Some(&STOP) => Some(ExternalTransportEvent::Stop),
+            _ => None,
+        }
+    }
+
+    fn handle_pulse(&mut self, time: Instant) -> Option<ExternalTransportEvent> {
+        let last_pulse = self.last_pulse.replace(time)?;
+
+        let interval = time.saturating_duration_since(last_pulse);
+        if interval.is_zero() || interval > MAX_PULSE_INTERVAL {
+            self.pulse_interval = None;
+            self.pulses_since_report = 0;
+            return None;
+        }
+
+        let interval = interval.as_secs_f64();
+        self.pulse_interval = Some(match self.pulse_interval {
+            Some(previous) => previous + SMOOTHING * (interval - previous),
+            None => interval,
+        });
+
+        // Report at most once per beat
+        self.pulses_since_report += 1;
+        if (self.pulses_since_report as f64) < PULSES_PER_QUARTER_NOTE {
+            return None;
+        }
+        self.pulses_since_report = 0;
+
+        let bpm = self.bpm()?;
+        if self
+            .reported_bpm
+            .is_some_and(|reported| (reported - bpm).abs() < TEMPO_CHANGE_THRESHOLD_BPM)
+        {
+            return None;
+        }
+
+        self.reported_bpm = Some(bpm);
+        Some(ExternalTransportEvent::Tempo(bpm))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pulse_interval(bpm: f64) -> Duration {
+        Duration::from_secs_f64(60.0 / (bpm * PULSES_PER_QUARTER_NOTE))
+    }
+
+    fn send_pulses(clock: &mut ExternalClock, start: Instant, count: u32, bpm: f64) -> Vec<ExternalTransportEvent> {
+        (0..count)
+            .filter_map(|index| clock.handle_message(&[TIMING_CLOCK], start + pulse_interval(bpm) * index))
+            .collect()
+    }
+
+    #[test]
+    fn measures_tempo_from_pulses() {
+        let mut clock = ExternalClock::default();
+
+        let events = send_pulses(&mut clock, Instant::now(), 49, 128.0);
+
+        assert_eq!(events.len(), 1);
+        let ExternalTransportEvent::Tempo(bpm) = events[0] else {
+            panic!("Expected a tempo event");
+        };
+        assert!((bpm - 128.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn smooths_jittery_pulses() {
+        let mut clock = ExternalClock::default();
+        let start = Instant::now();
+        let interval = pulse_interval(120.0);
+        let jitter = Duration::from_millis(2);
+
+        for index in 0..240 {
+            let time = start + interval * index;
+            let time = if index % 2 == 0 { time + jitter } else { time };
+            clock.handle_message(&[TIMING_CLOCK], time);
+        }
+
+        assert!((clock.bpm().unwrap() - 120.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn does_not_repeat_unchanged_tempo() {
+        let mut clock = ExternalClock::default();
+        let start = Instant::now();
+
+        send_pulses(&mut clock, start, 49, 100.0);
+        let events = send_pulses(&mut clock, start + pulse_interval(100.0) * 49, 48, 100.0);
+
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn long_gap_resets_tempo() {
+        let mut clock = ExternalClock::default();
+        let start = Instant::now();
+        send_pulses(&mut clock, start, 10, 120.0);
+
+        clock.handle_message(&[TIMING_CLOCK], start + Duration::from_secs(2));
+
+        assert!(clock.bpm().is_none());
+    }
+
+    #[test]
+    fn start_and_continue_start_playback_on_the_next_pulse() {
+        let mut clock = ExternalClock::default();
+        let start = Instant::now();
+        send_pulses(&mut clock, start, 10, 120.0);
+
+        let now = start + Duration::from_secs(1);
+        let expected = ExternalTransportEvent::Start {
+            next_pulse: now + Duration::from_secs_f64(clock.pulse_interval.unwrap()),
+        };
+
+        assert_eq!(clock.handle_message(&[START], now), Some(expected));
+        assert_eq!(clock.handle_message(&[CONTINUE], now), Some(expected));
+    }
+
+    #[test]
+    fn stop_stops_playback() {
+        let mut clock = ExternalClock::default();
+        assert_eq!(
+            clock.handle_message(&[STOP], Instant::now()),
+            Some(ExternalTransportEvent::Stop)
+        );
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        let mut clock = ExternalClock::default();
+        assert!(clock.handle_message(&[0x90, 60, 100], Instant::now()).is_none());
+    }
+}
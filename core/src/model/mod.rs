@@ -1,4 +1,5 @@
 mod id;
+mod patch;
 mod playback_state;
 mod project;
 mod sample;
@@ -0,0 +1,150 @@
+use crate::bloop::{Project, ProjectPatch, TargetLoudnessChange};
+
+impl ProjectPatch {
+    /// The changes that turn `old` into `new`.
+    pub fn between(old: &Project, new: &Project, version: u64) -> Self {
+        let mut patch = Self {
+            version,
+            ..Default::default()
+        };
+
+        patch.songs = new
+            .songs
+            .iter()
+            .filter(|song| old.song_with_id(song.id) != Some(song))
+            .cloned()
+            .collect();
+
+        patch.removed_songs = old
+            .songs
+            .iter()
+            .filter(|song| new.song_with_id(song.id).is_none())
+            .map(|song| song.id)
+            .collect();
+
+        let old_order: Vec<_> = old.songs.iter().map(|song| song.id).collect();
+        let new_order: Vec<_> = new.songs.iter().map(|song| song.id).collect();
+        if old_order != new_order {
+            patch.song_order = new_order;
+        }
+
+        if old.selections != new.selections {
+            patch.selections = new.selections.clone();
+        }
+
+        if old.target_loudness != new.target_loudness {
+            patch.target_loudness = Some(TargetLoudnessChange {
+                target_loudness: new.target_loudness,
+                ..Default::default()
+            })
+            .into();
+        }
+
+        patch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(patch: &ProjectPatch, mut project: Project) -> Project {
+        project.songs.retain(|song| !patch.removed_songs.contains(&song.id));
+
+        for song in patch.songs.iter() {
+            match project.song_with_id_mut(song.id) {
+                Some(existing) => *existing = song.clone(),
+                None => project.songs.push(song.clone()),
+            }
+        }
+
+        if !patch.song_order.is_empty() {
+            project
+                .songs
+                .sort_by_key(|song| patch.song_order.iter().position(|id| *id == song.id));
+        }
+
+        if patch.selections.is_some() {
+            project.selections = patch.selections.clone();
+        }
+
+        if let Some(change) = patch.target_loudness.as_ref() {
+            project.target_loudness = change.target_loudness;
+        }
+
+        project
+    }
+
+    #[test]
+    fn selection_changes_only_send_the_selections() {
+        let old = Project::empty().with_songs(3, 3);
+        let new = old.clone().select_song_index(2);
+
+        let patch = ProjectPatch::between(&old, &new, 2);
+        assert_eq!(patch.version, 2);
+        assert!(patch.songs.is_empty());
+        assert!(patch.removed_songs.is_empty());
+        assert!(patch.song_order.is_empty());
+        assert_eq!(patch.selections, new.selections);
+        assert_eq!(apply(&patch, old), new);
+    }
+
+    #[test]
+    fn only_changed_songs_are_sent() {
+        let old = Project::empty().with_songs(3, 3);
+        let mut new = old.clone();
+        new.songs[1].name = "Changed".to_string();
+        new.songs[1].sections[2].start = 16.0;
+
+        let patch = ProjectPatch::between(&old, &new, 2);
+        assert_eq!(patch.songs, vec![new.songs[1].clone()]);
+        assert!(patch.selections.is_none());
+        assert_eq!(apply(&patch, old), new);
+    }
+
+    #[test]
+    fn added_removed_and_moved_songs() {
+        let old = Project::empty().with_songs(3, 3);
+        let mut new = old.clone().add_song(2);
+        new.songs.remove(0);
+        new.songs.swap(0, 1);
+
+        let patch = ProjectPatch::between(&old, &new, 2);
+        assert_eq!(patch.removed_songs, vec![old.songs[0].id]);
+        assert_eq!(patch.songs.len(), 1);
+        assert_eq!(
+            patch.song_order,
+            new.songs.iter().map(|song| song.id).collect::<Vec<_>>()
+        );
+        assert_eq!(apply(&patch, old), new);
+    }
+
+    #[test]
+    fn target_loudness_changes_are_sent() {
+        let old = Project::empty().with_songs(2, 2);
+        let mut new = old.clone();
+        new.target_loudness = Some(-14.0);
+
+        let patch = ProjectPatch::between(&old, &new, 2);
+        assert!(patch.songs.is_empty());
+        assert_eq!(apply(&patch, old.clone()), new);
+
+        let patch = ProjectPatch::between(&new, &old, 3);
+        assert_eq!(patch.target_loudness.target_loudness, None);
+        assert_eq!(apply(&patch, new), old);
+    }
+
+    #[test]
+    fn identical_projects_have_an_empty_patch() {
+        let project = Project::empty().with_songs(2, 2);
+
+        let patch = ProjectPatch::between(&project, &project, 2);
+        assert_eq!(
+            patch,
+            ProjectPatch {
+                version: 2,
+                ..Default::default()
+            }
+        );
+    }
+}
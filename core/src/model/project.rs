@@ -1,10 +1,11 @@
 use super::id::ID;
 use super::Song;
 use super::INVALID_ID;
+use crate::api::api_error;
 use crate::bloop::*;
 use crate::model::random_project_id;
 use anyhow::anyhow;
-use anyhow::Context;
+use std::collections::HashSet;
 
 impl ProjectInfo {
     pub fn empty() -> Self {
@@ -24,6 +25,9 @@ impl ProjectInfo {
 }
 
 impl Project {
+    pub const MIN_TARGET_LOUDNESS: f64 = -40.0;
+    pub const MAX_TARGET_LOUDNESS: f64 = -5.0;
+
     pub fn empty() -> Self {
         let mut project = Self::new();
         project.selections = Some(Selections::default()).into();
@@ -61,12 +65,12 @@ impl Project {
 
     pub fn replace_song(mut self, song: &Song) -> anyhow::Result<Self> {
         if !song.is_valid() {
-            return Err(anyhow!("Invalid song"));
+            return Err(api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Invalid song"));
         }
 
         let old_song = match self.songs.iter_mut().find(|s| s.id == song.id) {
             Some(song) => song,
-            None => return Err(anyhow!("Song not found")),
+            None => return Err(api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song not found")),
         };
 
         *old_song = song.clone();
@@ -75,9 +79,12 @@ impl Project {
     }
 
     pub fn add_section_to_song(self, song_id: ID) -> anyhow::Result<Self> {
-        let song = self
-            .song_with_id(song_id)
-            .with_context(|| format!("Couldn't find song ID {song_id}"))?;
+        let song = self.song_with_id(song_id).ok_or_else(|| {
+            api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Couldn't find song ID {song_id}"),
+            )
+        })?;
 
         let mut song = song.clone();
 
@@ -145,11 +152,19 @@ impl Project {
     pub fn remove_section(mut self, section_id: ID) -> anyhow::Result<Self> {
         let mut song = self
             .song_with_section(section_id)
-            .with_context(|| format!("Couldn't find song with section ID: {section_id}"))?
+            .ok_or_else(|| {
+                api_error(
+                    ErrorCode::ERROR_CODE_NOT_FOUND,
+                    format!("Couldn't find song with section ID: {section_id}"),
+                )
+            })?
             .clone();
 
         if song.sections.len() < 2 {
-            return Err(anyhow!("Can't remove last section"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                "Can't remove last section",
+            ));
         }
 
         let section_index = song.sections.iter().position(|section| section.id == section_id);
@@ -223,11 +238,17 @@ impl Project {
 
     pub fn remove_song(mut self, song_id: ID) -> anyhow::Result<Self> {
         if self.songs.len() < 2 {
-            return Err(anyhow!("Can't remove last song"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                "Can't remove last song",
+            ));
         }
 
         if !self.contains_song(song_id) {
-            return Err(anyhow!("Song ID not found to remove - {song_id}"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Song ID not found to remove - {song_id}"),
+            ));
         }
 
         let selected_song_index = self.selected_song_index();
@@ -246,7 +267,7 @@ impl Project {
 
     pub fn replace_section(mut self, new_section: &Section) -> anyhow::Result<Self> {
         if !new_section.is_valid() {
-            return Err(anyhow!("Invalid section"));
+            return Err(api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Invalid section"));
         }
 
         self.songs
@@ -261,18 +282,108 @@ impl Project {
         Ok(self)
     }
 
+    /// Replace all of a song's sections, keeping the selection on the song if its
+    /// selected section has gone. Section IDs must be unique, and can't belong to
+    /// another song's sections.
+    pub fn replace_sections(mut self, song_id: ID, sections: &[Section]) -> anyhow::Result<Self> {
+        if sections.is_empty() || !sections.iter().all(|section| section.is_valid()) {
+            return Err(api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Invalid sections"));
+        }
+
+        let mut ids = HashSet::new();
+        for section in sections {
+            let in_other_song = self
+                .song_with_section(section.id)
+                .map_or(false, |song| song.id != song_id);
+            if !ids.insert(section.id) || in_other_song {
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                    format!("Section ID is already used: {}", section.id),
+                ));
+            }
+        }
+
+        let song = self
+            .song_with_id_mut(song_id)
+            .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Song not found"))?;
+
+        song.sections = sections.to_vec();
+        song.sections.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        if self.selections.song == song_id && !self.selection_is_valid() {
+            self = self.select_song_with_id(song_id);
+        }
+
+        Ok(self)
+    }
+
     pub fn is_valid(&self) -> bool {
         self.songs.iter().all(|song| song.is_valid())
+            && self.target_loudness.map_or(true, |target| {
+                (Self::MIN_TARGET_LOUDNESS..=Self::MAX_TARGET_LOUDNESS).contains(&target)
+            })
+    }
+
+    /// Whether the projects differ in anything that is saved and can be undone,
+    /// which excludes the selections.
+    pub fn has_edits_from(&self, other: &Project) -> bool {
+        self.songs != other.songs || self.target_loudness != other.target_loudness
+    }
+
+    /// Take the edits from another project, such as one from the undo history,
+    /// keeping the selections where they still exist.
+    pub fn with_edits_from(mut self, other: Project) -> Self {
+        let selected_song_id = self.selections.song;
+        let selected_song_index = self.selected_song_index();
+
+        self.songs = other.songs;
+        self.target_loudness = other.target_loudness;
+
+        if !self.selection_is_valid() {
+            self = if self.contains_song(selected_song_id) {
+                self.select_song_with_id(selected_song_id)
+            } else {
+                match selected_song_index {
+                    Some(index) => self.select_song_index(index),
+                    None => self.select_last_song(),
+                }
+            };
+        }
+
+        self
+    }
+
+    pub fn with_target_loudness(mut self, target_loudness: Option<f64>) -> anyhow::Result<Self> {
+        if let Some(target) = target_loudness {
+            if !(Self::MIN_TARGET_LOUDNESS..=Self::MAX_TARGET_LOUDNESS).contains(&target) {
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_VALIDATION_FAILED,
+                    format!(
+                        "Target loudness must be between {} and {} LUFS",
+                        Self::MIN_TARGET_LOUDNESS,
+                        Self::MAX_TARGET_LOUDNESS
+                    ),
+                ));
+            }
+        }
+
+        self.target_loudness = target_loudness;
+        Ok(self)
     }
 
     pub fn replace_sample(mut self, sample: &Sample) -> anyhow::Result<Self> {
         if !sample.is_valid() {
-            return Err(anyhow!("Invalid sample"));
+            return Err(api_error(ErrorCode::ERROR_CODE_VALIDATION_FAILED, "Invalid sample"));
         }
 
         let old_sample = match self.find_sample_mut(sample.id) {
             Some(sample) => sample,
-            None => return Err(anyhow!("Sample not found: {}", sample.id)),
+            None => {
+                return Err(api_error(
+                    ErrorCode::ERROR_CODE_NOT_FOUND,
+                    format!("Sample not found: {}", sample.id),
+                ))
+            }
         };
 
         *old_sample = sample.clone();
@@ -308,7 +419,10 @@ impl Project {
 
     pub fn select_section(mut self, section_id: ID) -> anyhow::Result<Self> {
         if self.section_with_id(section_id).is_none() {
-            return Err(anyhow!("Couldn't find section with ID: {section_id}"));
+            return Err(api_error(
+                ErrorCode::ERROR_CODE_NOT_FOUND,
+                format!("Couldn't find section with ID: {section_id}"),
+            ));
         }
 
         let song_id = self
@@ -512,6 +626,49 @@ mod tests {
         assert_eq!(project.songs[3].name, "New song name");
     }
 
+    #[test]
+    fn replace_sections() {
+        let mut project = Project::empty().with_songs(2, 3);
+        let song_id = project.songs[0].id;
+        let sections = vec![Section::empty().with_start(8.0), Section::empty().with_start(0.0)];
+
+        project = project
+            .replace_sections(song_id, &sections)
+            .expect("Couldn't replace sections");
+
+        assert_eq!(
+            project.songs[0].sections,
+            vec![sections[1].clone(), sections[0].clone()]
+        );
+        assert_eq!(project.selections.section, sections[1].id);
+    }
+
+    #[test]
+    fn replace_sections_needs_unique_ids() {
+        let project = Project::empty().with_songs(2, 1);
+        let song_id = project.songs[0].id;
+
+        let section = Section::empty();
+        let duplicates = vec![section.clone(), section.with_start(4.0)];
+        assert!(project.clone().replace_sections(song_id, &duplicates).is_err());
+
+        let other_song_section = project.songs[1].sections[0].clone();
+        assert!(project
+            .clone()
+            .replace_sections(song_id, &[other_song_section])
+            .is_err());
+
+        let own_section = project.songs[0].sections[0].clone();
+        assert!(project.replace_sections(song_id, &[own_section]).is_ok());
+    }
+
+    #[test]
+    fn replace_sections_needs_at_least_one() {
+        let project = Project::empty().with_songs(1, 1);
+        let song_id = project.songs[0].id;
+        assert!(project.replace_sections(song_id, &[]).is_err());
+    }
+
     #[test]
     fn select_next_song() {
         let mut project = Project::empty().with_songs(5, 5);
@@ -564,4 +721,45 @@ mod tests {
         project = project.select_previous_section().expect("Couldn't select next section");
         assert_eq!(project.selections.section, project.songs[0].sections[3].id);
     }
+
+    #[test]
+    fn target_loudness_must_be_in_range() {
+        let project = Project::empty().with_songs(1, 1);
+
+        let project = project.with_target_loudness(Some(-14.0)).unwrap();
+        assert_eq!(project.target_loudness, Some(-14.0));
+        assert!(project.is_valid());
+
+        assert!(project.clone().with_target_loudness(Some(-4.0)).is_err());
+        assert!(project.clone().with_target_loudness(Some(-41.0)).is_err());
+        assert_eq!(project.with_target_loudness(None).unwrap().target_loudness, None);
+    }
+
+    #[test]
+    fn target_loudness_is_an_edit_but_selections_are_not() {
+        let project = Project::empty().with_songs(2, 1);
+
+        assert!(!project.clone().select_song_index(1).has_edits_from(&project));
+        assert!(project
+            .clone()
+            .with_target_loudness(Some(-23.0))
+            .unwrap()
+            .has_edits_from(&project));
+    }
+
+    #[test]
+    fn edits_are_taken_without_the_selections() {
+        let before = Project::empty().with_songs(2, 2).select_song_index(0);
+        let after = before.clone().add_song(1).select_song_index(1);
+
+        let project = after.clone().with_edits_from(before.clone());
+        assert_eq!(project.songs, before.songs);
+        assert_eq!(project.selections, after.selections);
+
+        // A selected song that's no longer there moves the selection to its neighbour
+        let project = before.clone().with_edits_from(after.clone()).select_song_index(2);
+        let project = project.with_edits_from(before.clone());
+        assert_eq!(project.selections.song, before.songs[1].id);
+        assert!(project.selection_is_valid());
+    }
 }
@@ -33,10 +33,40 @@ impl Sample {
     }
 
     pub fn is_valid(&self) -> bool {
-        self.sample_rate > 0 && self.sample_count > 0
+        self.sample_rate > 0 && self.sample_count > 0 && (0..self.sample_count).contains(&self.start_offset)
     }
 
+    /// How far into the file beat 0 is.
+    pub fn start_offset_seconds(&self) -> f64 {
+        self.start_offset as f64 / self.sample_rate as f64
+    }
+
+    /// The number of beats from beat 0 to the end of the sample.
     pub fn beat_length(&self) -> f64 {
-        (self.sample_count as f64 * self.tempo.beat_frequency()) / self.sample_rate as f64
+        ((self.sample_count - self.start_offset) as f64 * self.tempo.beat_frequency()) / self.sample_rate as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn beat_length_starts_at_the_offset() {
+        let mut sample = Sample::empty().with_beat_length(Tempo::new_with_bpm(120.0), 16.0, 48_000);
+        sample.start_offset = 12_000;
+
+        assert_eq!(sample.start_offset_seconds(), 0.25);
+        assert_eq!(sample.beat_length(), 15.5);
+    }
+
+    #[test]
+    fn start_offset_must_be_within_the_sample() {
+        let mut sample = Sample::empty().with_beat_length(Tempo::new_with_bpm(120.0), 16.0, 48_000);
+        sample.start_offset = sample.sample_count;
+        assert!(!sample.is_valid());
+
+        sample.start_offset = -1;
+        assert!(!sample.is_valid());
     }
 }
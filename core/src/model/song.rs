@@ -3,6 +3,9 @@ use super::{random_id, Section, Song, Tempo, ID, INVALID_ID};
 impl Song {
     pub const MIN_VOLUME_DB: f64 = -20.0;
     pub const MAX_VOLUME_DB: f64 = 0.0;
+    /// Loudness correction never raises peaks above this, in dBTP.
+    pub const TRUE_PEAK_CEILING_DB: f64 = -1.0;
+    pub const MAX_LOUDNESS_CORRECTION_DB: f64 = 20.0;
 
     pub fn empty() -> Self {
         Self {
@@ -30,6 +33,23 @@ impl Song {
         self.volume.unwrap_or(Self::MAX_VOLUME_DB)
     }
 
+    /// The gain that brings the song's sample to `target_loudness`, in dB. It's only
+    /// raised as far as the peaks allow, and is zero without a target or before the
+    /// sample has been measured.
+    pub fn loudness_correction_db(&self, target_loudness: Option<f64>) -> f64 {
+        let Some(target_loudness) = target_loudness else {
+            return 0.0;
+        };
+        let Some(loudness) = self.sample.as_ref().and_then(|sample| sample.loudness.as_ref()) else {
+            return 0.0;
+        };
+
+        let headroom = (Self::TRUE_PEAK_CEILING_DB - loudness.true_peak).max(0.0);
+        (target_loudness - loudness.integrated)
+            .min(headroom)
+            .clamp(-Self::MAX_LOUDNESS_CORRECTION_DB, Self::MAX_LOUDNESS_CORRECTION_DB)
+    }
+
     pub fn remove_section(mut self, section_id: ID) -> Self {
         self.sections.retain(|section| section.id != section_id);
         self
@@ -99,7 +119,7 @@ impl Song {
 mod tests {
     use approx::assert_relative_eq;
 
-    use crate::model::Sample;
+    use crate::model::{Loudness, Sample};
 
     use super::*;
 
@@ -139,4 +159,37 @@ mod tests {
         song.volume = Some(-20.1);
         assert!(!song.is_valid());
     }
+
+    fn song_with_loudness(integrated: f64, true_peak: f64) -> Song {
+        let mut sample = Sample::empty();
+        sample.loudness = Some(Loudness {
+            integrated,
+            true_peak,
+            ..Default::default()
+        })
+        .into();
+
+        let mut song = Song::empty();
+        song.sample = Some(sample).into();
+        song
+    }
+
+    #[test]
+    fn corrects_loudness_to_the_target() {
+        let song = song_with_loudness(-8.0, -0.5);
+
+        assert_relative_eq!(song.loudness_correction_db(None), 0.0);
+        assert_relative_eq!(song.loudness_correction_db(Some(-14.0)), -6.0);
+        assert_relative_eq!(Song::empty().loudness_correction_db(Some(-14.0)), 0.0);
+    }
+
+    #[test]
+    fn loudness_correction_keeps_peaks_below_the_ceiling() {
+        assert_relative_eq!(song_with_loudness(-20.0, -3.0).loudness_correction_db(Some(-14.0)), 2.0);
+        assert_relative_eq!(song_with_loudness(-20.0, 0.5).loudness_correction_db(Some(-14.0)), 0.0);
+        assert_relative_eq!(
+            song_with_loudness(-60.0, -40.0).loudness_correction_db(Some(-14.0)),
+            Song::MAX_LOUDNESS_CORRECTION_DB
+        );
+    }
 }
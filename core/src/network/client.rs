@@ -1,4 +1,7 @@
-use crate::bloop::{Request, Response};
+use super::pairing::{check_access, PairingStore};
+use super::subscriptions::Subscriptions;
+use crate::api::api_error;
+use crate::bloop::{DeviceAuthentication, Entity, ErrorCode, Request, Response};
 
 use futures::SinkExt;
 use futures_util::stream::{SplitSink, SplitStream};
@@ -6,15 +9,60 @@ use futures_util::StreamExt;
 use log::{error, info, warn};
 use protobuf::Message as ProtobufMessage;
 use std::net::SocketAddr;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 use tokio::net::TcpStream;
 use tokio::select;
-use tokio::sync::{broadcast, mpsc};
-use tokio_tungstenite::{accept_async, tungstenite::Error as TungsteniteError, tungstenite::Message, WebSocketStream};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tokio::time::{sleep_until, Instant};
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request as HandshakeRequest, Response as HandshakeResponse},
+        http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue},
+        Error as TungsteniteError, Message,
+    },
+    WebSocketStream,
+};
+
+/// Clients offering this subprotocol exchange protobuf JSON in text frames.
+const JSON_PROTOCOL: &str = "bloop.json";
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How many times a connection has fallen behind the broadcast channel and been
+/// sent a snapshot to catch up.
+static LAG_RECOVERIES: AtomicU64 = AtomicU64::new(0);
+
+/// How many connections have fallen behind and been sent a snapshot so far.
+pub fn lag_recoveries() -> u64 {
+    LAG_RECOVERIES.load(Ordering::Relaxed)
+}
+
+/// How requests and responses are encoded on a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    Binary,
+    Json,
+}
 
 struct Client {
     request_tx: mpsc::Sender<Request>,
     response_rx: broadcast::Receiver<Response>,
     address: SocketAddr,
+    connection_id: u64,
+    pairing: Arc<PairingStore>,
+    /// The token this connection authenticated with, if any.
+    token: Option<String>,
+    subscriptions: Subscriptions,
+    encoding: Encoding,
+    /// Whether the encoding is settled, either by the subprotocol or the first message.
+    encoding_negotiated: bool,
     outgoing: SplitSink<WebSocketStream<TcpStream>, Message>,
     incoming: SplitStream<WebSocketStream<TcpStream>>,
 }
@@ -24,24 +72,48 @@ impl Client {
         socket: TcpStream,
         request_tx: mpsc::Sender<Request>,
         response_rx: broadcast::Receiver<Response>,
+        pairing: Arc<PairingStore>,
     ) -> Result<Self, String> {
         let address = socket.peer_addr().expect("Error getting peer address");
 
         info!("New connection: {address}");
 
-        let ws_stream = match accept_async(socket).await {
+        let mut encoding = Encoding::Binary;
+        // The error type is set by tungstenite
+        #[allow(clippy::result_large_err)]
+        let callback = |request: &HandshakeRequest, mut response: HandshakeResponse| {
+            if offers_json_protocol(request) {
+                encoding = Encoding::Json;
+                response
+                    .headers_mut()
+                    .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(JSON_PROTOCOL));
+            }
+            Ok::<_, ErrorResponse>(response)
+        };
+
+        let ws_stream = match accept_hdr_async(socket, callback).await {
             Ok(stream) => stream,
             Err(_) => {
                 return Err("Error during WebSocket handshake".to_string());
             }
         };
 
+        if encoding == Encoding::Json {
+            info!("Using JSON for client: {address}");
+        }
+
         let (outgoing, incoming) = ws_stream.split();
 
         Ok(Self {
             request_tx,
             response_rx,
             address,
+            connection_id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            pairing,
+            token: None,
+            subscriptions: Subscriptions::default(),
+            encoding,
+            encoding_negotiated: encoding == Encoding::Json,
             outgoing,
             incoming,
         })
@@ -58,12 +130,22 @@ impl Client {
             Err(_) => anyhow::bail!("Error receiving from client: {}", self.address),
         };
 
-        let message = match message {
-            Message::Binary(message) => message,
+        let encoding = match message {
+            Message::Binary(_) => Encoding::Binary,
+            Message::Text(_) => Encoding::Json,
             _ => return Ok(()),
         };
 
-        let api_request = match convert_bytes_to_request(&message) {
+        // Without the subprotocol, the first message decides the encoding
+        if !self.encoding_negotiated {
+            self.encoding = encoding;
+            self.encoding_negotiated = true;
+            if encoding == Encoding::Json {
+                info!("Using JSON for client: {}", self.address);
+            }
+        }
+
+        let api_request = match convert_message_to_request(&message) {
             Ok(request) => request,
             Err(error) => {
                 warn!("Error parsing request: {error}");
@@ -73,17 +155,114 @@ impl Client {
             }
         };
 
+        if api_request.pair.is_some() || api_request.authenticate.is_some() {
+            return self.handle_pairing_request(&api_request).await;
+        }
+
+        // Viewers choose what they follow too, so subscribing isn't checked
+        if let Some(subscribe) = api_request.subscribe.as_ref() {
+            self.subscriptions.update(subscribe);
+            let response = reply(&api_request.request_id, Response::default(), &Ok(()));
+            if !api_request.request_id.is_empty() {
+                self.send_response(&response).await;
+            }
+            return Ok(());
+        }
+
+        // The role is checked for each request, so revoking a device or making it a
+        // viewer applies straight away
+        let role = self.pairing.role(&self.address, self.token.as_deref());
+        if let Err(error) = check_access(&api_request, role) {
+            let response = reply(&api_request.request_id, Response::default(), &Err(error));
+            self.send_response(&response).await;
+            return Ok(());
+        }
+
+        let api_request = tag_request_id(api_request, self.connection_id);
+
         match self.request_tx.send(api_request).await {
             Ok(_) => Ok(()),
             Err(_) => anyhow::bail!("Client disconnected: {}", self.address),
         }
     }
 
+    /// Pairing and authentication are handled here rather than by the controller,
+    /// so that tokens only go to the connection they belong to.
+    async fn handle_pairing_request(&mut self, request: &Request) -> anyhow::Result<()> {
+        let result = match request.pair.as_ref() {
+            Some(pair) => self
+                .pairing
+                .pair(&self.address, &pair.pin, &pair.device_name)
+                .map_err(|error| api_error(ErrorCode::ERROR_CODE_NOT_PAIRED, error.to_string())),
+            None => self
+                .pairing
+                .device_for_token(&request.authenticate.token)
+                .map(|device| DeviceAuthentication {
+                    device_id: device.id,
+                    role: device.role,
+                    ..Default::default()
+                })
+                .ok_or_else(|| api_error(ErrorCode::ERROR_CODE_NOT_PAIRED, "Unknown device token")),
+        };
+
+        let mut response = Response::default();
+        if let Ok(authentication) = result.as_ref() {
+            info!(
+                "Device {} authenticated as {:?}: {}",
+                authentication.device_id,
+                authentication.role.enum_value_or_default(),
+                self.address
+            );
+            self.token = Some(if request.pair.is_some() {
+                authentication.token.clone()
+            } else {
+                request.authenticate.token.clone()
+            });
+            response = response.with_device_authentication(authentication);
+        }
+
+        let response = reply(&request.request_id, response, &result.map(|_| ()));
+        self.send_response(&response).await;
+
+        // Pairing attempts can change the PIN and the paired devices, so let the UI know
+        if request.pair.is_some()
+            && self
+                .request_tx
+                .send(Request::get_request(Entity::PAIRED_DEVICES, 0))
+                .await
+                .is_err()
+        {
+            anyhow::bail!("Client disconnected: {}", self.address);
+        }
+
+        Ok(())
+    }
+
     async fn run(&mut self) {
         loop {
+            let next_flush = self.subscriptions.next_flush();
+
             select! {
-                Ok(response) = self.response_rx.recv() => {
-                    self.send_response(&response).await;
+                response = self.response_rx.recv() => match response {
+                    Ok(response) => {
+                        let response = response_for_connection(response, self.connection_id)
+                            .and_then(|response| self.subscriptions.filter(response, Instant::now()));
+                        if let Some(response) = response {
+                            self.send_response(&response).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        if let Err(err) = self.recover_from_lag(skipped).await {
+                            error!("Error from client: {err}");
+                            break;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = sleep_until(next_flush.unwrap_or_else(Instant::now)), if next_flush.is_some() => {
+                    for response in self.subscriptions.flush(Instant::now()) {
+                        self.send_response(&response).await;
+                    }
                 },
                 message = self.incoming.next() => {
 
@@ -101,23 +280,117 @@ impl Client {
         info!("Client disconnected: {}", self.address);
     }
 
+    /// Responses were dropped before this connection could send them, so ask for
+    /// the whole state rather than leave the client out of date.
+    async fn recover_from_lag(&mut self, skipped: u64) -> anyhow::Result<()> {
+        let recoveries = LAG_RECOVERIES.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            "Client {} missed {skipped} responses, sending a snapshot ({recoveries} lagged clients so far)",
+            self.address
+        );
+
+        let request =
+            Request::get_request(Entity::SNAPSHOT, 0).with_request_id(&snapshot_request_id(self.connection_id));
+        match self.request_tx.send(request).await {
+            Ok(_) => Ok(()),
+            Err(_) => anyhow::bail!("Client disconnected: {}", self.address),
+        }
+    }
+
     async fn send_response(&mut self, response: &Response) {
-        if let Some(message) = convert_response(response) {
+        if let Some(message) = convert_response(response, self.encoding) {
             let _ = self.outgoing.send(message).await;
         }
     }
 }
 
-pub async fn run(socket: TcpStream, request_tx: mpsc::Sender<Request>, response_rx: broadcast::Receiver<Response>) {
-    match Client::new(socket, request_tx, response_rx).await {
+pub async fn run(
+    socket: TcpStream,
+    request_tx: mpsc::Sender<Request>,
+    response_rx: broadcast::Receiver<Response>,
+    pairing: Arc<PairingStore>,
+) {
+    match Client::new(socket, request_tx, response_rx, pairing).await {
         Ok(mut client) => client.run().await,
         Err(error) => error!("Error from client: {error}"),
     }
 }
 
-fn convert_response(response: &Response) -> Option<Message> {
-    match response.write_to_bytes() {
-        Ok(data) => Some(Message::binary(data)),
+/// Prefix a request's ID with the connection, so the acknowledgement can be
+/// routed back to it.
+fn tag_request_id(mut request: Request, connection_id: u64) -> Request {
+    if !request.request_id.is_empty() {
+        request.request_id = format!("{connection_id}:{}", request.request_id);
+    }
+    request
+}
+
+/// The connection's own requests have an empty ID after the prefix, which can't
+/// clash with a client's ID because requests without one aren't tagged.
+fn snapshot_request_id(connection_id: u64) -> String {
+    format!("{connection_id}:")
+}
+
+/// Acknowledgements only go to the connection that sent the request, with the
+/// ID as the client sent it. Other responses go to everyone, without the pairing
+/// PIN.
+fn response_for_connection(mut response: Response, connection_id: u64) -> Option<Response> {
+    if let Some(pairing_status) = response.pairing_status.as_mut() {
+        pairing_status.pin.clear();
+    }
+
+    let Some(acknowledgement) = response.acknowledgement.as_mut() else {
+        return Some(response);
+    };
+
+    let request_id = acknowledgement
+        .request_id
+        .strip_prefix(&format!("{connection_id}:"))?
+        .to_string();
+
+    // The client didn't send this request, so it only gets the data
+    if request_id.is_empty() {
+        response.acknowledgement.clear();
+        return Some(response);
+    }
+
+    acknowledgement.request_id = request_id;
+    Some(response)
+}
+
+/// Complete a response to a request handled by the connection, in the same way the
+/// controller acknowledges requests.
+fn reply(request_id: &str, response: Response, result: &anyhow::Result<()>) -> Response {
+    match result {
+        _ if !request_id.is_empty() => response.with_acknowledgement(request_id, result),
+        Err(error) => response.with_error(&error.to_string()),
+        Ok(()) => response,
+    }
+}
+
+fn offers_json_protocol(request: &HandshakeRequest) -> bool {
+    request
+        .headers()
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == JSON_PROTOCOL)
+}
+
+fn convert_response(response: &Response, encoding: Encoding) -> Option<Message> {
+    let message = match encoding {
+        Encoding::Binary => response
+            .write_to_bytes()
+            .map(Message::binary)
+            .map_err(anyhow::Error::from),
+        Encoding::Json => protobuf_json_mapping::print_to_string(response)
+            .map(Message::text)
+            .map_err(anyhow::Error::from),
+    };
+
+    match message {
+        Ok(message) => Some(message),
         Err(error) => {
             error!("Error serialising response: {error}");
             None
@@ -125,7 +398,318 @@ fn convert_response(response: &Response) -> Option<Message> {
     }
 }
 
-fn convert_bytes_to_request(message: &[u8]) -> anyhow::Result<Request> {
-    let request = Request::parse_from_bytes(message)?;
+fn convert_message_to_request(message: &Message) -> anyhow::Result<Request> {
+    let request = match message {
+        Message::Text(text) => protobuf_json_mapping::parse_from_str(text.as_str())?,
+        Message::Binary(data) => Request::parse_from_bytes(data)?,
+        _ => anyhow::bail!("Unsupported message type"),
+    };
     Ok(request)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloop::{DeviceRole, PairingStatus, PlaybackState, Progress, Project, Topic, TransportMethod};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{connect_async, tungstenite::client::IntoClientRequest, MaybeTlsStream};
+
+    async fn start_client() -> (SocketAddr, mpsc::Receiver<Request>, broadcast::Sender<Response>) {
+        let directory = tempfile::TempDir::new().unwrap();
+        start_client_with_pairing(Arc::new(PairingStore::new(directory.path()))).await
+    }
+
+    async fn start_client_with_pairing(
+        pairing: Arc<PairingStore>,
+    ) -> (SocketAddr, mpsc::Receiver<Request>, broadcast::Sender<Response>) {
+        let (request_tx, request_rx) = mpsc::channel(16);
+        let (response_tx, _) = broadcast::channel(16);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client_response_tx = response_tx.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            run(stream, request_tx, client_response_tx.subscribe(), pairing).await;
+        });
+
+        (address, request_rx, response_tx)
+    }
+
+    #[test]
+    fn acknowledgements_go_to_the_requesting_connection() {
+        let request = tag_request_id(
+            Request::transport_request(TransportMethod::PLAY).with_request_id("a"),
+            7,
+        );
+        let response = Response::default().with_acknowledgement(&request.request_id, &Ok(()));
+
+        let routed = response_for_connection(response.clone(), 7).unwrap();
+        assert_eq!(routed.acknowledgement.request_id, "a");
+        assert!(routed.acknowledgement.success);
+
+        assert!(response_for_connection(response, 17).is_none());
+    }
+
+    #[test]
+    fn snapshots_are_sent_without_an_acknowledgement() {
+        let response = Response::default()
+            .with_project(&Project::empty())
+            .with_acknowledgement(&snapshot_request_id(7), &Ok(()));
+
+        let routed = response_for_connection(response.clone(), 7).unwrap();
+        assert!(routed.acknowledgement.is_none());
+        assert!(routed.project.is_some());
+
+        assert!(response_for_connection(response, 17).is_none());
+    }
+
+    #[test]
+    fn requests_without_an_id_are_not_tagged() {
+        let request = tag_request_id(Request::transport_request(TransportMethod::PLAY), 7);
+        assert!(request.request_id.is_empty());
+    }
+
+    #[tokio::test]
+    async fn json_subprotocol_uses_text_frames() {
+        let (address, mut request_rx, response_tx) = start_client().await;
+
+        let mut request = format!("ws://{address}").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static("other, bloop.json"));
+        let (mut socket, handshake) = connect_async(request).await.unwrap();
+        assert_eq!(handshake.headers()[SEC_WEBSOCKET_PROTOCOL], JSON_PROTOCOL);
+
+        socket
+            .send(Message::text(r#"{"transport": {"method": "STOP"}}"#))
+            .await
+            .unwrap();
+        let request = request_rx.recv().await.unwrap();
+        assert_eq!(request.transport.method.enum_value_or_default(), TransportMethod::STOP);
+
+        response_tx.send(Response::default().with_error("Oops")).unwrap();
+        let message = socket.next().await.unwrap().unwrap();
+        let response: Response = protobuf_json_mapping::parse_from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(response.error, "Oops");
+    }
+
+    #[tokio::test]
+    async fn first_text_message_selects_json() {
+        let (address, mut request_rx, response_tx) = start_client().await;
+        let (mut socket, _) = connect_async(format!("ws://{address}")).await.unwrap();
+
+        socket
+            .send(Message::text(r#"{"get": {"entity": "PREFERENCES"}}"#))
+            .await
+            .unwrap();
+        let request = request_rx.recv().await.unwrap();
+        assert_eq!(request.get.entity.enum_value_or_default(), Entity::PREFERENCES);
+
+        response_tx.send(Response::default().with_error("Oops")).unwrap();
+        assert!(socket.next().await.unwrap().unwrap().is_text());
+    }
+
+    #[tokio::test]
+    async fn binary_clients_are_unaffected() {
+        let (address, mut request_rx, response_tx) = start_client().await;
+        let (mut socket, _) = connect_async(format!("ws://{address}")).await.unwrap();
+
+        let request = Request::transport_request(TransportMethod::PLAY);
+        socket
+            .send(Message::binary(request.write_to_bytes().unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(request_rx.recv().await.unwrap(), request);
+
+        // A later text message doesn't change the encoding
+        socket
+            .send(Message::text(r#"{"transport": {"method": "STOP"}}"#))
+            .await
+            .unwrap();
+        request_rx.recv().await.unwrap();
+
+        response_tx.send(Response::default().with_error("Oops")).unwrap();
+        let message = socket.next().await.unwrap().unwrap();
+        assert_eq!(Response::parse_from_bytes(&message.into_data()).unwrap().error, "Oops");
+    }
+
+    async fn receive_response(socket: &mut WebSocketStream<MaybeTlsStream<TcpStream>>) -> Response {
+        let message = socket.next().await.unwrap().unwrap();
+        Response::parse_from_bytes(&message.into_data()).unwrap()
+    }
+
+    async fn send_request(socket: &mut WebSocketStream<MaybeTlsStream<TcpStream>>, request: &Request) {
+        socket
+            .send(Message::binary(request.write_to_bytes().unwrap()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn unpaired_clients_are_read_only() {
+        let directory = tempfile::TempDir::new().unwrap();
+        let pairing = Arc::new(PairingStore::new(directory.path()).with_trusted_loopback(false));
+        let (address, mut request_rx, _response_tx) = start_client_with_pairing(pairing.clone()).await;
+        let (mut socket, _) = connect_async(format!("ws://{address}")).await.unwrap();
+
+        let request = Request::transport_request(TransportMethod::PLAY).with_request_id("play");
+        send_request(&mut socket, &request).await;
+        let response = receive_response(&mut socket).await;
+        assert!(!response.acknowledgement.success);
+        assert_eq!(
+            response.acknowledgement.error_code.enum_value_or_default(),
+            ErrorCode::ERROR_CODE_NOT_PAIRED
+        );
+
+        // Reading is still allowed
+        send_request(&mut socket, &Request::get_request(Entity::PROJECT, 0)).await;
+        assert!(request_rx.recv().await.unwrap().get.is_some());
+
+        let pair_request = Request::pair_request(&pairing.status().pin, "Phone");
+        send_request(&mut socket, &pair_request).await;
+        let response = receive_response(&mut socket).await;
+        assert!(response.error.is_empty());
+        assert!(!response.device_authentication.token.is_empty());
+        assert_eq!(
+            request_rx.recv().await.unwrap().get.entity.enum_value_or_default(),
+            Entity::PAIRED_DEVICES
+        );
+
+        pairing
+            .set_role(
+                &response.device_authentication.device_id,
+                DeviceRole::DEVICE_ROLE_CONTROLLER,
+            )
+            .unwrap();
+
+        send_request(&mut socket, &request).await;
+        assert!(request_rx.recv().await.unwrap().transport.is_some());
+    }
+
+    #[tokio::test]
+    async fn paired_clients_authenticate_with_their_token() {
+        let directory = tempfile::TempDir::new().unwrap();
+        let pairing = Arc::new(PairingStore::new(directory.path()).with_trusted_loopback(false));
+        let authentication = pairing
+            .pair(&"192.168.1.20:50000".parse().unwrap(), &pairing.status().pin, "Phone")
+            .unwrap();
+        pairing
+            .set_role(&authentication.device_id, DeviceRole::DEVICE_ROLE_CONTROLLER)
+            .unwrap();
+        let token = authentication.token;
+        let (address, mut request_rx, response_tx) = start_client_with_pairing(pairing).await;
+        let (mut socket, _) = connect_async(format!("ws://{address}")).await.unwrap();
+
+        send_request(&mut socket, &Request::authenticate_request("wrong")).await;
+        assert!(!receive_response(&mut socket).await.error.is_empty());
+
+        send_request(&mut socket, &Request::authenticate_request(&token)).await;
+        let response = receive_response(&mut socket).await;
+        assert!(response.error.is_empty());
+        assert!(!response.device_authentication.device_id.is_empty());
+
+        let request = Request::transport_request(TransportMethod::STOP);
+        send_request(&mut socket, &request).await;
+        assert_eq!(request_rx.recv().await.unwrap(), request);
+
+        // The PIN is only shown locally
+        let status = PairingStatus {
+            pin: "123456".to_string(),
+            ..Default::default()
+        };
+        response_tx
+            .send(Response::default().with_pairing_status(&status))
+            .unwrap();
+        assert!(receive_response(&mut socket).await.pairing_status.pin.is_empty());
+    }
+
+    #[tokio::test]
+    async fn viewers_follow_along_without_making_changes() {
+        let directory = tempfile::TempDir::new().unwrap();
+        let pairing = Arc::new(PairingStore::new(directory.path()).with_trusted_loopback(false));
+        let (address, mut request_rx, response_tx) = start_client_with_pairing(pairing.clone()).await;
+        let (mut socket, _) = connect_async(format!("ws://{address}")).await.unwrap();
+
+        // Devices can't choose to be controllers when they pair
+        let pair_request = Request::pair_request(&pairing.status().pin, "Bass");
+        send_request(&mut socket, &pair_request).await;
+        let response = receive_response(&mut socket).await;
+        assert_eq!(
+            response.device_authentication.role.enum_value_or_default(),
+            DeviceRole::DEVICE_ROLE_VIEWER
+        );
+        request_rx.recv().await.unwrap();
+
+        let request = Request::transport_request(TransportMethod::STOP).with_request_id("stop");
+        send_request(&mut socket, &request).await;
+        let response = receive_response(&mut socket).await;
+        assert_eq!(
+            response.acknowledgement.error_code.enum_value_or_default(),
+            ErrorCode::ERROR_CODE_FORBIDDEN
+        );
+
+        // State is still streamed to viewers
+        response_tx.send(Response::default().with_error("Oops")).unwrap();
+        assert_eq!(receive_response(&mut socket).await.error, "Oops");
+    }
+
+    #[tokio::test]
+    async fn lagging_clients_are_sent_a_snapshot() {
+        let (address, mut request_rx, response_tx) = start_client().await;
+        let (mut socket, _) = connect_async(format!("ws://{address}")).await.unwrap();
+        let recoveries = lag_recoveries();
+
+        // Wait for the connection to be ready, then overflow the channel before it
+        // gets a chance to read
+        send_request(&mut socket, &Request::get_request(Entity::PROJECT, 0)).await;
+        request_rx.recv().await.unwrap();
+        for _ in 0..32 {
+            response_tx.send(Response::default().with_error("Oops")).unwrap();
+        }
+
+        let request = request_rx.recv().await.unwrap();
+        assert_eq!(request.get.entity.enum_value_or_default(), Entity::SNAPSHOT);
+        // Other tests run at the same time can add to the count too
+        assert!(lag_recoveries() > recoveries);
+
+        response_tx
+            .send(
+                Response::default()
+                    .with_project(&Project::empty())
+                    .with_acknowledgement(&request.request_id, &Ok(())),
+            )
+            .unwrap();
+
+        let response = loop {
+            let response = receive_response(&mut socket).await;
+            if response.error.is_empty() {
+                break response;
+            }
+        };
+        assert!(response.project.is_some());
+        assert!(response.acknowledgement.is_none());
+    }
+
+    #[tokio::test]
+    async fn subscriptions_filter_broadcasts() {
+        let (address, _request_rx, response_tx) = start_client().await;
+        let (mut socket, _) = connect_async(format!("ws://{address}")).await.unwrap();
+
+        let request = Request::subscribe_request(&[(Topic::TOPIC_PLAYBACK, 0.0)]).with_request_id("subscribe");
+        send_request(&mut socket, &request).await;
+        assert!(receive_response(&mut socket).await.acknowledgement.success);
+
+        response_tx
+            .send(Response::default().with_progress(&Progress::default()))
+            .unwrap();
+        response_tx
+            .send(Response::default().with_playback_state(&PlaybackState::default()))
+            .unwrap();
+
+        let response = receive_response(&mut socket).await;
+        assert!(response.playback_state.is_some());
+        assert!(response.progress.is_none());
+    }
+}
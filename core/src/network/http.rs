@@ -0,0 +1,610 @@
+use super::pairing::{check_access, PairingStore};
+use crate::api::error_code;
+use crate::bloop::{Entity, ErrorCode, PairRequest, Request, Response, TransportMethod};
+use anyhow::{anyhow, bail};
+use log::{debug, info, warn};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::timeout,
+};
+
+const PORT: u16 = 14073;
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+/// How long a client has to send its whole request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Serve the API as JSON over HTTP on port 14073.
+///
+/// | Route                       | Request                                            |
+/// |-----------------------------|----------------------------------------------------|
+/// | `GET /state`                | The current project and playback state             |
+/// | `GET /projects`             | Local and cloud projects                           |
+/// | `GET /preferences`          | Preferences                                        |
+/// | `POST /transport/play`      | Start playback                                     |
+/// | `POST /transport/stop`      | Stop playback                                      |
+/// | `POST /transport/loop`      | Loop the playing section                           |
+/// | `POST /transport/exit_loop` | Stop looping                                       |
+/// | `POST /request`             | Any `Request`, sent as the body in protobuf JSON   |
+/// | `POST /pair`                | Pair with a `PairRequest` body, returning a token  |
+///
+/// Responses are a `Response` in protobuf JSON. `GET` routes and `POST /request`
+/// wait for the request to be acknowledged, and reply with what the request
+/// produced along with the acknowledgement. Transport routes reply as soon as the
+/// request has been accepted.
+///
+/// Requests that make changes need the token of a paired controller in an
+/// `Authorization: Bearer` header.
+pub async fn run(
+    request_tx: mpsc::Sender<Request>,
+    response_tx: broadcast::Sender<Response>,
+    pairing: Arc<PairingStore>,
+) {
+    let address = format!("0.0.0.0:{PORT}");
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(error) => {
+            warn!("Unable to start HTTP server on {address}: {error}");
+            return;
+        }
+    };
+
+    info!("HTTP server listening on {address}");
+    serve(listener, request_tx, response_tx, pairing).await;
+}
+
+async fn serve(
+    listener: TcpListener,
+    request_tx: mpsc::Sender<Request>,
+    response_tx: broadcast::Sender<Response>,
+    pairing: Arc<PairingStore>,
+) {
+    while let Ok((stream, address)) = listener.accept().await {
+        debug!("HTTP connection: {address}");
+        let request_tx = request_tx.clone();
+        // Subscribe before the request is sent so that its response can't be missed
+        let response_rx = response_tx.subscribe();
+        let pairing = pairing.clone();
+        tokio::spawn(async move {
+            if let Err(error) = handle_connection(stream, address, request_tx, response_rx, pairing).await {
+                debug!("HTTP error from {address}: {error}");
+            }
+        });
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    /// The bearer token from the `Authorization` header.
+    token: Option<String>,
+    body: Vec<u8>,
+}
+
+/// What a route sends, and which response completes it.
+enum Route {
+    Reply(Request, fn(&Response) -> bool),
+    Accept(Request),
+    Pair(PairRequest),
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    address: SocketAddr,
+    request_tx: mpsc::Sender<Request>,
+    mut response_rx: broadcast::Receiver<Response>,
+    pairing: Arc<PairingStore>,
+) -> anyhow::Result<()> {
+    let http_request = match timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(http_request)) => http_request,
+        Ok(Err(error)) => {
+            write_response(&mut stream, 400, &Response::default().with_error(&error.to_string())).await?;
+            return Ok(());
+        }
+        Err(_) => {
+            write_response(
+                &mut stream,
+                408,
+                &Response::default().with_error("Timed out reading request"),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let route = match route(&http_request) {
+        Ok(route) => route,
+        Err((status, error)) => {
+            write_response(&mut stream, status, &Response::default().with_error(&error)).await?;
+            return Ok(());
+        }
+    };
+
+    let (request, predicate) = match route {
+        Route::Reply(request, predicate) => (request, Some(predicate)),
+        Route::Accept(request) => (request, None),
+        Route::Pair(pair_request) => return pair(&mut stream, &address, &pair_request, &request_tx, &pairing).await,
+    };
+
+    let role = pairing.role(&address, http_request.token.as_deref());
+    if let Err(error) = check_access(&request, role) {
+        let status = match error_code(&error) {
+            ErrorCode::ERROR_CODE_FORBIDDEN => 403,
+            _ => 401,
+        };
+        write_response(&mut stream, status, &Response::default().with_error(&error.to_string())).await?;
+        return Ok(());
+    }
+
+    let request_id = format!("http:{}", NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed));
+    let request = match predicate {
+        Some(_) => request.with_request_id(&request_id),
+        None => request,
+    };
+
+    if request_tx.send(request).await.is_err() {
+        write_response(&mut stream, 503, &Response::default().with_error("Unavailable")).await?;
+        return Ok(());
+    }
+
+    let Some(predicate) = predicate else {
+        write_response(&mut stream, 202, &Response::default()).await?;
+        return Ok(());
+    };
+
+    match timeout(
+        RESPONSE_TIMEOUT,
+        wait_for_acknowledgement(&mut response_rx, &request_id, predicate),
+    )
+    .await
+    {
+        Ok(Some(mut response)) => {
+            if let Some(pairing_status) = response.pairing_status.as_mut() {
+                pairing_status.pin.clear();
+            }
+            let status = status_for_acknowledgement(&mut response);
+            write_response(&mut stream, status, &response).await
+        }
+        Ok(None) => write_response(&mut stream, 503, &Response::default().with_error("Unavailable")).await,
+        Err(_) => write_response(&mut stream, 504, &Response::default().with_error("Timed out")).await,
+    }
+}
+
+/// The HTTP status for an acknowledged request. Failures also get the error in
+/// the `error` field, like errors from the routes themselves.
+fn status_for_acknowledgement(response: &mut Response) -> u16 {
+    let acknowledgement = &response.acknowledgement;
+    if acknowledgement.success {
+        return 200;
+    }
+
+    let status = match acknowledgement.error_code.enum_value_or_default() {
+        ErrorCode::ERROR_CODE_INVALID_ENTITY | ErrorCode::ERROR_CODE_VALIDATION_FAILED => 400,
+        ErrorCode::ERROR_CODE_NOT_LOGGED_IN | ErrorCode::ERROR_CODE_NOT_PAIRED => 401,
+        ErrorCode::ERROR_CODE_FORBIDDEN => 403,
+        ErrorCode::ERROR_CODE_NOT_FOUND => 404,
+        ErrorCode::ERROR_CODE_INVALID_STATE => 409,
+        ErrorCode::ERROR_CODE_BACKEND_UNAVAILABLE => 503,
+        ErrorCode::ERROR_CODE_NONE | ErrorCode::ERROR_CODE_UNKNOWN => 500,
+    };
+
+    response.error = acknowledgement.error.clone();
+    status
+}
+
+async fn pair(
+    stream: &mut TcpStream,
+    address: &SocketAddr,
+    pair_request: &PairRequest,
+    request_tx: &mpsc::Sender<Request>,
+    pairing: &PairingStore,
+) -> anyhow::Result<()> {
+    let result = pairing.pair(address, &pair_request.pin, &pair_request.device_name);
+
+    // Pairing attempts can change the PIN and the paired devices, so let the UI know
+    let _ = request_tx.send(Request::get_request(Entity::PAIRED_DEVICES, 0)).await;
+
+    match result {
+        Ok(authentication) => {
+            write_response(
+                stream,
+                200,
+                &Response::default().with_device_authentication(&authentication),
+            )
+            .await
+        }
+        Err(error) => write_response(stream, 401, &Response::default().with_error(&error.to_string())).await,
+    }
+}
+
+/// Wait for the request to be acknowledged, returning the acknowledgement along
+/// with the last response matching `predicate` before it. The controller handles
+/// one request at a time and acknowledges it straight after, so that's the
+/// response to this request rather than to one handled earlier.
+async fn wait_for_acknowledgement(
+    response_rx: &mut broadcast::Receiver<Response>,
+    request_id: &str,
+    predicate: fn(&Response) -> bool,
+) -> Option<Response> {
+    let mut reply = Response::default();
+
+    loop {
+        match response_rx.recv().await {
+            Ok(response) if response.acknowledgement.request_id == request_id => {
+                reply.acknowledgement = response.acknowledgement;
+                return Some(reply);
+            }
+            Ok(mut response) if predicate(&response) => {
+                response.acknowledgement.clear();
+                reply = response;
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => (),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Progress and playback updates are broadcast continuously, and acknowledgements
+/// belong to other requests, so they can't be the reply to a request.
+fn is_reply(response: &Response) -> bool {
+    let mut without_updates = response.clone();
+    without_updates.progress = Default::default();
+    without_updates.playback_state = Default::default();
+    without_updates.acknowledgement = Default::default();
+
+    without_updates != Response::default()
+}
+
+fn route(http_request: &HttpRequest) -> Result<Route, (u16, String)> {
+    let path = http_request.path.split('?').next().unwrap_or_default();
+
+    match (http_request.method.as_str(), path) {
+        ("GET", "/state") => Ok(Route::Reply(Request::get_request(Entity::PROJECT, 0), |response| {
+            response.project.is_some()
+        })),
+        ("GET", "/projects") => Ok(Route::Reply(Request::get_request(Entity::PROJECTS, 0), |response| {
+            !response.projects.is_empty() || !response.cloud_projects.is_empty()
+        })),
+        ("GET", "/preferences") => Ok(Route::Reply(Request::get_request(Entity::PREFERENCES, 0), |response| {
+            response.preferences.is_some()
+        })),
+        ("POST", "/transport/play") => Ok(Route::Accept(Request::transport_request(TransportMethod::PLAY))),
+        ("POST", "/transport/stop") => Ok(Route::Accept(Request::transport_request(TransportMethod::STOP))),
+        ("POST", "/transport/loop") => Ok(Route::Accept(Request::transport_request(TransportMethod::LOOP))),
+        ("POST", "/transport/exit_loop") => Ok(Route::Accept(Request::transport_request(TransportMethod::EXIT_LOOP))),
+        ("POST", "/request") => {
+            let body = std::str::from_utf8(&http_request.body).map_err(|error| (400, error.to_string()))?;
+            let request =
+                protobuf_json_mapping::parse_from_str::<Request>(body).map_err(|error| (400, error.to_string()))?;
+            Ok(Route::Reply(request, is_reply))
+        }
+        ("POST", "/pair") => {
+            let body = std::str::from_utf8(&http_request.body).map_err(|error| (400, error.to_string()))?;
+            let pair_request =
+                protobuf_json_mapping::parse_from_str::<PairRequest>(body).map_err(|error| (400, error.to_string()))?;
+            Ok(Route::Pair(pair_request))
+        }
+        (_, "/state" | "/projects" | "/preferences" | "/request" | "/pair") => {
+            Err((405, "Method not allowed".to_string()))
+        }
+        (_, path) if path.starts_with("/transport/") => Err((405, "Method not allowed".to_string())),
+        _ => Err((404, "Not found".to_string())),
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> anyhow::Result<HttpRequest> {
+    let mut data = Vec::new();
+    let mut buffer = [0; 4096];
+
+    let header_end = loop {
+        if let Some(position) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position;
+        }
+
+        if data.len() > MAX_HEADER_SIZE {
+            bail!("Headers too large");
+        }
+
+        let length = stream.read(&mut buffer).await?;
+        if length == 0 {
+            bail!("Connection closed");
+        }
+        data.extend_from_slice(&buffer[..length]);
+    };
+
+    let (mut http_request, content_length) = parse_head(std::str::from_utf8(&data[..header_end])?)?;
+    if content_length > MAX_BODY_SIZE {
+        bail!("Body too large");
+    }
+
+    let mut body = data.split_off(header_end + 4);
+    while body.len() < content_length {
+        let length = stream.read(&mut buffer).await?;
+        if length == 0 {
+            bail!("Connection closed");
+        }
+        body.extend_from_slice(&buffer[..length]);
+    }
+    body.truncate(content_length);
+
+    http_request.body = body;
+    Ok(http_request)
+}
+
+/// Parse the request line and headers, returning the request and its content length.
+fn parse_head(head: &str) -> anyhow::Result<(HttpRequest, usize)> {
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(path), Some(_version)) = (parts.next(), parts.next(), parts.next()) else {
+        bail!("Invalid request line '{request_line}'");
+    };
+
+    let mut content_length = 0;
+    let mut token = None;
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(|| anyhow!("Invalid header '{line}'"))?;
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse()?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            token = value
+                .trim()
+                .strip_prefix("Bearer ")
+                .map(|token| token.trim().to_string());
+        }
+    }
+
+    Ok((
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            token,
+            body: vec![],
+        },
+        content_length,
+    ))
+}
+
+async fn write_response(stream: &mut TcpStream, status: u16, response: &Response) -> anyhow::Result<()> {
+    let body = protobuf_json_mapping::print_to_string(response)?;
+    let head = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        reason(status),
+        body.len()
+    );
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::api_error;
+    use crate::bloop::{DeviceRole, PlaybackState, Progress, Project, Song};
+    use std::net::SocketAddr;
+
+    fn get(path: &str) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            token: None,
+            body: vec![],
+        }
+    }
+
+    #[test]
+    fn head_is_parsed() {
+        let (request, content_length) =
+            parse_head("POST /request HTTP/1.1\r\nHost: localhost\r\nContent-Length: 12\r\nAuthorization: Bearer abc")
+                .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/request");
+        assert_eq!(request.token.as_deref(), Some("abc"));
+        assert_eq!(content_length, 12);
+
+        assert!(parse_head("nonsense").is_err());
+    }
+
+    #[test]
+    fn routes_map_to_requests() {
+        let Ok(Route::Reply(request, _)) = route(&get("/projects?fresh=1")) else {
+            panic!("Expected a reply route");
+        };
+        assert_eq!(request.get.entity.enum_value_or_default(), Entity::PROJECTS);
+
+        assert!(matches!(route(&get("/missing")), Err((404, _))));
+        assert!(matches!(route(&get("/transport/play")), Err((405, _))));
+
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            path: "/request".to_string(),
+            token: None,
+            body: br#"{"transport": {"method": "STOP"}}"#.to_vec(),
+        };
+        let Ok(Route::Reply(request, _)) = route(&request) else {
+            panic!("Expected a reply route");
+        };
+        assert_eq!(request.transport.method.enum_value_or_default(), TransportMethod::STOP);
+    }
+
+    #[test]
+    fn playback_updates_are_not_replies() {
+        assert!(!is_reply(&Response::default().with_progress(&Progress::default())));
+        assert!(!is_reply(
+            &Response::default().with_playback_state(&PlaybackState::default())
+        ));
+        assert!(is_reply(&Response::default().with_project(&Project::default())));
+        assert!(!is_reply(&Response::default().with_acknowledgement("1:a", &Ok(()))));
+    }
+
+    async fn http(address: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn loopback_get_state() {
+        let (request_tx, mut request_rx) = mpsc::channel(16);
+        let (response_tx, _) = broadcast::channel(16);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let directory = tempfile::TempDir::new().unwrap();
+        let pairing = Arc::new(PairingStore::new(directory.path()));
+        tokio::spawn(serve(listener, request_tx, response_tx.clone(), pairing));
+
+        tokio::spawn(async move {
+            while let Some(request) = request_rx.recv().await {
+                // Another client's project arrives first, and shouldn't be taken as the reply
+                let other_project = Project {
+                    songs: vec![Song {
+                        name: "Other song".to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                };
+                let _ = response_tx.send(Response::default().with_project(&other_project));
+
+                let result = match request.get.entity.enum_value_or_default() {
+                    Entity::PROJECT => {
+                        let _ = response_tx.send(Response::default().with_progress(&Progress::default()));
+                        let project = Project {
+                            songs: vec![Song {
+                                name: "First song".to_string(),
+                                ..Default::default()
+                            }],
+                            ..Default::default()
+                        };
+                        let _ = response_tx.send(Response::default().with_project(&project));
+                        Ok(())
+                    }
+                    Entity::SAMPLE => Err(api_error(ErrorCode::ERROR_CODE_NOT_FOUND, "Sample not found")),
+                    _ => Ok(()),
+                };
+
+                let _ = response_tx.send(Response::default().with_acknowledgement("1:other", &Ok(())));
+                let _ = response_tx.send(Response::default().with_acknowledgement(&request.request_id, &result));
+            }
+        });
+
+        let response = http(address, "GET /state HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("First song"));
+        assert!(!response.contains("Other song"));
+
+        // Requests that don't send anything back are answered by their acknowledgement
+        let body = r#"{"transport": {"method": "STOP"}}"#;
+        let request = format!("POST /request HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}", body.len());
+        let response = http(address, &request).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(r#""success": true"#));
+
+        let body = r#"{"get": {"entity": "SAMPLE", "id": "1"}}"#;
+        let request = format!("POST /request HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}", body.len());
+        let response = http(address, &request).await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(response.contains("Sample not found"));
+
+        let response = http(address, "POST /transport/play HTTP/1.1\r\nContent-Length: 0\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 202 Accepted\r\n"));
+
+        let response = http(address, "GET /nowhere HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[tokio::test]
+    async fn changes_need_a_paired_device() {
+        let (request_tx, mut request_rx) = mpsc::channel(16);
+        let (response_tx, _) = broadcast::channel(16);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let directory = tempfile::TempDir::new().unwrap();
+        let pairing = Arc::new(PairingStore::new(directory.path()).with_trusted_loopback(false));
+        tokio::spawn(serve(listener, request_tx, response_tx, pairing.clone()));
+
+        let response = http(address, "POST /transport/play HTTP/1.1\r\nContent-Length: 0\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+
+        let body = format!(r#"{{"pin": "{}", "deviceName": "Tablet"}}"#, pairing.status().pin);
+        let request = format!("POST /pair HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}", body.len());
+        let response = http(address, &request).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let response: Response = protobuf_json_mapping::parse_from_str(body).unwrap();
+        let token = &response.device_authentication.token;
+        assert!(!token.is_empty());
+
+        // New devices are viewers until they're made controllers
+        let request = format!("POST /transport/play HTTP/1.1\r\nAuthorization: Bearer {token}\r\n\r\n");
+        let response = http(address, &request).await;
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        pairing
+            .set_role(&pairing.status().devices[0].id, DeviceRole::DEVICE_ROLE_CONTROLLER)
+            .unwrap();
+
+        // The UI is told about the new device
+        assert_eq!(
+            request_rx.recv().await.unwrap().get.entity.enum_value_or_default(),
+            Entity::PAIRED_DEVICES
+        );
+
+        let request = format!("POST /transport/play HTTP/1.1\r\nAuthorization: Bearer {token}\r\n\r\n");
+        let response = http(address, &request).await;
+        assert!(response.starts_with("HTTP/1.1 202 Accepted\r\n"));
+        assert!(request_rx.recv().await.unwrap().transport.is_some());
+
+        let viewer = pairing
+            .pair(&"192.168.1.20:50000".parse().unwrap(), &pairing.status().pin, "Phone")
+            .unwrap();
+        let request = format!(
+            "POST /transport/play HTTP/1.1\r\nAuthorization: Bearer {}\r\n\r\n",
+            viewer.token
+        );
+        let response = http(address, &request).await;
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    }
+}
@@ -1,10 +1,24 @@
-use crate::bloop::{Request, Response};
+use crate::bloop::{OscPreferences, Request, Response};
 
-use super::server;
+use super::{http, osc, server, PairingStore};
+use std::sync::Arc;
 use tokio::sync::{broadcast, mpsc};
 
-pub async fn run(request_tx: mpsc::Sender<Request>, response_tx: broadcast::Sender<Response>) {
+pub async fn run(
+    request_tx: mpsc::Sender<Request>,
+    response_tx: broadcast::Sender<Response>,
+    osc_preferences: OscPreferences,
+    pairing: Arc<PairingStore>,
+) {
+    tokio::spawn(osc::run(
+        osc_preferences,
+        request_tx.clone(),
+        response_tx.clone(),
+        pairing.clone(),
+    ));
+    tokio::spawn(http::run(request_tx.clone(), response_tx.clone(), pairing.clone()));
+
     tokio::spawn(async move {
-        server::run(request_tx.clone(), response_tx).await;
+        server::run(request_tx.clone(), response_tx, pairing).await;
     });
 }
@@ -1,5 +1,12 @@
 mod client;
+mod http;
 mod manager;
+mod osc;
+mod osc_packet;
+mod pairing;
 mod server;
+mod subscriptions;
 
+pub use client::lag_recoveries;
 pub use manager::run as run_server;
+pub use pairing::PairingStore;
@@ -0,0 +1,688 @@
+use super::osc_packet::{decode_packet, OscArg, OscMessage};
+use super::pairing::{check_access, PairingStore};
+use crate::{
+    bloop::{
+        DeviceRole, Entity, OscPreferences, PlaybackState, PlayingState, Project, Request, Response, TransportMethod,
+    },
+    model::{Action, INVALID_ID},
+    preferences::default_osc_preferences,
+};
+use log::{debug, info, warn};
+use std::{
+    collections::{BTreeMap, HashSet},
+    convert::TryFrom,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{
+    net::UdpSocket,
+    select,
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    task::JoinHandle,
+};
+
+const MAX_PACKET_SIZE: usize = 65_536;
+
+/// Run an OSC server while it is enabled in the preferences, restarting it whenever
+/// updated preferences are broadcast.
+///
+/// # Receiving
+///
+/// | Address                      | Arguments | Request                                      |
+/// |------------------------------|-----------|----------------------------------------------|
+/// | `/bloop/transport/play`      |           | Start playback                               |
+/// | `/bloop/transport/stop`      |           | Stop playback                                |
+/// | `/bloop/transport/loop`      |           | Loop the playing section                     |
+/// | `/bloop/transport/exit_loop` |           | Stop looping                                 |
+/// | `/bloop/song/select`         | index     | Select a song                                |
+/// | `/bloop/song/next`           |           | Select the next song                         |
+/// | `/bloop/song/previous`       |           | Select the previous song                     |
+/// | `/bloop/section/select`      | index     | Select a section of the selected song        |
+/// | `/bloop/section/next`        |           | Select the next section                      |
+/// | `/bloop/section/previous`    |           | Select the previous section                  |
+/// | `/bloop/action/<action>`     | value     | Any action, e.g. `/bloop/action/toggle_loop` |
+/// | `/bloop/macro`               | name      | Run a macro                                  |
+/// | `/bloop/register`            | port      | Publish state to the sender                  |
+/// | `/bloop/unregister`          | port      | Stop publishing state to the sender          |
+///
+/// Numbers can be sent as ints or floats. Messages without arguments are also
+/// accepted with a single non-zero number, so that buttons which send 1 on press and
+/// 0 on release only trigger once. `/bloop/register` publishes to the sender's
+/// address, on the given port or else the port the message came from.
+///
+/// # Publishing
+///
+/// Registered clients are sent each of these whenever it changes, and all of them
+/// when they register:
+///
+/// | Address                   | Arguments | Description                                      |
+/// |---------------------------|-----------|--------------------------------------------------|
+/// | `/bloop/playing`          | 0 or 1    |                                                  |
+/// | `/bloop/looping`          | 0 or 1    |                                                  |
+/// | `/bloop/queued`           | 0 or 1    | A song or section is queued to play next         |
+/// | `/bloop/song/index`       | index     | Playing song, or the selected song when stopped  |
+/// | `/bloop/song/name`        | name      |                                                  |
+/// | `/bloop/section/index`    | index     | Playing section, or the selected section         |
+/// | `/bloop/section/name`     | name      |                                                  |
+/// | `/bloop/progress/song`    | 0–1       |                                                  |
+/// | `/bloop/progress/section` | 0–1       |                                                  |
+/// | `/bloop/progress/beat`    | beat      | Beat within the playing section                  |
+///
+/// Indices are zero-based, and -1 when there is no song or section.
+///
+/// # Access
+///
+/// OSC can't pair, so messages from this machine and the trusted hosts in the
+/// preferences control playback. Other senders are treated as unpaired devices,
+/// and their messages that would make changes are dropped.
+pub async fn run(
+    preferences: OscPreferences,
+    request_tx: mpsc::Sender<Request>,
+    response_tx: broadcast::Sender<Response>,
+    pairing: Arc<PairingStore>,
+) {
+    let mut response_rx = response_tx.subscribe();
+
+    let mut server = if preferences.enabled {
+        start_server(
+            &preferences,
+            request_tx.clone(),
+            response_tx.subscribe(),
+            pairing.clone(),
+        )
+        .await
+    } else {
+        None
+    };
+    let mut preferences = Some(preferences);
+
+    loop {
+        let response = match response_rx.recv().await {
+            Ok(response) => response,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        };
+
+        let Some(new_preferences) = response.preferences.as_ref() else {
+            continue;
+        };
+
+        let new_preferences = new_preferences.osc.clone().unwrap_or_else(default_osc_preferences);
+        if preferences.as_ref() == Some(&new_preferences) {
+            continue;
+        }
+
+        if let Some(server) = server.take() {
+            server.abort();
+            let _ = server.await;
+            info!("OSC server stopped");
+        }
+
+        if new_preferences.enabled {
+            server = start_server(
+                &new_preferences,
+                request_tx.clone(),
+                response_tx.subscribe(),
+                pairing.clone(),
+            )
+            .await;
+        }
+
+        preferences = Some(new_preferences);
+    }
+}
+
+async fn start_server(
+    preferences: &OscPreferences,
+    request_tx: mpsc::Sender<Request>,
+    response_rx: broadcast::Receiver<Response>,
+    pairing: Arc<PairingStore>,
+) -> Option<JoinHandle<()>> {
+    let Some(address) = listen_address(preferences) else {
+        warn!(
+            "Invalid OSC address: {}, port {}",
+            preferences.bind_address, preferences.port
+        );
+        return None;
+    };
+
+    let trusted_hosts = preferences
+        .trusted_hosts
+        .iter()
+        .filter_map(|host| match host.parse() {
+            Ok(address) => Some(address),
+            Err(_) => {
+                warn!("Invalid trusted OSC host: {host}");
+                None
+            }
+        })
+        .collect();
+
+    match OscServer::bind(address, request_tx, pairing, trusted_hosts).await {
+        Ok(server) => {
+            if let Ok(address) = server.local_addr() {
+                info!("OSC server listening on {address}");
+            }
+            Some(tokio::spawn(server.run(response_rx)))
+        }
+        Err(error) => {
+            warn!("Unable to start OSC server on {address}: {error}");
+            None
+        }
+    }
+}
+
+/// Where to listen, which is only this machine unless another address is set.
+fn listen_address(preferences: &OscPreferences) -> Option<SocketAddr> {
+    let port = u16::try_from(preferences.port).ok()?;
+    let ip = match preferences.bind_address.as_str() {
+        "" => IpAddr::from([127, 0, 0, 1]),
+        address => address.parse().ok()?,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+pub struct OscServer {
+    socket: UdpSocket,
+    request_tx: mpsc::Sender<Request>,
+    pairing: Arc<PairingStore>,
+    trusted_hosts: HashSet<IpAddr>,
+    clients: HashSet<SocketAddr>,
+    state: PublishedState,
+}
+
+impl OscServer {
+    pub async fn bind(
+        address: SocketAddr,
+        request_tx: mpsc::Sender<Request>,
+        pairing: Arc<PairingStore>,
+        trusted_hosts: HashSet<IpAddr>,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(address).await?,
+            request_tx,
+            pairing,
+            trusted_hosts,
+            clients: HashSet::new(),
+            state: PublishedState::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub async fn run(mut self, mut response_rx: broadcast::Receiver<Response>) {
+        // The project is only broadcast when it changes, so ask for the current one
+        let _ = self.request_tx.send(Request::get_request(Entity::PROJECT, 0)).await;
+
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+
+        loop {
+            select! {
+                received = self.socket.recv_from(&mut buffer) => match received {
+                    Ok((length, from)) => self.handle_packet(&buffer[..length], from).await,
+                    Err(error) => warn!("Error receiving OSC packet: {error}"),
+                },
+                response = response_rx.recv() => match response {
+                    Ok(response) => self.handle_response(&response).await,
+                    Err(RecvError::Lagged(_)) => (),
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+    }
+
+    async fn handle_packet(&mut self, data: &[u8], from: SocketAddr) {
+        let messages = match decode_packet(data) {
+            Ok(messages) => messages,
+            Err(error) => {
+                debug!("Invalid OSC packet from {from}: {error}");
+                return;
+            }
+        };
+
+        for message in messages {
+            match message.address.as_str() {
+                "/bloop/register" => self.register(client_address(&message, from)).await,
+                "/bloop/unregister" => {
+                    self.clients.remove(&client_address(&message, from));
+                }
+                _ => match request_for_message(&message) {
+                    Some(request) => match check_access(&request, self.role(&from)) {
+                        Ok(()) => {
+                            let _ = self.request_tx.send(request).await;
+                        }
+                        Err(error) => debug!("Dropping OSC message {} from {from}: {error}", message.address),
+                    },
+                    None => debug!("Ignoring OSC message {} from {from}", message.address),
+                },
+            }
+        }
+    }
+
+    /// OSC senders can't pair, so they're either trusted or treated as unpaired.
+    fn role(&self, from: &SocketAddr) -> Option<DeviceRole> {
+        if self.trusted_hosts.contains(&from.ip()) {
+            return Some(DeviceRole::DEVICE_ROLE_CONTROLLER);
+        }
+
+        self.pairing.role(from, None)
+    }
+
+    async fn register(&mut self, client: SocketAddr) {
+        if self.clients.insert(client) {
+            info!("Publishing OSC to {client}");
+        }
+
+        for message in self.state.messages() {
+            self.send(&message, client).await;
+        }
+    }
+
+    async fn handle_response(&mut self, response: &Response) {
+        let messages = self.state.update(response);
+        if messages.is_empty() {
+            return;
+        }
+
+        let clients: Vec<SocketAddr> = self.clients.iter().copied().collect();
+        for client in clients {
+            for message in messages.iter() {
+                self.send(message, client).await;
+            }
+        }
+    }
+
+    async fn send(&self, message: &OscMessage, client: SocketAddr) {
+        if let Err(error) = self.socket.send_to(&message.encode(), client).await {
+            debug!("Unable to send OSC to {client}: {error}");
+        }
+    }
+}
+
+fn client_address(message: &OscMessage, from: SocketAddr) -> SocketAddr {
+    let port = first_number(message)
+        .and_then(|port| u16::try_from(port.round() as i64).ok())
+        .filter(|port| *port != 0);
+    SocketAddr::new(from.ip(), port.unwrap_or(from.port()))
+}
+
+fn first_number(message: &OscMessage) -> Option<f64> {
+    message.args.first().and_then(OscArg::as_f64)
+}
+
+/// Buttons send a non-zero value when pressed and zero when released.
+fn is_trigger(message: &OscMessage) -> bool {
+    first_number(message).is_none_or(|value| value != 0.0)
+}
+
+fn action_takes_value(action: Action) -> bool {
+    matches!(
+        action,
+        Action::ACTION_SELECT_SONG
+            | Action::ACTION_SELECT_SECTION
+            | Action::ACTION_SET_SONG_VOLUME
+            | Action::ACTION_SET_MASTER_VOLUME
+    )
+}
+
+fn action_request(action: Action, message: &OscMessage) -> Option<Request> {
+    if action == Action::ACTION_RUN_MACRO {
+        let macro_name = message.args.first().and_then(OscArg::as_str)?;
+        return Some(Request::run_macro_request(macro_name));
+    }
+
+    if !action_takes_value(action) {
+        return is_trigger(message).then(|| Request::action_request(action, 0));
+    }
+
+    let value = first_number(message)?.round().max(0.0) as u32;
+    Some(Request::action_request(action, value))
+}
+
+fn transport_request(method: TransportMethod, message: &OscMessage) -> Option<Request> {
+    is_trigger(message).then(|| Request::transport_request(method))
+}
+
+fn request_for_message(message: &OscMessage) -> Option<Request> {
+    match message.address.as_str() {
+        "/bloop/transport/play" => transport_request(TransportMethod::PLAY, message),
+        "/bloop/transport/stop" => transport_request(TransportMethod::STOP, message),
+        "/bloop/transport/loop" => transport_request(TransportMethod::LOOP, message),
+        "/bloop/transport/exit_loop" => transport_request(TransportMethod::EXIT_LOOP, message),
+        "/bloop/song/select" => action_request(Action::ACTION_SELECT_SONG, message),
+        "/bloop/song/next" => action_request(Action::ACTION_NEXT_SONG, message),
+        "/bloop/song/previous" => action_request(Action::ACTION_PREVIOUS_SONG, message),
+        "/bloop/section/select" => action_request(Action::ACTION_SELECT_SECTION, message),
+        "/bloop/section/next" => action_request(Action::ACTION_NEXT_SECTION, message),
+        "/bloop/section/previous" => action_request(Action::ACTION_PREVIOUS_SECTION, message),
+        "/bloop/macro" => action_request(Action::ACTION_RUN_MACRO, message),
+        address => {
+            let name = address.strip_prefix("/bloop/action/")?;
+            let action = <Action as protobuf::Enum>::from_str(&format!("ACTION_{}", name.to_uppercase()))?;
+            if action == Action::ACTION_UNKNOWN {
+                return None;
+            }
+
+            action_request(action, message)
+        }
+    }
+}
+
+/// The latest value published at each address.
+#[derive(Default)]
+struct PublishedState {
+    project: Project,
+    playback_state: PlaybackState,
+    values: BTreeMap<&'static str, Vec<OscArg>>,
+}
+
+impl PublishedState {
+    /// Apply `response` and return messages for the values that changed.
+    fn update(&mut self, response: &Response) -> Vec<OscMessage> {
+        if let Some(project) = response.project.as_ref() {
+            self.project = project.clone();
+        }
+
+        if let Some(playback_state) = response.playback_state.as_ref() {
+            self.playback_state = playback_state.clone();
+        }
+
+        let mut values = self.playback_values();
+
+        if let Some(progress) = response.progress.as_ref() {
+            values.push((
+                "/bloop/progress/song",
+                vec![OscArg::Float(progress.song_progress as f32)],
+            ));
+            values.push((
+                "/bloop/progress/section",
+                vec![OscArg::Float(progress.section_progress as f32)],
+            ));
+            values.push((
+                "/bloop/progress/beat",
+                vec![OscArg::Float(progress.section_beat as f32)],
+            ));
+        }
+
+        let mut changed = Vec::new();
+        for (address, args) in values {
+            if self.values.get(address) != Some(&args) {
+                changed.push(OscMessage::new(address, args.clone()));
+                self.values.insert(address, args);
+            }
+        }
+
+        changed
+    }
+
+    fn messages(&self) -> Vec<OscMessage> {
+        self.values
+            .iter()
+            .map(|(address, args)| OscMessage::new(address, args.clone()))
+            .collect()
+    }
+
+    fn playback_values(&self) -> Vec<(&'static str, Vec<OscArg>)> {
+        let playback_state = &self.playback_state;
+        let playing = playback_state.playing.enum_value_or_default() == PlayingState::PLAYING;
+
+        let (song_id, section_id) = if playing && playback_state.song_id != INVALID_ID {
+            (playback_state.song_id, playback_state.section_id)
+        } else {
+            (self.project.selections.song, self.project.selections.section)
+        };
+
+        let song_index = self.project.songs.iter().position(|song| song.id == song_id);
+        let song = song_index.map(|index| &self.project.songs[index]);
+        let section_index = song.and_then(|song| song.sections.iter().position(|section| section.id == section_id));
+        let section = song.zip(section_index).map(|(song, index)| &song.sections[index]);
+
+        let flag = |value: bool| vec![OscArg::Int(value as i32)];
+        let index = |index: Option<usize>| vec![OscArg::Int(index.map_or(-1, |index| index as i32))];
+        let name = |name: Option<&String>| vec![OscArg::String(name.cloned().unwrap_or_default())];
+
+        vec![
+            ("/bloop/playing", flag(playing)),
+            ("/bloop/looping", flag(playback_state.looping)),
+            (
+                "/bloop/queued",
+                flag(playback_state.queued_song_id != INVALID_ID || playback_state.queued_section_id != INVALID_ID),
+            ),
+            ("/bloop/song/index", index(song_index)),
+            ("/bloop/song/name", name(song.map(|song| &song.name))),
+            ("/bloop/section/index", index(section_index)),
+            ("/bloop/section/name", name(section.map(|section| &section.name))),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloop::{Progress, Section, Selections, Song};
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn message(address: &str, args: Vec<OscArg>) -> OscMessage {
+        OscMessage::new(address, args)
+    }
+
+    fn project() -> Project {
+        let song = |id, name: &str, sections: &[(u64, &str)]| Song {
+            id,
+            name: name.to_string(),
+            sections: sections
+                .iter()
+                .map(|(id, name)| Section {
+                    id: *id,
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        Project {
+            songs: vec![
+                song(1, "First", &[(10, "Intro"), (11, "Verse")]),
+                song(2, "Second", &[(20, "Chorus")]),
+            ],
+            selections: Some(Selections {
+                song: 1,
+                section: 11,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn addresses_map_to_requests() {
+        let request = request_for_message(&message("/bloop/transport/play", vec![])).unwrap();
+        assert_eq!(request.transport.method.enum_value_or_default(), TransportMethod::PLAY);
+
+        let request = request_for_message(&message("/bloop/song/select", vec![OscArg::Float(2.0)])).unwrap();
+        let action = request.action.as_ref().unwrap();
+        assert_eq!(action.action.enum_value_or_default(), Action::ACTION_SELECT_SONG);
+        assert_eq!(action.value, 2);
+
+        let request = request_for_message(&message("/bloop/action/toggle_loop", vec![])).unwrap();
+        assert_eq!(
+            request.action.action.enum_value_or_default(),
+            Action::ACTION_TOGGLE_LOOP
+        );
+
+        let request = request_for_message(&message("/bloop/macro", vec![OscArg::String("Outro".to_string())])).unwrap();
+        assert_eq!(request.action.macro_name, "Outro");
+
+        assert!(request_for_message(&message("/bloop/action/unknown", vec![])).is_none());
+        assert!(request_for_message(&message("/other", vec![])).is_none());
+    }
+
+    #[test]
+    fn button_releases_are_ignored() {
+        assert!(request_for_message(&message("/bloop/song/next", vec![OscArg::Float(1.0)])).is_some());
+        assert!(request_for_message(&message("/bloop/song/next", vec![OscArg::Float(0.0)])).is_none());
+    }
+
+    #[test]
+    fn only_changed_values_are_published() {
+        let mut state = PublishedState::default();
+        let response = Response::default().with_project(&project());
+
+        let messages = state.update(&response);
+        assert!(messages.contains(&message("/bloop/song/name", vec![OscArg::String("First".to_string())])));
+        assert!(messages.contains(&message("/bloop/section/index", vec![OscArg::Int(1)])));
+
+        assert!(state.update(&response).is_empty());
+
+        let playing = PlaybackState {
+            playing: PlayingState::PLAYING.into(),
+            song_id: 2,
+            section_id: 20,
+            ..Default::default()
+        };
+        let messages = state.update(&Response::default().with_playback_state(&playing));
+        assert_eq!(
+            messages,
+            vec![
+                message("/bloop/playing", vec![OscArg::Int(1)]),
+                message("/bloop/song/index", vec![OscArg::Int(1)]),
+                message("/bloop/song/name", vec![OscArg::String("Second".to_string())]),
+                message("/bloop/section/index", vec![OscArg::Int(0)]),
+                message("/bloop/section/name", vec![OscArg::String("Chorus".to_string())]),
+            ]
+        );
+    }
+
+    #[test]
+    fn listens_on_this_machine_unless_an_address_is_set() {
+        let mut preferences = default_osc_preferences();
+        assert_eq!(
+            listen_address(&preferences),
+            Some(SocketAddr::from(([127, 0, 0, 1], 9000)))
+        );
+
+        preferences.bind_address = "0.0.0.0".to_string();
+        assert_eq!(
+            listen_address(&preferences),
+            Some(SocketAddr::from(([0, 0, 0, 0], 9000)))
+        );
+
+        preferences.bind_address = "not an address".to_string();
+        assert_eq!(listen_address(&preferences), None);
+
+        preferences.bind_address = String::new();
+        preferences.port = 70_000;
+        assert_eq!(listen_address(&preferences), None);
+    }
+
+    async fn receive(socket: &UdpSocket) -> OscMessage {
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        let (length, _) = timeout(Duration::from_secs(1), socket.recv_from(&mut buffer))
+            .await
+            .expect("Timed out waiting for OSC")
+            .unwrap();
+        decode_packet(&buffer[..length]).unwrap().remove(0)
+    }
+
+    async fn bind_loopback(
+        request_tx: mpsc::Sender<Request>,
+        pairing: PairingStore,
+        trusted_hosts: HashSet<IpAddr>,
+    ) -> OscServer {
+        OscServer::bind(
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            request_tx,
+            Arc::new(pairing),
+            trusted_hosts,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn loopback_requests_and_publishing() {
+        let (request_tx, mut request_rx) = mpsc::channel(16);
+        let (response_tx, _) = broadcast::channel(16);
+        let root_directory = tempfile::tempdir().unwrap();
+
+        let server = bind_loopback(request_tx, PairingStore::new(root_directory.path()), HashSet::new()).await;
+        let server_address = server.local_addr().unwrap();
+        tokio::spawn(server.run(response_tx.subscribe()));
+
+        // The server asks for the project when it starts
+        let request = request_rx.recv().await.unwrap();
+        assert_eq!(request.get.entity.enum_value_or_default(), Entity::PROJECT);
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let send = |message: OscMessage| {
+            let client = &client;
+            async move { client.send_to(&message.encode(), server_address).await.unwrap() }
+        };
+
+        send(message("/bloop/transport/stop", vec![])).await;
+        let request = timeout(Duration::from_secs(1), request_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.transport.method.enum_value_or_default(), TransportMethod::STOP);
+
+        send(message("/bloop/register", vec![])).await;
+        // Wait for the registration to be handled before publishing
+        send(message("/bloop/transport/play", vec![])).await;
+        timeout(Duration::from_secs(1), request_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let progress = Progress {
+            section_beat: 3.0,
+            ..Default::default()
+        };
+        response_tx.send(Response::default().with_progress(&progress)).unwrap();
+
+        let mut received = Vec::new();
+        while !received.contains(&message("/bloop/progress/beat", vec![OscArg::Float(3.0)])) {
+            received.push(receive(&client).await);
+        }
+        assert!(received.contains(&message("/bloop/playing", vec![OscArg::Int(0)])));
+    }
+
+    #[tokio::test]
+    async fn changes_from_untrusted_hosts_are_dropped() {
+        let root_directory = tempfile::tempdir().unwrap();
+        let untrusted = || PairingStore::new(root_directory.path()).with_trusted_loopback(false);
+
+        for (pairing, trusted_hosts, accepted) in [
+            (untrusted(), HashSet::new(), false),
+            (untrusted(), HashSet::from([IpAddr::from([127, 0, 0, 1])]), true),
+        ] {
+            let (request_tx, mut request_rx) = mpsc::channel(16);
+            let (response_tx, _) = broadcast::channel(16);
+
+            let server = bind_loopback(request_tx, pairing, trusted_hosts).await;
+            let server_address = server.local_addr().unwrap();
+            tokio::spawn(server.run(response_tx.subscribe()));
+
+            // Asking for the project only reads, so it's always allowed
+            let request = request_rx.recv().await.unwrap();
+            assert_eq!(request.get.entity.enum_value_or_default(), Entity::PROJECT);
+
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client
+                .send_to(&message("/bloop/transport/stop", vec![]).encode(), server_address)
+                .await
+                .unwrap();
+
+            let received = timeout(Duration::from_millis(200), request_rx.recv()).await;
+            assert_eq!(received.is_ok(), accepted, "{:?}", received);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::{anyhow, bail};
+use std::convert::{TryFrom, TryInto};
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+
+/// An argument of an OSC message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Bool(bool),
+}
+
+impl OscArg {
+    /// The argument as a number, if it is one. Booleans count as 0 or 1, as sent by
+    /// toggle buttons.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            OscArg::Int(value) => Some(*value as f64),
+            OscArg::Long(value) => Some(*value as f64),
+            OscArg::Float(value) => Some(*value as f64),
+            OscArg::Double(value) => Some(*value),
+            OscArg::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+            OscArg::String(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            OscArg::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn type_tag(&self) -> u8 {
+        match self {
+            OscArg::Int(_) => b'i',
+            OscArg::Long(_) => b'h',
+            OscArg::Float(_) => b'f',
+            OscArg::Double(_) => b'd',
+            OscArg::String(_) => b's',
+            OscArg::Bool(true) => b'T',
+            OscArg::Bool(false) => b'F',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: &str, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_string(&mut data, &self.address);
+
+        let type_tags: String = std::iter::once(',')
+            .chain(self.args.iter().map(|arg| arg.type_tag() as char))
+            .collect();
+        write_string(&mut data, &type_tags);
+
+        for arg in self.args.iter() {
+            match arg {
+                OscArg::Int(value) => data.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Long(value) => data.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Float(value) => data.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Double(value) => data.extend_from_slice(&value.to_be_bytes()),
+                OscArg::String(value) => write_string(&mut data, value),
+                OscArg::Bool(_) => (),
+            }
+        }
+
+        data
+    }
+}
+
+/// Decode an OSC packet, flattening any bundles into the messages they contain.
+/// Bundle time tags are ignored and messages are returned in the order received.
+pub fn decode_packet(data: &[u8]) -> anyhow::Result<Vec<OscMessage>> {
+    let mut messages = Vec::new();
+    decode_into(data, &mut messages)?;
+    Ok(messages)
+}
+
+fn decode_into(data: &[u8], messages: &mut Vec<OscMessage>) -> anyhow::Result<()> {
+    if !data.starts_with(BUNDLE_TAG) {
+        messages.push(decode_message(data)?);
+        return Ok(());
+    }
+
+    // Skip the tag and the 8-byte time tag
+    let mut reader = Reader::new(data);
+    reader.take(BUNDLE_TAG.len() + 8)?;
+
+    while !reader.is_empty() {
+        let size = reader.read_i32()?;
+        let size = usize::try_from(size).map_err(|_| anyhow!("Invalid bundle element size {size}"))?;
+        decode_into(reader.take(size)?, messages)?;
+    }
+
+    Ok(())
+}
+
+fn decode_message(data: &[u8]) -> anyhow::Result<OscMessage> {
+    let mut reader = Reader::new(data);
+
+    let address = reader.read_string()?;
+    if !address.starts_with('/') {
+        bail!("Invalid OSC address '{address}'");
+    }
+
+    // Some older senders omit the type tags for messages without arguments
+    if reader.is_empty() {
+        return Ok(OscMessage::new(&address, vec![]));
+    }
+
+    let type_tags = reader.read_string()?;
+    let type_tags = type_tags
+        .strip_prefix(',')
+        .ok_or_else(|| anyhow!("Missing OSC type tags"))?;
+
+    let args = type_tags
+        .chars()
+        .map(|tag| match tag {
+            'i' => Ok(OscArg::Int(reader.read_i32()?)),
+            'h' => Ok(OscArg::Long(i64::from_be_bytes(reader.take(8)?.try_into()?))),
+            'f' => Ok(OscArg::Float(f32::from_be_bytes(reader.take(4)?.try_into()?))),
+            'd' => Ok(OscArg::Double(f64::from_be_bytes(reader.take(8)?.try_into()?))),
+            's' => Ok(OscArg::String(reader.read_string()?)),
+            'T' => Ok(OscArg::Bool(true)),
+            'F' => Ok(OscArg::Bool(false)),
+            tag => Err(anyhow!("Unsupported OSC type tag '{tag}'")),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(OscMessage { address, args })
+}
+
+/// Write a null-terminated string, padded to a multiple of four bytes.
+fn write_string(data: &mut Vec<u8>, value: &str) {
+    data.extend_from_slice(value.as_bytes());
+    let padding = 4 - value.len() % 4;
+    data.extend(std::iter::repeat_n(0, padding));
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        if length > self.data.len() {
+            bail!("Truncated OSC packet");
+        }
+
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn read_i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn read_string(&mut self) -> anyhow::Result<String> {
+        let length = self
+            .data
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| anyhow!("Unterminated OSC string"))?;
+        let value = std::str::from_utf8(&self.data[..length])?.to_string();
+        self.take((length / 4 + 1) * 4)?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_round_trips() {
+        let message = OscMessage::new(
+            "/bloop/song/name",
+            vec![
+                OscArg::String("Intro".to_string()),
+                OscArg::Int(-3),
+                OscArg::Float(0.5),
+                OscArg::Bool(true),
+                OscArg::Double(1.25),
+                OscArg::Long(1 << 40),
+            ],
+        );
+
+        assert_eq!(decode_packet(&message.encode()).unwrap(), vec![message]);
+    }
+
+    #[test]
+    fn strings_are_padded_to_four_bytes() {
+        let data = OscMessage::new("/abc", vec![]).encode();
+        assert_eq!(data, b"/abc\0\0\0\0,\0\0\0");
+    }
+
+    #[test]
+    fn bundles_are_flattened() {
+        let first = OscMessage::new("/a", vec![OscArg::Int(1)]).encode();
+        let second = OscMessage::new("/b", vec![]).encode();
+
+        let mut data = BUNDLE_TAG.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        for element in [&first, &second] {
+            data.extend_from_slice(&(element.len() as i32).to_be_bytes());
+            data.extend_from_slice(element);
+        }
+
+        let messages = decode_packet(&data).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].address, "/a");
+        assert_eq!(messages[1].address, "/b");
+    }
+
+    #[test]
+    fn truncated_packets_are_rejected() {
+        let data = OscMessage::new("/a", vec![OscArg::Int(1)]).encode();
+        assert!(decode_packet(&data[..data.len() - 2]).is_err());
+        assert!(decode_packet(b"nope").is_err());
+    }
+}
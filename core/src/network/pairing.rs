@@ -0,0 +1,524 @@
+use crate::api::api_error;
+use crate::bloop::{DeviceAuthentication, DeviceRole, ErrorCode, PairedDevice, PairingStatus, Request};
+use crate::model::random_project_id;
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const DEVICES_FILE: &str = "paired_devices.json";
+const PIN_LENGTH: usize = 6;
+const TOKEN_LENGTH: usize = 32;
+
+/// The PIN is replaced after this many wrong guesses, so guesses can't rule out
+/// combinations of the same PIN one by one.
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+/// An address that makes too many wrong guesses can't try again for this long,
+/// which limits how quickly the PIN can be guessed at all.
+const LOCKOUT_DURATION: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum StoredRole {
+    #[default]
+    Controller,
+    Viewer,
+}
+
+impl From<DeviceRole> for StoredRole {
+    fn from(role: DeviceRole) -> Self {
+        match role {
+            DeviceRole::DEVICE_ROLE_CONTROLLER => StoredRole::Controller,
+            DeviceRole::DEVICE_ROLE_VIEWER => StoredRole::Viewer,
+        }
+    }
+}
+
+impl From<StoredRole> for DeviceRole {
+    fn from(role: StoredRole) -> Self {
+        match role {
+            StoredRole::Controller => DeviceRole::DEVICE_ROLE_CONTROLLER,
+            StoredRole::Viewer => DeviceRole::DEVICE_ROLE_VIEWER,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct StoredDevice {
+    id: String,
+    name: String,
+    token: String,
+    paired_at: DateTime<Utc>,
+    /// Devices paired before roles existed are controllers.
+    #[serde(default)]
+    role: StoredRole,
+}
+
+impl StoredDevice {
+    fn to_paired_device(&self) -> PairedDevice {
+        PairedDevice {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            paired_at: self.paired_at.to_rfc3339(),
+            role: DeviceRole::from(self.role).into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Wrong guesses from one address.
+#[derive(Default)]
+struct FailedAttempts {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+struct State {
+    pin: String,
+    failed_attempts: u32,
+    failed_attempts_by_address: HashMap<IpAddr, FailedAttempts>,
+    devices: Vec<StoredDevice>,
+}
+
+/// Devices paired for control over the network.
+///
+/// A device pairs by sending the PIN shown in the UI and logs, and is given a token
+/// to authenticate with when it reconnects. Tokens are kept in
+/// `paired_devices.json` in the root directory until the device is revoked.
+///
+/// Each device is either a controller, or a viewer that can follow along but not
+/// make changes. Devices pair as viewers, so only someone who can already make
+/// changes can let a device make them too.
+pub struct PairingStore {
+    path: PathBuf,
+    trust_loopback: bool,
+    state: Mutex<State>,
+}
+
+impl PairingStore {
+    pub fn new(root_directory: &Path) -> Self {
+        let path = root_directory.join(DEVICES_FILE);
+
+        let devices = match read_devices(&path) {
+            Ok(devices) => devices,
+            Err(error) => {
+                warn!("Unable to read paired devices: {error}");
+                vec![]
+            }
+        };
+
+        let pin = random_pin();
+        info!("Pairing PIN: {pin}");
+
+        Self {
+            path,
+            trust_loopback: true,
+            state: Mutex::new(State {
+                pin,
+                failed_attempts: 0,
+                failed_attempts_by_address: HashMap::new(),
+                devices,
+            }),
+        }
+    }
+
+    /// Whether connections from this machine are allowed without pairing.
+    #[cfg(test)]
+    pub fn with_trusted_loopback(mut self, trust_loopback: bool) -> Self {
+        self.trust_loopback = trust_loopback;
+        self
+    }
+
+    /// Pair a device with the current PIN, returning its ID and token. The PIN
+    /// changes once it has been used. New devices are viewers until they're made
+    /// controllers with `set_role`.
+    pub fn pair(&self, address: &SocketAddr, pin: &str, device_name: &str) -> anyhow::Result<DeviceAuthentication> {
+        let mut state = self.state();
+        let now = Instant::now();
+
+        state
+            .failed_attempts_by_address
+            .retain(|_, attempts| attempts.locked_until.map_or(true, |locked_until| locked_until > now));
+
+        let is_correct = constant_time_eq(pin.as_bytes(), state.pin.as_bytes());
+        let attempts = state.failed_attempts_by_address.entry(address.ip()).or_default();
+        if attempts.locked_until.is_some() {
+            bail!("Too many incorrect PINs, try again later");
+        }
+
+        if !is_correct {
+            attempts.count += 1;
+            if attempts.count >= MAX_FAILED_ATTEMPTS {
+                warn!("Too many incorrect PINs from {}", address.ip());
+                attempts.locked_until = Some(now + LOCKOUT_DURATION);
+            }
+
+            state.failed_attempts += 1;
+            if state.failed_attempts >= MAX_FAILED_ATTEMPTS {
+                replace_pin(&mut state);
+            }
+            bail!("Incorrect PIN");
+        }
+
+        state.failed_attempts_by_address.remove(&address.ip());
+
+        let device = StoredDevice {
+            id: random_project_id(),
+            name: device_name.to_string(),
+            token: random_token(),
+            paired_at: Utc::now(),
+            role: StoredRole::Viewer,
+        };
+
+        let mut devices = state.devices.clone();
+        devices.push(device.clone());
+        write_devices(&self.path, &devices)?;
+        state.devices = devices;
+
+        info!("Paired device: {} ({})", device.name, device.id);
+        replace_pin(&mut state);
+
+        Ok(DeviceAuthentication {
+            device_id: device.id,
+            token: device.token,
+            role: DeviceRole::from(device.role).into(),
+            ..Default::default()
+        })
+    }
+
+    /// The device with this token, if it's paired.
+    pub fn device_for_token(&self, token: &str) -> Option<PairedDevice> {
+        if token.is_empty() {
+            return None;
+        }
+
+        self.state()
+            .devices
+            .iter()
+            .find(|device| constant_time_eq(device.token.as_bytes(), token.as_bytes()))
+            .map(StoredDevice::to_paired_device)
+    }
+
+    /// The role of a connection, or `None` if it isn't paired. Connections from
+    /// this machine are controllers.
+    pub fn role(&self, address: &SocketAddr, token: Option<&str>) -> Option<DeviceRole> {
+        if self.trust_loopback && address.ip().is_loopback() {
+            return Some(DeviceRole::DEVICE_ROLE_CONTROLLER);
+        }
+
+        let device = self.device_for_token(token?)?;
+        Some(device.role.enum_value_or_default())
+    }
+
+    pub fn set_role(&self, device_id: &str, role: DeviceRole) -> anyhow::Result<()> {
+        let mut state = self.state();
+
+        let mut devices = state.devices.clone();
+        let device = devices
+            .iter_mut()
+            .find(|device| device.id == device_id)
+            .ok_or_else(|| anyhow!("No paired device with ID '{device_id}'"))?;
+        device.role = role.into();
+
+        write_devices(&self.path, &devices)?;
+        state.devices = devices;
+
+        info!("Device {device_id} is now a {role:?}");
+        Ok(())
+    }
+
+    pub fn revoke(&self, device_id: &str) -> anyhow::Result<()> {
+        let mut state = self.state();
+
+        let devices: Vec<StoredDevice> = state
+            .devices
+            .iter()
+            .filter(|device| device.id != device_id)
+            .cloned()
+            .collect();
+
+        if devices.len() == state.devices.len() {
+            return Err(anyhow!("No paired device with ID '{device_id}'"));
+        }
+
+        write_devices(&self.path, &devices)?;
+        state.devices = devices;
+
+        info!("Revoked device: {device_id}");
+        Ok(())
+    }
+
+    pub fn status(&self) -> PairingStatus {
+        let state = self.state();
+
+        PairingStatus {
+            pin: state.pin.clone(),
+            devices: state.devices.iter().map(StoredDevice::to_paired_device).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Check that a connection with this role, or `None` if it isn't paired, may send
+/// the request.
+pub fn check_access(request: &Request, role: Option<DeviceRole>) -> anyhow::Result<()> {
+    if is_read_only(request) {
+        return Ok(());
+    }
+
+    match role {
+        Some(DeviceRole::DEVICE_ROLE_CONTROLLER) => Ok(()),
+        Some(DeviceRole::DEVICE_ROLE_VIEWER) => {
+            Err(api_error(ErrorCode::ERROR_CODE_FORBIDDEN, "Viewers can't make changes"))
+        }
+        None => Err(api_error(
+            ErrorCode::ERROR_CODE_NOT_PAIRED,
+            "Pair this device to make changes",
+        )),
+    }
+}
+
+/// Requests that only read state, which viewers and unpaired connections may
+/// still send.
+fn is_read_only(request: &Request) -> bool {
+    let mut changes = request.clone();
+    changes.get = Default::default();
+    changes.request_id = Default::default();
+    changes == Request::default()
+}
+
+/// Compare secrets without the time taken depending on how much of them matches.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+fn replace_pin(state: &mut State) {
+    state.pin = random_pin();
+    state.failed_attempts = 0;
+    info!("Pairing PIN: {}", state.pin);
+}
+
+fn random_pin() -> String {
+    let mut rng = rand::rng();
+    (0..PIN_LENGTH)
+        .map(|_| char::from(b'0' + rng.random_range(0..10u8)))
+        .collect()
+}
+
+fn random_token() -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+    let mut rng = rand::rng();
+    (0..TOKEN_LENGTH)
+        .map(|_| CHARSET[rng.random_range(0..CHARSET.len())] as char)
+        .collect()
+}
+
+fn read_devices(path: &Path) -> anyhow::Result<Vec<StoredDevice>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let json = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+fn write_devices(path: &Path, devices: &[StoredDevice]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let json = serde_json::to_string_pretty(devices)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::error_code;
+    use crate::bloop::{Entity, TransportMethod};
+    use tempfile::TempDir;
+
+    const CONTROLLER: DeviceRole = DeviceRole::DEVICE_ROLE_CONTROLLER;
+    const VIEWER: DeviceRole = DeviceRole::DEVICE_ROLE_VIEWER;
+
+    fn remote_address() -> SocketAddr {
+        "192.168.1.20:50000".parse().unwrap()
+    }
+
+    #[test]
+    fn pairing_needs_the_pin() {
+        let directory = TempDir::new().unwrap();
+        let store = PairingStore::new(directory.path());
+
+        assert!(store.pair(&remote_address(), "not a pin", "Phone").is_err());
+
+        let authentication = store.pair(&remote_address(), &store.status().pin, "Phone").unwrap();
+        let device = store.device_for_token(&authentication.token).unwrap();
+        assert_eq!(device.id, authentication.device_id);
+        assert_eq!(device.name, "Phone");
+        assert_eq!(authentication.role.enum_value_or_default(), VIEWER);
+        assert_eq!(store.role(&remote_address(), Some(&authentication.token)), Some(VIEWER));
+    }
+
+    #[test]
+    fn pin_changes_after_pairing() {
+        let directory = TempDir::new().unwrap();
+        let store = PairingStore::new(directory.path());
+
+        let pin = store.status().pin;
+        store.pair(&remote_address(), &pin, "Phone").unwrap();
+
+        // Another device can't pair with the same PIN, unless it happens to be repeated
+        if store.status().pin != pin {
+            assert!(store.pair(&remote_address(), &pin, "Tablet").is_err());
+        }
+    }
+
+    #[test]
+    fn pin_changes_after_too_many_failed_attempts() {
+        let directory = TempDir::new().unwrap();
+        let store = PairingStore::new(directory.path());
+
+        let pin = store.status().pin;
+        let wrong_pin = if pin == "000000" { "111111" } else { "000000" };
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            assert!(store.pair(&remote_address(), wrong_pin, "Intruder").is_err());
+        }
+
+        assert_eq!(store.state().failed_attempts, 0);
+    }
+
+    #[test]
+    fn addresses_are_locked_out_after_too_many_failed_attempts() {
+        let directory = TempDir::new().unwrap();
+        let store = PairingStore::new(directory.path());
+        let other_address: SocketAddr = "192.168.1.21:50000".parse().unwrap();
+
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            let pin = store.status().pin;
+            let wrong_pin = if pin == "000000" { "111111" } else { "000000" };
+            assert!(store.pair(&remote_address(), wrong_pin, "Intruder").is_err());
+        }
+
+        assert!(store.pair(&remote_address(), &store.status().pin, "Intruder").is_err());
+        assert!(store.pair(&other_address, &store.status().pin, "Phone").is_ok());
+    }
+
+    #[test]
+    fn secrets_are_compared_in_full() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn paired_devices_are_remembered() {
+        let directory = TempDir::new().unwrap();
+
+        let authentication = {
+            let store = PairingStore::new(directory.path());
+            store.pair(&remote_address(), &store.status().pin, "Phone").unwrap()
+        };
+
+        let store = PairingStore::new(directory.path());
+        assert!(store.device_for_token(&authentication.token).is_some());
+        assert_eq!(store.status().devices[0].name, "Phone");
+        assert_eq!(store.status().devices[0].role.enum_value_or_default(), VIEWER);
+    }
+
+    #[test]
+    fn devices_without_a_role_are_controllers() {
+        let directory = TempDir::new().unwrap();
+        std::fs::write(
+            directory.path().join(DEVICES_FILE),
+            r#"[{"id": "a", "name": "Phone", "token": "abc", "paired_at": "2026-01-01T00:00:00Z"}]"#,
+        )
+        .unwrap();
+
+        let store = PairingStore::new(directory.path());
+        assert_eq!(store.role(&remote_address(), Some("abc")), Some(CONTROLLER));
+    }
+
+    #[test]
+    fn role_can_be_changed() {
+        let directory = TempDir::new().unwrap();
+        let store = PairingStore::new(directory.path());
+
+        let authentication = store.pair(&remote_address(), &store.status().pin, "Phone").unwrap();
+        store.set_role(&authentication.device_id, CONTROLLER).unwrap();
+        assert_eq!(
+            store.role(&remote_address(), Some(&authentication.token)),
+            Some(CONTROLLER)
+        );
+
+        store.set_role(&authentication.device_id, VIEWER).unwrap();
+        assert_eq!(store.role(&remote_address(), Some(&authentication.token)), Some(VIEWER));
+        assert!(store.set_role("missing", VIEWER).is_err());
+    }
+
+    #[test]
+    fn revoked_devices_are_not_paired() {
+        let directory = TempDir::new().unwrap();
+        let store = PairingStore::new(directory.path());
+
+        let authentication = store.pair(&remote_address(), &store.status().pin, "Phone").unwrap();
+        store.revoke(&authentication.device_id).unwrap();
+
+        assert_eq!(store.role(&remote_address(), Some(&authentication.token)), None);
+        assert!(store.status().devices.is_empty());
+        assert!(store.revoke(&authentication.device_id).is_err());
+    }
+
+    #[test]
+    fn loopback_is_trusted_unless_disabled() {
+        let directory = TempDir::new().unwrap();
+        let local_address: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+
+        let store = PairingStore::new(directory.path());
+        assert_eq!(store.role(&local_address, None), Some(CONTROLLER));
+        assert_eq!(store.role(&remote_address(), None), None);
+        assert_eq!(store.role(&remote_address(), Some("")), None);
+
+        let store = store.with_trusted_loopback(false);
+        assert_eq!(store.role(&local_address, None), None);
+    }
+
+    #[test]
+    fn viewers_can_only_read() {
+        let get = Request::get_request(Entity::PROJECT, 0);
+        let stop = Request::transport_request(TransportMethod::STOP);
+
+        assert!(check_access(&get, Some(VIEWER)).is_ok());
+        assert!(check_access(&get, None).is_ok());
+        assert!(check_access(&stop, Some(CONTROLLER)).is_ok());
+
+        let error = check_access(&stop, Some(VIEWER)).unwrap_err();
+        assert_eq!(error_code(&error), ErrorCode::ERROR_CODE_FORBIDDEN);
+
+        let error = check_access(&stop, None).unwrap_err();
+        assert_eq!(error_code(&error), ErrorCode::ERROR_CODE_NOT_PAIRED);
+    }
+
+    #[test]
+    fn only_get_requests_are_read_only() {
+        assert!(is_read_only(&Request::get_request(Entity::PROJECT, 0)));
+        assert!(is_read_only(&Request::get_request(Entity::ALL, 0).with_request_id("a")));
+        assert!(!is_read_only(&Request::transport_request(TransportMethod::STOP)));
+    }
+}
@@ -1,23 +1,37 @@
-use super::client;
+use super::{client, PairingStore};
 use crate::bloop::{Request, Response};
 use get_if_addrs::{get_if_addrs, Interface};
 use log::{debug, info, warn};
 use mdns_sd::{ServiceDaemon, ServiceInfo};
-use std::net::IpAddr;
+use std::{net::IpAddr, sync::Arc};
 use tokio::net::TcpListener;
 use tokio::sync::{broadcast, mpsc};
 
 const PORT: u16 = 14072;
 
-pub async fn run(request_tx: mpsc::Sender<Request>, response_tx: broadcast::Sender<Response>) {
+pub async fn run(
+    request_tx: mpsc::Sender<Request>,
+    response_tx: broadcast::Sender<Response>,
+    pairing: Arc<PairingStore>,
+) {
     let ips = get_ips_for_responder();
 
     for ip in ips.iter() {
-        tokio::spawn(listen_on_ip(*ip, request_tx.clone(), response_tx.clone()));
+        tokio::spawn(listen_on_ip(
+            *ip,
+            request_tx.clone(),
+            response_tx.clone(),
+            pairing.clone(),
+        ));
     }
 }
 
-pub async fn listen_on_ip(ip: IpAddr, request_tx: mpsc::Sender<Request>, response_tx: broadcast::Sender<Response>) {
+pub async fn listen_on_ip(
+    ip: IpAddr,
+    request_tx: mpsc::Sender<Request>,
+    response_tx: broadcast::Sender<Response>,
+    pairing: Arc<PairingStore>,
+) {
     let address = format!("{ip}:{PORT}");
     info!("Binding to: {address}");
     let listener = match TcpListener::bind(address.clone()).await {
@@ -61,8 +75,9 @@ pub async fn listen_on_ip(ip: IpAddr, request_tx: mpsc::Sender<Request>, respons
     while let Ok((stream, _)) = listener.accept().await {
         let tx = request_tx.clone();
         let rx = response_tx.subscribe();
+        let pairing = pairing.clone();
         tokio::spawn(async move {
-            client::run(stream, tx, rx).await;
+            client::run(stream, tx, rx, pairing).await;
         });
     }
 
@@ -0,0 +1,456 @@
+use crate::bloop::{Response, SubscribeRequest, Topic};
+
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+
+const TOPICS: [Topic; 5] = [
+    Topic::TOPIC_PROJECT,
+    Topic::TOPIC_PLAYBACK,
+    Topic::TOPIC_PROGRESS,
+    Topic::TOPIC_WAVEFORM,
+    Topic::TOPIC_AUDIO_STATUS,
+];
+
+/// Requested rates are clamped to this range, in updates per second.
+const MIN_RATE: f64 = 0.01;
+const MAX_RATE: f64 = 1000.0;
+
+struct TopicState {
+    /// The shortest time between updates, or `None` for every update.
+    min_interval: Option<Duration>,
+    last_sent: Option<Instant>,
+    /// The latest update held back by the rate limit, keyed by what it's about so
+    /// that waveforms for different samples aren't coalesced.
+    pending: Vec<(u64, Response)>,
+}
+
+impl TopicState {
+    fn is_due(&self, now: Instant) -> bool {
+        match (self.min_interval, self.last_sent) {
+            (Some(min_interval), Some(last_sent)) => now >= last_sent + min_interval,
+            _ => true,
+        }
+    }
+
+    fn defer(&mut self, key: u64, part: Response) {
+        self.pending.retain(|(pending_key, _)| *pending_key != key);
+        self.pending.push((key, part));
+    }
+}
+
+/// The topics a connection wants and how often it wants them.
+///
+/// Parts of a response for topics the connection hasn't subscribed to are
+/// removed, and parts that arrive faster than the topic's maximum rate are held
+/// back so that only the latest is sent. Responses that aren't for any topic, such
+/// as errors and replies to requests, always go through.
+#[derive(Default)]
+pub struct Subscriptions {
+    /// `None` until the connection subscribes, when it receives everything.
+    topics: Option<HashMap<Topic, TopicState>>,
+    project_patches: bool,
+    /// The project version the connection was last sent, while it uses patches.
+    project_version: Option<u64>,
+}
+
+impl Subscriptions {
+    /// Updates held back for topics that are subscribed to again are kept, and
+    /// sent at the new rate.
+    pub fn update(&mut self, request: &SubscribeRequest) {
+        let mut previous_topics = self.topics.take().unwrap_or_default();
+
+        let topics = request
+            .subscriptions
+            .iter()
+            .map(|subscription| {
+                let min_interval = if subscription.max_rate > 0.0 {
+                    Some(Duration::from_secs_f64(
+                        1.0 / subscription.max_rate.clamp(MIN_RATE, MAX_RATE),
+                    ))
+                } else {
+                    None
+                };
+
+                let topic = subscription.topic.enum_value_or_default();
+                let previous = previous_topics.remove(&topic);
+
+                let state = TopicState {
+                    min_interval,
+                    last_sent: previous.as_ref().and_then(|previous| previous.last_sent),
+                    pending: previous.map(|previous| previous.pending).unwrap_or_default(),
+                };
+
+                (topic, state)
+            })
+            .collect();
+
+        self.topics = Some(topics);
+        self.project_patches = request.project_patches;
+        self.project_version = None;
+    }
+
+    /// The part of a broadcast response to send now, if any.
+    pub fn filter(&mut self, mut response: Response, now: Instant) -> Option<Response> {
+        let Some(topics) = self.topics.as_mut() else {
+            return Some(self.encode_project(response));
+        };
+
+        let was_empty = response == Response::default();
+
+        for topic in TOPICS {
+            let Some((key, part)) = take_topic(&mut response, topic) else {
+                continue;
+            };
+
+            let Some(state) = topics.get_mut(&topic) else {
+                continue;
+            };
+
+            if state.is_due(now) {
+                state.last_sent = Some(now);
+                state.pending.retain(|(pending_key, _)| *pending_key != key);
+                merge(&mut response, part);
+            } else {
+                state.defer(key, part);
+            }
+        }
+
+        if !was_empty && response == Response::default() {
+            return None;
+        }
+
+        Some(self.encode_project(response))
+    }
+
+    /// Updates that were held back and are now due.
+    pub fn flush(&mut self, now: Instant) -> Vec<Response> {
+        let Some(topics) = self.topics.as_mut() else {
+            return vec![];
+        };
+
+        let responses: Vec<_> = topics
+            .values_mut()
+            .filter(|state| !state.pending.is_empty() && state.is_due(now))
+            .flat_map(|state| {
+                state.last_sent = Some(now);
+                std::mem::take(&mut state.pending)
+                    .into_iter()
+                    .map(|(_, response)| response)
+            })
+            .collect();
+
+        responses
+            .into_iter()
+            .map(|response| self.encode_project(response))
+            .collect()
+    }
+
+    /// Project changes carry both the whole project and a patch. Connections using
+    /// patches get the patch when it follows on from the version they have, and
+    /// otherwise the whole project. Other connections only get the whole project.
+    fn encode_project(&mut self, mut response: Response) -> Response {
+        if !self.project_patches {
+            response.project_patch.clear();
+            return response;
+        }
+
+        if response.project.is_none() {
+            return response;
+        }
+
+        let follows_on = response.project_patch.is_some()
+            && self.project_version.map(|version| version + 1) == Some(response.project_patch.version);
+        if follows_on {
+            response.project.clear();
+        } else {
+            response.project_patch.clear();
+        }
+
+        self.project_version = Some(response.project_version);
+        response
+    }
+
+    /// When the next held back update is due.
+    pub fn next_flush(&self) -> Option<Instant> {
+        self.topics
+            .as_ref()?
+            .values()
+            .filter(|state| !state.pending.is_empty())
+            .map(|state| match (state.last_sent, state.min_interval) {
+                (Some(last_sent), Some(min_interval)) => last_sent + min_interval,
+                // Only after subscribing again without a limit, when they're due now
+                (last_sent, _) => last_sent.unwrap_or_else(Instant::now),
+            })
+            .min()
+    }
+}
+
+/// Move the fields for a topic into a response of their own, along with the key
+/// used to coalesce them.
+fn take_topic(response: &mut Response, topic: Topic) -> Option<(u64, Response)> {
+    let mut part = Response::default();
+    let mut key = 0;
+
+    match topic {
+        Topic::TOPIC_PROJECT => {
+            part.project = std::mem::take(&mut response.project);
+            part.project_version = std::mem::take(&mut response.project_version);
+            part.project_patch = std::mem::take(&mut response.project_patch);
+            part.project_info = std::mem::take(&mut response.project_info);
+        }
+        Topic::TOPIC_PLAYBACK => part.playback_state = std::mem::take(&mut response.playback_state),
+        Topic::TOPIC_PROGRESS => part.progress = std::mem::take(&mut response.progress),
+        Topic::TOPIC_WAVEFORM => {
+            part.waveform = std::mem::take(&mut response.waveform);
+            key = part.waveform.sample_id;
+        }
+        Topic::TOPIC_AUDIO_STATUS => part.audio_status = std::mem::take(&mut response.audio_status),
+        Topic::TOPIC_UNDEFINED => (),
+    }
+
+    if part == Response::default() {
+        return None;
+    }
+
+    Some((key, part))
+}
+
+fn merge(response: &mut Response, part: Response) {
+    if part.project.is_some() {
+        response.project = part.project;
+        response.project_version = part.project_version;
+        response.project_patch = part.project_patch;
+    }
+
+    if part.project_info.is_some() {
+        response.project_info = part.project_info;
+    }
+
+    if part.playback_state.is_some() {
+        response.playback_state = part.playback_state;
+    }
+
+    if part.progress.is_some() {
+        response.progress = part.progress;
+    }
+
+    if part.waveform.is_some() {
+        response.waveform = part.waveform;
+    }
+
+    if part.audio_status.is_some() {
+        response.audio_status = part.audio_status;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloop::{AudioStatus, PlaybackState, Progress, Project, ProjectPatch, Request, WaveformResponse};
+
+    fn subscribe(subscriptions: &[(Topic, f64)]) -> Subscriptions {
+        let mut result = Subscriptions::default();
+        result.update(&Request::subscribe_request(subscriptions).subscribe);
+        result
+    }
+
+    fn project_change(project: &Project, version: u64) -> Response {
+        let patch = ProjectPatch {
+            version,
+            selections: project.selections.clone(),
+            ..Default::default()
+        };
+
+        Response::default()
+            .with_project(project)
+            .with_project_version(version)
+            .with_project_patch(&patch)
+    }
+
+    fn subscribe_to_patches(max_rate: f64) -> Subscriptions {
+        let mut request = Request::subscribe_request(&[(Topic::TOPIC_PROJECT, max_rate)]);
+        request.subscribe.as_mut().unwrap().project_patches = true;
+
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.update(&request.subscribe);
+        subscriptions
+    }
+
+    fn progress(beat: f64) -> Response {
+        Response::default().with_progress(&Progress {
+            song_progress: beat,
+            ..Default::default()
+        })
+    }
+
+    fn waveform(sample_id: u64) -> Response {
+        Response::default().with_waveform(&WaveformResponse {
+            sample_id,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn everything_is_sent_before_subscribing() {
+        let mut subscriptions = Subscriptions::default();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(subscriptions.filter(progress(1.0), now).is_some());
+        }
+    }
+
+    #[test]
+    fn unsubscribed_topics_are_removed() {
+        let mut subscriptions = subscribe(&[(Topic::TOPIC_PROJECT, 0.0)]);
+        let now = Instant::now();
+
+        assert!(subscriptions.filter(progress(1.0), now).is_none());
+
+        let response = Response::default()
+            .with_project(&Project::default())
+            .with_playback_state(&PlaybackState::default());
+        let filtered = subscriptions.filter(response, now).unwrap();
+        assert!(filtered.project.is_some());
+        assert!(filtered.playback_state.is_none());
+    }
+
+    #[test]
+    fn responses_without_a_topic_are_always_sent() {
+        let mut subscriptions = subscribe(&[]);
+        let now = Instant::now();
+
+        assert_eq!(
+            subscriptions.filter(Response::default().with_error("Oops"), now),
+            Some(Response::default().with_error("Oops"))
+        );
+        assert_eq!(
+            subscriptions.filter(Response::default(), now),
+            Some(Response::default())
+        );
+    }
+
+    #[test]
+    fn fast_updates_are_coalesced() {
+        let mut subscriptions = subscribe(&[(Topic::TOPIC_PROGRESS, 10.0)]);
+        let start = Instant::now();
+
+        assert!(subscriptions.filter(progress(1.0), start).is_some());
+        assert!(subscriptions
+            .filter(progress(2.0), start + Duration::from_millis(20))
+            .is_none());
+        assert!(subscriptions
+            .filter(progress(3.0), start + Duration::from_millis(40))
+            .is_none());
+
+        assert_eq!(subscriptions.next_flush(), Some(start + Duration::from_millis(100)));
+        assert!(subscriptions.flush(start + Duration::from_millis(50)).is_empty());
+
+        let flushed = subscriptions.flush(start + Duration::from_millis(100));
+        assert_eq!(flushed, vec![progress(3.0)]);
+        assert_eq!(subscriptions.next_flush(), None);
+    }
+
+    #[test]
+    fn extreme_rates_are_clamped() {
+        let mut subscriptions = subscribe(&[(Topic::TOPIC_PROGRESS, 1e-20), (Topic::TOPIC_PLAYBACK, 1e20)]);
+        let start = Instant::now();
+
+        assert!(subscriptions.filter(progress(1.0), start).is_some());
+        assert!(subscriptions.filter(progress(2.0), start).is_none());
+        assert_eq!(
+            subscriptions.next_flush(),
+            Some(start + Duration::from_secs_f64(1.0 / MIN_RATE))
+        );
+    }
+
+    #[test]
+    fn held_back_updates_survive_subscribing_again() {
+        let mut subscriptions = subscribe(&[(Topic::TOPIC_PROGRESS, 10.0)]);
+        let start = Instant::now();
+
+        assert!(subscriptions.filter(progress(1.0), start).is_some());
+        assert!(subscriptions.filter(progress(2.0), start).is_none());
+
+        subscriptions.update(&Request::subscribe_request(&[(Topic::TOPIC_PROGRESS, 2.0)]).subscribe);
+        assert_eq!(subscriptions.next_flush(), Some(start + Duration::from_millis(500)));
+        assert_eq!(
+            subscriptions.flush(start + Duration::from_millis(500)),
+            vec![progress(2.0)]
+        );
+    }
+
+    #[test]
+    fn topics_are_limited_separately() {
+        let mut subscriptions = subscribe(&[(Topic::TOPIC_PROGRESS, 1.0), (Topic::TOPIC_AUDIO_STATUS, 0.0)]);
+        let now = Instant::now();
+
+        assert!(subscriptions.filter(progress(1.0), now).is_some());
+
+        let response = progress(2.0).with_audio_status(&AudioStatus::default());
+        let filtered = subscriptions.filter(response, now).unwrap();
+        assert!(filtered.audio_status.is_some());
+        assert!(filtered.progress.is_none());
+    }
+
+    #[test]
+    fn waveforms_for_different_samples_are_kept() {
+        let mut subscriptions = subscribe(&[(Topic::TOPIC_WAVEFORM, 1.0)]);
+        let start = Instant::now();
+
+        assert!(subscriptions.filter(waveform(1), start).is_some());
+        assert!(subscriptions.filter(waveform(2), start).is_none());
+        assert!(subscriptions.filter(waveform(3), start).is_none());
+        assert!(subscriptions.filter(waveform(2), start).is_none());
+
+        let flushed = subscriptions.flush(start + Duration::from_secs(1));
+        assert_eq!(flushed, vec![waveform(3), waveform(2)]);
+    }
+
+    #[test]
+    fn patches_are_only_sent_when_asked_for() {
+        let mut subscriptions = Subscriptions::default();
+        let project = Project::empty().with_songs(2, 2);
+
+        let response = subscriptions
+            .filter(project_change(&project, 1), Instant::now())
+            .unwrap();
+        assert!(response.project.is_some());
+        assert!(response.project_patch.is_none());
+    }
+
+    #[test]
+    fn patches_follow_on_from_the_whole_project() {
+        let mut subscriptions = subscribe_to_patches(0.0);
+        let project = Project::empty().with_songs(2, 2);
+        let now = Instant::now();
+
+        // The first change after subscribing sends the whole project
+        let response = subscriptions.filter(project_change(&project, 1), now).unwrap();
+        assert!(response.project.is_some());
+        assert!(response.project_patch.is_none());
+        assert_eq!(response.project_version, 1);
+
+        let response = subscriptions.filter(project_change(&project, 2), now).unwrap();
+        assert!(response.project.is_none());
+        assert_eq!(response.project_patch.version, 2);
+    }
+
+    #[test]
+    fn missed_patches_send_the_whole_project() {
+        let mut subscriptions = subscribe_to_patches(1.0);
+        let project = Project::empty().with_songs(2, 2);
+        let start = Instant::now();
+
+        subscriptions.filter(project_change(&project, 1), start).unwrap();
+        assert!(subscriptions.filter(project_change(&project, 2), start).is_none());
+        assert!(subscriptions.filter(project_change(&project, 3), start).is_none());
+
+        // Version 2 was coalesced away, so version 3 can't be applied as a patch
+        let flushed = subscriptions.flush(start + Duration::from_secs(1));
+        assert_eq!(flushed.len(), 1);
+        assert!(flushed[0].project.is_some());
+        assert!(flushed[0].project_patch.is_none());
+        assert_eq!(flushed[0].project_version, 3);
+    }
+}
@@ -60,6 +60,7 @@ pub fn default_preferences() -> Preferences {
     Preferences {
         audio: Some(default_audio_preferences()).into(),
         midi: Some(default_midi_preferences()).into(),
+        osc: Some(default_osc_preferences()).into(),
         ..Default::default()
     }
 }
@@ -83,6 +84,15 @@ pub fn default_midi_preferences() -> MidiPreferences {
     }
 }
 
+pub fn default_osc_preferences() -> OscPreferences {
+    OscPreferences {
+        enabled: false,
+        port: 9000,
+        bind_address: "127.0.0.1".to_string(),
+        ..Default::default()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,6 +139,28 @@ mod tests {
         assert_eq!(audio_prefs.sample_rate, 44100);
     }
 
+    #[test]
+    fn osc_is_disabled_by_default() {
+        let prefs = read_preferences_from_str(r#"{"osc": {"port": 53000}}"#).unwrap();
+        let osc_prefs = prefs.osc.unwrap();
+        assert!(!osc_prefs.enabled);
+        assert_eq!(osc_prefs.port, 53000);
+
+        let prefs = read_preferences_from_str("{}").unwrap();
+        let osc_prefs = prefs.osc.unwrap();
+        assert_eq!(osc_prefs.port, 9000);
+        assert_eq!(osc_prefs.bind_address, "127.0.0.1");
+    }
+
+    #[test]
+    fn midi_clock_fields_are_read() {
+        let json = r#"{"midi": {"clockOutputDevices": ["Digitakt"], "clockLatencyMs": -5.5}}"#;
+        let prefs = read_preferences_from_str(json).unwrap();
+        let midi_prefs = prefs.midi.unwrap();
+        assert_eq!(midi_prefs.clock_output_devices, vec!["Digitakt".to_string()]);
+        assert_eq!(midi_prefs.clock_latency_ms, -5.5);
+    }
+
     #[test]
     fn default_midi_preferences_has_icon_g_boar_pattern() {
         let midi = default_midi_preferences();
@@ -1,8 +1,11 @@
-use super::sample::Sample;
-use crate::bloop::AudioFileFormat;
+use super::{
+    riff::{read_wav_metadata, Loop, Marker},
+    sample::{Sample, WAVEFORM_EXTENSION},
+};
+use crate::bloop::{AudioFileFormat, Section};
 use crate::{model::ID, types::extension_for_format};
 use anyhow::{anyhow, Context};
-use log::debug;
+use log::{debug, warn};
 use std::{
     collections::HashMap,
     ffi::OsStr,
@@ -21,6 +24,72 @@ pub struct SampleMetadata {
     pub sample_count: u32,
     pub num_channels: u32,
     pub detected_tempo: Option<f64>,
+    pub markers: Vec<Marker>,
+    pub loops: Vec<Loop>,
+}
+
+impl SampleMetadata {
+    /// Sections starting at the sample's markers and loop points, or none if the
+    /// sample doesn't have any. Loops become looping sections.
+    pub fn sections(&self, bpm: f64) -> Vec<Section> {
+        // Markers and loops past the end of the audio are ignored
+        let markers: Vec<&Marker> = self
+            .markers
+            .iter()
+            .filter(|marker| marker.position < self.sample_count)
+            .collect();
+        let loops: Vec<&Loop> = self
+            .loops
+            .iter()
+            .filter(|sample_loop| sample_loop.start < self.sample_count)
+            .collect();
+
+        if self.sample_rate == 0 || (markers.is_empty() && loops.is_empty()) {
+            return vec![];
+        }
+
+        // Boundaries this close together are treated as the same point
+        let tolerance = self.sample_rate / 100;
+
+        let mut boundaries: Vec<(u32, Option<String>, bool)> = vec![(0, None, false)];
+        boundaries.extend(
+            markers
+                .iter()
+                .map(|marker| (marker.position, marker.name.clone(), false)),
+        );
+        for sample_loop in loops {
+            boundaries.push((sample_loop.start, None, true));
+            match sample_loop.end.checked_add(1) {
+                Some(after_loop) if after_loop < self.sample_count => boundaries.push((after_loop, None, false)),
+                _ => {}
+            }
+        }
+        boundaries.sort_by_key(|(position, _, _)| *position);
+
+        let mut merged: Vec<(u32, Option<String>, bool)> = vec![];
+        for (position, name, looping) in boundaries {
+            match merged.last_mut() {
+                Some(last) if position - last.0 <= tolerance => {
+                    last.1 = last.1.take().or(name);
+                    last.2 |= looping;
+                }
+                _ => merged.push((position, name, looping)),
+            }
+        }
+
+        merged
+            .into_iter()
+            .map(|(position, name, looping)| {
+                let section = Section::empty()
+                    .with_start(position as f64 / self.sample_rate as f64 * bpm / 60.0)
+                    .with_loop(looping);
+                match name {
+                    Some(name) => section.with_name(name),
+                    None => section,
+                }
+            })
+            .collect()
+    }
 }
 
 impl SamplesCache {
@@ -93,12 +162,19 @@ impl SamplesCache {
 
         let wav_reader = hound::WavReader::open(path).with_context(|| format!("Couldn't read audio file: {id}"))?;
 
+        let wav_metadata = read_wav_metadata(path).unwrap_or_else(|error| {
+            warn!("Couldn't read metadata from audio file {id}: {error}");
+            Default::default()
+        });
+
         Ok(SampleMetadata {
             name: String::from(sample.get_name()),
             sample_rate: wav_reader.spec().sample_rate,
             sample_count: wav_reader.duration(),
             num_channels: u32::from(wav_reader.spec().channels),
-            detected_tempo: Self::detect_tempo(sample.get_name()),
+            detected_tempo: wav_metadata.tempo.or_else(|| Self::detect_tempo(sample.get_name())),
+            markers: wav_metadata.markers,
+            loops: wav_metadata.loops,
         })
     }
 
@@ -136,7 +212,7 @@ impl SamplesCache {
 
         while let Some(entry) = dir_entries.next_entry().await? {
             let path = entry.path();
-            if !path.is_file() {
+            if !path.is_file() || path.extension() == Some(OsStr::new(WAVEFORM_EXTENSION)) {
                 continue;
             }
 
@@ -185,6 +261,120 @@ impl SamplesCache {
             position += bytes_written;
         }
 
+        // Tokio finishes writes in the background, so make sure the data is on
+        // disk before the upload is acknowledged
+        file.flush()
+            .await
+            .with_context(|| format!("Error writing audio file: {}", path.display()))?;
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(markers: Vec<Marker>, loops: Vec<Loop>) -> SampleMetadata {
+        SampleMetadata {
+            name: "Stem.wav".to_string(),
+            sample_rate: 48000,
+            sample_count: 48000 * 60,
+            num_channels: 2,
+            detected_tempo: Some(120.0),
+            markers,
+            loops,
+        }
+    }
+
+    fn marker(seconds: u32, name: &str) -> Marker {
+        Marker {
+            position: seconds * 48000,
+            name: Some(name.to_string()),
+        }
+    }
+
+    fn starts_and_names(sections: &[Section]) -> Vec<(f64, &str, bool)> {
+        sections
+            .iter()
+            .map(|section| (section.start, section.name.as_str(), section.loop_))
+            .collect()
+    }
+
+    #[test]
+    fn no_markers_means_no_sections() {
+        assert!(metadata(vec![], vec![]).sections(120.0).is_empty());
+    }
+
+    #[test]
+    fn markers_become_sections_in_beats() {
+        let metadata = metadata(
+            vec![marker(0, "Intro"), marker(8, "Verse"), marker(24, "Chorus")],
+            vec![],
+        );
+
+        assert_eq!(
+            starts_and_names(&metadata.sections(120.0)),
+            vec![(0.0, "Intro", false), (16.0, "Verse", false), (48.0, "Chorus", false)]
+        );
+    }
+
+    #[test]
+    fn a_section_is_added_before_the_first_marker() {
+        let metadata = metadata(vec![marker(4, "Verse")], vec![]);
+
+        assert_eq!(
+            starts_and_names(&metadata.sections(90.0)),
+            vec![(0.0, "Section", false), (6.0, "Verse", false)]
+        );
+    }
+
+    #[test]
+    fn loops_mark_sections_as_looping() {
+        let loops = vec![Loop {
+            start: 8 * 48000,
+            end: 16 * 48000 - 1,
+        }];
+        let metadata = metadata(vec![marker(0, "Intro"), marker(8, "Vamp"), marker(16, "Outro")], loops);
+
+        assert_eq!(
+            starts_and_names(&metadata.sections(120.0)),
+            vec![(0.0, "Intro", false), (16.0, "Vamp", true), (32.0, "Outro", false)]
+        );
+    }
+
+    #[test]
+    fn loops_without_markers_get_their_own_sections() {
+        let loops = vec![Loop {
+            start: 2 * 48000,
+            end: 4 * 48000 - 1,
+        }];
+
+        assert_eq!(
+            starts_and_names(&metadata(vec![], loops).sections(120.0)),
+            vec![(0.0, "Section", false), (4.0, "Section", true), (8.0, "Section", false)]
+        );
+    }
+
+    #[test]
+    fn markers_and_loops_past_the_end_are_ignored() {
+        assert!(metadata(vec![marker(70, "Missing")], vec![]).sections(120.0).is_empty());
+
+        let loops = vec![
+            Loop {
+                start: 30 * 48000,
+                end: u32::MAX,
+            },
+            Loop {
+                start: 90 * 48000,
+                end: 100 * 48000,
+            },
+        ];
+        let metadata = metadata(vec![marker(0, "Intro"), marker(70, "Missing")], loops);
+
+        assert_eq!(
+            starts_and_names(&metadata.sections(120.0)),
+            vec![(0.0, "Intro", false), (60.0, "Section", true)]
+        );
+    }
+}
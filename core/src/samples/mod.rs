@@ -1,4 +1,5 @@
 mod cache;
+mod riff;
 mod sample;
 
-pub use cache::SamplesCache;
+pub use cache::{SampleMetadata, SamplesCache};
@@ -0,0 +1,306 @@
+use anyhow::{anyhow, Context};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Metadata chunks bigger than this are skipped rather than read into memory.
+const MAX_METADATA_CHUNK_SIZE: u32 = 1 << 20;
+
+/// Metadata that DAWs embed in WAV files alongside the audio.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WavMetadata {
+    /// The tempo from the `acid` chunk.
+    pub tempo: Option<f64>,
+    /// Cue points from the `cue ` chunk, named by `labl` chunks, in order.
+    pub markers: Vec<Marker>,
+    /// Loops from the `smpl` chunk.
+    pub loops: Vec<Loop>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    /// The position in sample frames.
+    pub position: u32,
+    pub name: Option<String>,
+}
+
+/// A loop from `start` up to and including `end`, in sample frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loop {
+    pub start: u32,
+    pub end: u32,
+}
+
+pub fn read_wav_metadata(path: &Path) -> anyhow::Result<WavMetadata> {
+    let file = File::open(path).with_context(|| format!("Couldn't open audio file: {}", path.display()))?;
+    parse_wav_metadata(BufReader::new(file))
+}
+
+/// Walk the chunks in a RIFF WAVE file, skipping over the audio.
+pub fn parse_wav_metadata<R: Read + Seek>(mut reader: R) -> anyhow::Result<WavMetadata> {
+    let mut header = [0; 12];
+    reader.read_exact(&mut header).context("Reading RIFF header")?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(anyhow!("Not a WAV file"));
+    }
+
+    let mut metadata = WavMetadata::default();
+    let mut cue_points = vec![];
+    let mut labels = HashMap::new();
+
+    loop {
+        let mut chunk_header = [0; 8];
+        if reader.read_exact(&mut chunk_header).is_err() {
+            break;
+        }
+
+        let id = &chunk_header[0..4];
+        let size = read_u32(&chunk_header, 4);
+        // Chunks are padded to an even length
+        let padded_size = u64::from(size) + u64::from(size % 2);
+
+        match id {
+            b"cue " | b"LIST" | b"acid" | b"smpl" if size <= MAX_METADATA_CHUNK_SIZE => {
+                let mut data = vec![0; size as usize];
+                reader.read_exact(&mut data).context("Reading chunk")?;
+                if size % 2 == 1 {
+                    reader.seek(SeekFrom::Current(1))?;
+                }
+
+                match id {
+                    b"cue " => cue_points = parse_cue_points(&data),
+                    b"LIST" => labels.extend(parse_labels(&data)),
+                    b"acid" => metadata.tempo = parse_acid_tempo(&data),
+                    _ => metadata.loops = parse_loops(&data),
+                }
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(padded_size as i64))?;
+            }
+        }
+    }
+
+    metadata.markers = cue_points
+        .into_iter()
+        .map(|(cue_id, position)| Marker {
+            position,
+            name: labels.remove(&cue_id),
+        })
+        .collect();
+    metadata.markers.sort_by_key(|marker| marker.position);
+
+    Ok(metadata)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Cue point IDs and their positions.
+fn parse_cue_points(data: &[u8]) -> Vec<(u32, u32)> {
+    const CUE_POINT_SIZE: usize = 24;
+
+    if data.len() < 4 {
+        return vec![];
+    }
+
+    data[4..]
+        .chunks_exact(CUE_POINT_SIZE)
+        .take(read_u32(data, 0) as usize)
+        .map(|cue_point| (read_u32(cue_point, 0), read_u32(cue_point, 20)))
+        .collect()
+}
+
+/// Names for cue points, from the `labl` chunks in an `adtl` list.
+fn parse_labels(data: &[u8]) -> HashMap<u32, String> {
+    let mut labels = HashMap::new();
+
+    if data.len() < 4 || &data[0..4] != b"adtl" {
+        return labels;
+    }
+
+    let mut offset = 4;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let size = read_u32(data, offset + 4) as usize;
+        let start = offset + 8;
+        let end = (start + size).min(data.len());
+
+        if id == b"labl" && end >= start + 4 {
+            let cue_id = read_u32(data, start);
+            let text = &data[start + 4..end];
+            let text = text.split(|byte| *byte == 0).next().unwrap_or_default();
+            let name = String::from_utf8_lossy(text).trim().to_string();
+            if !name.is_empty() {
+                labels.insert(cue_id, name);
+            }
+        }
+
+        offset = start + size + size % 2;
+    }
+
+    labels
+}
+
+fn parse_acid_tempo(data: &[u8]) -> Option<f64> {
+    const TEMPO_OFFSET: usize = 20;
+
+    if data.len() < TEMPO_OFFSET + 4 {
+        return None;
+    }
+
+    let tempo = f32::from_le_bytes(data[TEMPO_OFFSET..TEMPO_OFFSET + 4].try_into().ok()?);
+    let tempo = f64::from(tempo);
+    (30.0..=300.0).contains(&tempo).then_some(tempo)
+}
+
+fn parse_loops(data: &[u8]) -> Vec<Loop> {
+    const LOOP_COUNT_OFFSET: usize = 28;
+    const LOOPS_OFFSET: usize = 36;
+    const LOOP_SIZE: usize = 24;
+
+    if data.len() < LOOPS_OFFSET {
+        return vec![];
+    }
+
+    data[LOOPS_OFFSET..]
+        .chunks_exact(LOOP_SIZE)
+        .take(read_u32(data, LOOP_COUNT_OFFSET) as usize)
+        .map(|sample_loop| Loop {
+            start: read_u32(sample_loop, 8),
+            end: read_u32(sample_loop, 12),
+        })
+        .filter(|sample_loop| sample_loop.start < sample_loop.end)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut file = b"RIFF".to_vec();
+        file.extend((body.len() as u32 + 4).to_le_bytes());
+        file.extend(b"WAVE");
+        file.extend(body);
+        file
+    }
+
+    fn cue_chunk(cue_points: &[(u32, u32)]) -> Vec<u8> {
+        let mut data = (cue_points.len() as u32).to_le_bytes().to_vec();
+        for (id, position) in cue_points {
+            data.extend(id.to_le_bytes());
+            data.extend(position.to_le_bytes());
+            data.extend(b"data");
+            data.extend([0; 8]);
+            data.extend(position.to_le_bytes());
+        }
+        chunk(b"cue ", &data)
+    }
+
+    fn labels_chunk(labels: &[(u32, &str)]) -> Vec<u8> {
+        let mut data = b"adtl".to_vec();
+        for (id, name) in labels {
+            let mut label = id.to_le_bytes().to_vec();
+            label.extend(name.as_bytes());
+            label.push(0);
+            data.extend(chunk(b"labl", &label));
+        }
+        chunk(b"LIST", &data)
+    }
+
+    fn acid_chunk(tempo: f32) -> Vec<u8> {
+        let mut data = vec![0; 20];
+        data.extend(tempo.to_le_bytes());
+        chunk(b"acid", &data)
+    }
+
+    fn smpl_chunk(loops: &[(u32, u32)]) -> Vec<u8> {
+        let mut data = vec![0; 28];
+        data.extend((loops.len() as u32).to_le_bytes());
+        data.extend([0; 4]);
+        for (start, end) in loops {
+            data.extend([0; 8]);
+            data.extend(start.to_le_bytes());
+            data.extend(end.to_le_bytes());
+            data.extend([0; 8]);
+        }
+        chunk(b"smpl", &data)
+    }
+
+    #[test]
+    fn reads_markers_tempo_and_loops() {
+        let file = wav(&[
+            chunk(b"fmt ", &[0; 16]),
+            chunk(b"data", &[0; 9]),
+            cue_chunk(&[(2, 96000), (1, 0)]),
+            labels_chunk(&[(1, "Intro"), (2, "Verse")]),
+            acid_chunk(128.0),
+            smpl_chunk(&[(96000, 191999)]),
+        ]);
+
+        let metadata = parse_wav_metadata(Cursor::new(file)).unwrap();
+
+        assert_eq!(metadata.tempo, Some(128.0));
+        assert_eq!(
+            metadata.markers,
+            vec![
+                Marker {
+                    position: 0,
+                    name: Some("Intro".to_string())
+                },
+                Marker {
+                    position: 96000,
+                    name: Some("Verse".to_string())
+                },
+            ]
+        );
+        assert_eq!(
+            metadata.loops,
+            vec![Loop {
+                start: 96000,
+                end: 191999
+            }]
+        );
+    }
+
+    #[test]
+    fn files_without_metadata() {
+        let file = wav(&[chunk(b"fmt ", &[0; 16]), chunk(b"data", &[0; 8])]);
+        assert_eq!(parse_wav_metadata(Cursor::new(file)).unwrap(), WavMetadata::default());
+    }
+
+    #[test]
+    fn unlabelled_markers_have_no_name() {
+        let file = wav(&[cue_chunk(&[(1, 100)])]);
+        let metadata = parse_wav_metadata(Cursor::new(file)).unwrap();
+        assert_eq!(metadata.markers[0].name, None);
+    }
+
+    #[test]
+    fn unlikely_tempos_are_ignored() {
+        let file = wav(&[acid_chunk(0.0)]);
+        assert_eq!(parse_wav_metadata(Cursor::new(file)).unwrap().tempo, None);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        assert!(parse_wav_metadata(Cursor::new(b"not a wav file".to_vec())).is_err());
+    }
+}
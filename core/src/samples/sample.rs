@@ -2,6 +2,9 @@ use std::path::{Path, PathBuf};
 
 use log::error;
 
+/// The extension of a sample's cached waveform, which is stored next to it.
+pub const WAVEFORM_EXTENSION: &str = "waveform";
+
 pub struct Sample {
     path: PathBuf,
     name: String,
@@ -29,6 +32,10 @@ impl Sample {
         self.path.as_path()
     }
 
+    pub fn get_waveform_path(&self) -> PathBuf {
+        self.path.with_extension(WAVEFORM_EXTENSION)
+    }
+
     pub fn set_cache_location(&mut self, path: &Path) {
         self.path = PathBuf::from(path);
     }
@@ -38,10 +45,12 @@ impl Sample {
     }
 
     pub fn delete_sample_on_disk(&self) {
-        if self.path.exists() {
-            match std::fs::remove_file(&self.path) {
-                Ok(_) => (),
-                Err(error) => error!("Failed to remove sample from disk ({}): {}", self.path.display(), error),
+        for path in [self.path.clone(), self.get_waveform_path()] {
+            if path.is_file() {
+                match std::fs::remove_file(&path) {
+                    Ok(_) => (),
+                    Err(error) => error!("Failed to remove sample from disk ({}): {}", path.display(), error),
+                }
             }
         }
     }
@@ -10,13 +10,13 @@ use tokio::sync::mpsc;
 
 use crate::bloop::*;
 
-pub fn run(action_tx: mpsc::Sender<Action>, preferences: SwitchPreferences) -> JoinHandle<()> {
+pub fn run(action_tx: mpsc::Sender<ActionRequest>, preferences: SwitchPreferences) -> JoinHandle<()> {
     std::thread::spawn(move || run_thread(preferences, action_tx))
 }
 
 const HOLD_DURATION: Duration = Duration::from_millis(300);
 
-fn run_thread(preferences: SwitchPreferences, action_tx: mpsc::Sender<Action>) {
+fn run_thread(preferences: SwitchPreferences, action_tx: mpsc::Sender<ActionRequest>) {
     info!("Starting switch thread");
 
     let gpio = Gpio::new().expect("Error initializing GPIO");
@@ -87,11 +87,16 @@ fn init_gpio_pins(preferences: &SwitchPreferences, gpio: &Gpio) -> Vec<InputPin>
         .collect()
 }
 
+fn action_request(mapping: &SwitchMapping) -> ActionRequest {
+    ActionRequest::from_action(mapping.action.enum_value_or_default(), mapping.value)
+        .with_macro_name(&mapping.macro_name)
+}
+
 fn on_press(
     pin: u8,
     mappings: &[SwitchMapping],
     press_times: &mut HashMap<u8, Instant>,
-    action_tx: &mpsc::Sender<Action>,
+    action_tx: &mpsc::Sender<ActionRequest>,
 ) {
     debug!("Pressed pin: {}", pin);
 
@@ -105,14 +110,14 @@ fn on_press(
         None => return,
     };
 
-    let _ = action_tx.blocking_send(mapping.action.enum_value_or_default());
+    let _ = action_tx.blocking_send(action_request(mapping));
 }
 
 fn on_release(
     pin: u8,
     mappings: &[SwitchMapping],
     press_times: &mut HashMap<u8, Instant>,
-    action_tx: &mpsc::Sender<Action>,
+    action_tx: &mpsc::Sender<ActionRequest>,
 ) {
     debug!("Released pin: {}", pin);
 
@@ -129,11 +134,15 @@ fn on_release(
             None => return,
         };
 
-        let _ = action_tx.blocking_send(mapping.action.enum_value_or_default());
+        let _ = action_tx.blocking_send(action_request(mapping));
     }
 }
 
-fn on_tick(press_times: &mut HashMap<u8, Instant>, mappings: &[SwitchMapping], action_tx: &mpsc::Sender<Action>) {
+fn on_tick(
+    press_times: &mut HashMap<u8, Instant>,
+    mappings: &[SwitchMapping],
+    action_tx: &mpsc::Sender<ActionRequest>,
+) {
     for (index, press_time) in press_times.iter() {
         if press_time.elapsed() > HOLD_DURATION {
             let mapping = match mappings.iter().find(|mapping| {
@@ -143,7 +152,7 @@ fn on_tick(press_times: &mut HashMap<u8, Instant>, mappings: &[SwitchMapping], a
                 None => continue,
             };
 
-            let _ = action_tx.blocking_send(mapping.action.enum_value_or_default());
+            let _ = action_tx.blocking_send(action_request(mapping));
         }
     }
 
@@ -41,6 +41,9 @@ pub fn update(state: &mut State, message: Message) {
         Message::SetSettingsMidiPortEnabled(port_name, enabled) => {
             state.settings.set_midi_port_enabled(port_name, enabled);
         }
+        Message::SetSettingsMidiClockOutputEnabled(port_name, enabled) => {
+            state.settings.set_midi_clock_output_enabled(port_name, enabled);
+        }
         Message::AddSettingsSwitchMapping => state.settings.add_switch_mapping(),
         Message::RemoveSettingsSwitchMapping(index) => state.settings.remove_switch_mapping(index),
         Message::SetSettingsSwitchNumber(index, field, value) => {
@@ -50,6 +53,15 @@ pub fn update(state: &mut State, message: Message) {
             super::settings::SwitchPickField::Gesture => state.settings.set_switch_gesture(index, gesture),
             super::settings::SwitchPickField::Action => state.settings.set_switch_action(index, action),
         },
+        Message::SetSettingsSwitchMacro(index, macro_name) => state.settings.set_switch_macro(index, macro_name),
+        Message::RevokeDevice(device_id) => {
+            let request = Request::revoke_device_request(&device_id);
+            send_request(state.request_tx.clone(), request);
+        }
+        Message::SetDeviceRole(device_id, role) => {
+            let request = Request::set_device_role_request(&device_id, role);
+            send_request(state.request_tx.clone(), request);
+        }
         Message::StartPlayback => {
             let request = Request::transport_request(TransportMethod::PLAY);
             send_request(state.request_tx.clone(), request);
@@ -93,6 +105,7 @@ fn request_settings_data(state: &State) {
         Entity::AUDIO_DEVICES,
         Entity::AUDIO_STATUS,
         Entity::MIDI_DEVICES,
+        Entity::PAIRED_DEVICES,
     ] {
         send_request(state.request_tx.clone(), Request::get_request(entity, 0));
     }
@@ -233,6 +246,10 @@ fn handle_api_response(state: &mut State, response: Response) {
     if let Some(midi_devices) = response.midi_devices.as_ref() {
         state.midi_devices = Some(midi_devices.clone());
     }
+
+    if let Some(pairing_status) = response.pairing_status.as_ref() {
+        state.pairing_status = Some(pairing_status.clone());
+    }
 }
 
 fn send_request(request_tx: mpsc::Sender<Request>, request: Request) {
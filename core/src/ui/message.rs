@@ -1,4 +1,7 @@
-use crate::{bloop::Response, model::ID};
+use crate::{
+    bloop::{DeviceRole, Response},
+    model::ID,
+};
 
 use super::settings::{
     ActionOption, AudioDeviceOption, AudioNumberField, GestureOption, SampleRateOption, SettingsTab, SwitchNumberField,
@@ -30,8 +33,12 @@ pub enum Message {
     SetSettingsAudioNumber(AudioNumberField, String),
     SetSettingsUseJack(bool),
     SetSettingsMidiPortEnabled(String, bool),
+    SetSettingsMidiClockOutputEnabled(String, bool),
     AddSettingsSwitchMapping,
     RemoveSettingsSwitchMapping(usize),
     SetSettingsSwitchNumber(usize, SwitchNumberField, String),
     SetSettingsSwitchPick(usize, SwitchPickField, GestureOption, ActionOption),
+    SetSettingsSwitchMacro(usize, String),
+    RevokeDevice(String),
+    SetDeviceRole(String, DeviceRole),
 }
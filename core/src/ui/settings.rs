@@ -7,8 +7,8 @@ use iced::{
 
 use crate::{
     bloop::{
-        Action, AudioDevice, AudioDevices, AudioEngineStatus, AudioPreferences, AudioStatus, Gesture, MidiDevices,
-        MidiPreferences, Preferences, SwitchMapping, SwitchPreferences,
+        Action, AudioDevice, AudioDevices, AudioEngineStatus, AudioPreferences, AudioStatus, DeviceRole, Gesture,
+        Macro, MidiDevices, MidiPreferences, PairingStatus, Preferences, SwitchMapping, SwitchPreferences,
     },
     preferences::{default_audio_preferences, default_midi_preferences},
 };
@@ -20,6 +20,7 @@ pub enum SettingsTab {
     Audio,
     Midi,
     Switches,
+    Devices,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -33,6 +34,7 @@ pub enum AudioNumberField {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SwitchNumberField {
     Pin,
+    Value,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -93,6 +95,7 @@ pub struct SettingsUiState {
     pub main_channel_offset: String,
     pub click_channel_offset: String,
     pub switch_pin_values: Vec<String>,
+    pub switch_value_values: Vec<String>,
     pub is_saving: bool,
     pub validation_error: Option<String>,
 }
@@ -109,6 +112,7 @@ impl Default for SettingsUiState {
             main_channel_offset: String::new(),
             click_channel_offset: String::new(),
             switch_pin_values: Vec::new(),
+            switch_value_values: Vec::new(),
             is_saving: false,
             validation_error: None,
         };
@@ -169,6 +173,18 @@ impl SettingsUiState {
                 }
             }
         }
+        for (index, value_text) in self.switch_value_values.iter().enumerate() {
+            let Some(mapping) = switch.mappings.get_mut(index) else {
+                continue;
+            };
+            match parse_u32(value_text, "Switch value") {
+                Some(value) => mapping.value = value,
+                None => {
+                    self.validation_error = Some("Switch value must be a whole number".to_string());
+                    return None;
+                }
+            }
+        }
         preferences.switch = Some(switch).into();
 
         Some(preferences)
@@ -209,6 +225,12 @@ impl SettingsUiState {
         self.draft.midi = Some(midi).into();
     }
 
+    pub fn set_midi_clock_output_enabled(&mut self, port_name: String, enabled: bool) {
+        let mut midi = self.draft.midi.clone().unwrap_or_else(default_midi_preferences);
+        set_midi_clock_output_device(&mut midi, port_name, enabled);
+        self.draft.midi = Some(midi).into();
+    }
+
     pub fn add_switch_mapping(&mut self) {
         let mut switch = self.draft.switch.clone().unwrap_or_default();
         switch.mappings.push(default_switch_mapping());
@@ -232,6 +254,11 @@ impl SettingsUiState {
                     self.switch_pin_values[index] = value;
                 }
             }
+            SwitchNumberField::Value => {
+                if index < self.switch_value_values.len() {
+                    self.switch_value_values[index] = value;
+                }
+            }
         }
         self.validation_error = None;
     }
@@ -248,6 +275,12 @@ impl SettingsUiState {
         }
     }
 
+    pub fn set_switch_macro(&mut self, index: usize, macro_name: String) {
+        if let Some(mapping) = self.switch_mapping_mut(index) {
+            mapping.macro_name = macro_name;
+        }
+    }
+
     pub fn has_validation_error(&self) -> bool {
         validate_audio_number(&self.sample_rate, AudioNumberField::SampleRate).is_some()
             || validate_audio_number(&self.buffer_size, AudioNumberField::BufferSize).is_some()
@@ -257,6 +290,10 @@ impl SettingsUiState {
                 .switch_pin_values
                 .iter()
                 .any(|pin| parse_u32(pin, "Switch pin").is_none())
+            || self
+                .switch_value_values
+                .iter()
+                .any(|value| parse_u32(value, "Switch value").is_none())
     }
 
     fn switch_mapping_mut(&mut self, index: usize) -> Option<&mut SwitchMapping> {
@@ -286,6 +323,11 @@ impl SettingsUiState {
 
     fn sync_switch_pin_values(&mut self, switch: &SwitchPreferences) {
         self.switch_pin_values = switch.mappings.iter().map(|mapping| mapping.pin.to_string()).collect();
+        self.switch_value_values = switch
+            .mappings
+            .iter()
+            .map(|mapping| mapping.value.to_string())
+            .collect();
     }
 }
 
@@ -296,6 +338,7 @@ pub fn render_settings_overlay<'a>(
     audio_devices: Option<&'a AudioDevices>,
     audio_status: Option<&'a AudioStatus>,
     midi_devices: Option<&'a MidiDevices>,
+    pairing_status: Option<&'a PairingStatus>,
 ) -> Element<'a, Message> {
     if !settings.is_open {
         return base;
@@ -310,7 +353,8 @@ pub fn render_settings_overlay<'a>(
                     preferences,
                     audio_devices,
                     audio_status,
-                    midi_devices
+                    midi_devices,
+                    pairing_status
                 ))
                 .max_width(760.0)
                 .max_height(520.0)
@@ -334,6 +378,7 @@ fn settings_panel<'a>(
     audio_devices: Option<&'a AudioDevices>,
     audio_status: Option<&'a AudioStatus>,
     midi_devices: Option<&'a MidiDevices>,
+    pairing_status: Option<&'a PairingStatus>,
 ) -> Element<'a, Message> {
     let tabs = row![
         tab_button("Audio", SettingsTab::Audio, settings.active_tab),
@@ -346,6 +391,7 @@ fn settings_panel<'a>(
         } else {
             container(row![]).into()
         },
+        tab_button("Devices", SettingsTab::Devices, settings.active_tab),
     ]
     .spacing(display_units(1.0));
 
@@ -353,6 +399,7 @@ fn settings_panel<'a>(
         SettingsTab::Audio => audio_tab(settings, audio_devices, audio_status),
         SettingsTab::Midi => midi_tab(settings, midi_devices),
         SettingsTab::Switches => switch_tab(settings),
+        SettingsTab::Devices => devices_tab(pairing_status),
     };
 
     let error = settings
@@ -551,8 +598,11 @@ fn midi_tab<'a>(settings: &'a SettingsUiState, midi_devices: Option<&'a MidiDevi
         .cloned()
         .unwrap_or_else(default_midi_preferences);
     let port_names = midi_devices.map(|devices| devices.port_names.as_slice()).unwrap_or(&[]);
+    let output_port_names = midi_devices
+        .map(|devices| devices.output_port_names.as_slice())
+        .unwrap_or(&[]);
 
-    if port_names.is_empty() {
+    if port_names.is_empty() && output_port_names.is_empty() {
         return column![text("No MIDI devices found").size(16.0)].into();
     }
 
@@ -571,7 +621,73 @@ fn midi_tab<'a>(settings: &'a SettingsUiState, midi_devices: Option<&'a MidiDevi
             )
         });
 
-    rows.into()
+    if output_port_names.is_empty() {
+        return rows.into();
+    }
+
+    output_port_names
+        .iter()
+        .fold(rows.push(text("Clock Output").size(16.0)), |column, port_name| {
+            let enabled = midi.clock_output_devices.contains(port_name);
+            let port = port_name.clone();
+            column.push(
+                row![
+                    text(port_name).width(Length::Fill),
+                    checkbox(enabled)
+                        .on_toggle(move |checked| Message::SetSettingsMidiClockOutputEnabled(port.clone(), checked)),
+                ]
+                .align_y(iced::Alignment::Center),
+            )
+        })
+        .into()
+}
+
+fn devices_tab(pairing_status: Option<&PairingStatus>) -> Element<'_, Message> {
+    let Some(pairing_status) = pairing_status else {
+        return column![text("Loading paired devices").size(16.0)].into();
+    };
+
+    let content = column![
+        row![
+            text("Pairing PIN").width(Length::Fill),
+            text(&pairing_status.pin).size(24.0),
+        ]
+        .align_y(iced::Alignment::Center),
+        text("Paired Devices").size(16.0),
+    ]
+    .spacing(display_units(1.0));
+
+    if pairing_status.devices.is_empty() {
+        return content.push(text("No devices paired")).into();
+    }
+
+    pairing_status
+        .devices
+        .iter()
+        .fold(content, |column, device| {
+            let is_viewer = device.role.enum_value_or_default() == DeviceRole::DEVICE_ROLE_VIEWER;
+            let device_id = device.id.clone();
+            column.push(
+                row![
+                    text(&device.name).width(Length::Fill),
+                    text("View only"),
+                    checkbox(is_viewer).on_toggle(move |viewer| {
+                        let role = if viewer {
+                            DeviceRole::DEVICE_ROLE_VIEWER
+                        } else {
+                            DeviceRole::DEVICE_ROLE_CONTROLLER
+                        };
+                        Message::SetDeviceRole(device_id.clone(), role)
+                    }),
+                    button(text("Revoke"))
+                        .padding(button_padding())
+                        .on_press(Message::RevokeDevice(device.id.clone())),
+                ]
+                .spacing(display_units(1.0))
+                .align_y(iced::Alignment::Center),
+            )
+        })
+        .into()
 }
 
 fn switch_tab<'a>(settings: &'a SettingsUiState) -> Element<'a, Message> {
@@ -581,7 +697,12 @@ fn switch_tab<'a>(settings: &'a SettingsUiState) -> Element<'a, Message> {
     if let Some(switch) = switch {
         for (index, mapping) in switch.mappings.iter().enumerate() {
             let pin = settings.switch_pin_values.get(index).map(String::as_str).unwrap_or("");
-            content = content.push(switch_mapping_row(index, mapping, pin));
+            let value = settings
+                .switch_value_values
+                .get(index)
+                .map(String::as_str)
+                .unwrap_or("");
+            content = content.push(switch_mapping_row(index, mapping, pin, value, &settings.draft.macros));
         }
     }
 
@@ -598,51 +719,81 @@ fn switch_tab<'a>(settings: &'a SettingsUiState) -> Element<'a, Message> {
     content.into()
 }
 
-fn switch_mapping_row<'a>(index: usize, mapping: &'a SwitchMapping, pin: &'a str) -> Element<'a, Message> {
+fn switch_mapping_row<'a>(
+    index: usize,
+    mapping: &'a SwitchMapping,
+    pin: &'a str,
+    value: &'a str,
+    macros: &'a [Macro],
+) -> Element<'a, Message> {
     let gesture_options = gesture_options();
     let action_options = action_options();
     let selected_gesture = GestureOption(mapping.gesture.enum_value_or_default());
     let selected_action = ActionOption(mapping.action.enum_value_or_default());
 
-    container(
-        column![
-            row![
-                text(format!("Mapping {}", index + 1)).width(Length::Fill),
-                button(text("Remove"))
-                    .padding(button_padding())
-                    .on_press(Message::RemoveSettingsSwitchMapping(index)),
-            ]
-            .align_y(iced::Alignment::Center),
-            setting_row(
-                "Pin",
-                text_input("Pin", pin)
-                    .on_input(move |value| Message::SetSettingsSwitchNumber(index, SwitchNumberField::Pin, value))
-                    .width(Length::Fill)
-                    .into(),
-            ),
-            setting_row(
-                "Gesture",
-                pick_list(gesture_options, Some(selected_gesture), move |gesture| {
-                    Message::SetSettingsSwitchPick(index, SwitchPickField::Gesture, gesture, selected_action)
-                })
+    let mut content = column![
+        row![
+            text(format!("Mapping {}", index + 1)).width(Length::Fill),
+            button(text("Remove"))
+                .padding(button_padding())
+                .on_press(Message::RemoveSettingsSwitchMapping(index)),
+        ]
+        .align_y(iced::Alignment::Center),
+        setting_row(
+            "Pin",
+            text_input("Pin", pin)
+                .on_input(move |value| Message::SetSettingsSwitchNumber(index, SwitchNumberField::Pin, value))
                 .width(Length::Fill)
                 .into(),
-            ),
-            setting_row(
-                "Action",
-                pick_list(action_options, Some(selected_action), move |action| {
-                    Message::SetSettingsSwitchPick(index, SwitchPickField::Action, selected_gesture, action)
-                })
+        ),
+        setting_row(
+            "Gesture",
+            pick_list(gesture_options, Some(selected_gesture), move |gesture| {
+                Message::SetSettingsSwitchPick(index, SwitchPickField::Gesture, gesture, selected_action)
+            })
+            .width(Length::Fill)
+            .into(),
+        ),
+        setting_row(
+            "Action",
+            pick_list(action_options, Some(selected_action), move |action| {
+                Message::SetSettingsSwitchPick(index, SwitchPickField::Action, selected_gesture, action)
+            })
+            .width(Length::Fill)
+            .into(),
+        ),
+    ]
+    .spacing(display_units(1.0));
+
+    if action_takes_value(selected_action.0) {
+        content = content.push(setting_row(
+            "Value",
+            text_input("Value", value)
+                .on_input(move |value| Message::SetSettingsSwitchNumber(index, SwitchNumberField::Value, value))
                 .width(Length::Fill)
                 .into(),
-            ),
-        ]
-        .spacing(display_units(1.0)),
-    )
-    .padding(display_units(1.5))
-    .style(subtle_panel_style)
-    .width(Length::Fill)
-    .into()
+        ));
+    }
+
+    if selected_action.0 == Action::ACTION_RUN_MACRO {
+        let macro_names: Vec<String> = macros.iter().map(|macro_| macro_.name.clone()).collect();
+        let selected_macro = Some(mapping.macro_name.clone()).filter(|name| !name.is_empty());
+        content = content.push(setting_row(
+            "Macro",
+            pick_list(macro_names, selected_macro, move |macro_name| {
+                Message::SetSettingsSwitchMacro(index, macro_name)
+            })
+            .placeholder("No macros configured")
+            .width(Length::Fill)
+            .into(),
+        ));
+    }
+
+    container(content)
+        .padding(display_units(1.5))
+        .style(subtle_panel_style)
+        .width(Length::Fill)
+        .into()
 }
 
 fn setting_row<'a>(label: &'a str, control: Element<'a, Message>) -> Element<'a, Message> {
@@ -685,7 +836,10 @@ fn button_padding() -> Padding {
 
 fn backdrop_style(_theme: &Theme) -> container::Style {
     container::Style {
-        background: Some(Background::Color(Color { a: 0.55, ..theme::neutral::N8 })),
+        background: Some(Background::Color(Color {
+            a: 0.55,
+            ..theme::neutral::N8
+        })),
         ..Default::default()
     }
 }
@@ -700,7 +854,10 @@ fn panel_style(_theme: &Theme) -> container::Style {
             color: theme::neutral::N5,
         },
         shadow: Shadow {
-            color: Color { a: 0.35, ..theme::neutral::N8 },
+            color: Color {
+                a: 0.35,
+                ..theme::neutral::N8
+            },
             offset: iced::Vector::new(0.0, 12.0),
             blur_radius: 24.0,
         },
@@ -742,12 +899,20 @@ fn editable_preferences(mut preferences: Preferences) -> Preferences {
 }
 
 fn set_midi_enabled_device(midi: &mut MidiPreferences, port_name: String, enabled: bool) {
+    set_port_enabled(&mut midi.enabled_devices, port_name, enabled);
+}
+
+fn set_midi_clock_output_device(midi: &mut MidiPreferences, port_name: String, enabled: bool) {
+    set_port_enabled(&mut midi.clock_output_devices, port_name, enabled);
+}
+
+fn set_port_enabled(patterns: &mut Vec<String>, port_name: String, enabled: bool) {
     if enabled {
-        if !midi.enabled_devices.contains(&port_name) {
-            midi.enabled_devices.push(port_name);
+        if !patterns.contains(&port_name) {
+            patterns.push(port_name);
         }
     } else {
-        midi.enabled_devices.retain(|device| device != &port_name);
+        patterns.retain(|device| device != &port_name);
     }
 }
 
@@ -876,9 +1041,25 @@ fn action_options() -> Vec<ActionOption> {
         ActionOption(Action::ACTION_QUEUE_SELECTED),
         ActionOption(Action::ACTION_TOGGLE_LOOP),
         ActionOption(Action::ACTION_TOGGLE_PLAY),
+        ActionOption(Action::ACTION_SELECT_SONG),
+        ActionOption(Action::ACTION_SELECT_SECTION),
+        ActionOption(Action::ACTION_SET_SONG_VOLUME),
+        ActionOption(Action::ACTION_SET_MASTER_VOLUME),
+        ActionOption(Action::ACTION_RUN_MACRO),
+        ActionOption(Action::ACTION_CANCEL_MACRO),
     ]
 }
 
+fn action_takes_value(action: Action) -> bool {
+    matches!(
+        action,
+        Action::ACTION_SELECT_SONG
+            | Action::ACTION_SELECT_SECTION
+            | Action::ACTION_SET_SONG_VOLUME
+            | Action::ACTION_SET_MASTER_VOLUME
+    )
+}
+
 fn gesture_label(gesture: Gesture) -> &'static str {
     match gesture {
         Gesture::GESTURE_PRESS => "Press",
@@ -897,6 +1078,12 @@ fn action_label(action: Action) -> &'static str {
         Action::ACTION_QUEUE_SELECTED => "Queue Selected",
         Action::ACTION_TOGGLE_LOOP => "Toggle Loop",
         Action::ACTION_TOGGLE_PLAY => "Toggle Play",
+        Action::ACTION_SELECT_SONG => "Select Song",
+        Action::ACTION_SELECT_SECTION => "Select Section",
+        Action::ACTION_SET_SONG_VOLUME => "Set Song Volume",
+        Action::ACTION_SET_MASTER_VOLUME => "Set Master Volume",
+        Action::ACTION_RUN_MACRO => "Run Macro",
+        Action::ACTION_CANCEL_MACRO => "Cancel Macro",
         Action::ACTION_UNKNOWN => "Unknown",
     }
 }
@@ -943,6 +1130,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn midi_clock_output_toggle_leaves_input_devices_alone() {
+        let mut midi = MidiPreferences {
+            enabled_devices: vec!["Port A".to_string()],
+            ..Default::default()
+        };
+
+        set_midi_clock_output_device(&mut midi, "Port A".to_string(), true);
+
+        assert_eq!(midi.enabled_devices, vec!["Port A".to_string()]);
+        assert_eq!(midi.clock_output_devices, vec!["Port A".to_string()]);
+    }
+
     #[test]
     fn switch_mapping_add_update_remove_produces_expected_preferences() {
         let mut state = SettingsUiState::default();
@@ -950,13 +1150,15 @@ mod tests {
         state.add_switch_mapping();
         state.set_switch_number(0, SwitchNumberField::Pin, "17".to_string());
         state.set_switch_gesture(0, GestureOption(Gesture::GESTURE_HOLD));
-        state.set_switch_action(0, ActionOption(Action::ACTION_NEXT_SONG));
+        state.set_switch_action(0, ActionOption(Action::ACTION_SELECT_SONG));
+        state.set_switch_number(0, SwitchNumberField::Value, "3".to_string());
 
         let saved = state.draft_preferences_for_save().unwrap();
         let mapping = &saved.switch.unwrap().mappings[0];
         assert_eq!(mapping.pin, 17);
+        assert_eq!(mapping.value, 3);
         assert_eq!(mapping.gesture.enum_value_or_default(), Gesture::GESTURE_HOLD);
-        assert_eq!(mapping.action.enum_value_or_default(), Action::ACTION_NEXT_SONG);
+        assert_eq!(mapping.action.enum_value_or_default(), Action::ACTION_SELECT_SONG);
 
         state.remove_switch_mapping(0);
         let saved = state.draft_preferences_for_save().unwrap();
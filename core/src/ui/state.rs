@@ -1,7 +1,7 @@
 use tokio::sync::{broadcast, mpsc};
 
 use crate::{
-    bloop::{AudioDevices, AudioStatus, MidiDevices, Preferences, Request, Response},
+    bloop::{AudioDevices, AudioStatus, MidiDevices, PairingStatus, Preferences, Request, Response},
     model::{PlaybackState, Progress, Project},
 };
 
@@ -17,6 +17,7 @@ pub struct State {
     pub audio_devices: Option<AudioDevices>,
     pub audio_status: Option<AudioStatus>,
     pub midi_devices: Option<MidiDevices>,
+    pub pairing_status: Option<PairingStatus>,
     pub settings: SettingsUiState,
 }
 
@@ -32,6 +33,7 @@ impl State {
             audio_devices: None,
             audio_status: None,
             midi_devices: None,
+            pairing_status: None,
             settings: SettingsUiState::default(),
         }
     }
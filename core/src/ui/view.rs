@@ -41,6 +41,7 @@ pub fn render(state: &State) -> Element<'_, Message> {
         state.audio_devices.as_ref(),
         state.audio_status.as_ref(),
         state.midi_devices.as_ref(),
+        state.pairing_status.as_ref(),
     )
 }
 
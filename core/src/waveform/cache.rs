@@ -0,0 +1,86 @@
+use crate::bloop::WaveformCacheFile;
+use anyhow::{anyhow, Context};
+use protobuf::Message;
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::Path,
+};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// A 64-bit FNV-1a hash of a file's contents. It only needs to tell versions of a
+/// sample apart, and unlike the standard library's hasher it's stable between builds.
+pub fn content_hash(path: &Path) -> anyhow::Result<u64> {
+    let file = File::open(path).with_context(|| format!("Unable to open file to hash: {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut buffer = [0; 64 * 1024];
+    let mut hash = FNV_OFFSET_BASIS;
+
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            return Ok(hash);
+        }
+
+        for byte in &buffer[..count] {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+pub fn read_cache_file(path: &Path) -> anyhow::Result<WaveformCacheFile> {
+    let data = std::fs::read(path).with_context(|| format!("Unable to read waveform cache: {}", path.display()))?;
+    WaveformCacheFile::parse_from_bytes(&data).context("Parse waveform cache")
+}
+
+/// Write the cache to a temporary file first, so a reader never sees half of it.
+pub fn write_cache_file(path: &Path, cache: &WaveformCacheFile) -> anyhow::Result<()> {
+    let directory = path
+        .parent()
+        .ok_or_else(|| anyhow!("Waveform cache has no directory: {}", path.display()))?;
+
+    let mut file = tempfile::NamedTempFile::new_in(directory)?;
+    file.write_all(&cache.write_to_bytes()?)?;
+    file.persist(path)
+        .with_context(|| format!("Unable to write waveform cache: {}", path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloop::WaveformData;
+
+    #[test]
+    fn cache_files_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("1234.waveform");
+        let cache = WaveformCacheFile {
+            sample_id: 1234,
+            content_hash: 5678,
+            version: 1,
+            waveform_data: Some(WaveformData::empty(48_000)).into(),
+            ..Default::default()
+        };
+
+        write_cache_file(&path, &cache).unwrap();
+        assert_eq!(read_cache_file(&path).unwrap(), cache);
+    }
+
+    #[test]
+    fn content_hash_changes_with_content() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("sample.wav");
+
+        std::fs::write(&path, b"one").unwrap();
+        let first = content_hash(&path).unwrap();
+        assert_eq!(content_hash(&path).unwrap(), first);
+
+        std::fs::write(&path, b"two").unwrap();
+        assert_ne!(content_hash(&path).unwrap(), first);
+    }
+}
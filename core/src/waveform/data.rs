@@ -39,6 +39,53 @@ impl WaveformData {
         }
     }
 
+    /// The peaks of one level that cover frames `start..end`, along with the frame
+    /// the first of them starts at. The coarsest level with peaks no longer than
+    /// `length` is used, or the finest if every level is longer.
+    pub fn range(&self, length: i32, start: i64, end: i64) -> (Self, i64) {
+        let mut lengths: Vec<i32> = self.peaks.iter().map(|group| group.properties.length).collect();
+        lengths.sort_unstable();
+        lengths.dedup();
+
+        let mut data = Self::empty(self.sample_rate);
+        let Some(level) = lengths
+            .iter()
+            .rev()
+            .find(|level| **level <= length)
+            .or(lengths.first())
+            .map(|level| i64::from(*level))
+        else {
+            return (data, 0);
+        };
+
+        let first = (start.max(0) / level) as usize;
+        let last = (end.max(0).saturating_add(level - 1) / level) as usize;
+
+        data.peaks = self
+            .peaks
+            .iter()
+            .filter(|group| i64::from(group.properties.length) == level)
+            .map(|group| {
+                let count = group.values.len();
+                WaveformGroup {
+                    properties: group.properties.clone(),
+                    values: group.values[first.min(count)..last.clamp(first.min(count), count)].to_vec(),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        (data, first as i64 * level)
+    }
+
+    /// Remove the levels with peaks shorter than `length`, keeping at least the coarsest.
+    pub fn without_levels_below(mut self, length: i32) -> Self {
+        let coarsest = self.peaks.iter().map(|group| group.properties.length).max();
+        let min_length = coarsest.map_or(length, |coarsest| coarsest.min(length));
+        self.peaks.retain(|group| group.properties.length >= min_length);
+        self
+    }
+
     pub fn add(&mut self, mut other: Self) {
         if other == *self {
             return;
@@ -51,3 +98,71 @@ impl WaveformData {
         self.peaks.append(&mut other.peaks);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloop::WaveformAlgorithm;
+
+    /// Peaks whose values are their index, at each length.
+    fn waveform(lengths: &[i32], frame_count: i32) -> WaveformData {
+        let mut data = WaveformData::empty(48_000);
+        for length in lengths {
+            let properties = WaveformProperties {
+                length: *length,
+                algorithm: WaveformAlgorithm::MAX.into(),
+                ..Default::default()
+            };
+            for index in 0..(frame_count + length - 1) / length {
+                data.push(&properties, index as f32);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn range_uses_the_coarsest_level_that_is_detailed_enough() {
+        let data = waveform(&[256, 1024, 4096], 40_960);
+
+        let (range, start) = data.range(2000, 3000, 9000);
+        assert_eq!(start, 2048);
+        assert_eq!(range.peaks.len(), 1);
+        assert_eq!(range.peaks[0].properties.length, 1024);
+        assert_eq!(range.peaks[0].values, vec![2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+    }
+
+    #[test]
+    fn range_falls_back_to_the_finest_level() {
+        let data = waveform(&[256, 1024], 4096);
+
+        let (range, start) = data.range(10, 0, 600);
+        assert_eq!(start, 0);
+        assert_eq!(range.peaks[0].properties.length, 256);
+        assert_eq!(range.peaks[0].values, vec![0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn range_is_clipped_to_the_end_of_the_sample() {
+        let data = waveform(&[1024], 4096);
+
+        let (range, start) = data.range(1024, 3000, 100_000);
+        assert_eq!(start, 2048);
+        assert_eq!(range.peaks[0].values, vec![2.0, 3.0]);
+
+        let (range, _) = data.range(1024, 50_000, 100_000);
+        assert!(range.peaks[0].values.is_empty());
+
+        let (range, _) = data.range(1024, 3000, i64::MAX);
+        assert_eq!(range.peaks[0].values, vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn removing_detailed_levels_keeps_the_coarsest() {
+        let data = waveform(&[256, 1024, 4096], 40_960);
+        let lengths =
+            |data: &WaveformData| -> Vec<i32> { data.peaks.iter().map(|group| group.properties.length).collect() };
+
+        assert_eq!(lengths(&data.clone().without_levels_below(1024)), vec![1024, 4096]);
+        assert_eq!(lengths(&waveform(&[256], 4096).without_levels_below(1024)), vec![256]);
+    }
+}
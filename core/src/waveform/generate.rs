@@ -2,6 +2,7 @@ use rawdio::{AudioBuffer, BorrowedAudioBuffer, OwnedAudioBuffer, SampleLocation}
 
 use crate::audio::convert::convert_sample;
 use crate::bloop::{WaveformAlgorithm, WaveformData, WaveformProperties};
+use anyhow::Context;
 use std::convert::TryInto;
 use std::{collections::HashSet, sync::Arc};
 use std::{path::Path, thread::spawn};
@@ -11,11 +12,15 @@ pub struct Options {
     pub lengths: HashSet<i32>,
     pub algorithms: HashSet<WaveformAlgorithm>,
     pub num_channels: usize,
-    pub sample_rate: usize,
 }
 
 pub fn generate_waveform_from_file(sample_path: &Path, mut options: Options) -> anyhow::Result<WaveformData> {
-    let audio = convert_sample(sample_path, options.sample_rate)?;
+    // Peaks are made at the file's own rate, which saves resampling the whole file
+    let sample_rate = hound::WavReader::open(sample_path)
+        .context("Unable to open file to generate its waveform")?
+        .spec()
+        .sample_rate as usize;
+    let audio = convert_sample(sample_path, sample_rate)?;
 
     if options.num_channels == 0 {
         options.num_channels = audio.channel_count();
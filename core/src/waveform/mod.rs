@@ -1,4 +1,6 @@
+mod cache;
 mod data;
 mod generate;
 
+pub use cache::{content_hash, read_cache_file, write_cache_file};
 pub use generate::{generate_waveform_from_file, Options};
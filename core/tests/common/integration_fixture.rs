@@ -5,11 +5,11 @@ use std::{sync::Once, thread};
 use tokio::time::{timeout, Duration};
 
 use bloop::{
-    bloop::{Request, Response},
+    bloop::{Entity, Request, Response},
     run_core, AppConfig,
 };
 
-use crate::common::Mocketbase;
+use crate::common::{upload_sample_requests, Mocketbase};
 
 pub struct IntegrationFixture {
     _home_directory: tempfile::TempDir,
@@ -117,6 +117,31 @@ impl IntegrationFixture {
         self.wait_for_response_with_timeout(Duration::from_secs(3), predicate)
             .await
     }
+
+    /// Waits long enough for a sample to be analysed in a debug build.
+    pub async fn wait_for_analysis<F>(&mut self, predicate: F) -> Result<Response>
+    where
+        F: Fn(&Response) -> bool,
+    {
+        self.wait_for_response_with_timeout(Duration::from_secs(30), predicate)
+            .await
+    }
+
+    /// Uploads `data` as the sample of the first song in the project and returns that song's ID.
+    pub async fn add_sample_to_first_song(&mut self, upload_id: u64, filename: &str, data: Vec<u8>) -> u64 {
+        self.send_request(Request::get_request(Entity::PROJECT, 0)).await;
+        let response = self
+            .wait_for_response(|response| response.error.is_empty() && response.project.is_some())
+            .await
+            .expect("Didn't receive get response");
+        let song_id = response.project.songs[0].id;
+
+        for request in upload_sample_requests(upload_id, filename, data, song_id) {
+            self.send_request(request).await;
+        }
+
+        song_id
+    }
 }
 
 impl Drop for IntegrationFixture {
@@ -4,8 +4,14 @@ mod integration_fixture;
 #[allow(dead_code)]
 mod mocketbase;
 
+#[allow(dead_code)]
+mod samples;
+
 #[allow(unused_imports)]
 pub use integration_fixture::*;
 
 #[allow(unused_imports)]
 pub use mocketbase::*;
+
+#[allow(unused_imports)]
+pub use samples::*;
@@ -0,0 +1,110 @@
+use bloop::bloop::{AddSampleRequest, BeginUploadRequest, CompleteUploadRequest, Request, UploadRequest};
+use std::io::Cursor;
+
+/// A mono WAV file with a click on every beat, after `pre_roll` seconds of silence.
+pub fn click_track_wav(bpm: f64, seconds: f64, pre_roll: f64) -> Vec<u8> {
+    let sample_rate = 44_100;
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let beat_length = (60.0 * sample_rate as f64 / bpm) as usize;
+    let click_length = sample_rate as usize / 100;
+
+    let mut data = Cursor::new(vec![]);
+    let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+    for _ in 0..(pre_roll * sample_rate as f64) as usize {
+        writer.write_sample(0_i16).unwrap();
+    }
+    for frame in 0..(seconds * sample_rate as f64) as usize {
+        let offset = frame % beat_length;
+        let value = if offset < click_length {
+            let decay = 1.0 - offset as f64 / click_length as f64;
+            0.8 * decay * (offset as f64 * 0.15).sin()
+        } else {
+            0.0
+        };
+        writer.write_sample((value * i16::MAX as f64) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    data.into_inner()
+}
+
+/// Add named cue markers, at frame positions, to a WAV file.
+pub fn with_markers(mut wav: Vec<u8>, markers: &[(u32, &str)]) -> Vec<u8> {
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    let mut cue_points = (markers.len() as u32).to_le_bytes().to_vec();
+    let mut labels = b"adtl".to_vec();
+    for (id, (position, name)) in (1_u32..).zip(markers.iter()) {
+        cue_points.extend(id.to_le_bytes());
+        cue_points.extend(position.to_le_bytes());
+        cue_points.extend(b"data");
+        cue_points.extend([0; 8]);
+        cue_points.extend(position.to_le_bytes());
+
+        let mut label = id.to_le_bytes().to_vec();
+        label.extend(name.as_bytes());
+        label.push(0);
+        labels.extend(chunk(b"labl", &label));
+    }
+
+    wav.extend(chunk(b"cue ", &cue_points));
+    wav.extend(chunk(b"LIST", &labels));
+
+    let riff_size = wav.len() as u32 - 8;
+    wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    wav
+}
+
+pub fn upload_sample_requests(upload_id: u64, filename: &str, data: Vec<u8>, song_id: u64) -> Vec<Request> {
+    vec![
+        Request {
+            begin_upload: Some(BeginUploadRequest {
+                upload_id,
+                filename: filename.to_string(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        },
+        Request {
+            upload: Some(UploadRequest {
+                upload_id,
+                data,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        },
+        Request {
+            complete_upload: Some(CompleteUploadRequest {
+                upload_id,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        },
+        Request {
+            add_sample: Some(AddSampleRequest {
+                song_id,
+                upload_id,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        },
+    ]
+}
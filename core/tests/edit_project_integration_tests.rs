@@ -1,7 +1,7 @@
 mod common;
 
-use bloop::bloop::{Entity, Request};
-use common::IntegrationFixture;
+use bloop::bloop::{Action, Entity, Macro, MacroStep, Preferences, Request, UpdateRequest};
+use common::{click_track_wav, with_markers, IntegrationFixture};
 
 #[tokio::test]
 async fn add_song_request() {
@@ -36,3 +36,422 @@ async fn add_song_request() {
         "Song count should increase by 1"
     );
 }
+
+#[tokio::test]
+async fn select_song_action_selects_song_at_index() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture.send_request(Request::add_song_request()).await;
+    fixture
+        .wait_for_response(|response| response.error.is_empty() && response.project.is_some())
+        .await
+        .expect("Didn't receive add song response");
+
+    fixture
+        .send_request(Request::action_request(Action::ACTION_SELECT_SONG, 0))
+        .await;
+
+    let response = fixture
+        .wait_for_response(|response| response.error.is_empty() && response.project.is_some())
+        .await
+        .expect("Didn't receive select song response");
+
+    let project = response.project.as_ref().expect("Project should be present");
+    assert_eq!(project.selections.song, project.songs[0].id);
+
+    fixture
+        .send_request(Request::action_request(
+            Action::ACTION_SELECT_SONG,
+            project.songs.len() as u32,
+        ))
+        .await;
+
+    fixture
+        .wait_for_response(|response| !response.error.is_empty())
+        .await
+        .expect("Selecting a song out of range should fail");
+}
+
+#[tokio::test]
+async fn run_macro_performs_its_steps() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture.send_request(Request::add_song_request()).await;
+    fixture
+        .send_request(Request::action_request(Action::ACTION_SELECT_SONG, 0))
+        .await;
+
+    let preferences = Preferences {
+        macros: vec![Macro {
+            name: "Next song".to_string(),
+            steps: vec![MacroStep {
+                action: Action::ACTION_NEXT_SONG.into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    fixture
+        .send_request(Request {
+            update: Some(UpdateRequest {
+                preferences: Some(preferences).into(),
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        })
+        .await;
+    fixture
+        .wait_for_response(|response| response.error.is_empty() && response.preferences.is_some())
+        .await
+        .expect("Didn't receive preferences response");
+
+    fixture.send_request(Request::run_macro_request("Next song")).await;
+
+    fixture
+        .wait_for_response(|response| {
+            response
+                .project
+                .as_ref()
+                .is_some_and(|project| project.songs.len() == 2 && project.selections.song == project.songs[1].id)
+        })
+        .await
+        .expect("Macro should select the next song");
+}
+
+#[tokio::test]
+async fn run_unknown_macro_fails() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture.send_request(Request::run_macro_request("Missing")).await;
+
+    fixture
+        .wait_for_response(|response| !response.error.is_empty())
+        .await
+        .expect("Running an unknown macro should fail");
+}
+
+#[tokio::test]
+async fn project_changes_include_a_patch() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture.send_request(Request::get_request(Entity::PROJECT, 0)).await;
+    let response = fixture
+        .wait_for_response(|response| response.error.is_empty() && response.project.is_some())
+        .await
+        .expect("Didn't receive get response");
+    let version = response.project_version;
+
+    fixture.send_request(Request::add_song_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project_patch.is_some())
+        .await
+        .expect("Didn't receive add song response");
+
+    let patch = response.project_patch.as_ref().unwrap();
+    assert_eq!(patch.version, version + 1);
+    assert_eq!(response.project_version, patch.version);
+    assert_eq!(patch.songs.len(), 1);
+    assert!(patch.removed_songs.is_empty());
+    assert_eq!(patch.song_order.len(), response.project.songs.len());
+}
+
+#[tokio::test]
+async fn removed_song_can_be_undone_and_redone() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture.send_request(Request::add_song_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project.is_some())
+        .await
+        .expect("Didn't receive add song response");
+    let songs = response.project.songs.clone();
+
+    let response = fixture
+        .wait_for_response(|response| response.undo_status.is_some())
+        .await
+        .expect("Didn't receive undo status");
+    assert!(response.undo_status.can_undo);
+
+    let song_id = songs.last().unwrap().id;
+    fixture
+        .send_request(Request::remove_request(Entity::SONG, song_id))
+        .await;
+    fixture
+        .wait_for_response(|response| response.project.is_some() && response.project.song_with_id(song_id).is_none())
+        .await
+        .expect("Didn't receive remove song response");
+
+    fixture.send_request(Request::undo_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project.is_some())
+        .await
+        .expect("Didn't receive undo response");
+    assert_eq!(response.project.songs, songs);
+
+    let response = fixture
+        .wait_for_response(|response| response.undo_status.is_some())
+        .await
+        .expect("Didn't receive undo status");
+    assert!(response.undo_status.can_redo);
+
+    fixture.send_request(Request::redo_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project.is_some())
+        .await
+        .expect("Didn't receive redo response");
+    assert!(response.project.song_with_id(song_id).is_none());
+}
+
+#[tokio::test]
+async fn selections_are_not_undone() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture.send_request(Request::add_song_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project.songs.len() == 2)
+        .await
+        .expect("Didn't receive add song response");
+    let first_song = response.project.songs[0].id;
+    let second_song = response.project.songs[1].id;
+
+    fixture
+        .send_request(Request::select_request(Entity::SONG, first_song))
+        .await;
+    fixture
+        .wait_for_response(|response| response.project.selections.song == first_song)
+        .await
+        .expect("Didn't receive select response");
+
+    fixture.send_request(Request::add_song_request()).await;
+    fixture
+        .wait_for_response(|response| response.project.songs.len() == 3)
+        .await
+        .expect("Didn't receive add song response");
+
+    fixture
+        .send_request(Request::select_request(Entity::SONG, second_song))
+        .await;
+    fixture
+        .wait_for_response(|response| response.project.selections.song == second_song)
+        .await
+        .expect("Didn't receive select response");
+
+    // The song selected after the edit stays selected, rather than going back to
+    // the first song that was selected before it
+    fixture.send_request(Request::undo_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project.songs.len() == 2)
+        .await
+        .expect("Didn't receive undo response");
+    assert_eq!(response.project.selections.song, second_song);
+
+    // Undoing the song that's selected moves the selection to the song before it
+    fixture.send_request(Request::undo_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project.songs.len() == 1)
+        .await
+        .expect("Didn't receive undo response");
+    assert_eq!(response.project.selections.song, first_song);
+
+    fixture
+        .send_request(Request::undo_request().with_request_id("undo"))
+        .await;
+    let response = fixture
+        .wait_for_response(|response| response.acknowledgement.is_some())
+        .await
+        .expect("Didn't receive acknowledgement");
+    assert!(!response.acknowledgement.success);
+}
+
+#[tokio::test]
+async fn tempo_is_estimated_for_samples_without_one_in_their_name() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture
+        .add_sample_to_first_song(1234, "Song_v3_final.wav", click_track_wav(100.0, 16.0, 0.0))
+        .await;
+
+    let response = fixture
+        .wait_for_analysis(|response| response.tempo_analysis.is_some())
+        .await
+        .expect("Didn't receive tempo analysis");
+    let analysis = response.tempo_analysis.as_ref().unwrap();
+    assert_eq!(analysis.sample_id, 1234);
+    assert!((analysis.bpm - 100.0).abs() < 1.0, "{:?}", analysis);
+    assert!(analysis.confidence > 0.5, "{:?}", analysis);
+
+    let response = fixture
+        .wait_for_response(|response| response.project.is_some())
+        .await
+        .expect("Didn't receive project with the estimated tempo");
+    let song = &response.project.songs[0];
+    assert_eq!(song.tempo.bpm, analysis.bpm);
+    assert_eq!(song.sample.tempo.bpm, analysis.bpm);
+}
+
+#[tokio::test]
+async fn markers_are_placed_at_the_analysed_tempo() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    let wav = with_markers(
+        click_track_wav(100.0, 16.0, 0.0),
+        &[(0, "Intro"), (8 * 44_100, "Verse")],
+    );
+    fixture.add_sample_to_first_song(2345, "Song_v3_final.wav", wav).await;
+
+    let response = fixture
+        .wait_for_analysis(|response| {
+            response
+                .project
+                .songs
+                .first()
+                .is_some_and(|song| song.sections.iter().any(|section| section.name == "Verse"))
+        })
+        .await
+        .expect("Didn't receive project with the marked sections");
+    let song = &response.project.songs[0];
+    assert!((song.tempo.bpm - 100.0).abs() < 1.0, "{:?}", song.tempo);
+
+    let starts: Vec<(&str, f64)> = song
+        .sections
+        .iter()
+        .map(|section| (section.name.as_str(), section.start))
+        .collect();
+    assert_eq!(starts, vec![("Intro", 0.0), ("Verse", 8.0 * song.tempo.bpm / 60.0)]);
+}
+
+#[tokio::test]
+async fn suggested_sections_can_be_accepted() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    let song_id = fixture
+        .add_sample_to_first_song(5678, "Click 120.wav", click_track_wav(120.0, 16.0, 0.0))
+        .await;
+    fixture.send_request(Request::suggest_sections_request(song_id)).await;
+
+    let response = fixture
+        .wait_for_analysis(|response| response.section_suggestions.is_some())
+        .await
+        .expect("Didn't receive section suggestions");
+    let suggestions = response.section_suggestions.as_ref().unwrap();
+    assert_eq!(suggestions.song_id, song_id);
+    assert_eq!(suggestions.sections[0].start, 0.0);
+
+    fixture
+        .send_request(Request::replace_sections_request(song_id, &suggestions.sections))
+        .await;
+    let response = fixture
+        .wait_for_response(|response| response.project.is_some())
+        .await
+        .expect("Didn't receive project with the suggested sections");
+    assert_eq!(response.project.songs[0].sections, suggestions.sections);
+}
+
+#[tokio::test]
+async fn sample_start_is_detected_from_leading_silence() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    let song_id = fixture
+        .add_sample_to_first_song(9012, "Click 120.wav", click_track_wav(120.0, 4.0, 0.3))
+        .await;
+    fixture
+        .send_request(Request::detect_sample_start_request(song_id))
+        .await;
+
+    let response = fixture
+        .wait_for_response(|response| {
+            response
+                .project
+                .songs
+                .first()
+                .map_or(false, |song| song.sample.start_offset > 0)
+        })
+        .await
+        .expect("Didn't receive project with the sample start");
+    let sample = &response.project.songs[0].sample;
+    // The first click starts quietly, so its first loud sample is just after the silence
+    assert!(
+        (13_230..13_330).contains(&sample.start_offset),
+        "{}",
+        sample.start_offset
+    );
+}
+
+#[tokio::test]
+async fn loudness_is_measured_and_target_can_be_set() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture
+        .add_sample_to_first_song(3456, "Click 120.wav", click_track_wav(120.0, 4.0, 0.0))
+        .await;
+
+    let response = fixture
+        .wait_for_analysis(|response| {
+            response
+                .project
+                .songs
+                .first()
+                .map_or(false, |song| song.sample.loudness.is_some())
+        })
+        .await
+        .expect("Didn't receive project with the sample loudness");
+    let loudness = &response.project.songs[0].sample.loudness;
+    assert!(loudness.integrated < 0.0, "{:?}", loudness);
+    assert!(loudness.true_peak >= -3.0, "{:?}", loudness);
+
+    fixture
+        .send_request(Request::set_target_loudness_request(Some(-16.0)))
+        .await;
+    let response = fixture
+        .wait_for_response(|response| response.project.is_some())
+        .await
+        .expect("Didn't receive project with the target loudness");
+    assert_eq!(response.project.target_loudness, Some(-16.0));
+
+    fixture
+        .send_request(Request::set_target_loudness_request(Some(6.0)))
+        .await;
+    fixture
+        .wait_for_response(|response| !response.error.is_empty())
+        .await
+        .expect("Didn't receive error for a target out of range");
+}
+
+#[tokio::test]
+async fn waveforms_can_be_fetched_whole_or_by_range() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    let sample_id = 7890;
+    fixture
+        .add_sample_to_first_song(sample_id, "Click 120.wav", click_track_wav(120.0, 4.0, 0.0))
+        .await;
+
+    fixture
+        .send_request(Request::get_request(Entity::WAVEFORM, sample_id))
+        .await;
+    let response = fixture
+        .wait_for_analysis(|response| response.waveform.is_some())
+        .await
+        .expect("Didn't receive waveform");
+    let waveform = response.waveform.waveform_data.clone().unwrap();
+    assert_eq!(waveform.sample_rate, 44_100);
+    assert!(!waveform.peaks.is_empty());
+    assert!(waveform.peaks.iter().all(|group| group.properties.length >= 512));
+    assert!(waveform.peaks.iter().any(|group| group.properties.length == 512));
+
+    fixture
+        .send_request(Request::waveform_range_request(sample_id, 128, 44_100, 88_200))
+        .await;
+    let response = fixture
+        .wait_for_analysis(|response| response.waveform.is_some())
+        .await
+        .expect("Didn't receive waveform range");
+    assert_eq!(response.waveform.start, 44_032);
+    let peaks = &response.waveform.waveform_data.peaks;
+    assert!(!peaks.is_empty());
+    assert!(peaks
+        .iter()
+        .all(|group| group.properties.length == 128 && group.values.len() == 346));
+}
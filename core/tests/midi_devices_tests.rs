@@ -1,6 +1,6 @@
 mod common;
 
-use bloop::bloop::{Entity, MidiDevices, Request};
+use bloop::bloop::{Action, Entity, ErrorCode, MidiDevices, MidiMapping, MidiMappingMethod, Request};
 use common::IntegrationFixture;
 use protobuf::Message;
 
@@ -20,6 +20,48 @@ async fn get_midi_devices_returns_response() {
     assert!(response.midi_devices.is_some());
 }
 
+#[tokio::test]
+async fn get_midi_mappings_returns_response() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture
+        .send_request(Request::get_request(Entity::MIDI_MAPPINGS, 0))
+        .await;
+
+    let response = fixture
+        .wait_for_response(|response| response.error.is_empty() && response.midi_mappings.is_some())
+        .await
+        .expect("Didn't receive midi_mappings response");
+
+    assert!(response.midi_mappings.mappings.is_empty());
+}
+
+#[tokio::test]
+async fn midi_learn_fails_when_midi_is_disabled() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    let mapping = MidiMapping {
+        action: Action::ACTION_TOGGLE_PLAY.into(),
+        ..Default::default()
+    };
+    fixture
+        .send_request(
+            Request::midi_mapping_request(MidiMappingMethod::MIDI_MAPPING_METHOD_LEARN, mapping)
+                .with_request_id("learn"),
+        )
+        .await;
+
+    let response = fixture
+        .wait_for_response(|response| response.acknowledgement.is_some())
+        .await
+        .expect("Didn't receive acknowledgement");
+    assert!(!response.acknowledgement.success);
+    assert_eq!(
+        response.acknowledgement.error_code.enum_value_or_default(),
+        ErrorCode::ERROR_CODE_INVALID_STATE
+    );
+}
+
 #[test]
 fn midi_devices_round_trip_serialization() {
     let original = MidiDevices {
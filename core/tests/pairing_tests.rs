@@ -0,0 +1,55 @@
+mod common;
+
+use bloop::bloop::{Entity, ErrorCode, Request};
+use common::IntegrationFixture;
+
+#[tokio::test]
+async fn get_paired_devices() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture
+        .send_request(Request::get_request(Entity::PAIRED_DEVICES, 0))
+        .await;
+
+    let response = fixture
+        .wait_for_response(|response| response.pairing_status.is_some())
+        .await
+        .expect("Didn't receive pairing status");
+
+    let pairing_status = response.pairing_status.unwrap();
+    assert_eq!(pairing_status.pin.len(), 6);
+    assert!(pairing_status.devices.is_empty());
+}
+
+#[tokio::test]
+async fn revoke_unknown_device_fails() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture
+        .send_request(Request::revoke_device_request("missing").with_request_id("revoke"))
+        .await;
+
+    let response = fixture
+        .wait_for_response(|response| response.acknowledgement.is_some())
+        .await
+        .expect("Didn't receive acknowledgement");
+
+    assert_eq!(
+        response.acknowledgement.error_code.enum_value_or_default(),
+        ErrorCode::ERROR_CODE_NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn get_network_status() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture
+        .send_request(Request::get_request(Entity::NETWORK_STATUS, 0))
+        .await;
+
+    fixture
+        .wait_for_response(|response| response.network_status.is_some())
+        .await
+        .expect("Didn't receive network status");
+}
@@ -34,3 +34,72 @@ async fn get_projects_request_with_saved_project() {
         .await
         .expect("Didn't receive get projects response");
 }
+
+#[tokio::test]
+async fn export_and_import_project_bundle() {
+    let mut fixture = IntegrationFixture::new().await;
+    let bundle_path = "gigs/friday.bloop";
+
+    fixture.send_request(Request::save_project_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project_info.is_some() && !response.project_info.id.is_empty())
+        .await
+        .expect("Didn't receive save project response");
+    let project_id = response.project_info.id.clone();
+
+    fixture
+        .send_request(Request::export_project_request(&project_id, bundle_path))
+        .await;
+    let response = fixture
+        .wait_for_response(|response| !response.exported_bundle.is_empty() || !response.error.is_empty())
+        .await
+        .expect("Didn't receive export response");
+    assert!(response.error.is_empty(), "{}", response.error);
+    assert!(response.exported_bundle.ends_with(bundle_path));
+
+    fixture.send_request(Request::import_project_request(bundle_path)).await;
+    let response = fixture
+        .wait_for_response(|response| !response.projects.is_empty() || !response.error.is_empty())
+        .await
+        .expect("Didn't receive import response");
+    assert!(response.error.is_empty());
+    assert_eq!(response.projects.len(), 2);
+    assert!(response.projects.iter().any(|project| project.id != project_id));
+}
+
+#[tokio::test]
+async fn bundles_must_be_inside_the_exports_directory() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture.send_request(Request::save_project_request()).await;
+    let response = fixture
+        .wait_for_response(|response| response.project_info.is_some() && !response.project_info.id.is_empty())
+        .await
+        .expect("Didn't receive save project response");
+    let project_id = response.project_info.id.clone();
+
+    for path in ["/tmp/gig.bloop", "../gig.bloop"] {
+        fixture
+            .send_request(Request::export_project_request(&project_id, path))
+            .await;
+        fixture
+            .wait_for_response(|response| !response.error.is_empty())
+            .await
+            .expect("Didn't receive export error");
+
+        fixture.send_request(Request::import_project_request(path)).await;
+        fixture
+            .wait_for_response(|response| !response.error.is_empty())
+            .await
+            .expect("Didn't receive import error");
+    }
+
+    fixture
+        .send_request(Request::export_project_request("../projects", ""))
+        .await;
+    let response = fixture
+        .wait_for_response(|response| !response.error.is_empty())
+        .await
+        .expect("Didn't receive error for an unknown project");
+    assert!(response.error.contains("Project not found"), "{}", response.error);
+}
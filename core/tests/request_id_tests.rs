@@ -0,0 +1,109 @@
+mod common;
+
+use bloop::bloop::{Entity, ErrorCode, GetRequest, Request};
+use common::IntegrationFixture;
+
+#[tokio::test]
+async fn successful_request_is_acknowledged() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    let request = Request::get_request(Entity::PREFERENCES, 0).with_request_id("get-preferences");
+    fixture.send_request(request).await;
+
+    let response = fixture
+        .wait_for_response(|response| response.acknowledgement.is_some())
+        .await
+        .expect("Didn't receive acknowledgement");
+
+    let acknowledgement = response.acknowledgement.unwrap();
+    assert_eq!(acknowledgement.request_id, "get-preferences");
+    assert!(acknowledgement.success);
+    assert_eq!(
+        acknowledgement.error_code.enum_value_or_default(),
+        ErrorCode::ERROR_CODE_NONE
+    );
+}
+
+#[tokio::test]
+async fn failed_request_is_acknowledged_with_error_code() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture
+        .send_request(Request::run_macro_request("Missing").with_request_id("macro"))
+        .await;
+
+    let response = fixture
+        .wait_for_response(|response| response.acknowledgement.is_some() || !response.error.is_empty())
+        .await
+        .expect("Didn't receive acknowledgement");
+
+    // The error isn't broadcast when the request has an ID
+    assert!(response.error.is_empty());
+
+    let acknowledgement = response.acknowledgement.unwrap();
+    assert_eq!(acknowledgement.request_id, "macro");
+    assert!(!acknowledgement.success);
+    assert_eq!(
+        acknowledgement.error_code.enum_value_or_default(),
+        ErrorCode::ERROR_CODE_NOT_FOUND
+    );
+    assert!(!acknowledgement.error.is_empty());
+}
+
+#[tokio::test]
+async fn invalid_entity_is_reported() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    let request = Request {
+        get: Some(GetRequest {
+            entity: protobuf::EnumOrUnknown::from_i32(999),
+            ..Default::default()
+        })
+        .into(),
+        ..Default::default()
+    }
+    .with_request_id("invalid");
+    fixture.send_request(request).await;
+
+    let response = fixture
+        .wait_for_response(|response| response.acknowledgement.is_some())
+        .await
+        .expect("Didn't receive acknowledgement");
+
+    assert_eq!(
+        response.acknowledgement.error_code.enum_value_or_default(),
+        ErrorCode::ERROR_CODE_INVALID_ENTITY
+    );
+}
+
+#[tokio::test]
+async fn request_without_id_still_broadcasts_error() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    fixture.send_request(Request::run_macro_request("Missing")).await;
+
+    let response = fixture
+        .wait_for_response(|response| response.acknowledgement.is_some() || !response.error.is_empty())
+        .await
+        .expect("Didn't receive error");
+
+    assert!(response.acknowledgement.is_none());
+}
+
+#[tokio::test]
+async fn snapshot_is_sent_with_its_acknowledgement() {
+    let mut fixture = IntegrationFixture::new().await;
+
+    let request = Request::get_request(Entity::SNAPSHOT, 0).with_request_id("snapshot");
+    fixture.send_request(request).await;
+
+    let response = fixture
+        .wait_for_response(|response| response.acknowledgement.is_some())
+        .await
+        .expect("Didn't receive acknowledgement");
+
+    assert_eq!(response.acknowledgement.request_id, "snapshot");
+    assert!(response.project.is_some());
+    assert!(response.playback_state.is_some());
+    assert!(response.preferences.is_some());
+}